@@ -67,7 +67,8 @@ Rectangle {
             Keys.onReturnPressed: Herald.registerNewUser(
                                       entryField.text.trim(),
                                       serverAddrTextField.text.trim(),
-                                      serverPortTextField.text.trim())
+                                      serverPortTextField.text.trim(),
+                                      serverPinTextField.text.trim())
         }
 
         TextField {
@@ -88,6 +89,14 @@ Rectangle {
             text: '8080'
         }
 
+        TextField {
+            id: serverPinTextField
+            anchors.horizontalCenter: parent.horizontalCenter
+            width: 150
+            height: 25
+            placeholderText: qsTr("Server certificate pin")
+        }
+
         Button {
             id: registrationButton
             anchors.horizontalCenter: parent.horizontalCenter
@@ -107,7 +116,8 @@ Rectangle {
             onClicked: {
                 Herald.registerNewUser(entryField.text.trim(),
                                        serverAddrTextField.text.trim(),
-                                       serverPortTextField.text.trim())
+                                       serverPortTextField.text.trim(),
+                                       serverPinTextField.text.trim())
             }
         }
 
@@ -191,10 +191,12 @@ export function receiptCodeSwitch(receiptCode) {
         case 1 /* Ack */: {
             return "qrc:/read-receipt-sent-icon.svg";
         }
-        case 2 /* Received */: {
+        // no separate icon yet, a delivered message has reached one of the recipient's devices
+        case 2 /* Delivered */:
+        case 3 /* Received */: {
             return "qrc:/read-receipt-received-icon.svg";
         }
-        case 3 /* Read */: {
+        case 4 /* Read */: {
             return "qrc:/read-receipt-read-icon.svg";
         }
         default:
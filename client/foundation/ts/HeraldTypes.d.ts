@@ -38,10 +38,12 @@ declare const enum MessageStatus {
   NoAck = 0,
   // Received by server
   Ack = 1,
+  /// Fetched from the server by at least one recipient device
+  Delivered = 2,
   /// Received by user
-  Received = 2,
+  Received = 3,
   /// Read by the recipient
-  Read = 3
+  Read = 4
 }
 
 declare const enum RegistrationFailureCode {
@@ -79,7 +81,7 @@ declare class Herald {
   usersSearch: Users;
   utils: Utils;
 
-  registerNewUser(userid: UserId): boolean;
+  registerNewUser(userid: UserId, addr: string, port: string, pin: string): void;
   login(): boolean;
 }
 
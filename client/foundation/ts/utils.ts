@@ -236,6 +236,8 @@ export function receiptCodeSwitch(receiptCode: MessageStatus): string {
     case MessageStatus.Ack: {
       return "qrc:/read-receipt-sent-icon.svg";
     }
+    // no separate icon yet, a delivered message has reached one of the recipient's devices
+    case MessageStatus.Delivered:
     case MessageStatus.Received: {
       return "qrc:/read-receipt-received-icon.svg";
     }
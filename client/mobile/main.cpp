@@ -121,6 +121,9 @@ int main(int argc, char* argv[])
   qmlRegisterType<SharedConversations>("LibHerald", 1, 0,
                                        "SharedConversations");
 
+  // The account's devices
+  qmlRegisterType<Devices>("LibHerald", 1, 0, "Devices");
+
   // Support model for emoji input
   qmlRegisterType<EmojiPicker>("LibHerald", 1, 0, "EmojiPicker");
 
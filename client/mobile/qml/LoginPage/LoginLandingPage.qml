@@ -41,14 +41,25 @@ Page {
         id: serverPortTextField
         anchors {
             horizontalCenter: newAccButton.horizontalCenter
-            bottom: newAccButton.top
-            bottomMargin: CmnCfg.units.dp(30)
+            bottom: serverPinTextField.top
+            bottomMargin: CmnCfg.units.dp(15)
         }
         width: parent.width - 2 * CmnCfg.megaMargin
         placeholderText: qsTr("Server port")
         text: "8080"
     }
 
+    TextField {
+        id: serverPinTextField
+        anchors {
+            horizontalCenter: newAccButton.horizontalCenter
+            bottom: newAccButton.top
+            bottomMargin: CmnCfg.units.dp(30)
+        }
+        width: parent.width - 2 * CmnCfg.megaMargin
+        placeholderText: qsTr("Server certificate pin")
+    }
+
     LoginButton {
         id: newAccButton
 
@@ -64,7 +75,8 @@ Page {
         onClicked: {
             Herald.registerNewUser(entryField.text.trim(),
                                    serverAddrTextField.text.trim(),
-                                   serverPortTextField.text.trim())
+                                   serverPortTextField.text.trim(),
+                                   serverPinTextField.text.trim())
         }
     }
 
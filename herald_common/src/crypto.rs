@@ -216,6 +216,43 @@ pub mod sig {
         })
     }
 
+    pub const CHAIN_HASH_LEN: usize = kcl::hash::HASH_REC_LEN;
+
+    new_type! {
+        /// A running hash over the signatures on every link of a sigchain, up to some link
+        public ChainHash(CHAIN_HASH_LEN)
+    }
+
+    impl ChainHash {
+        fn of_initial(initial: &Signature) -> Self {
+            ChainHash(kcl::hash::simple_hash(initial.as_ref()))
+        }
+
+        fn then(
+            &self,
+            link: &Signature,
+        ) -> Self {
+            let mut buf = Vec::with_capacity(CHAIN_HASH_LEN + SIGNATURE_BYTES);
+            buf.extend_from_slice(self.as_ref());
+            buf.extend_from_slice(link.as_ref());
+
+            ChainHash(kcl::hash::simple_hash(&buf))
+        }
+    }
+
+    /// The latest state of a sigchain, as seen by some client.
+    ///
+    /// The hash covers every link up to the head, so two chains with the same head agree on all
+    /// of those links. Clients compare heads to catch a server showing them different versions of
+    /// a chain.
+    #[derive(Ser, De, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct SigChainHead {
+        /// The number of links in the chain, counting the initial one
+        pub len: u64,
+        /// The running hash over those links
+        pub hash: ChainHash,
+    }
+
     #[derive(Ser, De, Debug, Clone, PartialEq, Eq)]
     pub struct SigChain {
         pub initial: Signed<UserId>,
@@ -230,7 +267,11 @@ pub mod sig {
             self.sig_chain
                 .iter()
                 .fold(
-                    (HashSet::new(), self.initial.verify_sig()),
+                    (
+                        // the initial key is the one that starts endorsing others
+                        std::iter::once(*self.initial.signed_by()).collect::<HashSet<_>>(),
+                        self.initial.verify_sig(),
+                    ),
                     |(mut valid_keys, status), update| {
                         let new_status = status.and(|| {
                             validate_update(update).and(|| {
@@ -255,6 +296,41 @@ pub mod sig {
                 .1
         }
 
+        /// The running hashes over each prefix of the chain, starting with the initial link.
+        fn hashes(&self) -> impl Iterator<Item = ChainHash> + '_ {
+            let mut hash = ChainHash::of_initial(&self.initial.sig());
+
+            std::iter::once(hash).chain(self.sig_chain.iter().map(move |update| {
+                hash = hash.then(&update.sig());
+                hash
+            }))
+        }
+
+        /// The head of the chain, i.e., its length and a hash over all of its links.
+        pub fn head(&self) -> SigChainHead {
+            SigChainHead {
+                len: self.sig_chain.len() as u64 + 1,
+                hash: self
+                    .hashes()
+                    .last()
+                    .expect("every chain has an initial link"),
+            }
+        }
+
+        /// Indicates whether this chain is `head`'s chain, possibly with more links after it.
+        ///
+        /// Every link up to `head` has to match, not just the last one.
+        pub fn extends(
+            &self,
+            head: &SigChainHead,
+        ) -> bool {
+            if head.len == 0 {
+                return false;
+            }
+
+            self.hashes().nth(head.len as usize - 1) == Some(head.hash)
+        }
+
         pub fn active_keys(&self) -> std::collections::HashSet<sig::PublicKey> {
             let mut keys = std::collections::HashSet::new();
             keys.insert(*self.initial.signed_by());
@@ -273,7 +349,7 @@ pub mod sig {
     }
 }
 
-#[derive(Ser, De, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Ser, De, Hash, Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Prekey(pub kcl::kx::PublicKey);
 
 impl Prekey {
@@ -281,3 +357,184 @@ impl Prekey {
         kcl::kx::PublicKey::from_slice(bytes).map(Self)
     }
 }
+
+/// The prekeys a device publishes so that others can start sessions with it while it is offline.
+#[derive(Ser, De, Hash, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrekeyBundle {
+    /// Medium-term prekey, reused until the device rotates it
+    pub signed: Signed<Prekey>,
+    /// Single-use prekey, if the device had one available
+    pub one_time: Option<Signed<Prekey>>,
+}
+
+impl PrekeyBundle {
+    /// Checks that every prekey in the bundle was signed by `owner`.
+    pub fn verify(
+        &self,
+        owner: &sig::PublicKey,
+    ) -> SigValid {
+        let check = |p: &Signed<Prekey>| {
+            if p.signed_by() != owner {
+                SigValid::BadSigner
+            } else {
+                p.verify_sig()
+            }
+        };
+
+        check(&self.signed).and(|| self.one_time.as_ref().map(check).unwrap_or(SigValid::Yes))
+    }
+}
+
+/// Length of a server certificate pin, in bytes.
+pub const CERT_PIN_LEN: usize = kcl::hash::HASH_REC_LEN;
+
+/// Fingerprint of the DER-encoded leaf certificate a server presents.
+///
+/// Clients store this alongside their home server and refuse TLS connections to servers whose
+/// certificate does not hash to it.
+#[derive(Hash, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CertPin(pub [u8; CERT_PIN_LEN]);
+
+impl CertPin {
+    /// Computes the pin for a DER-encoded certificate.
+    pub fn of(der: &[u8]) -> Self {
+        CertPin(kcl::hash::simple_hash(der))
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != CERT_PIN_LEN {
+            return None;
+        }
+
+        let mut buf = [0u8; CERT_PIN_LEN];
+        buf.copy_from_slice(bytes);
+        Some(CertPin(buf))
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for CertPin {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        for b in self.0.iter() {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidCertPin;
+
+impl std::fmt::Display for InvalidCertPin {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        write!(
+            f,
+            "InvalidCertPin: expected {} hex-encoded bytes",
+            CERT_PIN_LEN
+        )
+    }
+}
+
+impl std::error::Error for InvalidCertPin {}
+
+impl std::str::FromStr for CertPin {
+    type Err = InvalidCertPin;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.len() != 2 * CERT_PIN_LEN || !s.is_ascii() {
+            return Err(InvalidCertPin);
+        }
+
+        let mut buf = [0u8; CERT_PIN_LEN];
+        for (ix, b) in buf.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&s[2 * ix..2 * ix + 2], 16).map_err(|_| InvalidCertPin)?;
+        }
+
+        Ok(CertPin(buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sig::{sign_ser, SigUpdate};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn sigchain_validates_endorsements() {
+        let uid = UserId::try_from("a").unwrap();
+        let first = sig::KeyPair::gen_new();
+        let second = sig::KeyPair::gen_new();
+        let stranger = sig::KeyPair::gen_new();
+
+        let mut chain = sig::SigChain {
+            initial: sign_ser(&first, uid),
+            sig_chain: vec![sign_ser(&first, SigUpdate::Endorse(sign_ser(&second, uid)))],
+        };
+
+        assert_eq!(chain.validate(), SigValid::Yes);
+        assert!(chain.active_keys().contains(second.public()));
+
+        chain
+            .sig_chain
+            .push(sign_ser(&stranger, SigUpdate::Deprecate(*first.public())));
+
+        assert_eq!(chain.validate(), SigValid::BadSigner);
+    }
+
+    #[test]
+    fn sigchain_heads() {
+        let uid = UserId::try_from("a").unwrap();
+        let first = sig::KeyPair::gen_new();
+        let second = sig::KeyPair::gen_new();
+
+        let mut chain = sig::SigChain {
+            initial: sign_ser(&first, uid),
+            sig_chain: vec![],
+        };
+
+        let initial = chain.head();
+        assert_eq!(initial.len, 1);
+        assert!(chain.extends(&initial));
+
+        let endorse = sign_ser(&first, SigUpdate::Endorse(sign_ser(&second, uid)));
+        let deprecate = sign_ser(&second, SigUpdate::Deprecate(*first.public()));
+
+        chain.sig_chain.push(endorse);
+        chain.sig_chain.push(deprecate);
+
+        let head = chain.head();
+        assert_eq!(head.len, 3);
+        assert!(chain.extends(&initial));
+        assert!(chain.extends(&head));
+
+        // a chain missing the deprecation doesn't extend the head
+        chain.sig_chain.pop();
+        assert!(!chain.extends(&head));
+
+        // nor does one that has something else in its place
+        chain
+            .sig_chain
+            .push(sign_ser(&first, SigUpdate::Deprecate(*second.public())));
+        assert!(!chain.extends(&head));
+
+        // nor does one that only differs in an earlier link
+        let third = sig::KeyPair::gen_new();
+        chain.sig_chain = vec![
+            sign_ser(&first, SigUpdate::Endorse(sign_ser(&third, uid))),
+            deprecate,
+        ];
+        assert_eq!(chain.head().len, head.len);
+        assert!(!chain.extends(&head));
+    }
+}
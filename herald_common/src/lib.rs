@@ -2,6 +2,10 @@ mod crypto;
 pub use crypto::*;
 #[cfg(feature = "rusqlite")]
 mod rusqlite_impls;
+#[cfg(feature = "rusqlite")]
+pub mod sqlite_cipher;
+#[cfg(feature = "rusqlite")]
+pub mod sqlite_migrations;
 mod types;
 pub use types::*;
 mod time;
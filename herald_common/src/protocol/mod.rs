@@ -3,3 +3,4 @@ use super::*;
 pub mod auth;
 pub mod pushes;
 pub mod requests;
+pub mod socket;
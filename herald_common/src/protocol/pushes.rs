@@ -4,6 +4,9 @@ use super::*;
 pub enum PushTag {
     User,
     Key,
+    /// Sent by the server rather than another device, `msg` is a `Signed<DeliveryReceipt>` signed
+    /// with the server's key.
+    Receipt,
 }
 
 #[derive(Ser, De, Debug, Clone, PartialEq, Eq)]
@@ -14,6 +17,19 @@ pub struct Push {
     pub gid: GlobalId,
 }
 
+/// Tells the device that sent a push that one of its recipient devices has fetched it.
+///
+/// The server sends one for each device it delivers a push to, as a push tagged
+/// `PushTag::Receipt`. It is signed with the key from `server_key`, so it can't be forged by
+/// anyone relaying it.
+#[derive(Ser, De, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeliveryReceipt {
+    /// The id the server gave the push, as returned in `push::Res::Success`
+    pub push_id: i64,
+    /// The device the push was delivered to
+    pub to: GlobalId,
+}
+
 #[derive(Ser, De, Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PushMeta {
     pub tag: PushTag,
@@ -23,10 +39,63 @@ pub struct PushMeta {
 #[derive(Ser, De, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PushAck {
     Success,
-    LogFailure,
+    /// The client can never process the push. The server logs it and stops delivering it,
+    /// instead of retrying forever.
+    ///
+    /// Failures that may go away, e.g., because the client's storage is busy, aren't acked at
+    /// all, so that the push is delivered again.
+    LogFailure {
+        reason: FailureCode,
+    },
     Quit,
 }
 
+/// Why a client can't process a push, see `PushAck::LogFailure`.
+#[derive(Ser, De, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FailureCode {
+    /// The push couldn't be decoded
+    Malformed,
+    /// A signature in the push didn't verify
+    BadSig,
+    /// The push came from a device that isn't allowed to send it
+    InvalidSender,
+}
+
+impl FailureCode {
+    /// The number the failure is stored as.
+    pub fn code(self) -> u32 {
+        match self {
+            FailureCode::Malformed => 1,
+            FailureCode::BadSig => 2,
+            FailureCode::InvalidSender => 3,
+        }
+    }
+
+    pub fn from_code(code: u32) -> Option<Self> {
+        match code {
+            1 => Some(FailureCode::Malformed),
+            2 => Some(FailureCode::BadSig),
+            3 => Some(FailureCode::InvalidSender),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for FailureCode {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        let name = match self {
+            FailureCode::Malformed => "malformed",
+            FailureCode::BadSig => "bad-sig",
+            FailureCode::InvalidSender => "invalid-sender",
+        };
+
+        f.write_str(name)
+    }
+}
+
 pub mod catchup {
     use super::*;
 
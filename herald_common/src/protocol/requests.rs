@@ -19,6 +19,15 @@ pub enum Recip {
 }
 
 impl Recip {
+    /// Number of users or devices addressed, before users are expanded into their devices.
+    pub fn fan_out(&self) -> usize {
+        match self {
+            Recip::One(_) => 1,
+            Recip::Many(Recips::Users(uids)) => uids.len(),
+            Recip::Many(Recips::Keys(keys)) => keys.len(),
+        }
+    }
+
     pub fn tag(&self) -> PushTag {
         match self {
             Recip::One(SingleRecip::User(_)) => PushTag::User,
@@ -59,7 +68,13 @@ pub mod new_sig {
 pub mod new_prekeys {
     use super::*;
 
-    pub type Req = Vec<(Signed<Prekey>, Option<Prekey>)>;
+    #[derive(Ser, De, Debug, Clone, PartialEq, Eq)]
+    pub struct Req {
+        /// New signed prekey, replaces the current one if present
+        pub signed: Option<Signed<Prekey>>,
+        /// New one-time prekeys, each optionally replacing an unused one
+        pub one_time: Vec<(Signed<Prekey>, Option<Prekey>)>,
+    }
 
     #[derive(Ser, De, Debug, Clone, Copy, PartialEq, Eq)]
     pub enum Res {
@@ -77,28 +92,80 @@ pub mod get_prekeys {
     /// Public key to fetch prekeys for
     pub type Req = Vec<sig::PublicKey>;
 
-    /// Corresponding prekeys
-    pub type Res = Vec<(sig::PublicKey, Signed<Prekey>)>;
+    /// Corresponding prekey bundles, keys without a signed prekey are omitted.
+    ///
+    /// One-time prekeys are removed from the server once they have been handed out.
+    pub type Res = Vec<(sig::PublicKey, PrekeyBundle)>;
+}
+
+pub mod prekey_count {
+    use super::*;
+
+    /// Public key to count the remaining one-time prekeys of
+    pub type Req = sig::PublicKey;
+
+    /// Number of one-time prekeys that haven't been handed out yet
+    pub type Res = u64;
+}
+
+pub mod held_prekeys {
+    use super::*;
+
+    /// Public key to list the remaining one-time prekeys of
+    pub type Req = sig::PublicKey;
+
+    /// One-time prekeys that haven't been handed out yet
+    pub type Res = Vec<Prekey>;
 }
 
 pub mod push {
     use super::*;
 
+    /// How far the timestamp of a push's signature may be from the server's clock, in
+    /// milliseconds. Older pushes are rejected, and the server remembers the signatures of those
+    /// it accepted for this long, so a push can't be replayed.
+    pub const MAX_CLOCK_SKEW: i64 = 5 * 60 * 1000;
+
     #[derive(Ser, De, Debug, Clone, PartialEq, Eq)]
-    pub struct Req {
-        // todo: replace this with auth token
-        pub from: GlobalId,
+    pub struct Body {
         pub to: Recip,
         pub msg: Bytes,
     }
 
+    /// A push signed by the sending device, the server attributes it to the signer.
+    pub type Req = Signed<Body>;
+
     #[derive(Ser, De, Debug, Clone, PartialEq, Eq)]
     pub enum Res {
-        Success(Time),
+        /// The push was accepted at `ts`. Delivery receipts for it carry `push_id`, which is
+        /// `None` if the push wasn't addressed to any device but the sender's.
+        Success {
+            ts: Time,
+            push_id: Option<i64>,
+        },
         Missing(SingleRecip),
+        BadSig(SigValid),
+        /// The signing key is unknown or deprecated
+        DeadKey,
+        /// The signature's timestamp is too far from the server's clock
+        Stale,
+        /// The same signed push was already accepted
+        Replayed,
+        /// Every device addressed has too many undelivered pushes waiting for it, nothing was
+        /// sent. Full devices are skipped when others can still take the push.
+        QueueFull(sig::PublicKey),
     }
 }
 
+pub mod server_key {
+    use super::*;
+
+    pub type Req = ();
+
+    /// The key the server signs delivery receipts with
+    pub type Res = sig::PublicKey;
+}
+
 pub mod register {
     use super::*;
 
@@ -117,6 +184,9 @@ macro_rules! proto_enum {
 
             NewPrekey(new_prekeys::$inner),
             GetPrekey(get_prekeys::$inner),
+            PrekeyCount(prekey_count::$inner),
+            HeldPrekeys(held_prekeys::$inner),
+            ServerKey(server_key::$inner),
 
             Push(push::$inner),
 
@@ -128,5 +198,47 @@ macro_rules! proto_enum {
     };
 }
 
+/// Why the server didn't handle a request.
+#[derive(Ser, De, Debug, Clone, PartialEq, Eq)]
+pub enum RequestError {
+    /// Too many requests of this kind were made recently
+    RateLimited { retry_after_ms: u64 },
+    /// The push's message is longer than the server accepts
+    MsgTooLarge { len: u64, max: u64 },
+    /// The push or prekey request is addressed to more users or devices than the server accepts
+    TooManyRecips { count: u64, max: u64 },
+    /// The request couldn't be decoded
+    Malformed,
+    /// The server failed to handle the request, the details are in its logs
+    Internal,
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter,
+    ) -> std::fmt::Result {
+        use RequestError::*;
+
+        match self {
+            RateLimited { retry_after_ms } => {
+                write!(f, "rate limited, retry in {}ms", retry_after_ms)
+            }
+            MsgTooLarge { len, max } => {
+                write!(f, "message is {} bytes, at most {} are allowed", len, max)
+            }
+            TooManyRecips { count, max } => write!(
+                f,
+                "request has {} recipients, at most {} are allowed",
+                count, max
+            ),
+            Malformed => write!(f, "malformed request"),
+            Internal => write!(f, "internal server error"),
+        }
+    }
+}
+
+impl std::error::Error for RequestError {}
+
 proto_enum!(Request, Req);
-proto_enum!(Response, Res, | Err(String));
+proto_enum!(Response, Res, | Err(RequestError));
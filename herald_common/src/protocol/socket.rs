@@ -0,0 +1,22 @@
+//! Messages exchanged over the login websocket once catchup is done.
+//!
+//! The server interleaves pushes with responses to requests the client made over the same
+//! socket. Requests are tagged with an id chosen by the client, which the server echoes back in
+//! the response, so several requests can be in flight at once. Push acknowledgements are sent in
+//! the order the pushes were received.
+
+use super::*;
+
+pub type RequestId = u64;
+
+#[derive(Ser, De, Debug, Clone, PartialEq, Eq)]
+pub enum ClientMsg {
+    PushAck(PushAck),
+    Request(RequestId, Request),
+}
+
+#[derive(Ser, De, Debug, Clone, PartialEq, Eq)]
+pub enum ServerMsg {
+    Push(Push),
+    Response(RequestId, Response),
+}
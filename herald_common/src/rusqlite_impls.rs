@@ -1,4 +1,4 @@
-use crate::{time::Time, types::*};
+use crate::{time::Time, types::*, CertPin};
 use rusqlite::{types as sql_types, ToSql};
 use std::convert::TryFrom;
 
@@ -30,3 +30,16 @@ impl sql_types::FromSql for Time {
         Ok(value.as_i64()?.into())
     }
 }
+
+impl ToSql for CertPin {
+    fn to_sql(&self) -> Result<sql_types::ToSqlOutput, rusqlite::Error> {
+        use sql_types::*;
+        Ok(ToSqlOutput::Borrowed(ValueRef::Blob(self.as_slice())))
+    }
+}
+
+impl sql_types::FromSql for CertPin {
+    fn column_result(value: sql_types::ValueRef) -> sql_types::FromSqlResult<Self> {
+        CertPin::from_slice(value.as_blob()?).ok_or(sql_types::FromSqlError::InvalidType)
+    }
+}
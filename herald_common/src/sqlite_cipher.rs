@@ -0,0 +1,83 @@
+//! At-rest encryption for the client's SQLite databases, using SQLCipher.
+//!
+//! Databases are keyed with a raw 256-bit key rather than a passphrase, so SQLCipher's own key
+//! derivation is skipped; passphrases are stretched with `kcl::pwhash` before they get here.
+//! A connection must be keyed before anything else is done with it.
+//!
+//! SQLCipher is linked when the `sqlcipher` feature is enabled. Without it, keying fails with
+//! `CipherError::Unsupported` rather than silently leaving the database in plaintext.
+
+use kcl::aead;
+use rusqlite::{Connection, NO_PARAMS};
+use std::fmt;
+
+#[derive(Debug)]
+pub enum CipherError {
+    Sqlite(rusqlite::Error),
+    /// SQLite was built without SQLCipher.
+    Unsupported,
+    /// The database is not encrypted with the key it was opened with.
+    WrongKey,
+}
+
+impl fmt::Display for CipherError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        match self {
+            CipherError::Sqlite(e) => write!(f, "Keying database failed: {}", e),
+            CipherError::Unsupported => write!(f, "SQLite was built without SQLCipher"),
+            CipherError::WrongKey => write!(f, "Database is encrypted with a different key"),
+        }
+    }
+}
+
+impl std::error::Error for CipherError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CipherError::Sqlite(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for CipherError {
+    fn from(e: rusqlite::Error) -> Self {
+        CipherError::Sqlite(e)
+    }
+}
+
+/// Keys `conn`, creating the database encrypted if it is new.
+pub fn set_key(
+    conn: &Connection,
+    key: &aead::Key,
+) -> Result<(), CipherError> {
+    // plain SQLite ignores unknown pragmas, so check that SQLCipher is actually there
+    let version: Option<String> =
+        match conn.query_row("PRAGMA cipher_version", NO_PARAMS, |row| row.get(0)) {
+            Ok(version) => Some(version),
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(e) => return Err(e.into()),
+        };
+
+    if version.is_none() {
+        return Err(CipherError::Unsupported);
+    }
+
+    let hex: String = key.as_ref().iter().map(|b| format!("{:02x}", b)).collect();
+    conn.execute_batch(&format!("PRAGMA key = \"x'{}'\";", hex))?;
+
+    // the key is only checked once the database is read
+    match conn.query_row("SELECT count(*) FROM sqlite_master", NO_PARAMS, |row| {
+        row.get::<_, i64>(0)
+    }) {
+        Ok(_) => Ok(()),
+        Err(rusqlite::Error::SqliteFailure(e, _))
+            if e.code == rusqlite::ErrorCode::NotADatabase =>
+        {
+            Err(CipherError::WrongKey)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,172 @@
+//! Forward-only schema migrations for the client's SQLite databases.
+//!
+//! A database's schema version is kept in `PRAGMA user_version`, which is 0 for a new database.
+//! Migration `n` (counting from 1) brings the schema from version `n - 1` to version `n`.
+//! Migrations are never edited once released; changes to a schema go in a new migration appended
+//! to the end of the list.
+//!
+//! The first migration of each database creates its tables with `IF NOT EXISTS`, so databases
+//! created before versions were tracked are picked up at version 0 and migrated like new ones.
+
+use rusqlite::{Connection, Transaction, TransactionBehavior, NO_PARAMS};
+use std::fmt;
+
+#[derive(Debug)]
+pub enum MigrationError {
+    Sqlite(rusqlite::Error),
+    /// The database was written by a newer version of the client.
+    TooNew {
+        found: u32,
+        expected: u32,
+    },
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        match self {
+            MigrationError::Sqlite(e) => write!(f, "Migration failed: {}", e),
+            MigrationError::TooNew { found, expected } => write!(
+                f,
+                "Database schema is at version {}, newer than the latest known version {}",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MigrationError::Sqlite(e) => Some(e),
+            MigrationError::TooNew { .. } => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for MigrationError {
+    fn from(e: rusqlite::Error) -> Self {
+        MigrationError::Sqlite(e)
+    }
+}
+
+/// The schema version of the database `conn` is connected to.
+pub fn schema_version(conn: &Connection) -> Result<u32, rusqlite::Error> {
+    conn.query_row("PRAGMA user_version", NO_PARAMS, |row| row.get(0))
+}
+
+/// Applies the `migrations` the database hasn't seen yet, returning the version it started at.
+///
+/// The migrations run in a single immediate transaction, so other connections wait for them to
+/// finish and a failed migration leaves the database as it was.
+pub fn migrate(
+    conn: &mut Connection,
+    migrations: &[&str],
+) -> Result<u32, MigrationError> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let from = migrate_tx(&tx, migrations)?;
+    tx.commit()?;
+
+    Ok(from)
+}
+
+/// Like `migrate`, but as part of a transaction the caller commits.
+pub fn migrate_tx(
+    tx: &Transaction,
+    migrations: &[&str],
+) -> Result<u32, MigrationError> {
+    let latest = migrations.len() as u32;
+    let from = schema_version(tx)?;
+
+    if from > latest {
+        return Err(MigrationError::TooNew {
+            found: from,
+            expected: latest,
+        });
+    }
+
+    for (version, migration) in (1..).zip(migrations).skip(from as usize) {
+        tx.execute_batch(migration)?;
+        set_schema_version(tx, version)?;
+    }
+
+    Ok(from)
+}
+
+/// Marks the database as being at `version`, e.g. after dropping all of its tables.
+pub fn set_schema_version(
+    conn: &Connection,
+    version: u32,
+) -> Result<(), rusqlite::Error> {
+    // pragmas can't take parameters
+    conn.execute_batch(&format!("PRAGMA user_version = {}", version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIGRATIONS: &[&str] = &[
+        "CREATE TABLE IF NOT EXISTS things (id INTEGER PRIMARY KEY)",
+        "ALTER TABLE things ADD COLUMN name TEXT",
+    ];
+
+    #[test]
+    fn migrates_in_order() {
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+        assert_eq!(migrate(&mut conn, &MIGRATIONS[..1]).unwrap(), 0);
+        assert_eq!(schema_version(&conn).unwrap(), 1);
+
+        conn.execute("INSERT INTO things(id) VALUES (1)", NO_PARAMS)
+            .unwrap();
+
+        assert_eq!(migrate(&mut conn, MIGRATIONS).unwrap(), 1);
+        assert_eq!(schema_version(&conn).unwrap(), 2);
+
+        // already up to date
+        assert_eq!(migrate(&mut conn, MIGRATIONS).unwrap(), 2);
+
+        let name: Option<String> = conn
+            .query_row("SELECT name FROM things WHERE id = 1", NO_PARAMS, |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(name, None);
+    }
+
+    #[test]
+    fn failed_migration_rolls_back() {
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        let broken = [MIGRATIONS[0], MIGRATIONS[1], "NOT SQL"];
+        assert!(migrate(&mut conn, &broken).is_err());
+
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+        let tables: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE name = 'things'",
+                NO_PARAMS,
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(tables, 0);
+    }
+
+    #[test]
+    fn rejects_newer_schema() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        set_schema_version(&conn, 3).unwrap();
+
+        match migrate(&mut conn, MIGRATIONS) {
+            Err(MigrationError::TooNew { found, expected }) => {
+                assert_eq!(found, 3);
+                assert_eq!(expected, 2);
+            }
+            other => panic!("expected TooNew, got {:?}", other),
+        }
+    }
+}
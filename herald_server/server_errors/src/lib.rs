@@ -21,6 +21,10 @@ pub enum Error {
     MissingData,
     #[error("Login failed")]
     LoginFailed,
+    #[error(
+        "Database schema is at version {found}, newer than the latest known version {expected}"
+    )]
+    SchemaTooNew { found: i64, expected: i64 },
     #[error("Uncategorized error. Please downcast")]
     Underscore(#[from] Box<dyn std::error::Error + Send + Sync + 'static>),
     #[error("Invalid user id")]
@@ -0,0 +1,180 @@
+//! Live delivery of pushes stored by other servers sharing the store, so clients can be spread
+//! across several servers and still get pushes as soon as they are sent.
+
+use super::*;
+
+/// How many announced pushes are delivered at once.
+const CONCURRENT_DELIVERIES: usize = 16;
+
+impl State {
+    /// Tells the other servers sharing the store that this one stored `pushes`, given with the
+    /// devices they are for.
+    ///
+    /// The pushes are already stored, so failing to announce them only delays delivery until
+    /// the devices next log in.
+    pub(crate) async fn announce(
+        &self,
+        pushes: &[(i64, Vec<sig::PublicKey>)],
+    ) {
+        let res = async {
+            self.new_connection()
+                .await?
+                .announce_pushes(self.node, pushes)
+                .await?;
+            Ok::<(), Error>(())
+        };
+
+        if let Err(e) = res.await {
+            self.metrics.error(&e);
+            tracing::warn!("failed to announce pushes: {}", e);
+        }
+    }
+
+    /// Delivers pushes stored by other servers to the sessions on this one, forever. Listening
+    /// is restarted after `retry` if it fails.
+    pub async fn run_fan_out(
+        &self,
+        retry: Duration,
+    ) {
+        loop {
+            let res = async {
+                let announcements = self.pool.announcements().await?;
+                self.fan_out(announcements).await
+            };
+
+            match res.await {
+                Ok(()) => tracing::warn!("stopped receiving announcements"),
+                Err(e) => tracing::warn!("failed to receive announcements: {}", e),
+            }
+
+            time::delay_for(retry).await;
+        }
+    }
+
+    async fn fan_out<S>(
+        &self,
+        announcements: S,
+    ) -> Result<(), Error>
+    where
+        S: Stream<Item = Result<Announcement, ServerError>>,
+    {
+        announcements
+            .map_err(Error::from)
+            .try_for_each_concurrent(CONCURRENT_DELIVERIES, |announcement| {
+                async move {
+                    // our own pushes were delivered when they were stored
+                    if announcement.node == self.node {
+                        return Ok(());
+                    }
+
+                    if let Err(e) = self.deliver_announced(announcement).await {
+                        self.metrics.error(&e);
+                        tracing::warn!("failed to deliver announced push: {}", e);
+                    }
+
+                    Ok(())
+                }
+            })
+            .await
+    }
+
+    async fn deliver_announced(
+        &self,
+        Announcement { push_id, devs, .. }: Announcement,
+    ) -> Result<(), Error> {
+        // only go to the store for pushes to devices connected here
+        let mut here = Vec::new();
+        for dev in devs {
+            if self.active.async_get(dev).await.is_some() {
+                here.push(dev);
+            }
+        }
+
+        if here.is_empty() {
+            return Ok(());
+        }
+
+        let pending = self.new_connection().await?.pending_push(push_id).await?;
+
+        // devices that already fetched the push don't get it twice
+        if let Some((push, pending)) = pending {
+            here.retain(|dev| pending.contains(dev));
+            self.notify_sessions(here, &push, push_id).await;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sig::sign_ser as sign;
+    use std::convert::TryInto;
+    use womp::*;
+
+    #[tokio::test]
+    async fn delivers_pushes_stored_elsewhere() {
+        let store = Memory::new();
+        let sender_node = State::with_backend(Box::new(store.clone()));
+        let recip_node = State::with_backend(Box::new(store.clone()));
+
+        let uid: UserId = "a".try_into().expect(womp!());
+        let kp = sig::KeyPair::gen_new();
+        let kp2 = sig::KeyPair::gen_new();
+        let from = GlobalId {
+            uid,
+            did: *kp.public(),
+        };
+        let to = GlobalId {
+            uid,
+            did: *kp2.public(),
+        };
+
+        let mut conn = store.get().await.expect(womp!());
+        conn.new_user(sign(&kp, uid)).await.expect(womp!());
+        conn.add_to_sigchain(sign(&kp, sig::SigUpdate::Endorse(sign(&kp2, uid))))
+            .await
+            .expect(womp!());
+
+        let mut incoming = recip_node.pushes(to).await.expect(womp!());
+        let announcements = recip_node.pool.announcements().await.expect(womp!());
+
+        let req = sign(
+            &kp,
+            push::Body {
+                to: Recip::One(SingleRecip::Key(to.did)),
+                msg: Bytes::from_static(b"test"),
+            },
+        );
+
+        match sender_node
+            .handle_request(from, Request::Push(req.clone()))
+            .await
+        {
+            Response::Push(push::Res::Success { .. }) => {}
+            other => panic!("push failed: {:?}", other),
+        }
+
+        // the store remembers the push, so it can't be replayed through another server either
+        match recip_node.handle_request(from, Request::Push(req)).await {
+            Response::Push(push::Res::Replayed) => {}
+            other => panic!("replayed push wasn't rejected: {:?}", other),
+        }
+
+        // the announcement stream never ends, so stop listening once it has been drained
+        let _ = time::timeout(
+            Duration::from_millis(100),
+            recip_node.fan_out(announcements),
+        )
+        .await;
+
+        let TaggedPush { push, .. } = time::timeout(Duration::from_secs(1), incoming.next())
+            .await
+            .expect(womp!())
+            .expect(womp!());
+
+        assert_eq!(push.gid, from);
+        assert_eq!(push.msg, Bytes::from_static(b"test"));
+    }
+}
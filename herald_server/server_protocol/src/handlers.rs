@@ -1,7 +1,112 @@
 use super::*;
 use futures::{future::TryFutureExt, stream::StreamExt};
+use std::collections::HashSet;
 
 impl State {
+    /// What to tell the client about a failed request. Errors that aren't a `RequestError` are
+    /// logged and counted rather than sent, since they can contain details about the server.
+    pub fn request_error(
+        &self,
+        e: Error,
+    ) -> RequestError {
+        match e.downcast::<RequestError>() {
+            Ok(e) => e,
+            Err(e) => {
+                tracing::error!("failed to handle request: {:?}", e);
+                self.metrics.error(&e);
+                RequestError::Internal
+            }
+        }
+    }
+
+    /// Records a request to `endpoint` that started at `started` and ended with `res` in the
+    /// metrics.
+    pub fn record_request(
+        &self,
+        endpoint: Endpoint,
+        started: std::time::Instant,
+        res: Result<(), &RequestError>,
+    ) {
+        self.metrics.request(endpoint, started, res)
+    }
+
+    /// Handles a request made over an authenticated session with `from`.
+    #[tracing::instrument(skip(self, req))]
+    pub async fn handle_request(
+        &self,
+        from: GlobalId,
+        req: Request,
+    ) -> Response {
+        let started = std::time::Instant::now();
+        let endpoint = Endpoint::of(&req);
+        tracing::debug!(endpoint = endpoint.name(), "handling request");
+
+        let res = self.dispatch(from, endpoint, req).await;
+
+        let outcome = match &res {
+            Response::Err(e) => Err(e),
+            _ => Ok(()),
+        };
+        self.record_request(endpoint, started, outcome);
+
+        res
+    }
+
+    async fn dispatch(
+        &self,
+        from: GlobalId,
+        endpoint: Endpoint,
+        req: Request,
+    ) -> Response {
+        if let Err(e) = self.check_rate(endpoint, Client::Device(from.did)) {
+            return Response::Err(e);
+        }
+
+        let res = match req {
+            Request::GetSigchain(r) => self.get_sigchain(r).await.map(Response::GetSigchain),
+            Request::RecipExists(r) => self.recip_exists(r).await.map(Response::RecipExists),
+            Request::NewSig(r) => self.new_sig(*r).await.map(Response::NewSig),
+            Request::NewPrekey(r) => self.new_prekeys(r).await.map(Response::NewPrekey),
+            Request::GetPrekey(r) => self.get_prekeys(r).await.map(Response::GetPrekey),
+            Request::PrekeyCount(r) => self.prekey_count(r).await.map(Response::PrekeyCount),
+            Request::HeldPrekeys(r) => self.held_prekeys(r).await.map(Response::HeldPrekeys),
+            Request::ServerKey(r) => self.server_key(r).await.map(Response::ServerKey),
+            // the session is already authenticated, but the push must still come from it
+            Request::Push(r) if *r.signed_by() != from.did => {
+                Ok(Response::Push(push::Res::BadSig(SigValid::BadSigner)))
+            }
+            Request::Push(r) => self.push(r).await.map(Response::Push),
+        };
+
+        res.unwrap_or_else(|e| Response::Err(self.request_error(e)))
+    }
+
+    /// Rejects pushes that are larger or addressed to more recipients than the limits allow.
+    fn check_push(
+        &self,
+        req: &push::Req,
+    ) -> Result<(), RequestError> {
+        let push::Body { to, msg } = req.data();
+
+        let len = msg.len() as u64;
+        if len > self.limits.max_push_len {
+            return Err(RequestError::MsgTooLarge {
+                len,
+                max: self.limits.max_push_len,
+            });
+        }
+
+        let count = to.fan_out() as u64;
+        if count > self.limits.max_recips {
+            return Err(RequestError::TooManyRecips {
+                count,
+                max: self.limits.max_recips,
+            });
+        }
+
+        Ok(())
+    }
+
     pub async fn get_sigchain(
         &self,
         of: UserId,
@@ -48,59 +153,155 @@ impl State {
 
     pub async fn new_prekeys(
         &self,
-        keys: Vec<(Signed<Prekey>, Option<Prekey>)>,
+        new_prekeys::Req { signed, one_time }: new_prekeys::Req,
     ) -> Result<new_prekeys::Res> {
         use new_prekeys::Res;
 
-        for (p, _) in &keys {
+        for p in signed.iter().chain(one_time.iter().map(|(p, _)| p)) {
             let valid = p.verify_sig();
             if valid != SigValid::Yes {
                 return Ok(Res::BadSig(valid, *p.data()));
             }
         }
 
+        // the signed and one-time prekeys are stored together, so any other response means
+        // nothing was stored
         Ok(self
             .new_connection()
             .await?
-            .new_prekeys(stream::iter(
-                keys.into_iter()
-                    .map(|(new, old)| PrekeyReplace { new, old }),
-            ))
+            .new_prekeys(
+                signed,
+                one_time
+                    .into_iter()
+                    .map(|(new, old)| PrekeyReplace { new, old })
+                    .collect(),
+                self.limits.max_prekeys,
+            )
             .await?)
     }
 
     pub async fn get_prekeys(
         &self,
-        of: Vec<sig::PublicKey>,
-    ) -> Result<Vec<(sig::PublicKey, Signed<Prekey>)>, Error> {
-        Ok(self
-            .new_connection()
-            .await?
-            .get_random_prekeys(stream::iter(of))
-            .await?
-            .into_iter()
-            .map(|t| (t.key, t.prekey))
-            .collect())
+        mut of: Vec<sig::PublicKey>,
+    ) -> Result<get_prekeys::Res, Error> {
+        // every key hands out a one-time prekey, so a single request can't name a device more
+        // than once or ask for more devices than a push can be addressed to
+        let mut seen = HashSet::new();
+        of.retain(|key| seen.insert(*key));
+
+        let count = of.len() as u64;
+        if count > self.limits.max_recips {
+            return Err(RequestError::TooManyRecips {
+                count,
+                max: self.limits.max_recips,
+            }
+            .into());
+        }
+
+        Ok(self.new_connection().await?.get_prekey_bundles(of).await?)
+    }
+
+    pub async fn prekey_count(
+        &self,
+        of: sig::PublicKey,
+    ) -> Result<prekey_count::Res, Error> {
+        Ok(self.new_connection().await?.prekey_count(of).await?)
+    }
+
+    /// The key delivery receipts are signed with.
+    pub async fn server_key(
+        &self,
+        _: server_key::Req,
+    ) -> Result<server_key::Res, Error> {
+        Ok(*self.signing_key.public())
+    }
+
+    pub async fn held_prekeys(
+        &self,
+        of: sig::PublicKey,
+    ) -> Result<held_prekeys::Res, Error> {
+        Ok(self.new_connection().await?.held_prekeys(of).await?)
     }
 
     pub async fn push(
         &self,
-        // from: GlobalId,
-        push::Req { from, to, msg }: push::Req,
+        req: push::Req,
     ) -> Result<push::Res, Error> {
         let timestamp = Time::now();
 
+        self.check_push(&req)?;
+
+        let valid = req.verify_sig();
+        if valid != SigValid::Yes {
+            return Ok(push::Res::BadSig(valid));
+        }
+
+        if !timestamp.within(push::MAX_CLOCK_SKEW, *req.timestamp()) {
+            return Ok(push::Res::Stale);
+        }
+
+        let did = *req.signed_by();
+        let mut conn = self.new_connection().await?;
+
+        if !conn.key_is_valid(did).await? {
+            return Ok(push::Res::DeadKey);
+        }
+
+        let uid = match conn.user_of(did).await? {
+            Some(uid) => uid,
+            None => return Ok(push::Res::DeadKey),
+        };
+
+        // the signature is only recorded once the push is stored, so that a push that was
+        // turned away can be sent again
+        let (sig, signed_ts) = (req.sig(), *req.timestamp());
+        if conn.push_sig_seen(sig).await? {
+            return Ok(push::Res::Replayed);
+        }
+
+        let push::Body { to, msg } = req.into_data();
+        let tag = to.tag();
+
+        // the sender's own device is never pushed to, so its queue doesn't matter
+        let full: Vec<sig::PublicKey> = conn
+            .full_queues(&to, self.limits.max_pending)
+            .await?
+            .into_iter()
+            .filter(|key| *key != did)
+            .collect();
+
+        // devices with full queues miss this push, the rest still get it
+        let to = if full.is_empty() {
+            to
+        } else {
+            let devs = match devices_of(&mut *conn, &to).await? {
+                Ok(devs) => devs,
+                Err(missing) => return Ok(push::Res::Missing(missing)),
+            };
+
+            let rest: Vec<sig::PublicKey> = devs
+                .into_iter()
+                .filter(|key| *key != did && !full.contains(key))
+                .collect();
+
+            if rest.is_empty() {
+                return Ok(push::Res::QueueFull(full[0]));
+            }
+
+            tracing::info!("skipping {} devices with full queues", full.len());
+
+            Recip::Many(Recips::Keys(rest))
+        };
+
         let psh = Push {
-            tag: to.tag(),
+            tag,
             timestamp,
-            gid: from,
+            gid: GlobalId { uid, did },
             msg,
         };
 
         // TODO Jack fix this
-        match self
-            .new_connection()
-            .await?
+        let push_id = match conn
             .add_to_pending_and_get_valid_devs(&[(&to, &psh)])
             .await?
             .recv()
@@ -108,28 +309,49 @@ impl State {
             .unwrap()
             .0
         {
-            PushedTo::NoRecipients => Ok(push::Res::Success(timestamp)),
-            PushedTo::Missing(m) => Ok(push::Res::Missing(m)),
+            PushedTo::NoRecipients => None,
+            PushedTo::Missing(m) => return Ok(push::Res::Missing(m)),
             PushedTo::PushedTo { devs, push_id } => {
-                stream::iter(devs)
-                    .for_each_concurrent(10, {
-                        let psh = &psh;
-                        move |d| {
-                            async move {
-                                if let Some(sess) = self.active.async_get(d).await {
-                                    sess.push(psh.clone(), push_id).await
-                                } else {
-                                    Ok(())
-                                }
-                            }
-                            // TODO: more sensible error handling here?
-                            .unwrap_or_else(|e| eprintln!("failed to sink push with error: {}", e))
-                        }
-                    })
-                    .await;
-                Ok(push::Res::Success(timestamp))
+                self.metrics.push_fan_out(devs.len());
+                self.notify_sessions(devs.clone(), &psh, push_id).await;
+                self.announce(&[(push_id, devs)]).await;
+                Some(push_id)
             }
+        };
+
+        // a copy of the same push may have been stored concurrently, which is harmless since
+        // clients drop messages they've already seen
+        if !conn.note_push_sig(sig, signed_ts).await? {
+            tracing::warn!("push was stored twice");
         }
+
+        Ok(push::Res::Success {
+            ts: timestamp,
+            push_id,
+        })
+    }
+
+    /// Hands `psh` to the sessions of the `devs` that are online. The others get it from their
+    /// pending queue when they next log in.
+    pub(crate) async fn notify_sessions(
+        &self,
+        devs: Vec<sig::PublicKey>,
+        psh: &Push,
+        push_id: i64,
+    ) {
+        stream::iter(devs)
+            .for_each_concurrent(10, move |d| {
+                async move {
+                    if let Some(sess) = self.active.async_get(d).await {
+                        sess.push(psh.clone(), push_id).await
+                    } else {
+                        Ok(())
+                    }
+                }
+                // TODO: more sensible error handling here?
+                .unwrap_or_else(|e| tracing::warn!("failed to sink push with error: {}", e))
+            })
+            .await;
     }
 
     pub async fn register(
@@ -149,3 +371,286 @@ impl State {
         Ok(res)
     }
 }
+
+/// The devices `to` is addressed to, or the first user that doesn't exist.
+async fn devices_of(
+    conn: &mut dyn Store,
+    to: &Recip,
+) -> Result<Result<Vec<sig::PublicKey>, SingleRecip>, Error> {
+    use Recip::*;
+    use Recips::*;
+    use SingleRecip::*;
+
+    let uids = match to {
+        One(Key(key)) => return Ok(Ok(vec![*key])),
+        Many(Keys(keys)) => return Ok(Ok(keys.clone())),
+        One(User(uid)) => std::slice::from_ref(uid),
+        Many(Users(uids)) => uids.as_slice(),
+    };
+
+    let mut devs = Vec::new();
+
+    for uid in uids {
+        match conn.get_sigchain(*uid).await? {
+            Some(chain) => devs.extend(chain.active_keys()),
+            None => return Ok(Err(User(*uid))),
+        }
+    }
+
+    Ok(Ok(devs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sig::sign_ser as sign;
+    use std::convert::TryInto;
+    use womp::*;
+
+    async fn send(
+        state: &State,
+        kp: &sig::KeyPair,
+        from: GlobalId,
+        to: Recip,
+        msg: &'static [u8],
+    ) -> push::Res {
+        let req = sign(
+            kp,
+            push::Body {
+                to,
+                msg: Bytes::from_static(msg),
+            },
+        );
+
+        send_req(state, from, req).await
+    }
+
+    async fn send_req(
+        state: &State,
+        from: GlobalId,
+        req: push::Req,
+    ) -> push::Res {
+        match state.handle_request(from, Request::Push(req)).await {
+            Response::Push(res) => res,
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn rejected_pushes_can_be_resent() {
+        let store = Memory::new();
+        let state = State::with_backend(Box::new(store.clone()));
+
+        let a_uid: UserId = "a".try_into().expect(womp!());
+        let a_kp = sig::KeyPair::gen_new();
+        let from = GlobalId {
+            uid: a_uid,
+            did: *a_kp.public(),
+        };
+
+        let b_uid: UserId = "b".try_into().expect(womp!());
+        let b_kp = sig::KeyPair::gen_new();
+
+        let mut conn = store.get().await.expect(womp!());
+        conn.new_user(sign(&a_kp, a_uid)).await.expect(womp!());
+
+        let req = sign(
+            &a_kp,
+            push::Body {
+                to: Recip::One(SingleRecip::User(b_uid)),
+                msg: Bytes::from_static(b"hi"),
+            },
+        );
+
+        match send_req(&state, from, req.clone()).await {
+            push::Res::Missing(_) => {}
+            other => panic!("push to a missing user wasn't rejected: {:?}", other),
+        }
+
+        conn.new_user(sign(&b_kp, b_uid)).await.expect(womp!());
+
+        match send_req(&state, from, req.clone()).await {
+            push::Res::Success { .. } => {}
+            other => panic!("push failed: {:?}", other),
+        }
+
+        match send_req(&state, from, req.clone()).await {
+            push::Res::Replayed => {}
+            other => panic!("replayed push wasn't rejected: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn full_queues_are_skipped() {
+        let store = Memory::new();
+        let state = State::with_backend(Box::new(store.clone())).with_limits(Limits {
+            max_pending: 1,
+            ..Limits::default()
+        });
+
+        let a_uid: UserId = "a".try_into().expect(womp!());
+        let a_kp = sig::KeyPair::gen_new();
+        let from = GlobalId {
+            uid: a_uid,
+            did: *a_kp.public(),
+        };
+
+        let b_uid: UserId = "b".try_into().expect(womp!());
+        let b_kp = sig::KeyPair::gen_new();
+        let b_kp2 = sig::KeyPair::gen_new();
+
+        let mut conn = store.get().await.expect(womp!());
+        conn.new_user(sign(&a_kp, a_uid)).await.expect(womp!());
+        conn.new_user(sign(&b_kp, b_uid)).await.expect(womp!());
+        conn.add_to_sigchain(sign(&b_kp, sig::SigUpdate::Endorse(sign(&b_kp2, b_uid))))
+            .await
+            .expect(womp!());
+
+        let full = Recip::One(SingleRecip::Key(*b_kp.public()));
+        let user = Recip::One(SingleRecip::User(b_uid));
+
+        match send(&state, &a_kp, from, full.clone(), b"first").await {
+            push::Res::Success { .. } => {}
+            other => panic!("push failed: {:?}", other),
+        }
+
+        // the full device is skipped, the other one still gets the push
+        match send(&state, &a_kp, from, user.clone(), b"second").await {
+            push::Res::Success { .. } => {}
+            other => panic!("push failed: {:?}", other),
+        }
+
+        assert_eq!(
+            conn.get_pending(*b_kp.public()).await.expect(womp!()).len(),
+            1
+        );
+        assert_eq!(
+            conn.get_pending(*b_kp2.public())
+                .await
+                .expect(womp!())
+                .len(),
+            1
+        );
+
+        // once every device is full, nothing is sent
+        match send(&state, &a_kp, from, user, b"third").await {
+            push::Res::QueueFull(_) => {}
+            other => panic!("push to full queues wasn't rejected: {:?}", other),
+        }
+    }
+
+    fn prekey(kp: &sig::KeyPair) -> Signed<Prekey> {
+        let pre_kp = sig::KeyPair::gen_new();
+        sign(
+            kp,
+            Prekey::from_slice(pre_kp.public().as_ref()).expect(womp!()),
+        )
+    }
+
+    #[tokio::test]
+    async fn prekey_quota_is_per_signer() {
+        let state = State::with_backend(Box::new(Memory::new())).with_limits(Limits {
+            max_prekeys: 2,
+            ..Limits::default()
+        });
+
+        let a_uid: UserId = "a".try_into().expect(womp!());
+        let a_kp = sig::KeyPair::gen_new();
+        let b_uid: UserId = "b".try_into().expect(womp!());
+        let b_kp = sig::KeyPair::gen_new();
+
+        let mut conn = state.new_connection().await.expect(womp!());
+        conn.new_user(sign(&a_kp, a_uid)).await.expect(womp!());
+        conn.new_user(sign(&b_kp, b_uid)).await.expect(womp!());
+
+        let req = new_prekeys::Req {
+            signed: None,
+            one_time: vec![(prekey(&b_kp), None), (prekey(&b_kp), None)],
+        };
+        assert_eq!(
+            state.new_prekeys(req).await.expect(womp!()),
+            new_prekeys::Res::Success
+        );
+
+        // `a` has room, but `b` doesn't
+        let b_pre = prekey(&b_kp);
+        let req = new_prekeys::Req {
+            signed: None,
+            one_time: vec![(prekey(&a_kp), None), (b_pre, None)],
+        };
+        assert_eq!(
+            state.new_prekeys(req).await.expect(womp!()),
+            new_prekeys::Res::NoSlotAvailable(*b_pre.data())
+        );
+        assert!(conn
+            .held_prekeys(*a_kp.public())
+            .await
+            .expect(womp!())
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejected_prekeys_are_not_stored() {
+        let state = State::with_backend(Box::new(Memory::new()));
+
+        let uid: UserId = "a".try_into().expect(womp!());
+        let kp = sig::KeyPair::gen_new();
+
+        let mut conn = state.new_connection().await.expect(womp!());
+        conn.new_user(sign(&kp, uid)).await.expect(womp!());
+
+        let signed = prekey(&kp);
+        let req = new_prekeys::Req {
+            signed: Some(signed),
+            one_time: vec![],
+        };
+        assert_eq!(
+            state.new_prekeys(req).await.expect(womp!()),
+            new_prekeys::Res::Success
+        );
+
+        // the signed prekey isn't newer than the current one, so the one-time prekey sent with
+        // it is dropped too
+        let req = new_prekeys::Req {
+            signed: Some(signed),
+            one_time: vec![(prekey(&kp), None)],
+        };
+        assert_eq!(
+            state.new_prekeys(req).await.expect(womp!()),
+            new_prekeys::Res::Redundant(*signed.data())
+        );
+        assert!(conn
+            .held_prekeys(*kp.public())
+            .await
+            .expect(womp!())
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn get_prekeys_is_capped() {
+        let state = State::with_backend(Box::new(Memory::new())).with_limits(Limits {
+            max_recips: 2,
+            ..Limits::default()
+        });
+
+        let kps: Vec<sig::KeyPair> = (0..3).map(|_| sig::KeyPair::gen_new()).collect();
+
+        // repeating a key doesn't count, or hand out more than one of its prekeys
+        let key = *kps[0].public();
+        assert!(state
+            .get_prekeys(vec![key, key, key])
+            .await
+            .expect(womp!())
+            .is_empty());
+
+        let err = state
+            .get_prekeys(kps.iter().map(|kp| *kp.public()).collect())
+            .await
+            .expect_err("too many keys were accepted");
+
+        match state.request_error(err) {
+            RequestError::TooManyRecips { count: 3, max: 2 } => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! Periodic clean-up of pushes that will never be delivered.
+
+use super::*;
+
+/// What a janitor pass removed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Swept {
+    /// Deliveries dropped because the push was older than the retention period
+    pub expired: u64,
+    /// Pushes removed because no device was waiting on them anymore
+    pub dangling: u64,
+    /// Rate limit buckets dropped because their clients have been idle
+    pub idle_clients: u64,
+    /// Push signatures forgotten because the pushes would be rejected as stale by now
+    pub push_sigs: u64,
+    /// Logged failures dropped because they were older than the retention period
+    pub dead_letters: u64,
+}
+
+impl State {
+    /// Sweeps the store every `every`, forever.
+    pub async fn run_janitor(
+        &self,
+        every: Duration,
+    ) {
+        let mut ticks = time::interval(every);
+
+        loop {
+            ticks.tick().await;
+
+            match self.sweep().await {
+                Ok(swept) => tracing::debug!("janitor pass done: {:?}", swept),
+                Err(e) => tracing::warn!("janitor pass failed: {}", e),
+            }
+        }
+    }
+
+    /// Expires pushes older than the retention period, removes pushes no device is waiting on
+    /// anymore, forgets idle clients' rate limits, the signatures of pushes that are past the
+    /// clock skew and old dead letters. Also updates the pending deliveries metric.
+    pub async fn sweep(&self) -> Result<Swept, Error> {
+        let mut conn = self.new_connection().await?;
+
+        let expired = match self.limits.retention_cutoff() {
+            Some(cutoff) => conn.expire_pending(cutoff).await?,
+            None => 0,
+        };
+
+        let dangling = conn.del_dangling_pushes().await?;
+        self.metrics.pending(conn.pending_count().await?);
+        let idle_clients = self.limiter.prune() as u64;
+
+        let push_sigs = conn
+            .forget_push_sigs(Time::from(*Time::now().as_i64() - push::MAX_CLOCK_SKEW))
+            .await?;
+
+        let dead_letters = conn
+            .forget_dead_letters(self.limits.dead_letter_cutoff())
+            .await?;
+
+        Ok(Swept {
+            expired,
+            dangling,
+            idle_clients,
+            push_sigs,
+            dead_letters,
+        })
+    }
+}
@@ -3,9 +3,9 @@
 use anyhow::*;
 use dashmap::DashMap;
 use futures::{
-    future::{self, TryFutureExt},
+    future::{self, FutureExt, TryFutureExt},
     sink::{self, Sink, SinkExt},
-    stream::{self, BoxStream, Stream, StreamExt},
+    stream::{self, BoxStream, Stream, StreamExt, TryStreamExt},
 };
 use herald_common::{
     protocol::{auth::*, *},
@@ -26,8 +26,20 @@ use tokio::{
     time,
 };
 
+mod fan_out;
 mod handlers;
+mod janitor;
+mod limiter;
 mod login;
+mod metrics;
+mod receipts;
+pub use janitor::Swept;
+use limiter::RateLimiter;
+pub use limiter::{Client, Endpoint, Rate, RateLimits};
+use metrics::Metrics;
+
+/// How many requests from a single session are handled at once.
+const CONCURRENT_REQUESTS: usize = 16;
 
 pub struct ActiveSession {
     interrupt: Trigger,
@@ -58,27 +70,128 @@ impl ActiveSession {
 
 type ActiveSessions = DashMap<sig::PublicKey, ActiveSession>;
 
-#[derive(Default)]
+/// Limits on what clients can store on the server.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// How long undelivered pushes are kept before they are dropped, forever if `None`
+    pub pending_retention: Option<Duration>,
+    /// How long pushes clients failed to process are kept around for debugging
+    pub dead_letter_retention: Duration,
+    /// Maximum number of one-time prekeys a device can have stored
+    pub max_prekeys: u64,
+    /// Maximum number of undelivered pushes a device can have waiting for it
+    pub max_pending: u64,
+    /// Maximum length of a push's message, in bytes
+    pub max_push_len: u64,
+    /// Maximum number of users or devices a single push or prekey request can be addressed to
+    pub max_recips: u64,
+    /// How often each client can make requests to each endpoint
+    pub rates: RateLimits,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            pending_retention: None,
+            dead_letter_retention: Duration::from_secs(7 * 24 * 60 * 60),
+            max_prekeys: PREKEY_SLOTS,
+            max_pending: 10_000,
+            max_push_len: 16 * 1024 * 1024,
+            max_recips: 1024,
+            rates: RateLimits::default(),
+        }
+    }
+}
+
+impl Limits {
+    /// Pushes sent before this are past the retention period.
+    fn retention_cutoff(&self) -> Option<Time> {
+        let retention = self.pending_retention?;
+        Some(Time::from(
+            *Time::now().as_i64() - retention.as_millis() as i64,
+        ))
+    }
+
+    /// Dead letters logged before this are past their retention period.
+    fn dead_letter_cutoff(&self) -> Time {
+        Time::from(*Time::now().as_i64() - self.dead_letter_retention.as_millis() as i64)
+    }
+}
+
 pub struct State {
     pub active: ActiveSessions,
-    pub pool: Pool,
+    pub pool: Box<dyn Backend>,
+    pub limits: Limits,
+    /// Signs the delivery receipts, shared by all servers using the same store
+    signing_key: sig::KeyPair,
+    /// Identifies this server to the others sharing its store
+    node: u64,
+    limiter: RateLimiter,
+    metrics: Metrics,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl State {
+    /// Creates a server backed by Postgres.
     pub fn new() -> Self {
+        Self::with_backend(Box::new(Pool::new()))
+    }
+
+    /// Creates a server backed by `backend`.
+    pub fn with_backend(backend: Box<dyn Backend>) -> Self {
         State {
             active: DashMap::default(),
-            pool: Pool::new(),
+            pool: backend,
+            limits: Limits::default(),
+            signing_key: sig::KeyPair::gen_new(),
+            node: random_node_id(),
+            limiter: RateLimiter::new(RateLimits::default()),
+            metrics: Metrics::new(),
         }
     }
 
-    pub async fn new_connection(&self) -> Result<Conn, Error> {
-        self.pool
-            .get()
-            .await
-            .context("failed to get connection to postgres")
+    pub fn with_limits(
+        mut self,
+        limits: Limits,
+    ) -> Self {
+        self.limits = limits;
+        self.limiter = RateLimiter::new(limits.rates);
+        self
     }
 
+    /// Signs delivery receipts with `key` instead of one generated when the server started.
+    pub fn with_signing_key(
+        mut self,
+        key: sig::KeyPair,
+    ) -> Self {
+        self.signing_key = key;
+        self
+    }
+
+    /// Counts a request from `client` to `endpoint` against its rate limit, failing with
+    /// `RequestError::RateLimited` if it is over it.
+    pub fn check_rate(
+        &self,
+        endpoint: Endpoint,
+        client: Client,
+    ) -> Result<(), RequestError> {
+        self.limiter.check(endpoint, client)
+    }
+
+    pub async fn new_connection(&self) -> Result<Box<dyn Store>, Error> {
+        let started = std::time::Instant::now();
+        let conn = self.pool.get().await;
+        self.metrics.store_wait(started);
+
+        conn.context("failed to get connection to the store")
+    }
+
+    #[tracing::instrument(skip(self, tx, rx))]
     pub async fn handle_auth_ws<Tx, Rx, E>(
         &self,
         tx: &mut Tx,
@@ -109,31 +222,117 @@ impl State {
         });
 
         // chunked catchup to reduce number of roundtrips after a long offline period
-        self.catchup(g.did, tx, rx).await?;
+        self.catchup(g, tx, rx).await?;
 
-        let mut incoming = self.pushes(g).await?;
-        while let Some(TaggedPush { id, push }) = incoming.next().await {
-            send_ser(tx, &push).await?;
-            match read_de(rx).await? {
-                PushAck::Success => {
-                    self.new_connection()
-                        .await?
-                        .del_pending(g.did, stream::once(future::ready(id)))
-                        .await?;
+        let incoming = self.pushes(g).await?;
+        self.session(g, incoming, tx, rx).await
+    }
+
+    /// Serves an authenticated session after catchup, interleaving pushes with responses to
+    /// the requests the client sends.
+    #[tracing::instrument(skip(self, incoming, tx, rx))]
+    async fn session<Tx, Rx, E>(
+        &self,
+        g: GlobalId,
+        incoming: Valved<Receiver<TaggedPush>>,
+        tx: &mut Tx,
+        rx: &mut Rx,
+    ) -> Result<(), anyhow::Error>
+    where
+        Tx: Sink<Bytes> + Unpin,
+        <Tx as Sink<Bytes>>::Error: StdError + Send + Sync + 'static,
+        Rx: Stream<Item = Result<Vec<u8>, E>> + Unpin,
+        E: StdError + Send + Sync + 'static,
+    {
+        use socket::*;
+
+        enum Event<E> {
+            Push(TaggedPush),
+            Client(Option<Result<Vec<u8>, E>>),
+            Response(RequestId, Response),
+        }
+
+        let client = rx
+            .map(|m| Event::Client(Some(m)))
+            .chain(stream::once(future::ready(Event::Client(None))));
+        let mut events = stream::select(incoming.map(Event::Push), client);
+
+        // pushes that were sent but not acknowledged yet, in the order they were sent
+        let mut unacked = std::collections::VecDeque::new();
+
+        // requests are handled concurrently, so a slow one doesn't hold up the others or pushes
+        let mut in_flight = stream::FuturesUnordered::new();
+
+        loop {
+            let event = if in_flight.len() >= CONCURRENT_REQUESTS {
+                let (req_id, res) = in_flight.select_next_some().await;
+                Event::Response(req_id, res)
+            } else {
+                futures::select! {
+                    (req_id, res) = in_flight.select_next_some() => Event::Response(req_id, res),
+                    event = events.next().fuse() => match event {
+                        Some(event) => event,
+                        None => break,
+                    },
                 }
-                PushAck::Quit => {
-                    break;
+            };
+
+            match event {
+                Event::Push(TaggedPush { id, push }) => {
+                    send_ser(tx, &ServerMsg::Push(push.clone())).await?;
+                    unacked.push_back((id, push));
+                }
+                Event::Client(None) => break,
+                Event::Client(Some(raw)) => match kson::from_bytes(raw?.into())? {
+                    ClientMsg::PushAck(ack) => {
+                        let (id, push) = unacked
+                            .pop_front()
+                            .ok_or_else(|| anyhow!("received ack without a push"))?;
+
+                        match ack {
+                            PushAck::Success => {
+                                self.new_connection()
+                                    .await?
+                                    .del_pending(g.did, vec![id])
+                                    .await?;
+
+                                self.send_receipts(g, std::iter::once((&push, id))).await?;
+                            }
+                            PushAck::Quit => {
+                                break;
+                            }
+                            PushAck::LogFailure { reason } => {
+                                tracing::warn!(
+                                    push_id = id,
+                                    ?reason,
+                                    "client failed to process push"
+                                );
+                                self.metrics.push_failure();
+
+                                self.new_connection()
+                                    .await?
+                                    .log_failure(g.did, id, reason)
+                                    .await?;
+                            }
+                        }
+                    }
+                    ClientMsg::Request(req_id, req) => {
+                        in_flight.push(async move { (req_id, self.handle_request(g, req).await) });
+                    }
+                },
+                Event::Response(req_id, res) => {
+                    send_ser(tx, &ServerMsg::Response(req_id, res)).await?;
                 }
-                // not sure what to do in this case, but we should probably have a way to leave a log that something went wrong without having to retry
-                PushAck::LogFailure => todo!(),
             }
         }
+
         Ok(())
     }
 
+    #[tracing::instrument(skip(self, tx, rx))]
     pub async fn catchup<Tx, Rx, E>(
         &self,
-        pk: sig::PublicKey,
+        g: GlobalId,
         tx: &mut Tx,
         rx: &mut Rx,
     ) -> Result<(), anyhow::Error>
@@ -145,7 +344,9 @@ impl State {
     {
         use catchup::{Catchup, CatchupAck};
 
-        let pending = self.new_connection().await?.get_pending(pk).await?;
+        let pending = self.live_pending(g.did).await?;
+        self.metrics.catchup_size(pending.len());
+
         let mut unsent = &pending[..];
 
         while !unsent.is_empty() {
@@ -160,10 +361,10 @@ impl State {
                 CatchupAck::Success => {
                     self.new_connection()
                         .await?
-                        .del_pending(
-                            pk,
-                            stream::iter(to_send.iter().map(|(_, id): &(Push, i64)| *id)),
-                        )
+                        .del_pending(g.did, to_send.iter().map(|(_, id)| *id).collect())
+                        .await?;
+
+                    self.send_receipts(g, to_send.iter().map(|(p, id)| (p, *id)))
                         .await?;
                 }
                 CatchupAck::Failure => {
@@ -177,6 +378,32 @@ impl State {
         Ok(())
     }
 
+    /// Gets the pushes waiting for `of`, dropping the ones older than the retention period.
+    async fn live_pending(
+        &self,
+        of: sig::PublicKey,
+    ) -> Result<Vec<(Push, i64)>, Error> {
+        let mut conn = self.new_connection().await?;
+        let pending = conn.get_pending(of).await?;
+
+        // the janitor expires these periodically, this catches the ones expired since it last ran
+        let cutoff = match self.limits.retention_cutoff() {
+            Some(cutoff) => cutoff,
+            None => return Ok(pending),
+        };
+
+        let (expired, live): (Vec<_>, Vec<_>) = pending
+            .into_iter()
+            .partition(|(push, _)| push.timestamp < cutoff);
+
+        if !expired.is_empty() {
+            conn.del_pending(of, expired.into_iter().map(|(_, id)| id).collect())
+                .await?;
+        }
+
+        Ok(live)
+    }
+
     pub async fn reset(&self) -> Result<(), Error> {
         self.new_connection().await?.reset_all().await?;
         Ok(())
@@ -211,7 +438,7 @@ impl State {
         self.active.insert(meta.did, sess);
 
         let catchup = async {
-            for (push, id) in self.new_connection().await?.get_pending(meta.did).await? {
+            for (push, id) in self.live_pending(meta.did).await? {
                 sender.send(TaggedPush { push, id })?;
             }
             Ok(())
@@ -236,6 +463,12 @@ impl State {
     }
 }
 
+fn random_node_id() -> u64 {
+    let mut buf = [0u8; 8];
+    kcl::random::gen_into(&mut buf);
+    u64::from_le_bytes(buf)
+}
+
 fn send_ser<'a, Tx: Sink<Bytes> + Unpin, T: Ser>(
     tx: &'a mut Tx,
     t: &T,
@@ -0,0 +1,286 @@
+//! Per-client rate limiting of the endpoints, using a token bucket for each client and endpoint.
+
+use super::*;
+use serde::Deserialize;
+use std::{net::IpAddr, time::Instant};
+
+/// An endpoint requests are rate limited on.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum Endpoint {
+    GetSigchain,
+    RecipExists,
+    NewSig,
+    NewPrekey,
+    GetPrekey,
+    PrekeyCount,
+    HeldPrekeys,
+    ServerKey,
+    Push,
+    Register,
+    Login,
+}
+
+impl Endpoint {
+    pub fn of(req: &Request) -> Self {
+        match req {
+            Request::GetSigchain(_) => Endpoint::GetSigchain,
+            Request::RecipExists(_) => Endpoint::RecipExists,
+            Request::NewSig(_) => Endpoint::NewSig,
+            Request::NewPrekey(_) => Endpoint::NewPrekey,
+            Request::GetPrekey(_) => Endpoint::GetPrekey,
+            Request::PrekeyCount(_) => Endpoint::PrekeyCount,
+            Request::HeldPrekeys(_) => Endpoint::HeldPrekeys,
+            Request::ServerKey(_) => Endpoint::ServerKey,
+            Request::Push(_) => Endpoint::Push,
+        }
+    }
+
+    /// The name the endpoint is reported under in metrics.
+    pub fn name(self) -> &'static str {
+        match self {
+            Endpoint::GetSigchain => "get_sigchain",
+            Endpoint::RecipExists => "recip_exists",
+            Endpoint::NewSig => "new_sig",
+            Endpoint::NewPrekey => "new_prekeys",
+            Endpoint::GetPrekey => "get_prekeys",
+            Endpoint::PrekeyCount => "prekey_count",
+            Endpoint::HeldPrekeys => "held_prekeys",
+            Endpoint::ServerKey => "server_key",
+            Endpoint::Push => "push",
+            Endpoint::Register => "register",
+            Endpoint::Login => "login",
+        }
+    }
+}
+
+/// Who a request is attributed to. Requests made over an authenticated session count against
+/// the device, everything else against the address it came from.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum Client {
+    Device(sig::PublicKey),
+    Addr(IpAddr),
+}
+
+/// Allows bursts of `burst` requests, refilled at `per_sec` requests per second.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rate {
+    pub burst: u32,
+    pub per_sec: f64,
+}
+
+impl Rate {
+    fn new(
+        burst: u32,
+        per_sec: f64,
+    ) -> Self {
+        Rate { burst, per_sec }
+    }
+}
+
+/// The rate each endpoint is limited to, per client.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimits {
+    pub get_sigchain: Rate,
+    pub recip_exists: Rate,
+    pub new_sig: Rate,
+    pub new_prekeys: Rate,
+    /// Each request hands out one-time prekeys, so this is kept low to stop them being drained
+    pub get_prekeys: Rate,
+    pub prekey_count: Rate,
+    pub held_prekeys: Rate,
+    pub server_key: Rate,
+    pub push: Rate,
+    pub register: Rate,
+    pub login: Rate,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            get_sigchain: Rate::new(50, 10.0),
+            recip_exists: Rate::new(50, 10.0),
+            new_sig: Rate::new(10, 1.0),
+            new_prekeys: Rate::new(10, 1.0),
+            get_prekeys: Rate::new(20, 1.0),
+            prekey_count: Rate::new(10, 1.0),
+            held_prekeys: Rate::new(10, 1.0),
+            server_key: Rate::new(10, 1.0),
+            push: Rate::new(100, 20.0),
+            register: Rate::new(5, 0.1),
+            login: Rate::new(10, 1.0),
+        }
+    }
+}
+
+impl RateLimits {
+    fn of(
+        &self,
+        endpoint: Endpoint,
+    ) -> Rate {
+        match endpoint {
+            Endpoint::GetSigchain => self.get_sigchain,
+            Endpoint::RecipExists => self.recip_exists,
+            Endpoint::NewSig => self.new_sig,
+            Endpoint::NewPrekey => self.new_prekeys,
+            Endpoint::GetPrekey => self.get_prekeys,
+            Endpoint::PrekeyCount => self.prekey_count,
+            Endpoint::HeldPrekeys => self.held_prekeys,
+            Endpoint::ServerKey => self.server_key,
+            Endpoint::Push => self.push,
+            Endpoint::Register => self.register,
+            Endpoint::Login => self.login,
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(
+        &mut self,
+        rate: Rate,
+        now: Instant,
+    ) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate.per_sec).min(f64::from(rate.burst));
+        self.updated = now;
+    }
+}
+
+pub(crate) struct RateLimiter {
+    rates: RateLimits,
+    buckets: DashMap<(Endpoint, Client), Bucket>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(rates: RateLimits) -> Self {
+        RateLimiter {
+            rates,
+            buckets: DashMap::default(),
+        }
+    }
+
+    /// Takes a token for a request from `client` to `endpoint`, or says how long until one is
+    /// available.
+    pub(crate) fn check(
+        &self,
+        endpoint: Endpoint,
+        client: Client,
+    ) -> Result<(), RequestError> {
+        self.check_at(endpoint, client, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        endpoint: Endpoint,
+        client: Client,
+        now: Instant,
+    ) -> Result<(), RequestError> {
+        let rate = self.rates.of(endpoint);
+
+        let mut bucket = self
+            .buckets
+            .entry((endpoint, client))
+            .or_insert_with(|| Bucket {
+                tokens: f64::from(rate.burst),
+                updated: now,
+            });
+
+        bucket.refill(rate, now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        // a bucket that never refills is never available again
+        let retry_after_ms = if rate.per_sec > 0.0 {
+            ((1.0 - bucket.tokens) / rate.per_sec * 1000.0).ceil() as u64
+        } else {
+            u64::max_value()
+        };
+
+        Err(RequestError::RateLimited { retry_after_ms })
+    }
+
+    /// Forgets clients whose buckets have refilled, since they are indistinguishable from new
+    /// ones. Returns how many were forgotten.
+    pub(crate) fn prune(&self) -> usize {
+        let now = Instant::now();
+        let before = self.buckets.len();
+
+        self.buckets.retain(|(endpoint, _), bucket| {
+            let rate = self.rates.of(*endpoint);
+            bucket.refill(rate, now);
+            bucket.tokens < f64::from(rate.burst)
+        });
+
+        before - self.buckets.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(rate: Rate) -> RateLimiter {
+        RateLimiter::new(RateLimits {
+            push: rate,
+            ..RateLimits::default()
+        })
+    }
+
+    fn client(last: u8) -> Client {
+        Client::Addr(IpAddr::from([127, 0, 0, last]))
+    }
+
+    #[test]
+    fn allows_bursts_then_refills() {
+        let limiter = limiter(Rate::new(2, 1.0));
+        let start = Instant::now();
+
+        assert!(limiter.check_at(Endpoint::Push, client(1), start).is_ok());
+        assert!(limiter.check_at(Endpoint::Push, client(1), start).is_ok());
+
+        match limiter.check_at(Endpoint::Push, client(1), start) {
+            Err(RequestError::RateLimited { retry_after_ms }) => assert_eq!(retry_after_ms, 1000),
+            other => panic!("expected to be rate limited, got {:?}", other),
+        }
+
+        let later = start + Duration::from_millis(1500);
+        assert!(limiter.check_at(Endpoint::Push, client(1), later).is_ok());
+        assert!(limiter.check_at(Endpoint::Push, client(1), later).is_err());
+    }
+
+    #[test]
+    fn buckets_are_separate() {
+        let limiter = limiter(Rate::new(1, 1.0));
+        let now = Instant::now();
+
+        assert!(limiter.check_at(Endpoint::Push, client(1), now).is_ok());
+        assert!(limiter.check_at(Endpoint::Push, client(1), now).is_err());
+
+        // other clients and endpoints have their own buckets
+        assert!(limiter.check_at(Endpoint::Push, client(2), now).is_ok());
+        assert!(limiter
+            .check_at(Endpoint::GetSigchain, client(1), now)
+            .is_ok());
+    }
+
+    #[test]
+    fn prunes_full_buckets() {
+        let limiter = limiter(Rate::new(1, 1000.0));
+        let long_ago = Instant::now() - Duration::from_secs(1);
+
+        assert!(limiter
+            .check_at(Endpoint::Push, client(1), long_ago)
+            .is_ok());
+        assert_eq!(limiter.prune(), 1);
+        assert!(limiter.buckets.is_empty());
+    }
+}
@@ -0,0 +1,237 @@
+//! Prometheus metrics for the server, rendered by `State::metrics`.
+
+use super::*;
+use prometheus::{
+    core::Collector, exponential_buckets, Encoder, Histogram, HistogramOpts, HistogramVec,
+    IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::time::Instant;
+
+pub(crate) struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_seconds: HistogramVec,
+    errors: IntCounterVec,
+    push_fan_out: Histogram,
+    push_failures: IntCounter,
+    catchup_size: Histogram,
+    pending: IntGauge,
+    active_sessions: IntGauge,
+    store_wait_seconds: Histogram,
+}
+
+impl Metrics {
+    pub(crate) fn new() -> Self {
+        let requests = IntCounterVec::new(
+            Opts::new(
+                "herald_requests_total",
+                "Requests handled, by endpoint and outcome",
+            ),
+            &["endpoint", "outcome"],
+        )
+        .expect("invalid metric");
+
+        let request_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "herald_request_duration_seconds",
+                "Time taken to handle requests, by endpoint",
+            ),
+            &["endpoint"],
+        )
+        .expect("invalid metric");
+
+        let errors = IntCounterVec::new(
+            Opts::new(
+                "herald_errors_total",
+                "Internal errors hit while handling requests, by kind",
+            ),
+            &["kind"],
+        )
+        .expect("invalid metric");
+
+        let push_fan_out = Histogram::with_opts(
+            HistogramOpts::new("herald_push_fan_out", "Devices each push was queued for")
+                .buckets(exponential_buckets(1.0, 2.0, 12).expect("invalid buckets")),
+        )
+        .expect("invalid metric");
+
+        let push_failures = IntCounter::new(
+            "herald_push_failures_total",
+            "Pushes clients reported they couldn't process",
+        )
+        .expect("invalid metric");
+
+        let catchup_size = Histogram::with_opts(
+            HistogramOpts::new(
+                "herald_catchup_pushes",
+                "Pushes waiting for devices when they log in",
+            )
+            .buckets(exponential_buckets(1.0, 4.0, 8).expect("invalid buckets")),
+        )
+        .expect("invalid metric");
+
+        let pending = IntGauge::new(
+            "herald_pending_deliveries",
+            "Deliveries waiting across all devices, as of the last janitor pass",
+        )
+        .expect("invalid metric");
+
+        let active_sessions =
+            IntGauge::new("herald_active_sessions", "Devices with an open session")
+                .expect("invalid metric");
+
+        let store_wait_seconds = Histogram::with_opts(
+            HistogramOpts::new(
+                "herald_store_wait_seconds",
+                "Time spent waiting for a connection to the store",
+            )
+            .buckets(exponential_buckets(0.0005, 2.0, 14).expect("invalid buckets")),
+        )
+        .expect("invalid metric");
+
+        let registry = Registry::new();
+        let register = |metric: Box<dyn Collector>| {
+            registry.register(metric).expect("metric registered twice")
+        };
+
+        register(Box::new(requests.clone()));
+        register(Box::new(request_seconds.clone()));
+        register(Box::new(errors.clone()));
+        register(Box::new(push_fan_out.clone()));
+        register(Box::new(push_failures.clone()));
+        register(Box::new(catchup_size.clone()));
+        register(Box::new(pending.clone()));
+        register(Box::new(active_sessions.clone()));
+        register(Box::new(store_wait_seconds.clone()));
+
+        Metrics {
+            registry,
+            requests,
+            request_seconds,
+            errors,
+            push_fan_out,
+            push_failures,
+            catchup_size,
+            pending,
+            active_sessions,
+            store_wait_seconds,
+        }
+    }
+
+    /// Records a request to `endpoint` that started at `started` and ended with `res`.
+    pub(crate) fn request(
+        &self,
+        endpoint: Endpoint,
+        started: Instant,
+        res: Result<(), &RequestError>,
+    ) {
+        let outcome = match res {
+            Ok(()) => "ok",
+            Err(RequestError::RateLimited { .. }) => "rate_limited",
+            Err(RequestError::MsgTooLarge { .. }) => "msg_too_large",
+            Err(RequestError::TooManyRecips { .. }) => "too_many_recips",
+            Err(RequestError::Malformed) => "malformed",
+            Err(RequestError::Internal) => "internal",
+        };
+
+        self.requests
+            .with_label_values(&[endpoint.name(), outcome])
+            .inc();
+        self.request_seconds
+            .with_label_values(&[endpoint.name()])
+            .observe(started.elapsed().as_secs_f64());
+    }
+
+    pub(crate) fn error(
+        &self,
+        e: &Error,
+    ) {
+        self.errors.with_label_values(&[error_kind(e)]).inc();
+    }
+
+    pub(crate) fn push_fan_out(
+        &self,
+        devices: usize,
+    ) {
+        self.push_fan_out.observe(devices as f64);
+    }
+
+    pub(crate) fn push_failure(&self) {
+        self.push_failures.inc();
+    }
+
+    pub(crate) fn catchup_size(
+        &self,
+        pushes: usize,
+    ) {
+        self.catchup_size.observe(pushes as f64);
+    }
+
+    pub(crate) fn pending(
+        &self,
+        deliveries: u64,
+    ) {
+        self.pending.set(deliveries as i64);
+    }
+
+    pub(crate) fn store_wait(
+        &self,
+        started: Instant,
+    ) {
+        self.store_wait_seconds
+            .observe(started.elapsed().as_secs_f64());
+    }
+}
+
+/// A short name for what went wrong, to group errors by without leaking their details.
+fn error_kind(e: &Error) -> &'static str {
+    match e.downcast_ref::<ServerError>() {
+        Some(ServerError::PgError(_)) => "postgres",
+        Some(ServerError::TimedOut(_)) => "timeout",
+        Some(ServerError::Kson(_)) => "kson",
+        Some(ServerError::IO(_)) => "io",
+        Some(ServerError::InvalidSig)
+        | Some(ServerError::InvalidKey)
+        | Some(ServerError::MissingData) => "invalid_data",
+        Some(_) => "store",
+        None => "other",
+    }
+}
+
+impl State {
+    /// Renders the server's metrics in the Prometheus text format.
+    pub fn metrics(&self) -> Result<String, Error> {
+        self.metrics.active_sessions.set(self.active.len() as i64);
+
+        let mut buf = Vec::new();
+        TextEncoder::new().encode(&self.metrics.registry.gather(), &mut buf)?;
+
+        Ok(String::from_utf8(buf)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+    use womp::*;
+
+    #[tokio::test]
+    async fn counts_requests() {
+        let state = State::with_backend(Box::new(Memory::new()));
+        let from = GlobalId {
+            uid: "a".try_into().expect(womp!()),
+            did: *sig::KeyPair::gen_new().public(),
+        };
+
+        state
+            .handle_request(from, Request::PrekeyCount(from.did))
+            .await;
+
+        let metrics = state.metrics().expect(womp!());
+        assert!(
+            metrics.contains(r#"herald_requests_total{endpoint="prekey_count",outcome="ok"} 1"#)
+        );
+        assert!(metrics.contains("herald_active_sessions 0"));
+    }
+}
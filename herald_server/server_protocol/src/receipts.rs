@@ -0,0 +1,61 @@
+//! Delivery receipts, telling senders which devices have fetched their pushes.
+
+use super::*;
+
+impl State {
+    /// Tells the senders of `delivered`, given with their push ids, that `to` has fetched them.
+    ///
+    /// Receipts are queued like any other push, so senders that are offline get them when they
+    /// next log in. Receipts themselves don't get receipts.
+    pub(crate) async fn send_receipts<'a, I>(
+        &self,
+        to: GlobalId,
+        delivered: I,
+    ) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = (&'a Push, i64)>,
+    {
+        let receipts: Vec<(Recip, Push)> = delivered
+            .into_iter()
+            .filter(|(push, _)| push.tag != PushTag::Receipt)
+            .map(|(push, push_id)| {
+                let receipt = sig::sign_ser(&self.signing_key, DeliveryReceipt { push_id, to });
+
+                let receipt = Push {
+                    tag: PushTag::Receipt,
+                    timestamp: Time::now(),
+                    msg: kson::to_vec(&receipt).into(),
+                    gid: to,
+                };
+
+                (Recip::One(SingleRecip::Key(push.gid.did)), receipt)
+            })
+            .collect();
+
+        if receipts.is_empty() {
+            return Ok(());
+        }
+
+        let pairs: Vec<(&Recip, &Push)> = receipts.iter().map(|(to, p)| (to, p)).collect();
+
+        let mut pushed = self
+            .new_connection()
+            .await?
+            .add_to_pending_and_get_valid_devs(&pairs)
+            .await?;
+
+        let mut stored = Vec::with_capacity(receipts.len());
+
+        while let Some((pushed_to, receipt)) = pushed.recv().await {
+            // senders that have since been deprecated have nobody left to tell
+            if let PushedTo::PushedTo { devs, push_id } = pushed_to {
+                self.notify_sessions(devs.clone(), &receipt, push_id).await;
+                stored.push((push_id, devs));
+            }
+        }
+
+        self.announce(&stored).await;
+
+        Ok(())
+    }
+}
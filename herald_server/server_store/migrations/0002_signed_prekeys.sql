@@ -0,0 +1,6 @@
+CREATE TABLE signed_prekeys (
+    signed_by  BYTEA     NOT NULL PRIMARY KEY,
+    key        BYTEA     NOT NULL,
+    signature  BYTEA     NOT NULL,
+    ts         BIGINT    NOT NULL
+);
@@ -0,0 +1,2 @@
+-- pending rows are looked up by push when removing pushes nobody is waiting on
+CREATE INDEX pending_push_id_ix ON pending(push_id);
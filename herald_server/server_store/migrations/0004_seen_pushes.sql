@@ -0,0 +1,6 @@
+CREATE TABLE seen_pushes (
+    signature  BYTEA   NOT NULL PRIMARY KEY,
+    ts         BIGINT  NOT NULL
+);
+
+CREATE INDEX seen_pushes_ts_ix ON seen_pushes(ts);
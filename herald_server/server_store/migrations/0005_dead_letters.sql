@@ -0,0 +1,12 @@
+CREATE TABLE dead_letters (
+    failure_id   BIGSERIAL   PRIMARY KEY,
+    key          BYTEA       NOT NULL,
+    push_id      BIGINT      NOT NULL,
+    push_ts      BIGINT,
+    push_key     BYTEA,
+    failed_ts    BIGINT      NOT NULL,
+    reason       BIGINT      NOT NULL
+);
+
+CREATE INDEX dead_letter_key_ix ON dead_letters(key);
+CREATE INDEX dead_letter_ts_ix ON dead_letters(failed_ts);
@@ -1,5 +0,0 @@
-DROP TABLE pending;
-DROP TABLE pushes;
-DROP TABLE prekeys;
-DROP TABLE userkeys;
-DROP TABLE keys;
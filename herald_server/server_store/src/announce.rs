@@ -0,0 +1,234 @@
+//! Announcements of stored pushes, so every server sharing a store can deliver them to the
+//! devices connected to it.
+//!
+//! With Postgres these are sent with `NOTIFY` on the `herald_pending` channel, so a server only
+//! hears about pushes once they are committed.
+
+use super::*;
+use futures::stream::BoxStream;
+use tokio_postgres::AsyncMessage;
+
+/// The Postgres channel announcements are sent on, matching `announce_pushes.sql`.
+pub(crate) const CHANNEL: &str = "herald_pending";
+
+/// How many recipient devices are named in each announcement, keeping the payload well under the
+/// 8000 byte limit on notifications. Pushes to more devices are announced several times.
+const DEVS_PER_ANNOUNCEMENT: usize = 100;
+
+/// A push a server stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Announcement {
+    /// The server that stored the push, which has already delivered it to its own sessions
+    pub node: u64,
+    pub push_id: i64,
+    /// The devices the push is for, so servers without any of them connected can ignore it
+    pub devs: Vec<sig::PublicKey>,
+}
+
+impl Announcement {
+    /// Splits the announcement of a push to `devs` into ones small enough to send.
+    pub(crate) fn chunked(
+        node: u64,
+        push_id: i64,
+        devs: &[sig::PublicKey],
+    ) -> impl Iterator<Item = Announcement> + '_ {
+        devs.chunks(DEVS_PER_ANNOUNCEMENT)
+            .map(move |devs| Announcement {
+                node,
+                push_id,
+                devs: devs.to_vec(),
+            })
+    }
+
+    /// Formats the announcement as `node:push_id:devs`, with the devices' keys in hex, separated
+    /// by commas.
+    fn to_payload(&self) -> String {
+        let devs: Vec<String> = self.devs.iter().map(|d| to_hex(d.as_ref())).collect();
+
+        format!("{}:{}:{}", self.node, self.push_id, devs.join(","))
+    }
+
+    /// Parses the payload of a notification, as formatted by `to_payload`.
+    fn from_payload(payload: &str) -> Option<Self> {
+        let mut parts = payload.splitn(3, ':');
+
+        let node = parts.next()?.parse().ok()?;
+        let push_id = parts.next()?.parse().ok()?;
+        let devs = parts
+            .next()?
+            .split(',')
+            .map(|dev| sig::PublicKey::from_slice(&from_hex(dev)?))
+            .collect::<Option<_>>()?;
+
+        Some(Announcement {
+            node,
+            push_id,
+            devs,
+        })
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+impl Conn {
+    /// Announces that `node` stored `pushes`, given with the devices they are for.
+    pub async fn announce_pushes(
+        &mut self,
+        node: u64,
+        pushes: &[(i64, Vec<sig::PublicKey>)],
+    ) -> Res<()> {
+        let payloads: Vec<String> = pushes
+            .iter()
+            .flat_map(|(push_id, devs)| Announcement::chunked(node, *push_id, devs))
+            .map(|announcement| announcement.to_payload())
+            .collect();
+
+        if payloads.is_empty() {
+            return Ok(());
+        }
+
+        let stmt = self
+            .prepare_typed(sql!("announce_pushes"), types![TEXT_ARRAY])
+            .await?;
+
+        self.query(&stmt, params![payloads]).await?;
+
+        Ok(())
+    }
+}
+
+impl Pool {
+    /// Listens for announcements on a connection of its own, which is closed when the stream is
+    /// dropped. The stream ends if the connection is lost.
+    pub async fn announcements(&self) -> Res<BoxStream<'static, Res<Announcement>>> {
+        let (client, mut connection) = tokio_postgres::connect(self.database_url(), NoTls).await?;
+
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+
+        // notifications are only delivered by polling the connection directly
+        let forward = futures::stream::poll_fn(move |cx| connection.poll_message(cx))
+            .map(Ok)
+            .forward(tx)
+            .map(|r| {
+                if r.is_err() {
+                    tracing::debug!("stopped listening for announcements");
+                }
+            });
+
+        tokio::spawn(forward);
+
+        client.batch_execute(&format!("LISTEN {}", CHANNEL)).await?;
+
+        let announcements = rx.filter_map(move |msg| {
+            // the connection is closed once the client is dropped, so keep it alive as long as
+            // the stream
+            let _client = &client;
+
+            let res = match msg {
+                Ok(AsyncMessage::Notification(n)) => {
+                    match Announcement::from_payload(n.payload()) {
+                        Some(announcement) => Some(Ok(announcement)),
+                        None => {
+                            tracing::warn!("ignoring malformed announcement {:?}", n.payload());
+                            None
+                        }
+                    }
+                }
+                Ok(_) => None,
+                Err(e) => Some(Err(Error::from(e))),
+            };
+
+            futures::future::ready(res)
+        });
+
+        Ok(announcements.boxed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::get_backend;
+    use serial_test_derive::serial;
+    use womp::*;
+
+    #[tokio::test]
+    #[serial]
+    async fn announces_pushes() {
+        let backend = get_backend();
+        let mut listener = backend.announcements().await.expect(womp!());
+        let mut conn = backend.get().await.expect(womp!());
+
+        let a = *sig::KeyPair::gen_new().public();
+        let b = *sig::KeyPair::gen_new().public();
+
+        conn.announce_pushes(7, &[(1, vec![a]), (2, vec![a, b])])
+            .await
+            .expect(womp!());
+        // nothing is sent for no pushes
+        conn.announce_pushes(7, &[]).await.expect(womp!());
+        conn.announce_pushes(8, &[(3, vec![b])])
+            .await
+            .expect(womp!());
+
+        for (node, push_id, devs) in vec![(7, 1, vec![a]), (7, 2, vec![a, b]), (8, 3, vec![b])] {
+            let announcement = listener.next().await.expect(womp!()).expect(womp!());
+
+            assert_eq!(
+                announcement,
+                Announcement {
+                    node,
+                    push_id,
+                    devs
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn parses_payloads() {
+        let dev = *sig::KeyPair::gen_new().public();
+        let announcement = Announcement {
+            node: u64::max_value(),
+            push_id: 42,
+            devs: vec![dev, dev],
+        };
+
+        assert_eq!(
+            Announcement::from_payload(&announcement.to_payload()),
+            Some(announcement)
+        );
+
+        assert_eq!(Announcement::from_payload("42"), None);
+        assert_eq!(Announcement::from_payload("a:42:"), None);
+        assert_eq!(Announcement::from_payload("1:42"), None);
+        assert_eq!(Announcement::from_payload("1:42:zz"), None);
+    }
+
+    #[test]
+    fn large_pushes_are_split() {
+        let devs: Vec<_> = (0..DEVS_PER_ANNOUNCEMENT + 1)
+            .map(|_| *sig::KeyPair::gen_new().public())
+            .collect();
+
+        let chunks: Vec<_> = Announcement::chunked(1, 2, &devs).collect();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1].devs, vec![devs[DEVS_PER_ANNOUNCEMENT]]);
+
+        // payloads fit in a notification
+        assert!(chunks[0].to_payload().len() < 8000);
+    }
+}
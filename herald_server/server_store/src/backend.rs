@@ -0,0 +1,398 @@
+use super::*;
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use protocol::auth::RegisterResponse;
+
+/// The storage operations the server needs, independent of where the data lives.
+#[async_trait]
+pub trait Store: Send {
+    /// Version of the stored schema, see `SCHEMA_VERSION`.
+    async fn schema_version(&mut self) -> Res<i64>;
+
+    /// Brings the schema up to `SCHEMA_VERSION`, returning the version it started at.
+    async fn migrate(&mut self) -> Res<i64>;
+
+    /// Drops all data and recreates the tables.
+    async fn reset_all(&mut self) -> Res<()>;
+
+    async fn user_of(
+        &mut self,
+        key: sig::PublicKey,
+    ) -> Res<Option<UserId>>;
+
+    async fn new_user(
+        &mut self,
+        init: Signed<UserId>,
+    ) -> Res<RegisterResponse>;
+
+    async fn key_is_valid(
+        &mut self,
+        key: sig::PublicKey,
+    ) -> Res<bool>;
+
+    async fn key_is_valid_for_user(
+        &mut self,
+        key: &sig::PublicKey,
+        user: &UserId,
+    ) -> Res<bool>;
+
+    async fn get_sigchain(
+        &mut self,
+        user: UserId,
+    ) -> Res<Option<sig::SigChain>>;
+
+    async fn add_to_sigchain(
+        &mut self,
+        new: Signed<sig::SigUpdate>,
+    ) -> Res<PKIResponse>;
+
+    async fn recip_exists(
+        &mut self,
+        recip: Recip,
+    ) -> Res<bool>;
+
+    /// Stores a signed prekey and one-time prekeys, all at once or not at all.
+    ///
+    /// Each signer may hold at most `max_held` one-time prekeys.
+    async fn new_prekeys(
+        &mut self,
+        signed: Option<Signed<Prekey>>,
+        keys: Vec<PrekeyReplace>,
+        max_held: u64,
+    ) -> Res<new_prekeys::Res>;
+
+    /// Fetches a prekey bundle for each of `keys`, removing the one-time prekeys that are handed
+    /// out.
+    async fn get_prekey_bundles(
+        &mut self,
+        keys: Vec<sig::PublicKey>,
+    ) -> Res<Vec<(sig::PublicKey, PrekeyBundle)>>;
+
+    async fn prekey_count(
+        &mut self,
+        key: sig::PublicKey,
+    ) -> Res<u64>;
+
+    /// The one-time prekeys signed by `key` that haven't been handed out yet.
+    async fn held_prekeys(
+        &mut self,
+        key: sig::PublicKey,
+    ) -> Res<Vec<Prekey>>;
+
+    /// Stores each push as pending for the devices it's addressed to, reporting who it was
+    /// delivered to.
+    async fn add_to_pending_and_get_valid_devs(
+        &mut self,
+        pairs: &[(&Recip, &Push)],
+    ) -> Res<tokio::sync::mpsc::Receiver<(PushedTo, Push)>>;
+
+    /// Pushes waiting to be delivered to `of`, oldest first.
+    async fn get_pending(
+        &mut self,
+        of: sig::PublicKey,
+    ) -> Res<Vec<(Push, i64)>>;
+
+    /// The push `push_id` along with the devices still waiting for it, or `None` if none are.
+    async fn pending_push(
+        &mut self,
+        push_id: i64,
+    ) -> Res<Option<(Push, Vec<sig::PublicKey>)>>;
+
+    /// Announces to every server sharing the store that `node` stored `pushes`, given with the
+    /// devices they are for.
+    async fn announce_pushes(
+        &mut self,
+        node: u64,
+        pushes: &[(i64, Vec<sig::PublicKey>)],
+    ) -> Res<()>;
+
+    /// Marks `items` as delivered to `of`. The pushes themselves are left for
+    /// `del_dangling_pushes` to remove.
+    async fn del_pending(
+        &mut self,
+        of: sig::PublicKey,
+        items: Vec<i64>,
+    ) -> Res<()>;
+
+    /// Stops waiting to deliver pushes sent before `before`, returning how many deliveries were
+    /// dropped.
+    async fn expire_pending(
+        &mut self,
+        before: Time,
+    ) -> Res<u64>;
+
+    /// Removes pushes that are no longer pending for any device, returning how many were removed.
+    async fn del_dangling_pushes(&mut self) -> Res<u64>;
+
+    /// The number of deliveries waiting, across all devices.
+    async fn pending_count(&mut self) -> Res<u64>;
+
+    /// The devices `recip` resolves to that have `quota` or more pushes waiting for them.
+    async fn full_queues(
+        &mut self,
+        recip: &Recip,
+        quota: u64,
+    ) -> Res<Vec<sig::PublicKey>>;
+
+    /// Records that `of` failed to process the push `push_id`, and stops delivering it to them.
+    async fn log_failure(
+        &mut self,
+        of: sig::PublicKey,
+        push_id: i64,
+        reason: FailureCode,
+    ) -> Res<()>;
+
+    /// The `limit` most recently logged failures, newest first. Only those of `of` if it is set.
+    async fn dead_letters(
+        &mut self,
+        of: Option<sig::PublicKey>,
+        limit: u64,
+    ) -> Res<Vec<DeadLetter>>;
+
+    /// Forgets failures logged before `before`, returning how many were forgotten.
+    async fn forget_dead_letters(
+        &mut self,
+        before: Time,
+    ) -> Res<u64>;
+
+    /// Records the signature of a push signed at `ts`, returning `false` if it was seen before.
+    async fn note_push_sig(
+        &mut self,
+        sig: sig::Signature,
+        ts: Time,
+    ) -> Res<bool>;
+
+    /// Whether the signature of a push was recorded before.
+    async fn push_sig_seen(
+        &mut self,
+        sig: sig::Signature,
+    ) -> Res<bool>;
+
+    /// Forgets the signatures of pushes signed before `before`, returning how many were
+    /// forgotten.
+    async fn forget_push_sigs(
+        &mut self,
+        before: Time,
+    ) -> Res<u64>;
+}
+
+/// A source of connections to a `Store`.
+#[async_trait]
+pub trait Backend: Send + Sync {
+    async fn get(&self) -> Res<Box<dyn Store>>;
+
+    /// Announcements of pushes stored from now on, by any server sharing the store.
+    async fn announcements(&self) -> Res<BoxStream<'static, Res<Announcement>>>;
+}
+
+#[async_trait]
+impl Backend for Pool {
+    async fn get(&self) -> Res<Box<dyn Store>> {
+        Ok(Box::new(Pool::get(self).await?))
+    }
+
+    async fn announcements(&self) -> Res<BoxStream<'static, Res<Announcement>>> {
+        Pool::announcements(self).await
+    }
+}
+
+#[async_trait]
+impl Store for Conn {
+    async fn schema_version(&mut self) -> Res<i64> {
+        Conn::schema_version(self).await
+    }
+
+    async fn migrate(&mut self) -> Res<i64> {
+        Conn::migrate(self).await
+    }
+
+    async fn reset_all(&mut self) -> Res<()> {
+        Conn::reset_all(self).await
+    }
+
+    async fn user_of(
+        &mut self,
+        key: sig::PublicKey,
+    ) -> Res<Option<UserId>> {
+        Conn::user_of(self, key).await
+    }
+
+    async fn new_user(
+        &mut self,
+        init: Signed<UserId>,
+    ) -> Res<RegisterResponse> {
+        Conn::new_user(self, init).await
+    }
+
+    async fn key_is_valid(
+        &mut self,
+        key: sig::PublicKey,
+    ) -> Res<bool> {
+        Conn::key_is_valid(self, key).await
+    }
+
+    async fn key_is_valid_for_user(
+        &mut self,
+        key: &sig::PublicKey,
+        user: &UserId,
+    ) -> Res<bool> {
+        Conn::key_is_valid_for_user(self, key, user).await
+    }
+
+    async fn get_sigchain(
+        &mut self,
+        user: UserId,
+    ) -> Res<Option<sig::SigChain>> {
+        Conn::get_sigchain(self, user).await
+    }
+
+    async fn add_to_sigchain(
+        &mut self,
+        new: Signed<sig::SigUpdate>,
+    ) -> Res<PKIResponse> {
+        Conn::add_to_sigchain(self, new).await
+    }
+
+    async fn recip_exists(
+        &mut self,
+        recip: Recip,
+    ) -> Res<bool> {
+        Conn::recip_exists(self, recip).await
+    }
+
+    async fn new_prekeys(
+        &mut self,
+        signed: Option<Signed<Prekey>>,
+        keys: Vec<PrekeyReplace>,
+        max_held: u64,
+    ) -> Res<new_prekeys::Res> {
+        Conn::new_prekeys(self, signed, keys, max_held).await
+    }
+
+    async fn get_prekey_bundles(
+        &mut self,
+        keys: Vec<sig::PublicKey>,
+    ) -> Res<Vec<(sig::PublicKey, PrekeyBundle)>> {
+        Conn::get_prekey_bundles(self, futures::stream::iter(keys)).await
+    }
+
+    async fn prekey_count(
+        &mut self,
+        key: sig::PublicKey,
+    ) -> Res<u64> {
+        Conn::prekey_count(self, key).await
+    }
+
+    async fn held_prekeys(
+        &mut self,
+        key: sig::PublicKey,
+    ) -> Res<Vec<Prekey>> {
+        Conn::held_prekeys(self, key).await
+    }
+
+    async fn add_to_pending_and_get_valid_devs(
+        &mut self,
+        pairs: &[(&Recip, &Push)],
+    ) -> Res<tokio::sync::mpsc::Receiver<(PushedTo, Push)>> {
+        Conn::add_to_pending_and_get_valid_devs(self, pairs).await
+    }
+
+    async fn get_pending(
+        &mut self,
+        of: sig::PublicKey,
+    ) -> Res<Vec<(Push, i64)>> {
+        Conn::get_pending(self, of).await
+    }
+
+    async fn pending_push(
+        &mut self,
+        push_id: i64,
+    ) -> Res<Option<(Push, Vec<sig::PublicKey>)>> {
+        Conn::pending_push(self, push_id).await
+    }
+
+    async fn announce_pushes(
+        &mut self,
+        node: u64,
+        pushes: &[(i64, Vec<sig::PublicKey>)],
+    ) -> Res<()> {
+        Conn::announce_pushes(self, node, pushes).await
+    }
+
+    async fn del_pending(
+        &mut self,
+        of: sig::PublicKey,
+        items: Vec<i64>,
+    ) -> Res<()> {
+        Conn::del_pending(self, of, futures::stream::iter(items)).await
+    }
+
+    async fn expire_pending(
+        &mut self,
+        before: Time,
+    ) -> Res<u64> {
+        Conn::expire_pending(self, before).await
+    }
+
+    async fn del_dangling_pushes(&mut self) -> Res<u64> {
+        Conn::del_dangling_pushes(self).await
+    }
+
+    async fn pending_count(&mut self) -> Res<u64> {
+        Conn::pending_count(self).await
+    }
+
+    async fn full_queues(
+        &mut self,
+        recip: &Recip,
+        quota: u64,
+    ) -> Res<Vec<sig::PublicKey>> {
+        Conn::full_queues(self, recip, quota).await
+    }
+
+    async fn log_failure(
+        &mut self,
+        of: sig::PublicKey,
+        push_id: i64,
+        reason: FailureCode,
+    ) -> Res<()> {
+        Conn::log_failure(self, of, push_id, reason).await
+    }
+
+    async fn dead_letters(
+        &mut self,
+        of: Option<sig::PublicKey>,
+        limit: u64,
+    ) -> Res<Vec<DeadLetter>> {
+        Conn::dead_letters(self, of, limit).await
+    }
+
+    async fn forget_dead_letters(
+        &mut self,
+        before: Time,
+    ) -> Res<u64> {
+        Conn::forget_dead_letters(self, before).await
+    }
+
+    async fn note_push_sig(
+        &mut self,
+        sig: sig::Signature,
+        ts: Time,
+    ) -> Res<bool> {
+        Conn::note_push_sig(self, sig, ts).await
+    }
+
+    async fn push_sig_seen(
+        &mut self,
+        sig: sig::Signature,
+    ) -> Res<bool> {
+        Conn::push_sig_seen(self, sig).await
+    }
+
+    async fn forget_push_sigs(
+        &mut self,
+        before: Time,
+    ) -> Res<u64> {
+        Conn::forget_push_sigs(self, before).await
+    }
+}
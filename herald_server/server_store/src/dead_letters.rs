@@ -0,0 +1,175 @@
+//! Pushes devices reported they couldn't process, kept so misbehaving clients can be debugged.
+
+use super::*;
+
+/// A push a device failed to process.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeadLetter {
+    /// The device that failed to process the push
+    pub device: sig::PublicKey,
+    pub push_id: i64,
+    /// When the push was sent, if it was still stored when the failure was logged
+    pub push_ts: Option<Time>,
+    /// The device that sent the push, if it was still stored when the failure was logged
+    pub sender: Option<sig::PublicKey>,
+    /// When the failure was logged
+    pub failed_ts: Time,
+    /// Why the client couldn't process the push
+    pub reason: FailureCode,
+}
+
+impl Conn {
+    /// Records that `of` failed to process the push `push_id`, and stops delivering it to them.
+    pub async fn log_failure(
+        &mut self,
+        of: sig::PublicKey,
+        push_id: i64,
+        reason: FailureCode,
+    ) -> Res<()> {
+        let tx = self.transaction().await?;
+
+        let add_stmt = tx
+            .prepare_typed(sql!("add_dead_letter"), types![BYTEA, INT8, INT8, INT8])
+            .await?;
+
+        tx.execute(
+            &add_stmt,
+            params![
+                of.as_ref(),
+                push_id,
+                Time::now().as_i64(),
+                i64::from(reason.code())
+            ],
+        )
+        .await?;
+
+        let del_stmt = tx
+            .prepare_typed(sql!("expire_pending"), types![BYTEA, INT8])
+            .await?;
+
+        tx.execute(&del_stmt, params![of.as_ref(), push_id]).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// The `limit` most recently logged failures, newest first. Only those of `of` if it is set.
+    pub async fn dead_letters(
+        &mut self,
+        of: Option<sig::PublicKey>,
+        limit: u64,
+    ) -> Res<Vec<DeadLetter>> {
+        let stmt = self
+            .prepare_typed(sql!("dead_letters"), types![BYTEA, INT8])
+            .await?;
+
+        let of: Option<&[u8]> = of.as_ref().map(AsRef::as_ref);
+
+        self.query(&stmt, params![of, limit as i64])
+            .await?
+            .into_iter()
+            .map(|row| -> Res<DeadLetter> {
+                let key = |col: &str| {
+                    row.get::<_, Option<&[u8]>>(col)
+                        .map(|key| sig::PublicKey::from_slice(key).ok_or(Error::InvalidKey))
+                        .transpose()
+                };
+
+                Ok(DeadLetter {
+                    device: key("key")?.ok_or(Error::MissingData)?,
+                    push_id: row.get("push_id"),
+                    push_ts: row.get::<_, Option<i64>>("push_ts").map(Time::from),
+                    sender: key("push_key")?,
+                    failed_ts: Time::from(row.get::<_, i64>("failed_ts")),
+                    reason: FailureCode::from_code(row.get::<_, i64>("reason") as u32)
+                        .ok_or(Error::MissingData)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Forgets failures logged before `before`, returning how many were forgotten.
+    pub async fn forget_dead_letters(
+        &mut self,
+        before: Time,
+    ) -> Res<u64> {
+        let stmt = self
+            .prepare_typed(sql!("forget_dead_letters"), types![INT8])
+            .await?;
+
+        Ok(self.execute(&stmt, params![before.as_i64()]).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::get_client;
+    use crate::{w, wa};
+    use serial_test_derive::serial;
+    use sig::sign_ser as sign;
+    use std::convert::TryInto;
+    use womp::*;
+
+    #[tokio::test]
+    #[serial]
+    async fn log_and_list_failures() {
+        let mut client = wa!(get_client());
+
+        let uid: UserId = w!("a".try_into());
+        let kp = sig::KeyPair::gen_new();
+        let kp2 = sig::KeyPair::gen_new();
+        let to = *kp2.public();
+
+        wa!(client.new_user(sign(&kp, uid)));
+        wa!(client.add_to_sigchain(sign(&kp, sig::SigUpdate::Endorse(sign(&kp2, uid)))));
+
+        let push = Push {
+            tag: PushTag::Key,
+            timestamp: Time::now(),
+            msg: Bytes::from_static(b"test"),
+            gid: GlobalId {
+                uid,
+                did: *kp.public(),
+            },
+        };
+
+        let recip = Recip::One(SingleRecip::Key(to));
+        w!(
+            wa!(client.add_to_pending_and_get_valid_devs(&[(&recip, &push)]))
+                .recv()
+                .await
+        );
+
+        let pending = wa!(client.get_pending(to));
+        assert_eq!(pending.len(), 1);
+        let push_id = pending[0].1;
+
+        wa!(client.log_failure(to, push_id, FailureCode::BadSig));
+
+        // the push is no longer delivered
+        assert!(wa!(client.get_pending(to)).is_empty());
+
+        let failures = wa!(client.dead_letters(None, 10));
+        assert_eq!(failures.len(), 1);
+
+        let failure = &failures[0];
+        assert_eq!(failure.device, to);
+        assert_eq!(failure.push_id, push_id);
+        assert_eq!(failure.push_ts, Some(push.timestamp));
+        assert_eq!(failure.sender, Some(*kp.public()));
+        assert_eq!(failure.reason, FailureCode::BadSig);
+
+        assert_eq!(wa!(client.dead_letters(Some(to), 10)), failures);
+        assert!(wa!(client.dead_letters(Some(*kp.public()), 10)).is_empty());
+        assert!(wa!(client.dead_letters(None, 0)).is_empty());
+
+        assert_eq!(wa!(client.forget_dead_letters(failure.failed_ts)), 0);
+        assert_eq!(
+            wa!(client.forget_dead_letters(Time::from(*failure.failed_ts.as_i64() + 1))),
+            1
+        );
+        assert!(wa!(client.dead_letters(None, 10)).is_empty());
+    }
+}
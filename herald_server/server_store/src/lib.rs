@@ -12,15 +12,26 @@ use tokio_postgres::{
 
 type Res<T> = std::result::Result<T, Error>;
 
+mod announce;
+mod backend;
+mod dead_letters;
 mod macros;
+mod memory;
+mod migrate;
 mod pending;
 mod pool;
 mod prekeys;
 mod recip_exists;
+mod seen_pushes;
 mod sigchain;
+pub use announce::Announcement;
+pub use backend::{Backend, Store};
+pub use dead_letters::DeadLetter;
+pub use memory::Memory;
+pub use migrate::SCHEMA_VERSION;
 pub use pending::PushedTo;
 pub use pool::*;
-pub use prekeys::{PrekeyReplace, TaggedPrekey};
+pub use prekeys::{PrekeyReplace, PREKEY_SLOTS};
 
 impl Conn {
     pub async fn user_of(
@@ -118,21 +129,10 @@ impl Conn {
 }
 
 impl Conn {
-    pub async fn setup(&mut self) -> Res<()> {
-        // create
-        self.batch_execute(include_str!("../schema/up.sql")).await?;
-        Ok(())
-    }
-
+    /// Drops all data and migrates the empty database to the latest schema.
     pub async fn reset_all(&mut self) -> Res<()> {
-        let tx = self.transaction().await?;
-
-        // drop
-        tx.batch_execute(include_str!("../schema/down.sql")).await?;
-
-        // create
-        tx.batch_execute(include_str!("../schema/up.sql")).await?;
-        tx.commit().await?;
+        self.batch_execute(sql!("drop_all")).await?;
+        self.migrate().await?;
         Ok(())
     }
 }
@@ -167,13 +167,37 @@ pub(crate) mod tests {
         };
     }
 
-    pub(crate) async fn get_client() -> Result<Conn, Error> {
-        let pool = Pool::new();
-        let mut client = pool.get().await?;
+    /// The backend to test against.
+    ///
+    /// Tests run against the in-memory backend unless `HERALD_TEST_POSTGRES` is set, in which
+    /// case they use the local Postgres instance.
+    pub(crate) fn get_backend() -> Box<dyn Backend> {
+        if std::env::var_os("HERALD_TEST_POSTGRES").is_some() {
+            Box::new(Pool::new())
+        } else {
+            Box::new(Memory::new())
+        }
+    }
+
+    /// Gets a freshly reset store to test against.
+    pub(crate) async fn get_client() -> Result<Box<dyn Store>, Error> {
+        let mut client = get_backend().get().await?;
         client.reset_all().await?;
         Ok(client)
     }
 
+    /// Gets a freshly reset Postgres connection, for tests of the Postgres specific code.
+    ///
+    /// These are skipped unless `HERALD_TEST_POSTGRES` is set.
+    pub(crate) async fn get_pg_client() -> Option<Conn> {
+        std::env::var_os("HERALD_TEST_POSTGRES")?;
+
+        let mut client = wa!(Pool::new().get());
+        wa!(client.reset_all());
+
+        Some(client)
+    }
+
     #[tokio::test]
     #[serial]
     async fn new_user_and_user_of() {
@@ -0,0 +1,711 @@
+//! An in-memory `Store`, for running the server and its tests without a Postgres instance.
+//!
+//! Nothing is persisted, so this is only meant for development and testing.
+
+use super::*;
+use async_trait::async_trait;
+use futures::{
+    channel::mpsc::{unbounded, UnboundedSender},
+    stream::BoxStream,
+};
+use protocol::auth::RegisterResponse;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex as StdMutex, MutexGuard},
+};
+
+#[derive(Clone, Copy)]
+struct KeyState {
+    user: UserId,
+    deprecated: bool,
+}
+
+#[derive(Default)]
+struct Tables {
+    chains: HashMap<UserId, sig::SigChain>,
+    keys: HashMap<sig::PublicKey, KeyState>,
+    signed_prekeys: HashMap<sig::PublicKey, Signed<Prekey>>,
+    /// One-time prekeys of each device, by slot
+    prekeys: HashMap<sig::PublicKey, BTreeMap<u8, Signed<Prekey>>>,
+    pushes: HashMap<i64, Push>,
+    pending: HashMap<sig::PublicKey, Vec<i64>>,
+    next_push_id: i64,
+    /// Logged failures, oldest first
+    dead_letters: Vec<DeadLetter>,
+    /// Signatures of recent pushes, with when they were signed
+    seen_pushes: HashMap<sig::Signature, Time>,
+    /// Streams of announcements that are still being listened to
+    listeners: Vec<UnboundedSender<Announcement>>,
+}
+
+impl Tables {
+    fn key_is_valid(
+        &self,
+        key: &sig::PublicKey,
+    ) -> bool {
+        self.keys.get(key).map(|k| !k.deprecated).unwrap_or(false)
+    }
+
+    fn valid_user_keys(
+        &self,
+        user: &UserId,
+    ) -> Vec<sig::PublicKey> {
+        self.keys
+            .iter()
+            .filter(|(_, k)| k.user == *user && !k.deprecated)
+            .map(|(key, _)| *key)
+            .collect()
+    }
+
+    fn add_push(
+        &mut self,
+        push: &Push,
+        to: &[sig::PublicKey],
+    ) -> i64 {
+        let push_id = self.next_push_id;
+        self.next_push_id += 1;
+
+        // a push nobody is waiting on would be dangling immediately
+        if !to.is_empty() {
+            self.pushes.insert(push_id, push.clone());
+        }
+
+        for key in to {
+            self.pending.entry(*key).or_default().push(push_id);
+        }
+
+        push_id
+    }
+
+    fn add_pending(
+        &mut self,
+        recip: &Recip,
+        push: &Push,
+    ) -> PushedTo {
+        use Recip::*;
+        use Recips::*;
+        use SingleRecip::*;
+
+        let did = push.gid.did;
+
+        let devs = match recip {
+            One(Key(key)) => {
+                if *key != did && !self.keys.contains_key(key) {
+                    return PushedTo::Missing(Key(*key));
+                }
+
+                vec![*key]
+            }
+            One(User(uid)) => {
+                if !self.chains.contains_key(uid) {
+                    return PushedTo::Missing(User(*uid));
+                }
+
+                self.valid_user_keys(uid)
+            }
+            Many(Users(uids)) => {
+                let mut devs = Vec::new();
+
+                for uid in uids {
+                    if !self.chains.contains_key(uid) {
+                        return PushedTo::Missing(User(*uid));
+                    }
+
+                    devs.extend(self.valid_user_keys(uid));
+                }
+
+                devs
+            }
+            Many(Keys(keys)) => {
+                for key in keys {
+                    if *key != did && !self.keys.contains_key(key) {
+                        return PushedTo::Missing(Key(*key));
+                    }
+                }
+
+                keys.clone()
+            }
+        };
+
+        // the sender's own device is never pushed to, however the recipients were addressed
+        let devs: Vec<sig::PublicKey> = devs.into_iter().filter(|k| *k != did).collect();
+
+        if devs.is_empty() {
+            return PushedTo::NoRecipients;
+        }
+
+        let push_id = self.add_push(push, &devs);
+
+        PushedTo::PushedTo { devs, push_id }
+    }
+}
+
+/// In-memory storage, shared between all of its connections.
+#[derive(Clone, Default)]
+pub struct Memory {
+    tables: Arc<StdMutex<Tables>>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn tables(&self) -> MutexGuard<Tables> {
+        // the tables are never left in an inconsistent state, so a poisoned lock is still usable
+        self.tables
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[async_trait]
+impl Backend for Memory {
+    async fn get(&self) -> Res<Box<dyn Store>> {
+        Ok(Box::new(self.clone()))
+    }
+
+    async fn announcements(&self) -> Res<BoxStream<'static, Res<Announcement>>> {
+        let (tx, rx) = unbounded();
+        self.tables().listeners.push(tx);
+
+        Ok(rx.map(Ok).boxed())
+    }
+}
+
+#[async_trait]
+impl Store for Memory {
+    async fn schema_version(&mut self) -> Res<i64> {
+        Ok(SCHEMA_VERSION)
+    }
+
+    async fn migrate(&mut self) -> Res<i64> {
+        Ok(SCHEMA_VERSION)
+    }
+
+    async fn reset_all(&mut self) -> Res<()> {
+        *self.tables() = Tables::default();
+        Ok(())
+    }
+
+    async fn user_of(
+        &mut self,
+        key: sig::PublicKey,
+    ) -> Res<Option<UserId>> {
+        Ok(self.tables().keys.get(&key).map(|k| k.user))
+    }
+
+    async fn new_user(
+        &mut self,
+        init: Signed<UserId>,
+    ) -> Res<RegisterResponse> {
+        let mut tables = self.tables();
+        let user = *init.data();
+        let key = *init.signed_by();
+
+        if tables.chains.contains_key(&user) || tables.keys.contains_key(&key) {
+            return Ok(RegisterResponse::Taken);
+        }
+
+        tables.keys.insert(
+            key,
+            KeyState {
+                user,
+                deprecated: false,
+            },
+        );
+        tables.chains.insert(
+            user,
+            sig::SigChain {
+                initial: init,
+                sig_chain: Vec::new(),
+            },
+        );
+
+        Ok(RegisterResponse::Success)
+    }
+
+    async fn key_is_valid(
+        &mut self,
+        key: sig::PublicKey,
+    ) -> Res<bool> {
+        Ok(self.tables().key_is_valid(&key))
+    }
+
+    async fn key_is_valid_for_user(
+        &mut self,
+        key: &sig::PublicKey,
+        user: &UserId,
+    ) -> Res<bool> {
+        let tables = self.tables();
+        Ok(tables.key_is_valid(key) && tables.keys.get(key).map(|k| k.user) == Some(*user))
+    }
+
+    async fn get_sigchain(
+        &mut self,
+        user: UserId,
+    ) -> Res<Option<sig::SigChain>> {
+        Ok(self.tables().chains.get(&user).cloned())
+    }
+
+    async fn add_to_sigchain(
+        &mut self,
+        new: Signed<sig::SigUpdate>,
+    ) -> Res<PKIResponse> {
+        use sig::SigUpdate::*;
+
+        let mut tables = self.tables();
+
+        if !tables.key_is_valid(new.signed_by()) {
+            return Ok(PKIResponse::DeadKey);
+        }
+
+        let user = match *new.data() {
+            Endorse(signed_uid) => {
+                let key = *signed_uid.signed_by();
+                let user = *signed_uid.data();
+
+                if tables.keys.contains_key(&key) {
+                    return Ok(PKIResponse::Redundant);
+                }
+
+                if !tables.chains.contains_key(&user) {
+                    return Ok(PKIResponse::InvalidOp);
+                }
+
+                tables.keys.insert(
+                    key,
+                    KeyState {
+                        user,
+                        deprecated: false,
+                    },
+                );
+
+                user
+            }
+            Deprecate(pk) => match tables.keys.get_mut(&pk) {
+                None => return Ok(PKIResponse::DeadKey),
+                Some(k) if k.deprecated => return Ok(PKIResponse::Redundant),
+                Some(k) => {
+                    k.deprecated = true;
+                    k.user
+                }
+            },
+        };
+
+        if let Some(chain) = tables.chains.get_mut(&user) {
+            chain.sig_chain.push(new);
+        }
+
+        Ok(PKIResponse::Success)
+    }
+
+    async fn recip_exists(
+        &mut self,
+        recip: Recip,
+    ) -> Res<bool> {
+        use Recip::*;
+        use Recips::*;
+        use SingleRecip::*;
+
+        let tables = self.tables();
+
+        Ok(match recip {
+            One(User(uid)) => tables.chains.contains_key(&uid),
+            One(Key(key)) => tables.keys.contains_key(&key),
+            Many(Users(uids)) => uids.iter().all(|uid| tables.chains.contains_key(uid)),
+            Many(Keys(keys)) => keys.iter().all(|key| tables.keys.contains_key(key)),
+        })
+    }
+
+    async fn new_prekeys(
+        &mut self,
+        signed: Option<Signed<Prekey>>,
+        keys: Vec<PrekeyReplace>,
+        max_held: u64,
+    ) -> Res<new_prekeys::Res> {
+        let max_held = max_held.min(PREKEY_SLOTS);
+
+        let mut tables = self.tables();
+
+        // changes are staged so that nothing is stored unless every key is accepted
+        let mut staged: HashMap<sig::PublicKey, BTreeMap<u8, Signed<Prekey>>> = HashMap::new();
+
+        if let Some(signed) = signed {
+            if !tables.key_is_valid(signed.signed_by()) {
+                return Ok(new_prekeys::Res::DeadKey(*signed.data()));
+            }
+
+            if let Some(current) = tables.signed_prekeys.get(signed.signed_by()) {
+                if current.timestamp() >= signed.timestamp() {
+                    return Ok(new_prekeys::Res::Redundant(*signed.data()));
+                }
+            }
+        }
+
+        for PrekeyReplace { new, old } in keys {
+            let signer = *new.signed_by();
+
+            if !tables.key_is_valid(&signer) {
+                return Ok(new_prekeys::Res::DeadKey(*new.data()));
+            }
+
+            let slots = staged
+                .entry(signer)
+                .or_insert_with(|| tables.prekeys.get(&signer).cloned().unwrap_or_default());
+
+            // the old prekey may have been handed out in the meantime
+            if let Some(old) = old {
+                if let Some(p) = slots.values_mut().find(|p| *p.data() == old) {
+                    *p = new;
+                    continue;
+                }
+            }
+
+            if slots.len() as u64 >= max_held {
+                return Ok(new_prekeys::Res::NoSlotAvailable(*new.data()));
+            }
+
+            if slots.values().any(|p| p.data() == new.data()) {
+                return Ok(new_prekeys::Res::Redundant(*new.data()));
+            }
+
+            let slot = (0..=255u8)
+                .find(|s| !slots.contains_key(s))
+                .expect("fewer than 256 slots are in use");
+
+            slots.insert(slot, new);
+        }
+
+        if let Some(signed) = signed {
+            tables.signed_prekeys.insert(*signed.signed_by(), signed);
+        }
+
+        tables.prekeys.extend(staged);
+
+        Ok(new_prekeys::Res::Success)
+    }
+
+    async fn get_prekey_bundles(
+        &mut self,
+        keys: Vec<sig::PublicKey>,
+    ) -> Res<Vec<(sig::PublicKey, PrekeyBundle)>> {
+        let mut tables = self.tables();
+        let mut bundles = Vec::with_capacity(keys.len());
+
+        for key in keys {
+            let signed = match tables.signed_prekeys.get(&key) {
+                Some(signed) => *signed,
+                None => continue,
+            };
+
+            let one_time = tables.prekeys.get_mut(&key).and_then(|slots| {
+                let slot = *slots.keys().next()?;
+                slots.remove(&slot)
+            });
+
+            bundles.push((key, PrekeyBundle { signed, one_time }));
+        }
+
+        Ok(bundles)
+    }
+
+    async fn prekey_count(
+        &mut self,
+        key: sig::PublicKey,
+    ) -> Res<u64> {
+        Ok(self
+            .tables()
+            .prekeys
+            .get(&key)
+            .map(|slots| slots.len() as u64)
+            .unwrap_or(0))
+    }
+
+    async fn held_prekeys(
+        &mut self,
+        key: sig::PublicKey,
+    ) -> Res<Vec<Prekey>> {
+        Ok(self
+            .tables()
+            .prekeys
+            .get(&key)
+            .map(|slots| slots.values().map(|p| *p.data()).collect())
+            .unwrap_or_default())
+    }
+
+    async fn add_to_pending_and_get_valid_devs(
+        &mut self,
+        pairs: &[(&Recip, &Push)],
+    ) -> Res<tokio::sync::mpsc::Receiver<(PushedTo, Push)>> {
+        let (mut sender, recv) = tokio::sync::mpsc::channel(pairs.len());
+
+        let mut tables = self.tables();
+
+        for (recip, push) in pairs {
+            let pushed_to = tables.add_pending(recip, push);
+
+            sender
+                .try_send((pushed_to, (*push).clone()))
+                .expect("channel has room for every pair");
+        }
+
+        Ok(recv)
+    }
+
+    async fn get_pending(
+        &mut self,
+        of: sig::PublicKey,
+    ) -> Res<Vec<(Push, i64)>> {
+        let tables = self.tables();
+
+        let mut out: Vec<(Push, i64)> = tables
+            .pending
+            .get(&of)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| Some((tables.pushes.get(id)?.clone(), *id)))
+            .collect();
+
+        out.sort_by_key(|(push, id)| (push.timestamp, *id));
+
+        Ok(out)
+    }
+
+    async fn pending_push(
+        &mut self,
+        push_id: i64,
+    ) -> Res<Option<(Push, Vec<sig::PublicKey>)>> {
+        let tables = self.tables();
+
+        let push = match tables.pushes.get(&push_id) {
+            Some(push) => push.clone(),
+            None => return Ok(None),
+        };
+
+        let devs: Vec<sig::PublicKey> = tables
+            .pending
+            .iter()
+            .filter(|(_, ids)| ids.contains(&push_id))
+            .map(|(key, _)| *key)
+            .collect();
+
+        if devs.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some((push, devs)))
+    }
+
+    async fn announce_pushes(
+        &mut self,
+        node: u64,
+        pushes: &[(i64, Vec<sig::PublicKey>)],
+    ) -> Res<()> {
+        let announcements: Vec<Announcement> = pushes
+            .iter()
+            .flat_map(|(push_id, devs)| Announcement::chunked(node, *push_id, devs))
+            .collect();
+
+        // listeners that were dropped are forgotten
+        self.tables().listeners.retain(|listener| {
+            announcements
+                .iter()
+                .all(|announcement| listener.unbounded_send(announcement.clone()).is_ok())
+        });
+
+        Ok(())
+    }
+
+    async fn del_pending(
+        &mut self,
+        of: sig::PublicKey,
+        items: Vec<i64>,
+    ) -> Res<()> {
+        let mut tables = self.tables();
+
+        if let Some(pending) = tables.pending.get_mut(&of) {
+            pending.retain(|id| !items.contains(id));
+
+            if pending.is_empty() {
+                tables.pending.remove(&of);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn expire_pending(
+        &mut self,
+        before: Time,
+    ) -> Res<u64> {
+        let mut tables = self.tables();
+        let Tables {
+            pushes, pending, ..
+        } = &mut *tables;
+
+        let mut expired = 0;
+
+        for ids in pending.values_mut() {
+            let len = ids.len();
+            ids.retain(|id| {
+                pushes
+                    .get(id)
+                    .map(|p| p.timestamp >= before)
+                    .unwrap_or(true)
+            });
+            expired += (len - ids.len()) as u64;
+        }
+
+        pending.retain(|_, ids| !ids.is_empty());
+
+        Ok(expired)
+    }
+
+    async fn del_dangling_pushes(&mut self) -> Res<u64> {
+        let mut tables = self.tables();
+        let Tables {
+            pushes, pending, ..
+        } = &mut *tables;
+
+        let len = pushes.len();
+        pushes.retain(|id, _| pending.values().any(|ids| ids.contains(id)));
+
+        Ok((len - pushes.len()) as u64)
+    }
+
+    async fn pending_count(&mut self) -> Res<u64> {
+        Ok(self
+            .tables()
+            .pending
+            .values()
+            .map(|ids| ids.len() as u64)
+            .sum())
+    }
+
+    async fn full_queues(
+        &mut self,
+        recip: &Recip,
+        quota: u64,
+    ) -> Res<Vec<sig::PublicKey>> {
+        use Recip::*;
+        use Recips::*;
+        use SingleRecip::*;
+
+        let tables = self.tables();
+
+        let keys = match recip {
+            One(Key(key)) => vec![*key],
+            Many(Keys(keys)) => keys.clone(),
+            One(User(uid)) => tables.valid_user_keys(uid),
+            Many(Users(uids)) => uids
+                .iter()
+                .flat_map(|uid| tables.valid_user_keys(uid))
+                .collect(),
+        };
+
+        Ok(keys
+            .into_iter()
+            .filter(|key| {
+                tables
+                    .pending
+                    .get(key)
+                    .map(|ids| ids.len() as u64 >= quota)
+                    .unwrap_or(false)
+            })
+            .collect())
+    }
+
+    async fn log_failure(
+        &mut self,
+        of: sig::PublicKey,
+        push_id: i64,
+        reason: FailureCode,
+    ) -> Res<()> {
+        let mut tables = self.tables();
+
+        let push = tables.pushes.get(&push_id);
+        let dead_letter = DeadLetter {
+            device: of,
+            push_id,
+            push_ts: push.map(|p| p.timestamp),
+            sender: push.map(|p| p.gid.did),
+            failed_ts: Time::now(),
+            reason,
+        };
+        tables.dead_letters.push(dead_letter);
+
+        if let Some(pending) = tables.pending.get_mut(&of) {
+            pending.retain(|id| *id != push_id);
+
+            if pending.is_empty() {
+                tables.pending.remove(&of);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn dead_letters(
+        &mut self,
+        of: Option<sig::PublicKey>,
+        limit: u64,
+    ) -> Res<Vec<DeadLetter>> {
+        Ok(self
+            .tables()
+            .dead_letters
+            .iter()
+            .rev()
+            .filter(|d| of.map(|of| d.device == of).unwrap_or(true))
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn forget_dead_letters(
+        &mut self,
+        before: Time,
+    ) -> Res<u64> {
+        let mut tables = self.tables();
+        let len = tables.dead_letters.len();
+
+        tables.dead_letters.retain(|d| d.failed_ts >= before);
+
+        Ok((len - tables.dead_letters.len()) as u64)
+    }
+
+    async fn note_push_sig(
+        &mut self,
+        sig: sig::Signature,
+        ts: Time,
+    ) -> Res<bool> {
+        let mut tables = self.tables();
+
+        if tables.seen_pushes.contains_key(&sig) {
+            return Ok(false);
+        }
+
+        tables.seen_pushes.insert(sig, ts);
+
+        Ok(true)
+    }
+
+    async fn push_sig_seen(
+        &mut self,
+        sig: sig::Signature,
+    ) -> Res<bool> {
+        Ok(self.tables().seen_pushes.contains_key(&sig))
+    }
+
+    async fn forget_push_sigs(
+        &mut self,
+        before: Time,
+    ) -> Res<u64> {
+        let mut tables = self.tables();
+        let len = tables.seen_pushes.len();
+
+        tables.seen_pushes.retain(|_, ts| *ts >= before);
+
+        Ok((len - tables.seen_pushes.len()) as u64)
+    }
+}
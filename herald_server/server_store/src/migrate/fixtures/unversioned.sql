@@ -0,0 +1,11 @@
+-- Rows in a database created by `setup-db` before migrations were tracked, to be loaded on top of
+-- the initial schema.
+
+INSERT INTO userkeys(key, user_id)
+VALUES ('\x0101010101010101010101010101010101010101010101010101010101010101', 'a');
+
+INSERT INTO pushes(push_ts, push_data, push_tag, push_user_id, push_key)
+VALUES (0, '\x00', '\x00', 'a', '\x0101010101010101010101010101010101010101010101010101010101010101');
+
+INSERT INTO pending(key, push_id)
+VALUES ('\x0202020202020202020202020202020202020202020202020202020202020202', 1);
@@ -0,0 +1,111 @@
+//! Forward-only schema migrations.
+//!
+//! Migration `n` lives in `migrations/000n_*.sql` and brings the schema from version `n - 1` to
+//! version `n`. Applied versions are recorded in the `schema_version` table. Migrations are never
+//! edited once released; changes to the schema go in a new migration at the end of `MIGRATIONS`.
+
+use super::*;
+use tokio_postgres::Transaction;
+
+const MIGRATIONS: &[&str] = &[
+    include_str!("../../migrations/0001_initial.sql"),
+    include_str!("../../migrations/0002_signed_prekeys.sql"),
+    include_str!("../../migrations/0003_pending_push_ix.sql"),
+    include_str!("../../migrations/0004_seen_pushes.sql"),
+    include_str!("../../migrations/0005_dead_letters.sql"),
+];
+
+/// The schema version this build of the server expects.
+pub const SCHEMA_VERSION: i64 = MIGRATIONS.len() as i64;
+
+/// The latest version recorded in `schema_version`, if any.
+async fn recorded_version(tx: &Transaction<'_>) -> Res<Option<i64>> {
+    let versioned = tx
+        .query_one(sql!("schema_version_exists"), params![])
+        .await?
+        .get::<_, bool>(0);
+
+    if !versioned {
+        return Ok(None);
+    }
+
+    Ok(tx
+        .query_one(sql!("schema_version"), params![])
+        .await?
+        .get(0))
+}
+
+/// Whether the tables exist without any recorded version, which is the case for databases
+/// created before migrations were tracked. Those have the initial schema.
+async fn is_unversioned(tx: &Transaction<'_>) -> Res<bool> {
+    Ok(tx
+        .query_one(sql!("unversioned_schema_exists"), params![])
+        .await?
+        .get::<_, bool>(0))
+}
+
+impl Conn {
+    /// Version of the database schema, 0 if it is empty.
+    pub async fn schema_version(&mut self) -> Res<i64> {
+        let tx = self.transaction().await?;
+
+        let version = match recorded_version(&tx).await? {
+            Some(version) => version,
+            None if is_unversioned(&tx).await? => 1,
+            None => 0,
+        };
+
+        tx.commit().await?;
+
+        Ok(version)
+    }
+
+    /// Applies every migration the database hasn't seen yet, returning the version it started at.
+    ///
+    /// All of them are applied in a single transaction, so a failed migration leaves the schema
+    /// untouched. Servers starting at the same time wait on each other rather than racing.
+    pub async fn migrate(&mut self) -> Res<i64> {
+        let tx = self.transaction().await?;
+
+        tx.batch_execute(sql!("lock_migrations")).await?;
+
+        let unversioned = is_unversioned(&tx).await?;
+        tx.batch_execute(sql!("create_schema_version")).await?;
+
+        let record_stmt = tx
+            .prepare_typed(sql!("record_migration"), types![INT8, INT8])
+            .await?;
+
+        let from = match recorded_version(&tx).await? {
+            Some(version) => version,
+            None if unversioned => {
+                tx.execute(&record_stmt, params![1i64, Time::now().as_i64()])
+                    .await?;
+                1
+            }
+            None => 0,
+        };
+
+        if from > SCHEMA_VERSION {
+            return Err(Error::SchemaTooNew {
+                found: from,
+                expected: SCHEMA_VERSION,
+            });
+        }
+
+        for (version, migration) in (1i64..).zip(MIGRATIONS).skip(from as usize) {
+            tracing::info!("applying schema migration {}", version);
+
+            tx.batch_execute(migration).await?;
+            tx.execute(&record_stmt, params![version, Time::now().as_i64()])
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(from)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,89 @@
+use super::*;
+use serial_test_derive::serial;
+use std::convert::TryInto;
+use womp::*;
+
+/// Migrations only exist for Postgres, so these tests are skipped unless
+/// `HERALD_TEST_POSTGRES` is set.
+async fn empty_db() -> Option<Conn> {
+    std::env::var_os("HERALD_TEST_POSTGRES")?;
+
+    let conn = wa!(Pool::new().get());
+    wa!(conn.batch_execute(sql!("drop_all")));
+
+    Some(conn)
+}
+
+#[tokio::test]
+#[serial]
+async fn migrates_empty_db() {
+    let mut conn = match empty_db().await {
+        Some(conn) => conn,
+        None => return,
+    };
+
+    assert_eq!(wa!(conn.schema_version()), 0);
+    assert_eq!(wa!(conn.migrate()), 0);
+    assert_eq!(wa!(conn.schema_version()), SCHEMA_VERSION);
+
+    // migrating again does nothing
+    assert_eq!(wa!(conn.migrate()), SCHEMA_VERSION);
+    assert_eq!(wa!(conn.schema_version()), SCHEMA_VERSION);
+}
+
+#[tokio::test]
+#[serial]
+async fn migrates_unversioned_db() {
+    let mut conn = match empty_db().await {
+        Some(conn) => conn,
+        None => return,
+    };
+
+    wa!(conn.batch_execute(MIGRATIONS[0]));
+    wa!(conn.batch_execute(include_str!("fixtures/unversioned.sql")));
+
+    assert_eq!(wa!(conn.schema_version()), 1);
+    assert_eq!(wa!(conn.migrate()), 1);
+    assert_eq!(wa!(conn.schema_version()), SCHEMA_VERSION);
+
+    // existing data survives the migration
+    let key = w!(sig::PublicKey::from_slice(&[1; 32]).ok_or("bad key"));
+    assert_eq!(wa!(conn.user_of(key)), Some(w!("a".try_into())));
+
+    let pending: i64 = wa!(conn.query_one("SELECT COUNT(*) FROM pending", params![])).get(0);
+    assert_eq!(pending, 1);
+
+    // and tables added since are there
+    let signed_prekeys: bool = wa!(conn.query_one(
+        "SELECT to_regclass('signed_prekeys') IS NOT NULL",
+        params![]
+    ))
+    .get(0);
+    assert!(signed_prekeys);
+}
+
+#[tokio::test]
+#[serial]
+async fn rejects_newer_schema() {
+    let mut conn = match empty_db().await {
+        Some(conn) => conn,
+        None => return,
+    };
+
+    wa!(conn.migrate());
+    wa!(conn.execute(
+        sql!("record_migration"),
+        params![SCHEMA_VERSION + 1, Time::now().as_i64()]
+    ));
+
+    match conn.migrate().await {
+        Err(Error::SchemaTooNew { found, expected }) => {
+            assert_eq!(found, SCHEMA_VERSION + 1);
+            assert_eq!(expected, SCHEMA_VERSION);
+        }
+        other => panic!("expected SchemaTooNew, got {:?}", other),
+    }
+
+    // leave a usable database behind for the other tests
+    wa!(conn.reset_all());
+}
@@ -1,5 +1,8 @@
 use super::*;
-use tokio_postgres::Transaction as Tx;
+use tokio_postgres::{Statement, Transaction as Tx};
+
+// The sender's own device is never pushed to. If that leaves no devices, nothing is stored and
+// `PushedTo::NoRecipients` is returned, whichever way the recipients were addressed.
 
 pub(crate) async fn one_key(
     tx: &Tx<'_>,
@@ -7,53 +10,9 @@ pub(crate) async fn one_key(
     msg: &Bytes,
     tag: PushTag,
     timestamp: Time,
-    GlobalId { uid, did }: GlobalId,
+    gid: GlobalId,
 ) -> Res<PushedTo> {
-    if *key == did {
-        return Ok(PushedTo::NoRecipients);
-    }
-
-    let exists_stmt = tx
-        .prepare_typed(sql!("device_exists"), types![BYTEA])
-        .await?;
-
-    if !tx
-        .query_one(&exists_stmt, params![key.as_ref()])
-        .await?
-        .get::<_, bool>(0)
-    {
-        return Ok(PushedTo::Missing(SingleRecip::Key(*key)));
-    }
-
-    let push_stmt = tx
-        .prepare_typed(sql!("add_push"), types![BYTEA, BYTEA, INT8, TEXT, BYTEA])
-        .await?;
-
-    let push_row_id: i64 = tx
-        .query_one(
-            &push_stmt,
-            params![
-                msg.as_ref(),
-                kson::to_vec(&tag),
-                timestamp.as_i64(),
-                uid.as_str(),
-                did.as_ref()
-            ],
-        )
-        .await?
-        .get(0);
-
-    let pending_stmt = tx
-        .prepare_typed(sql!("add_pending"), types![BYTEA, INT8])
-        .await?;
-
-    tx.execute(&pending_stmt, params![key.as_ref(), push_row_id])
-        .await?;
-
-    Ok(PushedTo::PushedTo {
-        devs: vec![*key],
-        push_id: push_row_id,
-    })
+    many_keys(tx, std::slice::from_ref(key), msg, tag, timestamp, gid).await
 }
 
 pub(crate) async fn one_user(
@@ -62,161 +21,124 @@ pub(crate) async fn one_user(
     msg: &Bytes,
     tag: PushTag,
     timestamp: Time,
-    GlobalId { uid: from, did }: GlobalId,
+    gid: GlobalId,
 ) -> Res<PushedTo> {
-    let exists_stmt = tx.prepare_typed(sql!("user_exists"), types![TEXT]).await?;
-
-    if !tx
-        .query_one(&exists_stmt, params![uid.as_str()])
-        .await?
-        .get::<_, bool>(0)
-    {
-        return Ok(PushedTo::Missing(SingleRecip::User(*uid)));
-    }
-
-    let push_stmt = tx
-        .prepare_typed(sql!("add_push"), types![BYTEA, BYTEA, INT8, TEXT, BYTEA])
-        .await?;
-
-    let push_row_id: i64 = tx
-        .query_one(
-            &push_stmt,
-            params![
-                msg.as_ref(),
-                kson::to_vec(&tag),
-                timestamp.as_i64(),
-                from.as_str(),
-                did.as_ref()
-            ],
-        )
-        .await?
-        .get(0);
+    many_users(tx, std::slice::from_ref(uid), msg, tag, timestamp, gid).await
+}
 
-    let (keys_stmt, pending_stmt) = try_join!(
+pub(crate) async fn many_users(
+    tx: &Tx<'_>,
+    uids: &[UserId],
+    msg: &Bytes,
+    tag: PushTag,
+    timestamp: Time,
+    gid: GlobalId,
+) -> Res<PushedTo> {
+    let (keys_stmt, exists_stmt) = try_join!(
         tx.prepare_typed(sql!("valid_user_keys"), types![TEXT]),
-        tx.prepare_typed(sql!("add_pending"), types![BYTEA, INT8])
+        tx.prepare_typed(sql!("user_exists"), types![TEXT]),
     )?;
 
-    let pushed_to = Mutex::new(Vec::new());
+    let mut devs = Vec::new();
 
-    tx.query_raw(&keys_stmt, slice_iter(params![uid.as_str()]))
-        .await?
-        .map_err(Error::PgError)
-        .try_for_each_concurrent(10, |row| {
-            let pending_stmt = &pending_stmt;
-            let tx = &tx;
-            let key = row.get::<_, Vec<u8>>(0);
-            let pushed_to = &pushed_to;
-
-            async move {
-                let key = sig::PublicKey::from_slice(&key).ok_or(Error::InvalidKey)?;
-
-                if key == did {
-                    return Ok(());
-                }
+    // TODO: process concurrently?
+    for uid in uids {
+        if !tx
+            .query_one(&exists_stmt, params![uid.as_str()])
+            .await?
+            .get::<_, bool>(0)
+        {
+            return Ok(PushedTo::Missing(SingleRecip::User(*uid)));
+        }
 
-                tx.execute(pending_stmt, params![key.as_ref(), push_row_id])
-                    .await?;
-                pushed_to.lock().await.push(key);
-                Ok(())
-            }
-        })
-        .await?;
+        devs.extend(user_devs(tx, &keys_stmt, uid, gid.did).await?);
+    }
 
-    Ok(PushedTo::PushedTo {
-        devs: pushed_to.into_inner(),
-        push_id: push_row_id,
-    })
+    add_push(tx, devs, msg, tag, timestamp, gid).await
 }
 
-pub(crate) async fn many_users(
+pub(crate) async fn many_keys(
     tx: &Tx<'_>,
-    uids: &[UserId],
+    keys: &[sig::PublicKey],
     msg: &Bytes,
     tag: PushTag,
     timestamp: Time,
-    GlobalId { uid, did }: GlobalId,
+    gid: GlobalId,
 ) -> Res<PushedTo> {
-    let push_stmt = tx
-        .prepare_typed(sql!("add_push"), types![BYTEA, BYTEA, INT8, TEXT, BYTEA])
+    let exists_stmt = tx
+        .prepare_typed(sql!("device_exists"), types![BYTEA])
         .await?;
 
-    let push_row_id: i64 = tx
-        .query_one(
-            &push_stmt,
-            params![
-                msg.as_ref(),
-                kson::to_vec(&tag),
-                timestamp.as_i64(),
-                uid.as_str(),
-                did.as_ref()
-            ],
-        )
-        .await?
-        .get(0);
-
-    let pushed_to = Mutex::new(Vec::new());
-
-    let (keys_stmt, pending_stmt, exists_stmt) = try_join!(
-        tx.prepare_typed(sql!("valid_user_keys"), types![TEXT]),
-        tx.prepare_typed(sql!("add_pending"), types![BYTEA, INT8]),
-        tx.prepare_typed(sql!("user_exists"), types![TEXT]),
-    )?;
+    let mut devs = Vec::with_capacity(keys.len());
 
     // TODO: process concurrently?
-    for uid in uids {
-        let pending_stmt = &pending_stmt;
-        let exists_stmt = &exists_stmt;
-        let pushed_to = &pushed_to;
-        let tx = &tx;
+    for key in keys {
+        if *key == gid.did {
+            continue;
+        }
 
         if !tx
-            .query_one(exists_stmt, params![uid.as_str()])
+            .query_one(&exists_stmt, params![key.as_ref()])
             .await?
             .get::<_, bool>(0)
         {
-            return Ok(PushedTo::Missing(SingleRecip::User(*uid)));
+            return Ok(PushedTo::Missing(SingleRecip::Key(*key)));
         }
 
-        tx.query_raw(&keys_stmt, slice_iter(params![uid.as_str()]))
-            .await?
-            .map_err(Error::PgError)
-            .try_for_each_concurrent(10, |row| {
-                let key = row.get::<_, Vec<u8>>(0);
+        devs.push(*key);
+    }
 
-                async move {
-                    let key = sig::PublicKey::from_slice(&key).ok_or(Error::InvalidKey)?;
-                    if key == did {
-                        return Ok(());
-                    }
-                    tx.execute(pending_stmt, params![key.as_ref(), push_row_id])
-                        .await?;
+    add_push(tx, devs, msg, tag, timestamp, gid).await
+}
 
-                    pushed_to.lock().await.push(key);
+/// The valid devices of `uid`, other than `did`.
+async fn user_devs(
+    tx: &Tx<'_>,
+    keys_stmt: &Statement,
+    uid: &UserId,
+    did: sig::PublicKey,
+) -> Res<Vec<sig::PublicKey>> {
+    let devs = Mutex::new(Vec::new());
 
-                    Ok(())
+    tx.query_raw(keys_stmt, slice_iter(params![uid.as_str()]))
+        .await?
+        .map_err(Error::PgError)
+        .try_for_each(|row| {
+            let devs = &devs;
+            let key = row.get::<_, Vec<u8>>(0);
+
+            async move {
+                let key = sig::PublicKey::from_slice(&key).ok_or(Error::InvalidKey)?;
+
+                if key != did {
+                    devs.lock().await.push(key);
                 }
-            })
-            .await?;
-    }
 
-    Ok(PushedTo::PushedTo {
-        devs: pushed_to.into_inner(),
-        push_id: push_row_id,
-    })
+                Ok(())
+            }
+        })
+        .await?;
+
+    Ok(devs.into_inner())
 }
 
-pub(crate) async fn many_keys(
+/// Stores the push and makes it pending for `devs`.
+async fn add_push(
     tx: &Tx<'_>,
-    keys: &[sig::PublicKey],
+    devs: Vec<sig::PublicKey>,
     msg: &Bytes,
     tag: PushTag,
     timestamp: Time,
     GlobalId { uid, did }: GlobalId,
 ) -> Res<PushedTo> {
-    let push_stmt = tx
-        .prepare_typed(sql!("add_push"), types![BYTEA, BYTEA, INT8, TEXT, BYTEA])
-        .await?;
+    if devs.is_empty() {
+        return Ok(PushedTo::NoRecipients);
+    }
+
+    let (push_stmt, pending_stmt) = try_join!(
+        tx.prepare_typed(sql!("add_push"), types![BYTEA, BYTEA, INT8, TEXT, BYTEA]),
+        tx.prepare_typed(sql!("add_pending"), types![BYTEA, INT8])
+    )?;
 
     let push_row_id: i64 = tx
         .query_one(
@@ -232,35 +154,13 @@ pub(crate) async fn many_keys(
         .await?
         .get(0);
 
-    let (pending_stmt, exists_stmt) = try_join!(
-        tx.prepare_typed(sql!("add_pending"), types![BYTEA, INT8]),
-        tx.prepare_typed(sql!("device_exists"), types![BYTEA])
-    )?;
-
-    let mut pushed_to = Vec::new();
-
-    // TODO: process concurrently?
-    for key in keys {
-        if *key == did {
-            continue;
-        }
-
-        if !tx
-            .query_one(&exists_stmt, params![key.as_ref()])
-            .await?
-            .get::<_, bool>(0)
-        {
-            return Ok(PushedTo::Missing(SingleRecip::Key(*key)));
-        }
-
+    for key in devs.iter() {
         tx.execute(&pending_stmt, params![key.as_ref(), push_row_id])
             .await?;
-
-        pushed_to.push(*key);
     }
 
     Ok(PushedTo::PushedTo {
-        devs: pushed_to,
+        devs,
         push_id: push_row_id,
     })
 }
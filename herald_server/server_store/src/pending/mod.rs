@@ -107,29 +107,44 @@ impl Conn {
         let mut out = Vec::with_capacity(rows.len());
 
         for row in rows {
-            let push_data: &[u8] = row.get("push_data");
-            let push_ts: i64 = row.get("push_ts");
-            let push_tag: &[u8] = row.get("push_tag");
-            let push_id: i64 = row.get("push_id");
-            let push_user_id: &str = row.get("push_user_id");
-            let push_key: &[u8] = row.get("push_key");
-
-            let push = Push {
-                tag: kson::from_slice(push_tag)?,
-                msg: Bytes::copy_from_slice(push_data),
-                timestamp: Time::from(push_ts),
-                gid: GlobalId {
-                    uid: UserId::try_from(push_user_id)?,
-                    did: sig::PublicKey::from_slice(push_key).ok_or(Error::InvalidKey)?,
-                },
-            };
-
-            out.push((push, push_id));
+            out.push((push_of_row(&row)?, row.get("push_id")));
         }
 
         Ok(out)
     }
 
+    /// The push `push_id` along with the devices still waiting for it, if any are.
+    pub async fn pending_push(
+        &mut self,
+        push_id: i64,
+    ) -> Res<Option<(Push, Vec<sig::PublicKey>)>> {
+        let push_stmt = self.prepare_typed(sql!("get_push"), types![INT8]).await?;
+
+        let rows = self.query(&push_stmt, params![push_id]).await?;
+
+        let push = match rows.first() {
+            Some(row) => push_of_row(row)?,
+            None => return Ok(None),
+        };
+
+        let recips_stmt = self
+            .prepare_typed(sql!("push_recipients"), types![INT8])
+            .await?;
+
+        let devs = self
+            .query(&recips_stmt, params![push_id])
+            .await?
+            .into_iter()
+            .map(|row| sig::PublicKey::from_slice(row.get::<_, &[u8]>(0)).ok_or(Error::InvalidKey))
+            .collect::<Res<Vec<_>>>()?;
+
+        if devs.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some((push, devs)))
+    }
+
     pub async fn del_pending<S: Stream<Item = i64> + Send>(
         &mut self,
         of: sig::PublicKey,
@@ -153,10 +168,104 @@ impl Conn {
             })
             .await?;
 
-        self.execute(sql!("del_dangling_pushes"), params![]).await?;
-
         Ok(())
     }
+
+    /// Removes pending pushes sent before `before`, returning how many deliveries were dropped.
+    pub async fn expire_pending(
+        &mut self,
+        before: Time,
+    ) -> Res<u64> {
+        let stmt = self
+            .prepare_typed(sql!("expire_old_pending"), types![INT8])
+            .await?;
+
+        Ok(self.execute(&stmt, params![before.as_i64()]).await?)
+    }
+
+    /// Removes pushes that are no longer pending for any device, returning how many were removed.
+    pub async fn del_dangling_pushes(&mut self) -> Res<u64> {
+        Ok(self.execute(sql!("del_dangling_pushes"), params![]).await?)
+    }
+
+    /// The number of deliveries waiting, across all devices.
+    pub async fn pending_count(&mut self) -> Res<u64> {
+        let count = self
+            .query_one(sql!("pending_count"), params![])
+            .await?
+            .get::<_, i64>(0);
+
+        Ok(count as u64)
+    }
+
+    /// The devices `recip` resolves to that have `quota` or more pushes waiting for them.
+    pub async fn full_queues(
+        &mut self,
+        recip: &Recip,
+        quota: u64,
+    ) -> Res<Vec<sig::PublicKey>> {
+        use Recip::*;
+        use Recips::*;
+        use SingleRecip::*;
+
+        let keys = match recip {
+            One(Key(key)) => vec![*key],
+            Many(Keys(keys)) => keys.clone(),
+            One(User(uid)) => self.valid_keys_of(std::slice::from_ref(uid)).await?,
+            Many(Users(uids)) => self.valid_keys_of(uids).await?,
+        };
+
+        let stmt = self
+            .prepare_typed(sql!("full_queues"), types![BYTEA_ARRAY, INT8])
+            .await?;
+
+        let keys: Vec<&[u8]> = keys.iter().map(AsRef::as_ref).collect();
+
+        self.query(&stmt, params![keys, quota as i64])
+            .await?
+            .into_iter()
+            .map(|row| sig::PublicKey::from_slice(row.get::<_, &[u8]>(0)).ok_or(Error::InvalidKey))
+            .collect()
+    }
+
+    async fn valid_keys_of(
+        &mut self,
+        uids: &[UserId],
+    ) -> Res<Vec<sig::PublicKey>> {
+        let stmt = self
+            .prepare_typed(sql!("valid_user_keys"), types![TEXT])
+            .await?;
+
+        let mut keys = Vec::new();
+
+        for uid in uids {
+            for row in self.query(&stmt, params![uid.as_str()]).await? {
+                keys.push(
+                    sig::PublicKey::from_slice(row.get::<_, &[u8]>(0)).ok_or(Error::InvalidKey)?,
+                );
+            }
+        }
+
+        Ok(keys)
+    }
+}
+
+fn push_of_row(row: &tokio_postgres::Row) -> Res<Push> {
+    let push_data: &[u8] = row.get("push_data");
+    let push_ts: i64 = row.get("push_ts");
+    let push_tag: &[u8] = row.get("push_tag");
+    let push_user_id: &str = row.get("push_user_id");
+    let push_key: &[u8] = row.get("push_key");
+
+    Ok(Push {
+        tag: kson::from_slice(push_tag)?,
+        msg: Bytes::copy_from_slice(push_data),
+        timestamp: Time::from(push_ts),
+        gid: GlobalId {
+            uid: UserId::try_from(push_user_id)?,
+            did: sig::PublicKey::from_slice(push_key).ok_or(Error::InvalidKey)?,
+        },
+    })
 }
 
 #[cfg(test)]
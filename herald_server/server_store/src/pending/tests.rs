@@ -1,6 +1,5 @@
 use super::*;
 use crate::tests::get_client;
-use futures::stream::iter;
 use protocol::auth::RegisterResponse;
 use serial_test_derive::serial;
 use sig::sign_ser as sign;
@@ -48,7 +47,7 @@ fn same_devs(
 }
 
 async fn check_pending(
-    client: &mut Conn,
+    client: &mut dyn Store,
     push: &Push,
     devs: Vec<sig::PublicKey>,
 ) {
@@ -64,7 +63,7 @@ async fn check_pending(
             })
             .collect::<Vec<_>>();
 
-        client.del_pending(k, iter(pending)).await.unwrap();
+        client.del_pending(k, pending).await.unwrap();
 
         let pending = client.get_pending(k).await.unwrap();
         assert!(pending.is_empty());
@@ -278,3 +277,201 @@ async fn many_users() {
 
     check_pending(&mut client, &push, devs).await;
 }
+
+/// Registers `a` with a second device, returning a recipient for that device.
+async fn second_device(
+    client: &mut dyn Store,
+    a_uid: UserId,
+    a_kp: &sig::KeyPair,
+) -> (Recip, sig::PublicKey) {
+    let a_kp2 = sig::KeyPair::gen_new();
+    let a_endorse = sig::SigUpdate::Endorse(sign(&a_kp2, a_uid));
+
+    client.new_user(sign(a_kp, a_uid)).await.expect(womp!());
+    client
+        .add_to_sigchain(sign(a_kp, a_endorse))
+        .await
+        .expect(womp!());
+
+    (
+        Recip::One(SingleRecip::Key(*a_kp2.public())),
+        *a_kp2.public(),
+    )
+}
+
+async fn add(
+    client: &mut dyn Store,
+    recip: &Recip,
+    push: &Push,
+) {
+    client
+        .add_to_pending_and_get_valid_devs(&[(recip, push)])
+        .await
+        .expect(womp!())
+        .recv()
+        .await
+        .expect(womp!());
+}
+
+#[tokio::test]
+#[serial]
+async fn expire_and_sweep() {
+    let mut client = get_client().await.unwrap();
+
+    let (push, a_uid, a_kp) = setup();
+    let (recip, to) = second_device(&mut *client, a_uid, &a_kp).await;
+
+    let old = Push {
+        timestamp: Time::from(0),
+        ..push.clone()
+    };
+
+    add(&mut *client, &recip, &old).await;
+    add(&mut *client, &recip, &push).await;
+    assert_eq!(client.pending_count().await.expect(womp!()), 2);
+
+    assert_eq!(
+        client.expire_pending(Time::from(1)).await.expect(womp!()),
+        1
+    );
+    assert_eq!(client.pending_count().await.expect(womp!()), 1);
+
+    let pending = client.get_pending(to).await.expect(womp!());
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].0, push);
+
+    // the expired push is no longer referenced
+    assert_eq!(client.del_dangling_pushes().await.expect(womp!()), 1);
+
+    client
+        .del_pending(to, vec![pending[0].1])
+        .await
+        .expect(womp!());
+
+    assert_eq!(client.del_dangling_pushes().await.expect(womp!()), 1);
+    assert_eq!(client.del_dangling_pushes().await.expect(womp!()), 0);
+}
+
+#[tokio::test]
+#[serial]
+async fn pending_push() {
+    let mut client = get_client().await.unwrap();
+
+    let (push, a_uid, a_kp) = setup();
+    let (recip, to) = second_device(&mut *client, a_uid, &a_kp).await;
+
+    add(&mut *client, &recip, &push).await;
+
+    let push_id = client.get_pending(to).await.expect(womp!())[0].1;
+
+    assert_eq!(
+        client.pending_push(push_id).await.expect(womp!()),
+        Some((push, vec![to]))
+    );
+
+    client.del_pending(to, vec![push_id]).await.expect(womp!());
+
+    // nobody is waiting on it anymore
+    assert_eq!(client.pending_push(push_id).await.expect(womp!()), None);
+    assert_eq!(client.pending_push(push_id + 1).await.expect(womp!()), None);
+}
+
+#[tokio::test]
+#[serial]
+async fn full_queues() {
+    let mut client = get_client().await.unwrap();
+
+    let (push, a_uid, a_kp) = setup();
+    let (recip, to) = second_device(&mut *client, a_uid, &a_kp).await;
+    let user = Recip::One(SingleRecip::User(a_uid));
+
+    assert!(client
+        .full_queues(&recip, 2)
+        .await
+        .expect(womp!())
+        .is_empty());
+
+    add(&mut *client, &recip, &push).await;
+
+    assert!(client
+        .full_queues(&recip, 2)
+        .await
+        .expect(womp!())
+        .is_empty());
+
+    add(&mut *client, &recip, &push).await;
+
+    assert_eq!(
+        client.full_queues(&recip, 2).await.expect(womp!()),
+        vec![to]
+    );
+    assert_eq!(client.full_queues(&user, 2).await.expect(womp!()), vec![to]);
+    assert!(client
+        .full_queues(&user, 3)
+        .await
+        .expect(womp!())
+        .is_empty());
+}
+
+async fn pushed_to(
+    client: &mut dyn Store,
+    recip: &Recip,
+    push: &Push,
+) -> PushedTo {
+    client
+        .add_to_pending_and_get_valid_devs(&[(recip, push)])
+        .await
+        .expect(womp!())
+        .recv()
+        .await
+        .expect(womp!())
+        .0
+}
+
+#[tokio::test]
+#[serial]
+async fn sender_device_skipped() {
+    let mut client = get_client().await.unwrap();
+
+    let (push, a_uid, a_kp) = setup();
+    let a_key = *a_kp.public();
+    client.new_user(sign(&a_kp, a_uid)).await.expect(womp!());
+
+    let b_uid: UserId = "b".try_into().expect(womp!());
+    let b_kp = sig::KeyPair::gen_new();
+    client.new_user(sign(&b_kp, b_uid)).await.expect(womp!());
+
+    // addressed only to the sender's own device, however that's spelled
+    for recip in vec![
+        Recip::One(SingleRecip::Key(a_key)),
+        Recip::One(SingleRecip::User(a_uid)),
+        Recip::Many(Recips::Keys(vec![a_key])),
+        Recip::Many(Recips::Users(vec![a_uid])),
+    ] {
+        assert_eq!(
+            pushed_to(&mut *client, &recip, &push).await,
+            PushedTo::NoRecipients
+        );
+    }
+
+    // otherwise the sender's device is left out
+    for recip in vec![
+        Recip::Many(Recips::Keys(vec![a_key, *b_kp.public()])),
+        Recip::Many(Recips::Users(vec![a_uid, b_uid])),
+    ] {
+        match pushed_to(&mut *client, &recip, &push).await {
+            PushedTo::PushedTo { devs, .. } => assert_eq!(devs, vec![*b_kp.public()]),
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    assert!(client.get_pending(a_key).await.expect(womp!()).is_empty());
+    assert_eq!(
+        client
+            .get_pending(*b_kp.public())
+            .await
+            .expect(womp!())
+            .len(),
+        2
+    );
+}
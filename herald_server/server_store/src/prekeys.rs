@@ -1,10 +1,7 @@
 use super::*;
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub struct TaggedPrekey {
-    pub key: sig::PublicKey,
-    pub prekey: Signed<Prekey>,
-}
+/// One-time prekeys are stored in this many slots per device, so more than this can never be stored.
+pub const PREKEY_SLOTS: u64 = 256;
 
 #[derive(Clone, Copy, Debug)]
 pub struct PrekeyReplace {
@@ -13,170 +10,246 @@ pub struct PrekeyReplace {
 }
 
 impl Conn {
-    pub async fn new_prekeys<Keys: Stream<Item = PrekeyReplace> + Send>(
+    /// Stores a device's signed prekey and one-time prekeys, all at once or not at all.
+    ///
+    /// Each signer may hold at most `max_held` one-time prekeys. Anything other than
+    /// [`new_prekeys::Res::Success`] means nothing was stored.
+    pub async fn new_prekeys(
         &mut self,
-        keys: Keys,
+        signed: Option<Signed<Prekey>>,
+        keys: Vec<PrekeyReplace>,
+        max_held: u64,
     ) -> Result<new_prekeys::Res, Error> {
-        let (insert_stmt, update_stmt, slot_stmt, is_valid_stmt) = try_join!(
-            self.prepare_typed(sql!("add_prekey"), types![BYTEA, BYTEA, BYTEA, INT8, INT2]),
-            self.prepare_typed(
+        let max_held = max_held.min(PREKEY_SLOTS);
+
+        let tx = self.transaction().await?;
+
+        let (lock_stmt, insert_stmt, update_stmt, slot_stmt, is_valid_stmt, set_signed_stmt) = try_join!(
+            tx.prepare_typed(sql!("lock_prekey_signers"), types![BYTEA_ARRAY]),
+            tx.prepare_typed(sql!("add_prekey"), types![BYTEA, BYTEA, BYTEA, INT8, INT2]),
+            tx.prepare_typed(
                 sql!("replace_prekey"),
                 types![BYTEA, BYTEA, BYTEA, INT8, BYTEA]
             ),
-            self.prepare_typed(sql!("prekey_slot"), types![BYTEA]),
-            self.prepare_typed(sql!("key_is_valid"), types![BYTEA])
+            tx.prepare_typed(sql!("prekey_slot"), types![BYTEA]),
+            tx.prepare_typed(sql!("key_is_valid"), types![BYTEA]),
+            tx.prepare_typed(sql!("set_signed_prekey"), types![BYTEA, BYTEA, BYTEA, INT8])
         )?;
 
-        // in both cases, check that the signing key is valid
+        // each device has its own quota, so the keys are grouped per signer
+        let mut by_signer: Vec<(sig::PublicKey, Vec<PrekeyReplace>)> = Vec::new();
 
-        // if old is some, replace it
+        for key in keys {
+            let signer = *key.new.signed_by();
 
-        // if old is none, pick the smallest free slot or fail if one cannot be found
-        let res: Result<(), Res<new_prekeys::Res>> = keys
-            .map(Ok::<_, Res<new_prekeys::Res>>)
-            .try_for_each_concurrent(10, |PrekeyReplace { new, old }| {
-                let conn = &self;
-                let (new, meta) = new.split();
+            match by_signer.iter_mut().find(|(s, _)| *s == signer) {
+                Some((_, keys)) => keys.push(key),
+                None => by_signer.push((signer, vec![key])),
+            }
+        }
+
+        // concurrent uploads from the same device wait for each other here, so the quota can't
+        // be exceeded between counting the held prekeys and adding new ones
+        let signers: Vec<&[u8]> = signed
+            .iter()
+            .map(|s| s.signed_by().as_ref())
+            .chain(by_signer.iter().map(|(s, _)| s.as_ref()))
+            .collect();
+
+        tx.query(&lock_stmt, params![signers]).await?;
+
+        if let Some(signed) = signed {
+            let (Prekey(new), meta) = signed.split();
+
+            if !tx
+                .query_one(&is_valid_stmt, params![meta.signed_by().as_ref()])
+                .await?
+                .get::<_, bool>(0)
+            {
+                return Ok(new_prekeys::Res::DeadKey(Prekey(new)));
+            }
+
+            let num_updated = tx
+                .execute(
+                    &set_signed_stmt,
+                    params![
+                        new.as_ref(),
+                        meta.signed_by().as_ref(),
+                        meta.sig().as_ref(),
+                        meta.timestamp().as_i64()
+                    ],
+                )
+                .await?;
+
+            // the current signed prekey is at least as new
+            if num_updated != 1 {
+                return Ok(new_prekeys::Res::Redundant(Prekey(new)));
+            }
+        }
+
+        for (signer, keys) in by_signer {
+            if !tx
+                .query_one(&is_valid_stmt, params![signer.as_ref()])
+                .await?
+                .get::<_, bool>(0)
+            {
+                return Ok(new_prekeys::Res::DeadKey(*keys[0].new.data()));
+            }
+
+            let mut slots: Vec<u8> = tx
+                .query(&slot_stmt, params![signer.as_ref()])
+                .await?
+                .into_iter()
+                .map(|row| row.get::<_, i16>(0) as u8)
+                .collect();
 
-                let insert_stmt = &insert_stmt;
-                let update_stmt = &update_stmt;
-                let slot_stmt = &slot_stmt;
-                let is_valid_stmt = &is_valid_stmt;
+            for PrekeyReplace { new, old } in keys {
+                let (Prekey(new), meta) = new.split();
 
-                let signed_by_bytes = meta.signed_by().as_ref().to_vec();
-                let sig_bytes = meta.sig().as_ref().to_vec();
+                let signed_by_bytes = meta.signed_by().as_ref();
+                let sig_bytes = meta.sig().as_ref();
                 let ts = *meta.timestamp().as_i64();
 
-                async move {
-                    if !conn
-                        .query_one(is_valid_stmt, params![meta.signed_by().as_ref()])
-                        .await
-                        .map_err(Error::from)
-                        .map_err(Err)?
-                        .get::<_, bool>(0)
-                    {
-                        return Err(Ok(new_prekeys::Res::DeadKey(new)));
+                if let Some(Prekey(old)) = old {
+                    let num_updated = tx
+                        .execute(
+                            &update_stmt,
+                            params![new.as_ref(), signed_by_bytes, sig_bytes, ts, old.as_ref()],
+                        )
+                        .await?;
+
+                    // the old prekey may have been handed out in the meantime
+                    if num_updated == 1 {
+                        continue;
                     }
+                }
 
-                    let Prekey(new) = new;
-
-                    match old {
-                        Some(Prekey(old)) => {
-                            conn.execute(
-                                update_stmt,
-                                params![new.as_ref(), signed_by_bytes, sig_bytes, ts, old.as_ref()],
-                            )
-                            .await
-                            .map_err(Error::from)
-                            .map_err(Err)?;
-
-                            Ok(())
-                        }
-                        None => {
-                            let slots = conn
-                                .query(slot_stmt, params![signed_by_bytes])
-                                .await
-                                .map_err(Error::from)
-                                .map_err(Err)?
-                                .into_iter()
-                                .map(|row| row.get::<_, i16>(0) as u8);
-
-                            let slot = if slots.len() == 256 {
-                                return Err(Ok(new_prekeys::Res::NoSlotAvailable(Prekey(new))));
-                            } else {
-                                let mut prev = 0u8;
-
-                                for (ix, s) in slots.enumerate() {
-                                    if (ix as u8) != s {
-                                        break;
-                                    } else {
-                                        prev += 1;
-                                    }
-                                }
-
-                                prev as i16
-                            };
-
-                            let num_updated = conn
-                                .execute(
-                                    insert_stmt,
-                                    params![new.as_ref(), signed_by_bytes, sig_bytes, ts, slot],
-                                )
-                                .await
-                                .map_err(Error::from)
-                                .map_err(Err)?;
-
-                            if num_updated != 1 {
-                                return Err(Ok(new_prekeys::Res::Redundant(Prekey(new))));
-                            }
-
-                            Ok(())
-                        }
-                    }
+                if slots.len() as u64 >= max_held {
+                    return Ok(new_prekeys::Res::NoSlotAvailable(Prekey(new)));
                 }
-            })
-            .await;
 
-        match res {
-            Err(v @ Ok(_)) => return v,
-            Err(Err(e)) => return Err(e),
-            _ => {}
-        };
+                let slot = (0..=255u8)
+                    .find(|s| !slots.contains(s))
+                    .expect("fewer than 256 slots are in use");
+
+                let num_updated = tx
+                    .execute(
+                        &insert_stmt,
+                        params![new.as_ref(), signed_by_bytes, sig_bytes, ts, slot as i16],
+                    )
+                    .await?;
+
+                if num_updated != 1 {
+                    return Ok(new_prekeys::Res::Redundant(Prekey(new)));
+                }
+
+                slots.push(slot);
+            }
+        }
+
+        tx.commit().await?;
 
         Ok(new_prekeys::Res::Success)
     }
 
-    pub async fn get_random_prekeys<Keys: Stream<Item = sig::PublicKey> + Send>(
+    /// Fetches a prekey bundle for each of `keys`, removing the one-time prekeys that are handed
+    /// out.
+    ///
+    /// Keys without a signed prekey are skipped. When a key has run out of one-time prekeys, its
+    /// bundle only contains the signed prekey.
+    pub async fn get_prekey_bundles<Keys: Stream<Item = sig::PublicKey> + Send>(
         &mut self,
         keys: Keys,
-    ) -> Res<Vec<TaggedPrekey>> {
-        let prekeys = Mutex::new(Vec::new());
+    ) -> Res<Vec<(sig::PublicKey, PrekeyBundle)>> {
+        let bundles = Mutex::new(Vec::new());
 
-        let stmt = self
-            .prepare_typed(sql!("get_random_prekeys"), types![BYTEA])
-            .await?;
+        let (signed_stmt, pop_stmt) = try_join!(
+            self.prepare_typed(sql!("get_signed_prekey"), types![BYTEA]),
+            self.prepare_typed(sql!("pop_prekey"), types![BYTEA])
+        )?;
 
         keys.map(Ok::<_, Error>)
             .try_for_each_concurrent(10, |k| {
                 let conn = &self;
-                let stmt = &stmt;
-                let prekeys = &prekeys;
+                let signed_stmt = &signed_stmt;
+                let pop_stmt = &pop_stmt;
+                let bundles = &bundles;
 
                 async move {
-                    let row = conn.query_one(stmt, params![k.as_ref()]).await?;
-
-                    let prekey = Prekey::from_slice(row.get("key")).ok_or(Error::InvalidKey)?;
+                    let signed = match conn.query(signed_stmt, params![k.as_ref()]).await?.pop() {
+                        Some(row) => signed_prekey_of_row(&row)?,
+                        None => return Ok(()),
+                    };
+
+                    let one_time = match conn.query(pop_stmt, params![k.as_ref()]).await?.pop() {
+                        Some(row) => Some(signed_prekey_of_row(&row)?),
+                        None => None,
+                    };
+
+                    bundles
+                        .lock()
+                        .await
+                        .push((k, PrekeyBundle { signed, one_time }));
 
-                    let sig = sig::Signature::from_slice(row.get("signature"))
-                        .ok_or(Error::InvalidSig)?;
+                    Ok(())
+                }
+            })
+            .await?;
 
-                    let signed_by = sig::PublicKey::from_slice(row.get("signed_by"))
-                        .ok_or(Error::InvalidKey)?;
+        Ok(bundles.into_inner())
+    }
 
-                    let timestamp = Time::from(row.get::<_, i64>("ts"));
+    pub async fn prekey_count(
+        &mut self,
+        key: sig::PublicKey,
+    ) -> Res<u64> {
+        let stmt = self
+            .prepare_typed(sql!("prekey_count"), types![BYTEA])
+            .await?;
 
-                    let meta = SigMeta::new(sig, signed_by, timestamp);
+        let count = self
+            .query_one(&stmt, params![key.as_ref()])
+            .await?
+            .get::<_, i64>(0);
 
-                    prekeys.lock().await.push(TaggedPrekey {
-                        key: k,
-                        prekey: Signed::from((prekey, meta)),
-                    });
+        Ok(count as u64)
+    }
 
-                    Ok(())
-                }
-            })
+    pub async fn held_prekeys(
+        &mut self,
+        key: sig::PublicKey,
+    ) -> Res<Vec<Prekey>> {
+        let stmt = self
+            .prepare_typed(sql!("held_prekeys"), types![BYTEA])
             .await?;
 
-        Ok(prekeys.into_inner())
+        self.query(&stmt, params![key.as_ref()])
+            .await?
+            .into_iter()
+            .map(|row| Prekey::from_slice(row.get("key")).ok_or(Error::InvalidKey))
+            .collect()
     }
 }
 
+fn signed_prekey_of_row(row: &tokio_postgres::Row) -> Res<Signed<Prekey>> {
+    let prekey = Prekey::from_slice(row.get("key")).ok_or(Error::InvalidKey)?;
+
+    let sig = sig::Signature::from_slice(row.get("signature")).ok_or(Error::InvalidSig)?;
+
+    let signed_by = sig::PublicKey::from_slice(row.get("signed_by")).ok_or(Error::InvalidKey)?;
+
+    let timestamp = Time::from(row.get::<_, i64>("ts"));
+
+    let meta = SigMeta::new(sig, signed_by, timestamp);
+
+    Ok(Signed::from((prekey, meta)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::tests::get_client;
     use crate::{w, wa};
-    use futures::stream::iter;
     use serial_test_derive::serial;
     use sig::sign_ser as sign;
     use std::convert::TryInto;
@@ -201,33 +274,66 @@ mod tests {
             new: signed_pre,
         };
 
-        match wa!(client.new_prekeys(iter(vec![replace]))) {
+        match wa!(client.new_prekeys(None, vec![replace], PREKEY_SLOTS)) {
             new_prekeys::Res::DeadKey(_) => {}
             _ => panic!(),
         };
 
         wa!(client.new_user(init));
 
-        match wa!(client.new_prekeys(iter(vec![replace]))) {
+        match wa!(client.new_prekeys(None, vec![replace], PREKEY_SLOTS)) {
             new_prekeys::Res::Success => {}
             _ => panic!(),
         };
 
-        let tagged = wa!(client.get_random_prekeys(iter(vec![*kp.public()])));
-        assert_eq!(tagged.len(), 1);
-        assert_eq!(
-            tagged[0],
-            TaggedPrekey {
-                key: *kp.public(),
-                prekey: signed_pre
-            }
-        );
+        assert_eq!(wa!(client.prekey_count(*kp.public())), 1);
+        assert_eq!(wa!(client.held_prekeys(*kp.public())), vec![pre]);
+
+        // no signed prekey yet, so no bundle
+        let bundles = wa!(client.get_prekey_bundles(vec![*kp.public()]));
+        assert!(bundles.is_empty());
+        assert_eq!(wa!(client.prekey_count(*kp.public())), 1);
+
+        let signed_kp = sig::KeyPair::gen_new();
+        let signed = sign(&kp, w!(Prekey::from_slice(signed_kp.public().as_ref())));
+
+        match wa!(client.new_prekeys(Some(signed), vec![], PREKEY_SLOTS)) {
+            new_prekeys::Res::Success => {}
+            _ => panic!(),
+        };
+
+        // a signed prekey that isn't newer than the current one is rejected, along with the
+        // one-time prekeys it was sent with
+        let pre_unused = w!(Prekey::from_slice(
+            sig::KeyPair::gen_new().public().as_ref()
+        ));
+
+        let unused = PrekeyReplace {
+            old: None,
+            new: sign(&kp, pre_unused),
+        };
+
+        match wa!(client.new_prekeys(Some(signed), vec![unused], PREKEY_SLOTS)) {
+            new_prekeys::Res::Redundant(_) => {}
+            _ => panic!(),
+        };
 
-        match wa!(client.new_prekeys(iter(vec![replace]))) {
+        assert_eq!(wa!(client.held_prekeys(*kp.public())), vec![pre]);
+
+        // a one-time prekey that is already stored is rejected, and nothing else is stored
+        match wa!(client.new_prekeys(None, vec![unused, replace], PREKEY_SLOTS)) {
             new_prekeys::Res::Redundant(_) => {}
             _ => panic!(),
         };
 
+        assert_eq!(wa!(client.held_prekeys(*kp.public())), vec![pre]);
+
+        // the quota counts prekeys that are already held
+        match wa!(client.new_prekeys(None, vec![unused], 1)) {
+            new_prekeys::Res::NoSlotAvailable(_) => {}
+            _ => panic!(),
+        };
+
         let pre_kp2 = sig::KeyPair::gen_new();
         let pre2 = w!(Prekey::from_slice(pre_kp2.public().as_ref()));
 
@@ -238,19 +344,57 @@ mod tests {
             new: signed_pre2,
         };
 
-        match wa!(client.new_prekeys(iter(vec![replace]))) {
+        match wa!(client.new_prekeys(None, vec![replace], PREKEY_SLOTS)) {
             new_prekeys::Res::Success => {}
             _ => panic!(),
         };
 
-        let tagged = wa!(client.get_random_prekeys(iter(vec![*kp.public()])));
-        assert_eq!(tagged.len(), 1);
+        let bundles = wa!(client.get_prekey_bundles(vec![*kp.public()]));
         assert_eq!(
-            tagged[0],
-            TaggedPrekey {
-                key: *kp.public(),
-                prekey: signed_pre2
-            }
+            bundles,
+            vec![(
+                *kp.public(),
+                PrekeyBundle {
+                    signed,
+                    one_time: Some(signed_pre2)
+                }
+            )]
+        );
+
+        // the one-time prekey was consumed, so we fall back to the signed prekey
+        assert_eq!(wa!(client.prekey_count(*kp.public())), 0);
+
+        let bundles = wa!(client.get_prekey_bundles(vec![*kp.public()]));
+        assert_eq!(
+            bundles,
+            vec![(
+                *kp.public(),
+                PrekeyBundle {
+                    signed,
+                    one_time: None
+                }
+            )]
         );
+
+        // replacing a prekey that was already handed out takes a free slot instead
+        let pre3 = w!(Prekey::from_slice(
+            sig::KeyPair::gen_new().public().as_ref()
+        ));
+
+        let replace = PrekeyReplace {
+            old: Some(pre2),
+            new: sign(&kp, pre3),
+        };
+
+        match wa!(client.new_prekeys(None, vec![replace], PREKEY_SLOTS)) {
+            new_prekeys::Res::Success => {}
+            _ => panic!(),
+        };
+
+        assert_eq!(wa!(client.held_prekeys(*kp.public())), vec![pre3]);
+
+        let no_prekeys = *sig::KeyPair::gen_new().public();
+        let bundles = wa!(client.get_prekey_bundles(vec![no_prekeys]));
+        assert!(bundles.is_empty());
     }
 }
@@ -114,12 +114,15 @@ mod tests {
     use std::convert::TryInto;
     use womp::*;
 
-    use crate::tests::get_client;
+    use crate::tests::{get_client, get_pg_client};
 
     #[tokio::test]
     #[serial]
     async fn test_defaults() {
-        let mut client = wa!(get_client());
+        let mut client = match get_pg_client().await {
+            Some(client) => client,
+            None => return,
+        };
 
         let kp = sig::KeyPair::gen_new();
         assert!(!wa!(client.one_key_exists(kp.public())));
@@ -133,7 +136,10 @@ mod tests {
     #[tokio::test]
     #[serial]
     async fn users() {
-        let mut client = wa!(get_client());
+        let mut client = match get_pg_client().await {
+            Some(client) => client,
+            None => return,
+        };
 
         let a_uid: UserId = "a".try_into().expect(womp!());
         let a_kp = sig::KeyPair::gen_new();
@@ -155,7 +161,10 @@ mod tests {
     #[tokio::test]
     #[serial]
     async fn keys() {
-        let mut client = wa!(get_client());
+        let mut client = match get_pg_client().await {
+            Some(client) => client,
+            None => return,
+        };
 
         let a_uid: UserId = "a".try_into().expect(womp!());
         let a_kp = sig::KeyPair::gen_new();
@@ -177,4 +186,87 @@ mod tests {
         let c_kp = sig::KeyPair::gen_new();
         assert!(!wa!(client.one_key_exists(c_kp.public())));
     }
+
+    #[tokio::test]
+    #[serial]
+    async fn recip_defaults() {
+        let mut client = wa!(get_client());
+
+        let kp = sig::KeyPair::gen_new();
+        assert!(!wa!(
+            client.recip_exists(Recip::One(SingleRecip::Key(*kp.public())))
+        ));
+        assert!(!wa!(
+            client.recip_exists(Recip::Many(Recips::Keys(vec![*kp.public()])))
+        ));
+
+        let uid = "a".try_into().expect(womp!());
+        assert!(!wa!(client.recip_exists(Recip::One(SingleRecip::User(uid)))));
+        assert!(!wa!(
+            client.recip_exists(Recip::Many(Recips::Users(vec![uid])))
+        ));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn recip_users() {
+        let mut client = wa!(get_client());
+
+        let a_uid: UserId = "a".try_into().expect(womp!());
+        let a_kp = sig::KeyPair::gen_new();
+        let a_init = sign(&a_kp, a_uid);
+        assert_eq!(wa!(client.new_user(a_init)), RegisterResponse::Success);
+        assert!(wa!(
+            client.recip_exists(Recip::One(SingleRecip::User(a_uid)))
+        ));
+
+        let b_uid: UserId = "b".try_into().expect(womp!());
+        let b_kp = sig::KeyPair::gen_new();
+        let b_init = sign(&b_kp, b_uid);
+        assert_eq!(wa!(client.new_user(b_init)), RegisterResponse::Success);
+
+        assert!(wa!(
+            client.recip_exists(Recip::Many(Recips::Users(vec![a_uid, b_uid])))
+        ));
+
+        let c_uid: UserId = "c".try_into().expect(womp!());
+        assert!(!wa!(
+            client.recip_exists(Recip::One(SingleRecip::User(c_uid)))
+        ));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn recip_keys() {
+        let mut client = wa!(get_client());
+
+        let a_uid: UserId = "a".try_into().expect(womp!());
+        let a_kp = sig::KeyPair::gen_new();
+        assert!(!wa!(
+            client.recip_exists(Recip::One(SingleRecip::Key(*a_kp.public())))
+        ));
+
+        let a_init = sign(&a_kp, a_uid);
+        assert_eq!(wa!(client.new_user(a_init)), RegisterResponse::Success);
+        assert!(wa!(
+            client.recip_exists(Recip::One(SingleRecip::Key(*a_kp.public())))
+        ));
+
+        let b_uid: UserId = "b".try_into().expect(womp!());
+        let b_kp = sig::KeyPair::gen_new();
+        assert!(!wa!(
+            client.recip_exists(Recip::One(SingleRecip::Key(*b_kp.public())))
+        ));
+        let b_init = sign(&b_kp, b_uid);
+        assert_eq!(wa!(client.new_user(b_init)), RegisterResponse::Success);
+        assert!(wa!(client.recip_exists(Recip::Many(Recips::Keys(vec![
+            *a_kp.public(),
+            *b_kp.public()
+        ])))));
+
+        let c_kp = sig::KeyPair::gen_new();
+        assert!(!wa!(
+            client.recip_exists(Recip::One(SingleRecip::Key(*c_kp.public())))
+        ));
+    }
 }
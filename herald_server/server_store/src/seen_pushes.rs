@@ -0,0 +1,86 @@
+//! Signatures of recently accepted pushes, so that a push can't be replayed while its timestamp
+//! is still within the allowed clock skew.
+
+use super::*;
+
+impl Conn {
+    /// Records the signature of a push signed at `ts`, returning `false` if it was seen before.
+    pub async fn note_push_sig(
+        &mut self,
+        sig: sig::Signature,
+        ts: Time,
+    ) -> Res<bool> {
+        let stmt = self
+            .prepare_typed(sql!("add_seen_push"), types![BYTEA, INT8])
+            .await?;
+
+        let num_updated = self
+            .execute(&stmt, params![sig.as_ref(), ts.as_i64()])
+            .await?;
+
+        Ok(num_updated == 1)
+    }
+
+    /// Whether the signature of a push was recorded before.
+    pub async fn push_sig_seen(
+        &mut self,
+        sig: sig::Signature,
+    ) -> Res<bool> {
+        let stmt = self
+            .prepare_typed(sql!("push_sig_seen"), types![BYTEA])
+            .await?;
+
+        Ok(self
+            .query_one(&stmt, params![sig.as_ref()])
+            .await?
+            .get::<_, bool>(0))
+    }
+
+    /// Forgets the signatures of pushes signed before `before`, returning how many were
+    /// forgotten.
+    pub async fn forget_push_sigs(
+        &mut self,
+        before: Time,
+    ) -> Res<u64> {
+        let stmt = self
+            .prepare_typed(sql!("forget_seen_pushes"), types![INT8])
+            .await?;
+
+        Ok(self.execute(&stmt, params![before.as_i64()]).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::get_client;
+    use crate::wa;
+    use serial_test_derive::serial;
+    use womp::*;
+
+    #[tokio::test]
+    #[serial]
+    async fn seen_pushes() {
+        let mut client = wa!(get_client());
+
+        let kp = sig::KeyPair::gen_new();
+        let old = sig::sign_ser(&kp, 0u8);
+        let new = sig::sign_ser(&kp, 1u8);
+
+        let old_ts = Time::from(*old.timestamp().as_i64() - 1000);
+
+        assert!(!wa!(client.push_sig_seen(old.sig())));
+        assert!(wa!(client.note_push_sig(old.sig(), old_ts)));
+        assert!(wa!(client.push_sig_seen(old.sig())));
+        assert!(wa!(client.note_push_sig(new.sig(), *new.timestamp())));
+
+        // replays are caught
+        assert!(!wa!(client.note_push_sig(old.sig(), old_ts)));
+        assert!(!wa!(client.note_push_sig(new.sig(), *new.timestamp())));
+
+        assert_eq!(wa!(client.forget_push_sigs(*new.timestamp())), 1);
+
+        assert!(wa!(client.note_push_sig(old.sig(), old_ts)));
+        assert!(!wa!(client.note_push_sig(new.sig(), *new.timestamp())));
+    }
+}
@@ -0,0 +1,16 @@
+INSERT INTO dead_letters(
+  key,
+  push_id,
+  push_ts,
+  push_key,
+  failed_ts,
+  reason
+)
+VALUES (
+  $1,
+  $2,
+  (SELECT push_ts FROM pushes WHERE push_id = $2),
+  (SELECT push_key FROM pushes WHERE push_id = $2),
+  $3,
+  $4
+)
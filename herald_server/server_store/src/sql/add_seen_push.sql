@@ -0,0 +1,4 @@
+INSERT INTO
+  seen_pushes(signature, ts)
+VALUES($1, $2)
+ON CONFLICT(signature) DO NOTHING
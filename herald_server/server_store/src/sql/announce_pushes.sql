@@ -0,0 +1,4 @@
+SELECT
+  pg_notify('herald_pending', payload)
+FROM
+  unnest($1::TEXT[]) AS payload
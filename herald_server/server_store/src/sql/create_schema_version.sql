@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS schema_version (
+    version     BIGINT  NOT NULL PRIMARY KEY,
+    applied_ts  BIGINT  NOT NULL
+)
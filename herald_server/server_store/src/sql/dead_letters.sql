@@ -0,0 +1,15 @@
+SELECT
+  key,
+  push_id,
+  push_ts,
+  push_key,
+  failed_ts,
+  reason
+FROM
+  dead_letters
+WHERE
+  $1::BYTEA IS NULL OR key = $1
+ORDER BY
+  failed_ts DESC, failure_id DESC
+LIMIT
+  $2
@@ -1,6 +1,6 @@
 DELETE FROM
     pushes
 WHERE
-    push_id NOT IN (
-        SELECT push_id FROM pending
+    NOT EXISTS (
+        SELECT 1 FROM pending WHERE pending.push_id = pushes.push_id
 )
@@ -0,0 +1,7 @@
+DELETE FROM
+  pending
+USING
+  pushes
+WHERE
+  pending.push_id = pushes.push_id AND
+  pushes.push_ts < $1
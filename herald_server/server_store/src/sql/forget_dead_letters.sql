@@ -0,0 +1,3 @@
+DELETE FROM dead_letters
+WHERE
+    failed_ts < $1
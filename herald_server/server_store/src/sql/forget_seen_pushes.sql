@@ -0,0 +1,3 @@
+DELETE FROM seen_pushes
+WHERE
+    ts < $1
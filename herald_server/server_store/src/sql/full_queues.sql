@@ -0,0 +1,10 @@
+SELECT
+  key
+FROM
+  pending
+WHERE
+  key = ANY($1)
+GROUP BY
+  key
+HAVING
+  COUNT(*) >= $2
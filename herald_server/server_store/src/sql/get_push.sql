@@ -0,0 +1,10 @@
+SELECT
+  push_data,
+  push_tag,
+  push_ts,
+  push_user_id,
+  push_key
+FROM
+  pushes
+WHERE
+  push_id = $1
@@ -1,12 +1,9 @@
 SELECT
     key,
-    slot,
     signed_by,
     signature,
     ts
 FROM
-    prekeys
+    signed_prekeys
 WHERE
     signed_by = $1
-ORDER BY RANDOM()
-LIMIT 1
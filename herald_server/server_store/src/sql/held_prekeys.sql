@@ -0,0 +1,6 @@
+SELECT
+    key
+FROM
+    prekeys
+WHERE
+    signed_by = $1
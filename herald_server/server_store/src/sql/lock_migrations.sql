@@ -0,0 +1,2 @@
+-- released when the transaction ends
+SELECT pg_advisory_xact_lock(7218063105)
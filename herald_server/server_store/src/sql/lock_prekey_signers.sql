@@ -0,0 +1,9 @@
+SELECT
+    key
+FROM
+    userkeys
+WHERE
+    key = ANY($1)
+ORDER BY
+    key
+FOR UPDATE
@@ -0,0 +1,4 @@
+SELECT
+  COUNT(*)
+FROM
+  pending
@@ -1,11 +1,17 @@
 DELETE FROM prekeys
-WHERE sealed_key = any(array(
+WHERE (key, signed_by) IN (
     SELECT
-      sealed_key
+        key,
+        signed_by
     FROM
-      prekeys
+        prekeys
     WHERE
-      signing_key = $1
+        signed_by = $1
     LIMIT 1
-))
-RETURNING sealed_key
+    FOR UPDATE SKIP LOCKED
+)
+RETURNING
+    key,
+    signed_by,
+    signature,
+    ts
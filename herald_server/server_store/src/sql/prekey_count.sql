@@ -0,0 +1,6 @@
+SELECT
+    COUNT(*)
+FROM
+    prekeys
+WHERE
+    signed_by = $1
@@ -0,0 +1,6 @@
+SELECT
+  key
+FROM
+  pending
+WHERE
+  push_id = $1
@@ -0,0 +1,8 @@
+SELECT EXISTS (
+  SELECT
+    1
+  FROM
+    seen_pushes
+  WHERE
+    signature = $1
+)
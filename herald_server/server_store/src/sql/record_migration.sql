@@ -0,0 +1,3 @@
+INSERT INTO
+  schema_version(version, applied_ts)
+VALUES($1, $2)
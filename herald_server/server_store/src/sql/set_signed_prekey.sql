@@ -0,0 +1,10 @@
+INSERT INTO
+  signed_prekeys(key, signed_by, signature, ts)
+VALUES($1, $2, $3, $4)
+ON CONFLICT(signed_by) DO UPDATE
+SET
+    key = EXCLUDED.key,
+    signature = EXCLUDED.signature,
+    ts = EXCLUDED.ts
+WHERE
+    signed_prekeys.ts < EXCLUDED.ts
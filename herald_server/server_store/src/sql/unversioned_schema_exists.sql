@@ -0,0 +1 @@
+SELECT to_regclass('userkeys') IS NOT NULL
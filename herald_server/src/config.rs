@@ -0,0 +1,538 @@
+//! Server configuration, read from a TOML file with command line flags taking precedence.
+//!
+//! Every setting has a default, so the server can be started without a config file:
+//!
+//! ```toml
+//! listen = "0.0.0.0:8080"
+//! admin_listen = "127.0.0.1:9090"
+//! store = "postgres"
+//! database_url = "host=/var/run/postgresql user=postgres"
+//! pool_size = 10000
+//! pending_retention_secs = 2592000
+//! dead_letter_retention_secs = 604800
+//! max_prekeys = 256
+//! max_pending = 10000
+//! max_push_len = 16777216
+//! max_recips = 1024
+//! janitor_interval_secs = 600
+//! log_level = "info"
+//! auto_migrate = true
+//! signing_key_path = "/etc/herald/signing.key"
+//! trusted_proxies = ["127.0.0.1"]
+//!
+//! # any endpoint left out keeps its default
+//! [rate_limits]
+//! push = { burst = 100, per_sec = 20.0 }
+//! get_prekeys = { burst = 20, per_sec = 1.0 }
+//!
+//! [tls]
+//! cert_path = "/etc/herald/cert.pem"
+//! key_path = "/etc/herald/key.pem"
+//! ```
+
+use crate::http::TlsConfig;
+use anyhow::*;
+use herald_common::{kson, sig};
+use serde::{Deserialize, Deserializer};
+use server_protocol::{Limits, RateLimits};
+use server_store::{Backend, Memory, Pool, PREKEY_SLOTS};
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
+use structopt::StructOpt;
+use tracing::Level;
+
+/// Where the server keeps its data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    Postgres,
+    /// Kept in memory and lost when the server stops, for development only
+    Memory,
+}
+
+impl FromStr for StoreKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "postgres" => Ok(StoreKind::Postgres),
+            "memory" => Ok(StoreKind::Memory),
+            _ => Err(anyhow!("unknown store {}, expected postgres or memory", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address the server listens on
+    pub listen: SocketAddr,
+    /// Address the metrics are served on, separately from the API
+    pub admin_listen: SocketAddr,
+    /// Where data is kept
+    pub store: StoreKind,
+    /// Postgres connection string
+    pub database_url: String,
+    /// Maximum number of idle Postgres connections
+    pub pool_size: usize,
+    /// Certificate and key to serve TLS with, plaintext if unset
+    pub tls: Option<TlsConfig>,
+    /// Reverse proxies whose `X-Forwarded-For` header is trusted to name the client. Without
+    /// any, clients behind a proxy are rate limited together as the proxy's address.
+    pub trusted_proxies: Vec<IpAddr>,
+    /// How long undelivered pushes are kept, in seconds, forever if unset
+    pub pending_retention_secs: Option<u64>,
+    /// How long pushes clients failed to process are kept, in seconds
+    pub dead_letter_retention_secs: u64,
+    /// Maximum number of one-time prekeys a device can have stored
+    pub max_prekeys: u64,
+    /// Maximum number of undelivered pushes a device can have waiting for it
+    pub max_pending: u64,
+    /// Maximum length of a push's message, in bytes
+    pub max_push_len: u64,
+    /// Maximum number of users or devices a single push can be addressed to
+    pub max_recips: u64,
+    /// How often each client can make requests to each endpoint
+    pub rate_limits: RateLimits,
+    /// How often expired and orphaned pushes are cleaned up, in seconds
+    pub janitor_interval_secs: u64,
+    #[serde(deserialize_with = "de_level")]
+    pub log_level: Level,
+    /// Whether `serve` brings the database schema up to date before starting
+    pub auto_migrate: bool,
+    /// Key file delivery receipts are signed with, created if missing. Servers sharing a store
+    /// must use the same one. If unset, a new key is generated every time the server starts.
+    pub signing_key_path: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen: ([0u8, 0, 0, 0], 8080).into(),
+            admin_listen: ([127u8, 0, 0, 1], 9090).into(),
+            store: StoreKind::Postgres,
+            database_url: server_store::DEFAULT_DATABASE_URL.to_owned(),
+            pool_size: server_store::DEFAULT_POOL_SIZE,
+            tls: None,
+            trusted_proxies: Vec::new(),
+            pending_retention_secs: None,
+            dead_letter_retention_secs: 7 * 24 * 60 * 60,
+            max_prekeys: PREKEY_SLOTS,
+            max_pending: 10_000,
+            max_push_len: 16 * 1024 * 1024,
+            max_recips: 1024,
+            rate_limits: RateLimits::default(),
+            janitor_interval_secs: 600,
+            log_level: Level::INFO,
+            auto_migrate: true,
+            signing_key_path: None,
+        }
+    }
+}
+
+fn de_level<'de, D: Deserializer<'de>>(d: D) -> Result<Level, D::Error> {
+    let s = String::deserialize(d)?;
+    s.parse().map_err(serde::de::Error::custom)
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "herald_server", about = "The Herald server")]
+pub struct Opts {
+    /// Path to a TOML config file
+    #[structopt(short, long, parse(from_os_str))]
+    pub config: Option<PathBuf>,
+
+    /// Address to listen on
+    #[structopt(long)]
+    pub listen: Option<SocketAddr>,
+
+    /// Address to serve metrics on
+    #[structopt(long)]
+    pub admin_listen: Option<SocketAddr>,
+
+    /// Where to keep data, either `postgres` or `memory`
+    #[structopt(long)]
+    pub store: Option<StoreKind>,
+
+    /// Postgres connection string
+    #[structopt(long)]
+    pub database_url: Option<String>,
+
+    /// Maximum number of idle Postgres connections
+    #[structopt(long)]
+    pub pool_size: Option<usize>,
+
+    /// PEM-encoded certificate chain to serve TLS with
+    #[structopt(long, parse(from_os_str), requires = "tls-key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM-encoded private key for the TLS certificate
+    #[structopt(long, parse(from_os_str), requires = "tls-cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// How long undelivered pushes are kept, in seconds
+    #[structopt(long)]
+    pub pending_retention_secs: Option<u64>,
+
+    /// How long pushes clients failed to process are kept, in seconds
+    #[structopt(long)]
+    pub dead_letter_retention_secs: Option<u64>,
+
+    /// Maximum number of one-time prekeys a device can have stored
+    #[structopt(long)]
+    pub max_prekeys: Option<u64>,
+
+    /// Maximum number of undelivered pushes a device can have waiting for it
+    #[structopt(long)]
+    pub max_pending: Option<u64>,
+
+    /// Maximum length of a push's message, in bytes
+    #[structopt(long)]
+    pub max_push_len: Option<u64>,
+
+    /// Maximum number of users or devices a single push can be addressed to
+    #[structopt(long)]
+    pub max_recips: Option<u64>,
+
+    /// How often expired and orphaned pushes are cleaned up, in seconds
+    #[structopt(long)]
+    pub janitor_interval_secs: Option<u64>,
+
+    /// Log level, one of `error`, `warn`, `info`, `debug` or `trace`
+    #[structopt(long)]
+    pub log_level: Option<Level>,
+
+    /// Refuse to serve from an outdated database instead of migrating it on startup
+    #[structopt(long)]
+    pub no_auto_migrate: bool,
+
+    /// Key file to sign delivery receipts with, created if missing
+    #[structopt(long, parse(from_os_str))]
+    pub signing_key_path: Option<PathBuf>,
+
+    #[structopt(subcommand)]
+    pub cmd: Option<Command>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, StructOpt)]
+pub enum Command {
+    /// Serves the API, the default if no command is given
+    Serve,
+    /// Creates or migrates the database tables to the latest schema
+    Migrate,
+    /// Drops and recreates all database tables, deleting all data
+    ResetDb,
+    /// Prints the pin clients should use for the configured TLS certificate
+    CertPin,
+    /// Lists the pushes clients most recently reported they couldn't process
+    DeadLetters {
+        /// How many failures to list
+        #[structopt(long, default_value = "50")]
+        limit: u64,
+    },
+}
+
+impl Config {
+    /// Reads the config file named in `opts`, if any, and applies the flags on top of it.
+    pub fn load(opts: &Opts) -> Result<Config, Error> {
+        let config = match &opts.config {
+            Some(path) => {
+                let raw = std::fs::read_to_string(path)
+                    .with_context(|| format!("failed to read config file {}", path.display()))?;
+                toml::from_str(&raw)
+                    .with_context(|| format!("invalid config file {}", path.display()))?
+            }
+            None => Config::default(),
+        };
+
+        config.with_opts(opts).validate()
+    }
+
+    fn with_opts(
+        mut self,
+        opts: &Opts,
+    ) -> Self {
+        if let Some(listen) = opts.listen {
+            self.listen = listen;
+        }
+
+        if let Some(admin_listen) = opts.admin_listen {
+            self.admin_listen = admin_listen;
+        }
+
+        if let Some(store) = opts.store {
+            self.store = store;
+        }
+
+        if let Some(url) = &opts.database_url {
+            self.database_url = url.clone();
+        }
+
+        if let Some(size) = opts.pool_size {
+            self.pool_size = size;
+        }
+
+        if let (Some(cert_path), Some(key_path)) = (&opts.tls_cert, &opts.tls_key) {
+            self.tls = Some(TlsConfig {
+                cert_path: cert_path.clone(),
+                key_path: key_path.clone(),
+            });
+        }
+
+        if let Some(secs) = opts.pending_retention_secs {
+            self.pending_retention_secs = Some(secs);
+        }
+
+        if let Some(secs) = opts.dead_letter_retention_secs {
+            self.dead_letter_retention_secs = secs;
+        }
+
+        if let Some(max) = opts.max_prekeys {
+            self.max_prekeys = max;
+        }
+
+        if let Some(max) = opts.max_pending {
+            self.max_pending = max;
+        }
+
+        if let Some(max) = opts.max_push_len {
+            self.max_push_len = max;
+        }
+
+        if let Some(max) = opts.max_recips {
+            self.max_recips = max;
+        }
+
+        if let Some(secs) = opts.janitor_interval_secs {
+            self.janitor_interval_secs = secs;
+        }
+
+        if let Some(level) = opts.log_level {
+            self.log_level = level;
+        }
+
+        if opts.no_auto_migrate {
+            self.auto_migrate = false;
+        }
+
+        if let Some(path) = &opts.signing_key_path {
+            self.signing_key_path = Some(path.clone());
+        }
+
+        self
+    }
+
+    fn validate(self) -> Result<Self, Error> {
+        ensure!(
+            self.admin_listen != self.listen,
+            "admin_listen must differ from listen"
+        );
+        ensure!(self.pool_size > 0, "pool_size must be positive");
+        ensure!(self.max_pending > 0, "max_pending must be positive");
+        ensure!(
+            self.janitor_interval_secs > 0,
+            "janitor_interval_secs must be positive"
+        );
+        ensure!(
+            self.max_prekeys <= PREKEY_SLOTS,
+            "max_prekeys can be at most {}",
+            PREKEY_SLOTS
+        );
+
+        Ok(self)
+    }
+
+    pub fn backend(&self) -> Box<dyn Backend> {
+        match self.store {
+            StoreKind::Postgres => {
+                Box::new(Pool::with_config(self.database_url.clone(), self.pool_size))
+            }
+            StoreKind::Memory => Box::new(Memory::new()),
+        }
+    }
+
+    /// Reads the key delivery receipts are signed with, generating and saving it if the file
+    /// doesn't exist yet. `None` if no key file is configured.
+    pub fn signing_key(&self) -> Result<Option<sig::KeyPair>, Error> {
+        use std::{fs, io::Write};
+        #[cfg(unix)]
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let path = match &self.signing_key_path {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+
+        if path.exists() {
+            let raw = fs::read(path)
+                .with_context(|| format!("failed to read signing key {}", path.display()))?;
+            let key = kson::from_bytes(raw.into())
+                .map_err(|e| anyhow!("invalid signing key {}: {}", path.display(), e))?;
+
+            return Ok(Some(key));
+        }
+
+        let key = sig::KeyPair::gen_new();
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+
+        // only the owner can read it on unix; elsewhere it gets the default permissions, so the
+        // path should be somewhere private
+        #[cfg(unix)]
+        options.mode(0o600);
+
+        options
+            .open(path)
+            .and_then(|mut file| file.write_all(&kson::to_vec(&key)))
+            .with_context(|| format!("failed to write signing key {}", path.display()))?;
+
+        Ok(Some(key))
+    }
+
+    pub fn janitor_interval(&self) -> Duration {
+        Duration::from_secs(self.janitor_interval_secs)
+    }
+
+    pub fn limits(&self) -> Limits {
+        Limits {
+            pending_retention: self.pending_retention_secs.map(Duration::from_secs),
+            dead_letter_retention: Duration::from_secs(self.dead_letter_retention_secs),
+            max_prekeys: self.max_prekeys,
+            max_pending: self.max_pending,
+            max_push_len: self.max_push_len,
+            max_recips: self.max_recips,
+            rates: self.rate_limits,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts(args: &[&str]) -> Opts {
+        Opts::from_iter(std::iter::once("herald_server").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn defaults() {
+        let config = Config::load(&opts(&[])).expect("failed to load default config");
+
+        assert_eq!(config.listen, ([0u8, 0, 0, 0], 8080).into());
+        assert_eq!(config.admin_listen, ([127u8, 0, 0, 1], 9090).into());
+        assert_eq!(config.store, StoreKind::Postgres);
+        assert!(config.tls.is_none());
+        assert!(config.trusted_proxies.is_empty());
+        assert_eq!(config.max_prekeys, PREKEY_SLOTS);
+        assert_eq!(config.log_level, Level::INFO);
+        assert!(config.auto_migrate);
+    }
+
+    #[test]
+    fn file_and_flags() {
+        let file: Config = toml::from_str(
+            r#"
+            listen = "127.0.0.1:9000"
+            admin_listen = "127.0.0.1:9100"
+            store = "memory"
+            pending_retention_secs = 60
+            log_level = "debug"
+            auto_migrate = false
+            trusted_proxies = ["127.0.0.1"]
+
+            [rate_limits]
+            push = { burst = 5, per_sec = 0.5 }
+
+            [tls]
+            cert_path = "cert.pem"
+            key_path = "key.pem"
+            "#,
+        )
+        .expect("failed to parse config");
+
+        assert_eq!(file.store, StoreKind::Memory);
+        assert_eq!(file.log_level, Level::DEBUG);
+        assert_eq!(
+            file.limits().pending_retention,
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            file.limits().dead_letter_retention,
+            Duration::from_secs(7 * 24 * 60 * 60)
+        );
+        assert!(file.tls.is_some());
+        assert!(!file.auto_migrate);
+        assert_eq!(file.admin_listen, ([127u8, 0, 0, 1], 9100).into());
+        assert_eq!(file.trusted_proxies, vec![IpAddr::from([127, 0, 0, 1])]);
+        assert_eq!(file.rate_limits.push.burst, 5);
+        assert_eq!(file.rate_limits.register, RateLimits::default().register);
+
+        let config = file
+            .with_opts(&opts(&[
+                "--listen",
+                "127.0.0.1:9001",
+                "--max-prekeys",
+                "10",
+                "--max-pending",
+                "5",
+            ]))
+            .validate()
+            .expect("invalid config");
+
+        assert_eq!(config.listen, ([127u8, 0, 0, 1], 9001).into());
+        assert_eq!(config.max_prekeys, 10);
+        assert_eq!(config.limits().max_pending, 5);
+        assert_eq!(config.store, StoreKind::Memory);
+    }
+
+    #[test]
+    fn signing_key() {
+        assert!(Config::default()
+            .signing_key()
+            .expect("failed to load signing key")
+            .is_none());
+
+        let path = std::env::temp_dir().join(format!("herald_signing_key_{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let config = Config::default().with_opts(&opts(&[
+            "--signing-key-path",
+            path.to_str().expect("non-UTF-8 temp dir"),
+        ]));
+
+        // created the first time, and the same key after that
+        let key = config.signing_key().expect("failed to create signing key");
+        let again = config.signing_key().expect("failed to read signing key");
+        assert!(key.is_some());
+        assert_eq!(
+            key.as_ref().map(sig::KeyPair::public),
+            again.as_ref().map(sig::KeyPair::public)
+        );
+
+        std::fs::remove_file(&path).expect("failed to remove signing key");
+    }
+
+    #[test]
+    fn rejects_bad_config() {
+        assert!(toml::from_str::<Config>("not_a_setting = 1").is_err());
+        assert!(toml::from_str::<Config>(r#"log_level = "loud""#).is_err());
+        assert!(toml::from_str::<Config>("[rate_limits]\nlogin = { burst = 1 }").is_err());
+        assert!(Config::default()
+            .with_opts(&opts(&["--max-prekeys", "1000"]))
+            .validate()
+            .is_err());
+        assert!(Config::default()
+            .with_opts(&opts(&["--admin-listen", "0.0.0.0:8080"]))
+            .validate()
+            .is_err());
+        assert!(Config::default()
+            .with_opts(&opts(&["--janitor-interval-secs", "0"]))
+            .validate()
+            .is_err());
+    }
+}
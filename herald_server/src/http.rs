@@ -1,55 +1,214 @@
 use super::*;
 use futures::{future::*, sink::*, stream::*};
-use warp::{filters::ws, Filter};
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+};
+use warp::{filters::ws, http::StatusCode, reply::Response, Filter, Reply};
 
-#[derive(Debug)]
-struct Rejection(anyhow::Error);
-
-impl warp::reject::Reject for Rejection {}
+/// Room for everything in a request besides a push's message, like its signature and recipients.
+const BODY_OVERHEAD: u64 = 1024 * 1024;
 
 macro_rules! mk_filter {
-    ($this: expr, $f: ident) => {
+    ($this: expr, $proxies: expr, $f: ident => $endpoint: ident) => {
         warp::path(stringify!($f))
             .boxed()
-            .and(::warp::filters::body::bytes())
+            .and(client_addr($proxies))
+            .and(warp::body::content_length_limit(
+                $this.limits.max_push_len + BODY_OVERHEAD,
+            ))
+            .and(warp::body::bytes())
             .boxed()
-            .and_then(move |b: Bytes| async move {
-                let r1: Result<Vec<u8>, anyhow::Error> = req_handler_async($this, b, State::$f).await;
-                let r2: Result<Vec<u8>, ::warp::reject::Rejection> =
-                    r1.map_err(|e| ::warp::reject::custom(Rejection(e)));
-                r2
+            .and_then(move |addr: Option<IpAddr>, b: Bytes| async move {
+                let res = req_handler_async($this, Endpoint::$endpoint, addr, b, State::$f).await;
+                Ok::<Response, warp::Rejection>(res)
             })
             .boxed()
     };
-    ($this:expr,$f:ident,) => {mk_filter!($this,$f)};
-    ($this: expr, $f: ident, $($fs: ident),+) => {
-        mk_filter!($this,$f).or(mk_filter!($this, $($fs),+).boxed())
+    ($this: expr, $proxies: expr, $f: ident => $endpoint: ident,) => {
+        mk_filter!($this, $proxies, $f => $endpoint)
+    };
+    ($this: expr, $proxies: expr, $f: ident => $endpoint: ident, $($fs: ident => $endpoints: ident),+) => {
+        mk_filter!($this, $proxies, $f => $endpoint)
+            .or(mk_filter!($this, $proxies, $($fs => $endpoints),+).boxed())
+            .unify()
+    };
+    ($this: expr, $proxies: expr, $f: ident => $endpoint: ident, $($fs: ident => $endpoints: ident),+,) => {
+        mk_filter!($this, $proxies, $f => $endpoint, $($fs => $endpoints),+)
     };
-    ($this: expr, $f: ident, $($fs: ident),+,) => {
-        mk_filter!($this, $f, $($fs),+)
+}
+
+/// The address a request came from, for rate limiting.
+///
+/// Requests relayed by one of the `proxies` are attributed to the last address in their
+/// `X-Forwarded-For` header that isn't a proxy. Without trusted proxies the header is ignored,
+/// and everything behind a reverse proxy shares the proxy's rate limits.
+fn client_addr(
+    proxies: &'static [IpAddr]
+) -> impl Filter<Extract = (Option<IpAddr>,), Error = Infallible> + Clone {
+    warp::addr::remote()
+        .and(
+            warp::header::optional::<String>("x-forwarded-for")
+                .or(warp::any().map(|| None))
+                .unify(),
+        )
+        .map(
+            move |remote: Option<SocketAddr>, forwarded: Option<String>| {
+                remote.map(|remote| forwarded_for(remote.ip(), forwarded.as_deref(), proxies))
+            },
+        )
+}
+
+fn forwarded_for(
+    remote: IpAddr,
+    forwarded: Option<&str>,
+    proxies: &[IpAddr],
+) -> IpAddr {
+    let mut addr = remote;
+
+    // each proxy appends the address it got the request from, so walk back from the end
+    for hop in forwarded.unwrap_or_default().rsplit(',') {
+        if !proxies.contains(&addr) {
+            break;
+        }
+
+        match hop.trim().parse() {
+            Ok(hop) => addr = hop,
+            Err(_) => break,
+        }
+    }
+
+    addr
+}
+
+/// Handles a request made over plain HTTP, where clients are identified by their address.
+#[tracing::instrument(skip(state, buf, f))]
+async fn req_handler_async<'a, I, O, F, Fut>(
+    state: &'a State,
+    endpoint: Endpoint,
+    addr: Option<IpAddr>,
+    buf: Bytes,
+    f: F,
+) -> Response
+where
+    I: De,
+    O: Ser,
+    F: FnOnce(&'a State, I) -> Fut,
+    Fut: Future<Output = Result<O, Error>>,
+{
+    let started = std::time::Instant::now();
+
+    let res = async {
+        if let Some(addr) = addr {
+            state.check_rate(endpoint, Client::Addr(addr))?;
+        }
+
+        let req: I = kson::from_bytes(buf).map_err(|_| RequestError::Malformed)?;
+        let res: O = f(state, req).await.map_err(|e| state.request_error(e))?;
+
+        Ok::<_, RequestError>(kson::to_vec(&res))
+    }
+    .await;
+
+    state.record_request(endpoint, started, res.as_ref().map(|_| ()));
+
+    match res {
+        Ok(res) => Response::new(res.into()),
+        Err(e) => error_reply(e),
+    }
+}
+
+/// Responds with `e`, and a status code matching it.
+fn error_reply(e: RequestError) -> Response {
+    let status = match e {
+        RequestError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+        RequestError::MsgTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+        RequestError::TooManyRecips { .. } | RequestError::Malformed => StatusCode::BAD_REQUEST,
+        RequestError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+
+    let retry_after = match e {
+        RequestError::RateLimited { retry_after_ms } => Some((retry_after_ms + 999) / 1000),
+        _ => None,
     };
+
+    let mut res = Response::new(kson::to_vec(&e).into());
+    *res.status_mut() = status;
+
+    if let Some(secs) = retry_after {
+        res.headers_mut()
+            .insert(warp::http::header::RETRY_AFTER, secs.into());
+    }
+
+    res
+}
+
+/// Certificate and private key the server uses to accept HTTPS and WSS connections.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM-encoded certificate chain, leaf first
+    pub cert_path: PathBuf,
+    /// PEM-encoded private key for the leaf certificate
+    pub key_path: PathBuf,
+}
+
+impl TlsConfig {
+    /// Computes the pin clients should store for this server, i.e. the fingerprint of the leaf
+    /// certificate.
+    pub fn cert_pin(&self) -> Result<CertPin, Error> {
+        let file = std::fs::File::open(&self.cert_path)?;
+        let certs =
+            rustls::internal::pemfile::certs(&mut std::io::BufReader::new(file)).map_err(|_| {
+                anyhow!(
+                    "failed to parse certificate at {}",
+                    self.cert_path.display()
+                )
+            })?;
+        let leaf = certs
+            .first()
+            .ok_or_else(|| anyhow!("no certificate found at {}", self.cert_path.display()))?;
+
+        Ok(CertPin::of(&leaf.0))
+    }
 }
 
+/// Serves the API on `addr`, over TLS if `tls` is set and in plaintext otherwise. Clients behind
+/// the `trusted_proxies` are identified by their `X-Forwarded-For` header.
 pub async fn serve(
     state: &'static State,
-    port: u16,
+    addr: SocketAddr,
+    tls: Option<&TlsConfig>,
+    trusted_proxies: &'static [IpAddr],
 ) {
     let routes = {
         mk_filter!(
             state,
-            get_sigchain,
-            recip_exists,
-            new_sig,
-            new_prekeys,
-            get_prekeys,
-            push,
-            register,
+            trusted_proxies,
+            get_sigchain => GetSigchain,
+            recip_exists => RecipExists,
+            new_sig => NewSig,
+            new_prekeys => NewPrekey,
+            get_prekeys => GetPrekey,
+            prekey_count => PrekeyCount,
+            held_prekeys => HeldPrekeys,
+            server_key => ServerKey,
+            push => Push,
+            register => Register,
         )
         .or(warp::path("login")
             .boxed()
+            .and(client_addr(trusted_proxies))
             .and(ws::ws().boxed())
             .boxed()
-            .map(move |w: ws::Ws| {
+            .map(move |addr: Option<IpAddr>, w: ws::Ws| {
+                if let Some(addr) = addr {
+                    if let Err(e) = state.check_rate(Endpoint::Login, Client::Addr(addr)) {
+                        return error_reply(e);
+                    }
+                }
+
                 w.on_upgrade(move |w: ws::WebSocket| {
                     async move {
                         let (wtx, wrx) = w.split();
@@ -78,14 +237,79 @@ pub async fn serve(
                             .handle_auth_ws(&mut tx, &mut rx)
                             .boxed()
                             .await
-                            .unwrap_or_else(|e| eprintln!("connection died, error was: {:?}", e))
+                            .unwrap_or_else(|e| {
+                                tracing::warn!("connection died, error was: {:?}", e)
+                            })
                     }
                     .boxed()
                 })
+                .into_response()
             })
             .boxed())
         .boxed()
     };
 
-    warp::serve(routes).run(([0u8, 0, 0, 0], port)).await
+    match tls {
+        Some(tls) => {
+            warp::serve(routes)
+                .tls()
+                .cert_path(&tls.cert_path)
+                .key_path(&tls.key_path)
+                .run(addr)
+                .await
+        }
+        None => warp::serve(routes).run(addr).await,
+    }
+}
+
+/// Serves the Prometheus metrics at `/metrics` on `addr`, in plaintext. This is kept off the API's
+/// address so it can be left unreachable from outside.
+pub async fn serve_admin(
+    state: &'static State,
+    addr: SocketAddr,
+) {
+    let routes = warp::path("metrics")
+        .and(warp::get())
+        .map(move || match state.metrics() {
+            Ok(metrics) => {
+                warp::reply::with_header(metrics, "content-type", "text/plain; version=0.0.4")
+                    .into_response()
+            }
+            Err(e) => {
+                tracing::error!("failed to render metrics: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        });
+
+    warp::serve(routes).run(addr).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([10, 0, 0, last])
+    }
+
+    #[test]
+    fn forwarded_for_only_from_proxies() {
+        let proxies = [ip(1), ip(2)];
+
+        // not a proxy, so the header could be made up
+        assert_eq!(forwarded_for(ip(9), Some("10.0.0.3"), &proxies), ip(9));
+
+        assert_eq!(forwarded_for(ip(1), Some("10.0.0.3"), &proxies), ip(3));
+        assert_eq!(forwarded_for(ip(1), None, &proxies), ip(1));
+
+        // entries before the first one that isn't a proxy are up to the client
+        assert_eq!(
+            forwarded_for(ip(1), Some("10.0.0.7, 10.0.0.3, 10.0.0.2"), &proxies),
+            ip(3)
+        );
+        assert_eq!(
+            forwarded_for(ip(1), Some("junk, 10.0.0.2"), &proxies),
+            ip(2)
+        );
+    }
 }
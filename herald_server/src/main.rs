@@ -1,13 +1,144 @@
-#[macro_use]
-extern crate lazy_static;
-
+use anyhow::*;
+use herald_server::config::{Command, Config, Opts, StoreKind};
 use server_protocol::State;
+use server_store::SCHEMA_VERSION;
+use std::time::Duration;
+use structopt::StructOpt;
 
-lazy_static! {
-    static ref HANDLER: State = State::new();
-}
+/// How long to wait before listening for pushes stored by other servers again, if it fails.
+const FAN_OUT_RETRY: Duration = Duration::from_secs(5);
 
 #[tokio::main]
-async fn main() {
-    herald_server::http::serve(&HANDLER, 8080).await;
+async fn main() -> Result<(), Error> {
+    let opts = Opts::from_args();
+    let config = Config::load(&opts)?;
+
+    tracing_subscriber::fmt()
+        .with_max_level(config.log_level)
+        .init();
+
+    match opts.cmd.unwrap_or(Command::Serve) {
+        Command::Serve => serve(config).await,
+        Command::Migrate => migrate(&config).await,
+        Command::ResetDb => {
+            config.backend().get().await?.reset_all().await?;
+            Ok(())
+        }
+        Command::CertPin => {
+            let tls = config
+                .tls
+                .as_ref()
+                .ok_or_else(|| anyhow!("no TLS certificate is configured"))?;
+            println!("{}", tls.cert_pin()?);
+            Ok(())
+        }
+        Command::DeadLetters { limit } => dead_letters(&config, limit).await,
+    }
+}
+
+async fn serve(config: Config) -> Result<(), Error> {
+    match &config.tls {
+        Some(tls) => {
+            let pin = tls.cert_pin().context("invalid TLS certificate")?;
+            tracing::info!("serving over TLS, certificate pin is {}", pin);
+        }
+        None => tracing::warn!("serving without TLS"),
+    }
+
+    if config.store == StoreKind::Memory {
+        tracing::warn!("using in-memory storage, nothing will be persisted");
+    }
+
+    if config.auto_migrate {
+        migrate(&config).await?;
+    } else {
+        let version = config.backend().get().await?.schema_version().await?;
+        ensure!(
+            version == SCHEMA_VERSION,
+            "database schema is at version {}, expected {}, run `herald_server migrate` first",
+            version,
+            SCHEMA_VERSION
+        );
+    }
+
+    let mut state = State::with_backend(config.backend()).with_limits(config.limits());
+
+    match config.signing_key().context("failed to load signing key")? {
+        Some(key) => state = state.with_signing_key(key),
+        None => tracing::warn!(
+            "no signing_key_path configured, receipts can't be verified after a restart"
+        ),
+    }
+
+    // the server runs until the process exits, so the state can live forever
+    let state: &'static State = Box::leak(Box::new(state));
+
+    tokio::spawn(state.run_janitor(config.janitor_interval()));
+    tokio::spawn(state.run_fan_out(FAN_OUT_RETRY));
+    tokio::spawn(herald_server::http::serve_admin(state, config.admin_listen));
+
+    let trusted_proxies: &'static [_] =
+        Box::leak(config.trusted_proxies.clone().into_boxed_slice());
+
+    herald_server::http::serve(state, config.listen, config.tls.as_ref(), trusted_proxies).await;
+
+    Ok(())
+}
+
+async fn migrate(config: &Config) -> Result<(), Error> {
+    let from = config
+        .backend()
+        .get()
+        .await?
+        .migrate()
+        .await
+        .context("failed to migrate the database")?;
+
+    if from == SCHEMA_VERSION {
+        tracing::info!("database schema is up to date at version {}", from);
+    } else {
+        tracing::info!(
+            "migrated database schema from version {} to {}",
+            from,
+            SCHEMA_VERSION
+        );
+    }
+
+    Ok(())
+}
+
+async fn dead_letters(
+    config: &Config,
+    limit: u64,
+) -> Result<(), Error> {
+    let failures = config
+        .backend()
+        .get()
+        .await?
+        .dead_letters(None, limit)
+        .await?;
+
+    for failure in failures {
+        println!(
+            "{} device={} push={} sent={} sender={} reason={}",
+            failure.failed_ts.as_i64(),
+            hex(failure.device.as_ref()),
+            failure.push_id,
+            failure
+                .push_ts
+                .map(|ts| ts.as_i64().to_string())
+                .unwrap_or_else(|| "-".into()),
+            failure
+                .sender
+                .map(|key| hex(key.as_ref()))
+                .unwrap_or_else(|| "-".into()),
+            failure.reason,
+        );
+    }
+
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
 }
@@ -22,6 +22,8 @@ pub struct Config {
     pub nts_conversation: ConversationId,
     /// The server this account is registered on
     pub home_server: SocketAddr,
+    /// Pin for the home server's TLS certificate, if the server uses TLS
+    pub server_pin: Option<CertPin>,
     /// The default preferred expiration period
     pub preferred_expiration: ExpirationPeriod,
 }
@@ -75,7 +75,7 @@ impl std::fmt::Display for Error {
         match self {
             SendStatus(n) => write!(
                 out,
-                "Unknown message send status: found {}, expected 0, or 1",
+                "Unknown message send status: found {}, expected 0, 1, or 2",
                 n
             ),
             ReceiptStatus(n) => write!(
@@ -96,6 +96,7 @@ impl TryFrom<u8> for SendStatus {
         match n {
             0 => Ok(Self::NoAck),
             1 => Ok(Self::Ack),
+            2 => Ok(Self::Delivered),
             i => Err(i),
         }
     }
@@ -108,6 +109,7 @@ impl std::convert::TryFrom<i64> for SendStatus {
         match n {
             0 => Ok(Self::NoAck),
             1 => Ok(Self::Ack),
+            2 => Ok(Self::Delivered),
             i => Err(Error::SendStatus(i as i64)),
         }
     }
@@ -123,6 +125,7 @@ impl From<(SendStatus, Option<ReceiptStatus>)> for Status {
             (_, Some(R::Read)) => Status::Read,
             (S::NoAck, _) => Status::NoAck,
             (S::Ack, _) => Status::Ack,
+            (S::Delivered, _) => Status::Delivered,
         }
     }
 }
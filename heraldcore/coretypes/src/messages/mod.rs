@@ -236,6 +236,8 @@ pub enum SendStatus {
     NoAck = 0,
     /// Acknowledged by server
     Ack = 1,
+    /// Fetched from the server by at least one recipient device
+    Delivered = 2,
 }
 
 #[derive(Hash, Debug, Clone, PartialEq, Eq, Copy, Ord, PartialOrd)]
@@ -256,10 +258,12 @@ pub enum Status {
     NoAck = 0,
     /// Acknowledged by server
     Ack = 1,
+    /// Fetched from the server by at least one recipient device
+    Delivered = 2,
     /// Received by user
-    Received = 2,
+    Received = 3,
     /// Read by the recipient
-    Read = 3,
+    Read = 4,
 }
 
 #[derive(Clone, Debug)]
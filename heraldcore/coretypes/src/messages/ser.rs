@@ -17,7 +17,7 @@ impl De for SendStatus {
             E!(
                 CustomError(format!(
                     "expected a value between {} and {}, found {}",
-                    0, 1, u
+                    0, 2, u
                 )),
                 d.data.clone(),
                 d.ix
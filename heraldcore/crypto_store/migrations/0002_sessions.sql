@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS session_inits (
+    public_key BLOB NOT NULL PRIMARY KEY,
+    init BLOB NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS prekeys (
+    public_key BLOB NOT NULL PRIMARY KEY,
+    keypair BLOB NOT NULL,
+    -- the medium-term prekey, at most one at a time
+    signed BOOLEAN NOT NULL DEFAULT 0,
+    -- a previous signed prekey, kept around for sessions started before rotation
+    retired BOOLEAN NOT NULL DEFAULT 0,
+    ts INTEGER NOT NULL
+);
@@ -0,0 +1,27 @@
+-- Sender key tables
+CREATE TABLE IF NOT EXISTS own_sender_ratchets (
+    group_id BLOB NOT NULL PRIMARY KEY,
+    ratchet BLOB NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS sender_key_recipients (
+    group_id BLOB NOT NULL,
+    public_key BLOB NOT NULL,
+    PRIMARY KEY(group_id, public_key)
+);
+
+CREATE TABLE IF NOT EXISTS sender_ratchets (
+    group_id BLOB NOT NULL,
+    public_key BLOB NOT NULL,
+    ratchet BLOB NOT NULL,
+    PRIMARY KEY(group_id, public_key)
+);
+
+CREATE TABLE IF NOT EXISTS sender_keys (
+    group_id BLOB NOT NULL,
+    public_key BLOB NOT NULL,
+    generation INTEGER NOT NULL,
+    ix INTEGER NOT NULL,
+    key BLOB NOT NULL,
+    PRIMARY KEY(group_id, public_key, generation, ix)
+);
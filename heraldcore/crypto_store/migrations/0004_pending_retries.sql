@@ -0,0 +1,2 @@
+-- number of times the payload was retransmitted to this recipient
+ALTER TABLE pending ADD COLUMN retries INTEGER NOT NULL DEFAULT 0;
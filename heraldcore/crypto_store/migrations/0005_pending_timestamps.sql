@@ -0,0 +1,7 @@
+-- when the payload was first queued, payloads from before this migration count from now
+ALTER TABLE payloads ADD COLUMN ts INTEGER NOT NULL DEFAULT 0;
+
+UPDATE payloads SET ts = CAST(strftime('%s', 'now') AS INTEGER) * 1000;
+
+-- when the payload was last sent to this recipient, 0 resends it at the next check
+ALTER TABLE pending ADD COLUMN sent INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- one-time prekeys that were used to start a session, until they are replaced on the server
+CREATE TABLE IF NOT EXISTS consumed_prekeys (
+    public_key BLOB NOT NULL PRIMARY KEY
+);
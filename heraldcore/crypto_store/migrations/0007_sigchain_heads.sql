@@ -0,0 +1,25 @@
+-- storing a sigchain again used to insert its links again
+DELETE FROM sigchain_endorsements
+WHERE rowid NOT IN (
+    SELECT MIN(rowid) FROM sigchain_endorsements GROUP BY outer_signature
+);
+
+DELETE FROM sigchain_deprecations
+WHERE rowid NOT IN (
+    SELECT MIN(rowid) FROM sigchain_deprecations GROUP BY signature
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS sigchain_endorsements_sig_ix
+    ON sigchain_endorsements(outer_signature);
+
+CREATE UNIQUE INDEX IF NOT EXISTS sigchain_deprecations_sig_ix
+    ON sigchain_deprecations(signature);
+
+-- the latest head of each user's sigchain that this client has seen
+CREATE TABLE IF NOT EXISTS sigchain_heads (
+    user_id TEXT NOT NULL PRIMARY KEY,
+    -- number of links, counting the initial one
+    len INTEGER NOT NULL,
+    -- running hash over the signatures on every link
+    hash BLOB NOT NULL
+);
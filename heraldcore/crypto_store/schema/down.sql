@@ -1,10 +0,0 @@
-DROP TABLE IF EXISTS members;
-
-DROP TABLE IF EXISTS ratchets;
-
-DROP TABLE IF EXISTS payloads;
-DROP TABLE IF EXISTS pending;
-
-DROP TABLE IF EXISTS sigchain_genesis;
-DROP TABLE IF EXISTS sigchain_endorsements;
-DROP TABLE IF EXISTS sigchain_deprecations;
@@ -1,8 +1,32 @@
 use super::*;
-use coremacros::exit_err;
+use coremacros::w;
+use herald_common::{
+    sqlite_cipher,
+    sqlite_migrations::{self, MigrationError},
+};
+use kcl::aead;
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
 use platform_dirs::db_dir;
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+/// The header every plain SQLite database starts with, which encrypted ones don't.
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// Schema migrations for the crypto store, see `herald_common::sqlite_migrations`.
+const MIGRATIONS: &[&str] = &[
+    include_str!("../migrations/0001_initial.sql"),
+    include_str!("../migrations/0002_sessions.sql"),
+    include_str!("../migrations/0003_sender_keys.sql"),
+    include_str!("../migrations/0004_pending_retries.sql"),
+    include_str!("../migrations/0005_pending_timestamps.sql"),
+    include_str!("../migrations/0006_consumed_prekeys.sql"),
+    include_str!("../migrations/0007_sigchain_heads.sql"),
+];
 
 pub struct Conn<'conn>(rusqlite::Transaction<'conn>);
 
@@ -32,31 +56,92 @@ impl StoreLike for Conn<'_> {
 
 static CONN: OnceCell<Mutex<rusqlite::Connection>> = OnceCell::new();
 
-pub fn raw_conn() -> &'static Mutex<rusqlite::Connection> {
-    CONN.get_or_init(|| {
+static KEY: OnceCell<aead::Key> = OnceCell::new();
+
+/// The connection to the crypto store, opened the first time this is called.
+///
+/// Fails with `Error::Locked` if the store is encrypted and its key hasn't been set, in which
+/// case it can be opened once it has.
+pub fn raw_conn() -> Result<&'static Mutex<rusqlite::Connection>, errors::Error> {
+    CONN.get_or_try_init(|| -> Result<_, errors::Error> {
         kcl::init();
 
-        let path = db_dir().join("ck.sqlite3");
-        let mut conn = exit_err!(rusqlite::Connection::open(path));
-        let tx = exit_err!(conn.transaction());
+        let path = db_path();
+
+        if KEY.get().is_none() && w!(is_encrypted(&path)) {
+            return Err(errors::Error::Locked);
+        }
+
+        let mut conn = w!(rusqlite::Connection::open(path));
+
+        if let Some(key) = KEY.get() {
+            w!(sqlite_cipher::set_key(&conn, key));
+        }
+
+        w!(migrate(&mut conn));
 
-        exit_err!(tx.execute_batch(include_str!("../schema/up.sql")));
-        exit_err!(tx.commit());
-        Mutex::new(conn)
+        Ok(Mutex::new(conn))
     })
 }
 
+/// Indicates whether the database at `path` exists and isn't plain SQLite, i.e., is encrypted.
+fn is_encrypted(path: &Path) -> Result<bool, io::Error> {
+    let mut file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+
+    let mut header = [0u8; 16];
+    match file.read_exact(&mut header) {
+        Ok(()) => Ok(&header != SQLITE_HEADER),
+        // SQLite creates databases empty until something is written
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// The path of the crypto store's database.
+pub fn db_path() -> std::path::PathBuf {
+    db_dir().join("ck.sqlite3")
+}
+
+/// Sets the key the crypto store is encrypted with.
+///
+/// This must be done before the store is first used, and can only be done once.
+pub fn set_key(key: aead::Key) -> Result<(), errors::Error> {
+    match KEY.get() {
+        Some(old) if *old == key => Ok(()),
+        None if CONN.get().is_none() => {
+            drop(KEY.set(key));
+            Ok(())
+        }
+        _ => Err(errors::Error::AlreadyOpen),
+    }
+}
+
+/// Indicates whether the crypto store has been opened.
+pub fn is_open() -> bool {
+    CONN.get().is_some()
+}
+
 pub fn as_conn(raw: &mut rusqlite::Connection) -> Result<Conn, rusqlite::Error> {
     let tx = raw.transaction()?;
     Ok(tx.into())
 }
 
-pub fn reset() -> Result<(), rusqlite::Error> {
-    let mut raw = raw_conn().lock();
+/// Brings the crypto store's schema up to date, returning the version it started at.
+pub fn migrate(raw: &mut rusqlite::Connection) -> Result<u32, MigrationError> {
+    sqlite_migrations::migrate(raw, MIGRATIONS)
+}
+
+pub fn reset() -> Result<(), errors::Error> {
+    let mut raw = raw_conn()?.lock();
     let conn = Conn::from(raw.transaction()?);
 
-    conn.execute_batch(include_str!("../schema/down.sql"))?;
-    conn.execute_batch(include_str!("../schema/up.sql"))?;
+    conn.execute_batch(include_str!("sql/drop_all.sql"))?;
+    sqlite_migrations::set_schema_version(&conn, 0)?;
+    sqlite_migrations::migrate_tx(&conn, MIGRATIONS)?;
 
     conn.commit()?;
     Ok(())
@@ -67,11 +152,7 @@ pub(crate) fn in_memory() -> rusqlite::Connection {
     use coremacros::womp;
 
     let mut conn = rusqlite::Connection::open_in_memory().expect(womp!());
-    let tx = conn.transaction().expect(womp!());
-
-    tx.execute_batch(include_str!("../schema/up.sql"))
-        .expect(womp!());
-    tx.commit().expect(womp!());
+    migrate(&mut conn).expect(womp!());
 
     conn
 }
@@ -82,12 +163,84 @@ mod tests {
 
     #[test]
     fn get() {
-        let mut raw = raw_conn().lock();
+        let mut raw = raw_conn().unwrap().lock();
         let _conn = Conn::from(raw.transaction().unwrap());
     }
 
+    #[test]
+    fn detects_encrypted_databases() {
+        use coremacros::womp;
+
+        let dir = std::env::temp_dir().join(format!("herald-crypto-store-{}", std::process::id()));
+        fs::create_dir_all(&dir).expect(womp!());
+
+        let plain = dir.join("plain.sqlite3");
+        rusqlite::Connection::open(&plain)
+            .expect(womp!())
+            .execute_batch("CREATE TABLE t(x INTEGER)")
+            .expect(womp!());
+
+        let empty = dir.join("empty.sqlite3");
+        fs::write(&empty, b"").expect(womp!());
+
+        let encrypted = dir.join("encrypted.sqlite3");
+        fs::write(&encrypted, &[0xab; 1024][..]).expect(womp!());
+
+        assert!(!is_encrypted(&dir.join("missing.sqlite3")).expect(womp!()));
+        assert!(!is_encrypted(&plain).expect(womp!()));
+        assert!(!is_encrypted(&empty).expect(womp!()));
+        assert!(is_encrypted(&encrypted).expect(womp!()));
+
+        fs::remove_dir_all(&dir).expect(womp!());
+    }
+
     #[test]
     fn reset() {
         super::reset().unwrap();
     }
+
+    #[test]
+    fn migrates_unversioned_db() {
+        use coremacros::womp;
+        use rusqlite::NO_PARAMS;
+
+        // a database created before schema versions were tracked
+        let mut conn = rusqlite::Connection::open_in_memory().expect(womp!());
+        conn.execute_batch(include_str!("../migrations/0001_initial.sql"))
+            .expect(womp!());
+        conn.execute_batch(
+            "INSERT INTO payloads(payload_id, payload) VALUES (x'01', x'02');
+            INSERT INTO pending(pending_payload_id, recipient) VALUES (x'01', x'03');",
+        )
+        .expect(womp!());
+
+        assert_eq!(migrate(&mut conn).expect(womp!()), 0);
+        assert_eq!(
+            sqlite_migrations::schema_version(&conn).expect(womp!()),
+            MIGRATIONS.len() as u32
+        );
+
+        // pending payloads survive, and are resent at the next check rather than expired
+        let (ts, sent, retries): (i64, i64, i64) = conn
+            .query_row(
+                "SELECT ts, sent, retries FROM payloads
+                JOIN pending ON pending_payload_id = payload_id",
+                NO_PARAMS,
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .expect(womp!());
+        assert!(ts > 0);
+        assert_eq!(sent, 0);
+        assert_eq!(retries, 0);
+
+        let inits: i64 = conn
+            .query_row("SELECT COUNT(*) FROM session_inits", NO_PARAMS, |row| {
+                row.get(0)
+            })
+            .expect(womp!());
+        assert_eq!(inits, 0);
+
+        // migrating an up to date database does nothing
+        assert_eq!(migrate(&mut conn).expect(womp!()), MIGRATIONS.len() as u32);
+    }
 }
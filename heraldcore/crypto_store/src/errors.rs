@@ -1,16 +1,28 @@
-use herald_common::*;
+use herald_common::{sqlite_cipher::CipherError, sqlite_migrations::MigrationError, *};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Database error: {0}")]
     Db(#[from] rusqlite::Error),
+    #[error("Migration: {0}")]
+    Migration(#[from] MigrationError),
     #[error("Kson: {0}")]
     Kson(#[from] kson::prelude::KsonError),
     #[error("Bad signature")]
     BadSignature,
     #[error("Bad key")]
     BadKey,
+    #[error("Bad hash")]
+    BadHash,
     #[error("Invalid user id")]
     InvalidUserId(#[from] InvalidUserId),
+    #[error("Encryption: {0}")]
+    Cipher(#[from] CipherError),
+    #[error("Store was already opened")]
+    AlreadyOpen,
+    #[error("Store is encrypted and its key hasn't been set")]
+    Locked,
+    #[error("IO: {0}")]
+    Io(#[from] std::io::Error),
 }
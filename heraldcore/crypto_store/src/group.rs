@@ -0,0 +1,307 @@
+use crate::*;
+use coremacros::w;
+use herald_common::{kson, sig, UQ};
+use kcl::aead;
+use ratchet_chat::{
+    protocol::{GroupId, GroupStore},
+    ratchet::sender,
+};
+
+impl<'conn> GroupStore for Conn<'conn> {
+    fn get_own_sender_ratchet(
+        &mut self,
+        group: GroupId,
+    ) -> Result<Option<sender::Ratchet>, Self::Error> {
+        let mut stmt = st!(self, "group", "get_own");
+
+        let params = np!("@group_id": group.0.as_ref());
+        let mut res = w!(stmt.query_map_named(params, |row| row.get::<_, Vec<u8>>("ratchet")));
+
+        let bytes = ok_none!(w!(res.next().transpose()));
+
+        Ok(Some(w!(kson::from_slice(&bytes))))
+    }
+
+    fn store_own_sender_ratchet(
+        &mut self,
+        group: GroupId,
+        ratchet: sender::Ratchet,
+    ) -> Result<(), Self::Error> {
+        let mut stmt = st!(self, "group", "store_own");
+
+        let params = np!("@group_id": group.0.as_ref(), "@ratchet": kson::to_vec(&ratchet));
+
+        w!(stmt.execute_named(params));
+
+        Ok(())
+    }
+
+    fn sender_key_recipients(
+        &mut self,
+        group: GroupId,
+    ) -> Result<Vec<sig::PublicKey>, Self::Error> {
+        let mut stmt = st!(self, "group", "recipients");
+
+        let params = np!("@group_id": group.0.as_ref());
+        let res = w!(stmt.query_map_named(params, |row| row.get::<_, Vec<u8>>("public_key")));
+
+        let mut out = Vec::new();
+        for raw in res {
+            out.push(w!(sig::PublicKey::from_slice(&w!(raw)).ok_or(Error::BadKey)));
+        }
+
+        Ok(out)
+    }
+
+    fn add_sender_key_recipients(
+        &mut self,
+        group: GroupId,
+        to: &[sig::PublicKey],
+    ) -> Result<(), Self::Error> {
+        let mut stmt = st!(self, "group", "add_recipient");
+
+        for key in to {
+            w!(stmt.execute_named(np!(
+                "@group_id": group.0.as_ref(),
+                "@public_key": key.as_ref()
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn clear_sender_key_recipients(
+        &mut self,
+        group: GroupId,
+    ) -> Result<(), Self::Error> {
+        let mut stmt = st!(self, "group", "clear_recipients");
+
+        w!(stmt.execute_named(np!("@group_id": group.0.as_ref())));
+
+        Ok(())
+    }
+
+    fn remove_sender_key_recipient(
+        &mut self,
+        group: GroupId,
+        key: sig::PublicKey,
+    ) -> Result<(), Self::Error> {
+        let mut stmt = st!(self, "group", "remove_recipient");
+
+        w!(stmt.execute_named(np!(
+            "@group_id": group.0.as_ref(),
+            "@public_key": key.as_ref()
+        )));
+
+        Ok(())
+    }
+
+    fn get_sender_ratchet(
+        &mut self,
+        group: GroupId,
+        from: sig::PublicKey,
+    ) -> Result<Option<sender::Ratchet>, Self::Error> {
+        let mut stmt = st!(self, "group", "get");
+
+        let params = np!("@group_id": group.0.as_ref(), "@public_key": from.as_ref());
+        let mut res = w!(stmt.query_map_named(params, |row| row.get::<_, Vec<u8>>("ratchet")));
+
+        let bytes = ok_none!(w!(res.next().transpose()));
+
+        Ok(Some(w!(kson::from_slice(&bytes))))
+    }
+
+    fn store_sender_ratchet(
+        &mut self,
+        group: GroupId,
+        from: sig::PublicKey,
+        ratchet: sender::Ratchet,
+    ) -> Result<(), Self::Error> {
+        let mut stmt = st!(self, "group", "store");
+
+        let params = np!(
+            "@group_id": group.0.as_ref(),
+            "@public_key": from.as_ref(),
+            "@ratchet": kson::to_vec(&ratchet)
+        );
+
+        w!(stmt.execute_named(params));
+
+        Ok(())
+    }
+
+    fn store_sender_keys(
+        &mut self,
+        group: GroupId,
+        from: sig::PublicKey,
+        generation: u32,
+        keys: sender::ExtraKeys,
+    ) -> Result<(), Self::Error> {
+        let mut stmt = st!(self, "group", "store_key");
+
+        for (ix, key) in keys {
+            w!(stmt.execute_named(np!(
+                "@group_id": group.0.as_ref(),
+                "@public_key": from.as_ref(),
+                "@generation": generation,
+                "@ix": ix,
+                "@key": key.as_ref()
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn take_sender_key(
+        &mut self,
+        group: GroupId,
+        from: sig::PublicKey,
+        generation: u32,
+        ix: u32,
+    ) -> Result<Option<aead::Key>, Self::Error> {
+        let params = np!(
+            "@group_id": group.0.as_ref(),
+            "@public_key": from.as_ref(),
+            "@generation": generation,
+            "@ix": ix
+        );
+
+        let raw_key = {
+            let mut stmt = st!(self, "group", "get_key");
+            let mut res = w!(stmt.query_map_named(params, |row| row.get::<_, Vec<u8>>("key")));
+
+            ok_none!(w!(res.next().transpose()))
+        };
+
+        w!(st!(self, "group", "remove_key").execute_named(params));
+
+        Ok(Some(w!(
+            aead::Key::from_slice(&raw_key).ok_or(Error::BadKey)
+        )))
+    }
+}
+
+impl<'conn> Conn<'conn> {
+    /// The groups `key` has been sent our current sender key for.
+    pub fn sender_key_groups(
+        &mut self,
+        key: sig::PublicKey,
+    ) -> Result<Vec<GroupId>, Error> {
+        let mut stmt = st!(self, "group", "recipient_groups");
+
+        let params = np!("@public_key": key.as_ref());
+        let res = w!(stmt.query_map_named(params, |row| row.get::<_, Vec<u8>>("group_id")));
+
+        let mut out = Vec::new();
+        for raw in res {
+            out.push(GroupId(w!(UQ::from_slice(&w!(raw)).ok_or(Error::BadKey))));
+        }
+
+        Ok(out)
+    }
+
+    /// Forgets the sender keys received from `key`, and that it was sent ours.
+    pub fn purge_sender_keys(
+        &mut self,
+        key: sig::PublicKey,
+    ) -> Result<(), Error> {
+        let params = np!("@public_key": key.as_ref());
+
+        w!(st!(self, "group", "purge_ratchets").execute_named(params));
+        w!(st!(self, "group", "purge_keys").execute_named(params));
+        w!(st!(self, "group", "purge_recipient").execute_named(params));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::in_memory;
+    use coremacros::womp;
+    use herald_common::UQ;
+
+    #[test]
+    fn sender_ratchet_ops() {
+        let mut conn = in_memory();
+        let mut conn = Conn::from(conn.transaction().expect(womp!()));
+
+        let group = GroupId(UQ::gen_new());
+        let pk = *sig::KeyPair::gen_new().public();
+
+        assert!(conn.get_own_sender_ratchet(group).expect(womp!()).is_none());
+        assert!(conn.get_sender_ratchet(group, pk).expect(womp!()).is_none());
+
+        conn.store_own_sender_ratchet(group, sender::Ratchet::gen_new(3))
+            .expect(womp!());
+        conn.store_sender_ratchet(group, pk, sender::Ratchet::gen_new(5))
+            .expect(womp!());
+
+        assert_eq!(
+            conn.get_own_sender_ratchet(group)
+                .expect(womp!())
+                .map(|r| r.generation()),
+            Some(3)
+        );
+        assert_eq!(
+            conn.get_sender_ratchet(group, pk)
+                .expect(womp!())
+                .map(|r| r.generation()),
+            Some(5)
+        );
+    }
+
+    #[test]
+    fn sender_key_recipient_ops() {
+        let mut conn = in_memory();
+        let mut conn = Conn::from(conn.transaction().expect(womp!()));
+
+        let group = GroupId(UQ::gen_new());
+        let pk = *sig::KeyPair::gen_new().public();
+
+        assert!(conn.sender_key_recipients(group).expect(womp!()).is_empty());
+
+        conn.add_sender_key_recipients(group, &[pk, pk])
+            .expect(womp!());
+        assert_eq!(conn.sender_key_recipients(group).expect(womp!()), vec![pk]);
+
+        conn.remove_sender_key_recipient(group, pk).expect(womp!());
+        assert!(conn.sender_key_recipients(group).expect(womp!()).is_empty());
+
+        conn.add_sender_key_recipients(group, &[pk]).expect(womp!());
+        conn.clear_sender_key_recipients(group).expect(womp!());
+        assert!(conn.sender_key_recipients(group).expect(womp!()).is_empty());
+    }
+
+    #[test]
+    fn sender_key_ops() {
+        let mut conn = in_memory();
+        let mut conn = Conn::from(conn.transaction().expect(womp!()));
+
+        let group = GroupId(UQ::gen_new());
+        let pk = *sig::KeyPair::gen_new().public();
+        let key = aead::Key::new();
+
+        assert!(conn
+            .take_sender_key(group, pk, 0, 1)
+            .expect(womp!())
+            .is_none());
+
+        conn.store_sender_keys(group, pk, 0, vec![(1, key.clone())])
+            .expect(womp!());
+
+        assert!(conn
+            .take_sender_key(group, pk, 1, 1)
+            .expect(womp!())
+            .is_none());
+        assert_eq!(
+            conn.take_sender_key(group, pk, 0, 1).expect(womp!()),
+            Some(key)
+        );
+        assert!(conn
+            .take_sender_key(group, pk, 0, 1)
+            .expect(womp!())
+            .is_none());
+    }
+}
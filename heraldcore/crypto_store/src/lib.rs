@@ -7,7 +7,7 @@ pub use coremacros::w;
 pub use errors::Error;
 pub mod connection;
 pub mod prelude {
-    pub use crate::connection::{as_conn, raw_conn, Conn};
+    pub use crate::connection::{as_conn, raw_conn, set_key, Conn};
     pub use crate::errors::Error;
     pub use ratchet_chat::protocol::*;
     pub use ratchet_chat::ratchet::double::KeyStore;
@@ -42,7 +42,9 @@ macro_rules! st {
 }
 
 mod errors;
+mod group;
 mod keystore;
 mod pending;
+mod prekeys;
 mod ratchet;
 mod sigstore;
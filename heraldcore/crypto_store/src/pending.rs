@@ -1,6 +1,6 @@
 use crate::*;
 use coremacros::w;
-use herald_common::{kson, sig};
+use herald_common::{kson, sig, Time};
 use ratchet_chat::protocol::{Payload, PayloadId, PendingStore};
 use rusqlite::NO_PARAMS;
 
@@ -35,6 +35,34 @@ impl<'conn> PendingStore for Conn<'conn> {
 
         Ok(())
     }
+
+    fn note_retry(
+        &mut self,
+        id: PayloadId,
+        to: sig::PublicKey,
+    ) -> Result<u32, Self::Error> {
+        let id = kson::to_vec(&id);
+        Ok(w!(self.note_retry_raw(&id, to)))
+    }
+
+    fn pending_to(
+        &mut self,
+        to: sig::PublicKey,
+    ) -> Result<Vec<PayloadId>, Self::Error> {
+        let mut stmt = st!(self, "pending", "to_recipient");
+        let params = np!("@recipient": to.as_ref());
+
+        let res = w!(stmt.query_map_named(params, |row| {
+            row.get::<_, Vec<u8>>("pending_payload_id")
+        }));
+
+        let mut out = Vec::new();
+        for raw in res {
+            out.push(w!(kson::from_slice(&w!(raw))));
+        }
+
+        Ok(out)
+    }
 }
 
 impl<'conn> Conn<'conn> {
@@ -52,6 +80,103 @@ impl<'conn> Conn<'conn> {
         Ok(())
     }
 
+    /// The pending payloads that were last sent before `sent_before`, along with the recipient
+    /// that hasn't acknowledged them yet.
+    pub fn unacked_pending(
+        &mut self,
+        sent_before: Time,
+    ) -> Result<Vec<(PayloadId, sig::PublicKey)>, Error> {
+        let mut stmt = st!(self, "pending", "unacked");
+        let params = np!("@cutoff": sent_before.as_i64());
+
+        let res = w!(stmt.query_map_named(params, |row| {
+            Ok((
+                row.get::<_, Vec<u8>>("pending_payload_id")?,
+                row.get::<_, Vec<u8>>("recipient")?,
+            ))
+        }));
+
+        let mut out = Vec::new();
+        for row in res {
+            let (raw_id, raw_key) = w!(row);
+
+            let id = w!(kson::from_slice(&raw_id));
+            let key = w!(sig::PublicKey::from_slice(&raw_key).ok_or(Error::BadKey));
+
+            out.push((id, key));
+        }
+
+        Ok(out)
+    }
+
+    /// Records that `id` was just sent to `to` again.
+    pub fn mark_sent(
+        &mut self,
+        id: PayloadId,
+        to: sig::PublicKey,
+    ) -> Result<(), Error> {
+        let id = kson::to_vec(&id);
+        let mut stmt = st!(self, "pending", "mark_sent");
+
+        let params = np!(
+            "@id": id,
+            "@recipient": to.as_ref(),
+            "@ts": *Time::now().as_i64()
+        );
+        w!(stmt.execute_named(params));
+
+        Ok(())
+    }
+
+    /// Stops waiting on acknowledgements from devices that have been deprecated.
+    pub fn drop_deprecated_pending(&mut self) -> Result<usize, Error> {
+        let dropped = w!(st!(self, "pending", "del_deprecated").execute(NO_PARAMS));
+        w!(self.gc_pending());
+
+        Ok(dropped)
+    }
+
+    /// Stops waiting on acknowledgements from `to`, returning how many payloads it was sent.
+    pub fn drop_pending_to(
+        &mut self,
+        to: sig::PublicKey,
+    ) -> Result<usize, Error> {
+        let params = np!("@recipient": to.as_ref());
+
+        let dropped = w!(st!(self, "pending", "del_recipient").execute_named(params));
+        w!(self.gc_pending());
+
+        Ok(dropped)
+    }
+
+    /// Deletes payloads created before `created_before`, whether or not they were acknowledged.
+    pub fn expire_pending(
+        &mut self,
+        created_before: Time,
+    ) -> Result<usize, Error> {
+        let params = np!("@cutoff": created_before.as_i64());
+
+        let expired = w!(st!(self, "pending", "expire").execute_named(params));
+        w!(self.gc_pending());
+
+        Ok(expired)
+    }
+
+    fn note_retry_raw(
+        &mut self,
+        id: &[u8],
+        to: sig::PublicKey,
+    ) -> Result<u32, rusqlite::Error> {
+        let params = np!("@id": id, "@recipient": to.as_ref());
+
+        w!(st!(self, "pending", "note_retry").execute_named(params));
+
+        let mut stmt = st!(self, "pending", "retries");
+        let mut res = w!(stmt.query_map_named(params, |row| row.get::<_, u32>("retries")));
+
+        Ok(w!(res.next().transpose()).unwrap_or(0))
+    }
+
     fn get_pending_payload_raw(
         &mut self,
         id: &[u8],
@@ -88,9 +213,10 @@ impl<'conn> Conn<'conn> {
         recips: &[sig::PublicKey],
     ) -> Result<(), rusqlite::Error> {
         let mut stmt = st!(self, "pending", "add_pending");
+        let ts = *Time::now().as_i64();
 
         for recip in recips {
-            let params = np!("@id": id, "@recipient": recip.as_ref());
+            let params = np!("@id": id, "@recipient": recip.as_ref(), "@ts": ts);
             w!(stmt.execute_named(params));
         }
 
@@ -104,7 +230,11 @@ impl<'conn> Conn<'conn> {
     ) -> Result<(), rusqlite::Error> {
         let mut stmt = st!(self, "pending", "add_payload");
 
-        let params = np!("@id": id, "@payload": kson::to_vec(payload));
+        let params = np!(
+            "@id": id,
+            "@payload": kson::to_vec(payload),
+            "@ts": *Time::now().as_i64()
+        );
         w!(stmt.execute_named(params));
         Ok(())
     }
@@ -154,6 +284,10 @@ mod tests {
         let stored_payload = conn.get_pending_payload_raw(id2).expect(womp!());
         assert_eq!(stored_payload, Some(payload()));
 
+        assert_eq!(conn.note_retry_raw(id2, recips[0]).expect(womp!()), 1);
+        assert_eq!(conn.note_retry_raw(id2, recips[0]).expect(womp!()), 2);
+        assert_eq!(conn.note_retry_raw(id2, recips[1]).expect(womp!()), 1);
+
         conn.del_pending_raw(id2, recips[0]).expect(womp!());
 
         let stored_payload = conn.get_pending_payload_raw(id2).expect(womp!());
@@ -163,4 +297,83 @@ mod tests {
 
         assert!(conn.get_pending_payload_raw(id1).expect(womp!()).is_none());
     }
+
+    #[test]
+    fn pending_expiry() {
+        let mut conn = in_memory();
+        let mut conn = Conn::from(conn.transaction().expect(womp!()));
+
+        let id = PayloadId::gen_new();
+        let recip = *sig::KeyPair::gen_new().public();
+
+        conn.add_pending_payload(id, Bytes::from_static(b"a"), &[recip])
+            .expect(womp!());
+
+        let past = Time::from(*Time::now().as_i64() - 1000);
+        let future = Time::from(*Time::now().as_i64() + 1000);
+
+        assert!(conn.unacked_pending(past).expect(womp!()).is_empty());
+        assert_eq!(
+            conn.unacked_pending(future).expect(womp!()),
+            vec![(id, recip)]
+        );
+
+        // nothing is deprecated yet
+        assert_eq!(conn.drop_deprecated_pending().expect(womp!()), 0);
+
+        assert_eq!(conn.expire_pending(past).expect(womp!()), 0);
+        assert_eq!(conn.expire_pending(future).expect(womp!()), 1);
+
+        assert!(conn.get_pending_payload(id).expect(womp!()).is_none());
+        assert!(conn.unacked_pending(future).expect(womp!()).is_empty());
+    }
+
+    #[test]
+    fn drop_deprecated_pending() {
+        use ratchet_chat::protocol::SigStore;
+        use std::convert::TryInto;
+
+        let mut conn = in_memory();
+        let mut conn = Conn::from(conn.transaction().expect(womp!()));
+
+        let uid: herald_common::UserId = "a".try_into().expect(womp!());
+        let kp1 = sig::KeyPair::gen_new();
+        let kp2 = sig::KeyPair::gen_new();
+
+        conn.start_sigchain(sig::sign_ser(&kp1, uid))
+            .expect(womp!());
+        conn.extend_sigchain(
+            uid,
+            sig::sign_ser(&kp1, sig::SigUpdate::Endorse(sig::sign_ser(&kp2, uid))),
+        )
+        .expect(womp!());
+
+        let id = PayloadId::gen_new();
+        conn.add_pending_payload(
+            id,
+            Bytes::from_static(b"a"),
+            &[*kp1.public(), *kp2.public()],
+        )
+        .expect(womp!());
+
+        // nothing is deprecated yet
+        assert_eq!(conn.drop_deprecated_pending().expect(womp!()), 0);
+
+        conn.extend_sigchain(
+            uid,
+            sig::sign_ser(&kp1, sig::SigUpdate::Deprecate(*kp2.public())),
+        )
+        .expect(womp!());
+
+        assert_eq!(conn.drop_deprecated_pending().expect(womp!()), 1);
+
+        let future = Time::from(*Time::now().as_i64() + 1000);
+        assert_eq!(
+            conn.unacked_pending(future).expect(womp!()),
+            vec![(id, *kp1.public())]
+        );
+
+        // the payload is kept for the remaining recipient
+        assert!(conn.get_pending_payload(id).expect(womp!()).is_some());
+    }
 }
@@ -0,0 +1,284 @@
+use crate::*;
+use coremacros::w;
+use herald_common::{kson, Prekey, Time};
+use kcl::kx;
+use ratchet_chat::protocol::PrekeyStore;
+use rusqlite::NO_PARAMS;
+
+impl<'conn> PrekeyStore for Conn<'conn> {
+    fn store_prekey(
+        &mut self,
+        kp: kx::KeyPair,
+    ) -> Result<(), Self::Error> {
+        let mut stmt = st!(self, "prekeys", "store");
+
+        let params = np!(
+            "@public_key": kp.public().as_ref(),
+            "@keypair": kson::to_vec(&kp),
+            "@ts": *Time::now().as_i64()
+        );
+
+        w!(stmt.execute_named(params));
+
+        Ok(())
+    }
+
+    fn get_prekey(
+        &mut self,
+        pre: Prekey,
+    ) -> Result<Option<kx::KeyPair>, Self::Error> {
+        let mut stmt = st!(self, "prekeys", "get");
+
+        let params = np!("@public_key": pre.0.as_ref());
+        let mut res = w!(stmt.query_map_named(params, |row| row.get::<_, Vec<u8>>("keypair")));
+
+        let bytes = ok_none!(w!(res.next().transpose()));
+
+        Ok(Some(w!(kson::from_slice(&bytes))))
+    }
+
+    fn del_prekey(
+        &mut self,
+        pre: Prekey,
+    ) -> Result<(), Self::Error> {
+        let params = np!("@public_key": pre.0.as_ref());
+
+        // one-time prekeys are remembered so that they can be replaced on the server
+        w!(st!(self, "prekeys", "consume").execute_named(params));
+        w!(st!(self, "prekeys", "del").execute_named(params));
+
+        Ok(())
+    }
+}
+
+impl<'conn> Conn<'conn> {
+    /// Makes `kp` the signed prekey, retiring the current one.
+    ///
+    /// The previously retired prekey is deleted, so sessions can still be started with a signed
+    /// prekey for one rotation period after it has been replaced. Returns the prekey that was
+    /// retired, if any.
+    pub fn rotate_signed_prekey(
+        &mut self,
+        kp: kx::KeyPair,
+    ) -> Result<Option<Prekey>, Error> {
+        let old = w!(self.signed_prekey()).map(|(pre, _)| pre);
+
+        w!(st!(self, "prekeys", "del_retired").execute(NO_PARAMS));
+        w!(st!(self, "prekeys", "retire_signed").execute(NO_PARAMS));
+
+        let mut stmt = st!(self, "prekeys", "store_signed");
+
+        let params = np!(
+            "@public_key": kp.public().as_ref(),
+            "@keypair": kson::to_vec(&kp),
+            "@ts": *Time::now().as_i64()
+        );
+
+        w!(stmt.execute_named(params));
+
+        Ok(old)
+    }
+
+    /// The current signed prekey and when it was created.
+    pub fn signed_prekey(&mut self) -> Result<Option<(Prekey, Time)>, Error> {
+        let mut stmt = st!(self, "prekeys", "signed");
+
+        let mut res = w!(stmt.query_map(NO_PARAMS, |row| {
+            Ok((
+                row.get::<_, Vec<u8>>("public_key")?,
+                row.get::<_, i64>("ts")?,
+            ))
+        }));
+
+        let (raw_pre, ts) = ok_none!(w!(res.next().transpose()));
+        let pre = w!(Prekey::from_slice(&raw_pre).ok_or(Error::BadKey));
+
+        Ok(Some((pre, Time::from(ts))))
+    }
+
+    /// Deletes a prekey without marking it as consumed, for keys that never made it to the
+    /// server.
+    pub fn discard_prekey(
+        &mut self,
+        pre: Prekey,
+    ) -> Result<(), Error> {
+        let mut stmt = st!(self, "prekeys", "del");
+
+        w!(stmt.execute_named(np!("@public_key": pre.0.as_ref())));
+
+        Ok(())
+    }
+
+    /// One-time prekeys that have been used to start a session, but haven't been replaced on the
+    /// server yet.
+    pub fn consumed_prekeys(&mut self) -> Result<Vec<Prekey>, Error> {
+        let mut stmt = st!(self, "prekeys", "consumed");
+
+        let res = w!(stmt.query_map(NO_PARAMS, |row| row.get::<_, Vec<u8>>("public_key")));
+
+        let mut out = Vec::new();
+        for raw in res {
+            out.push(w!(Prekey::from_slice(&w!(raw)).ok_or(Error::BadKey)));
+        }
+
+        Ok(out)
+    }
+
+    /// Forgets about a consumed prekey, once it has been replaced.
+    pub fn del_consumed_prekey(
+        &mut self,
+        pre: Prekey,
+    ) -> Result<(), Error> {
+        let mut stmt = st!(self, "prekeys", "del_consumed");
+
+        w!(stmt.execute_named(np!("@public_key": pre.0.as_ref())));
+
+        Ok(())
+    }
+
+    /// Deletes the one-time prekeys created before `before` that aren't in `held`.
+    ///
+    /// The server hands out each one-time prekey once, and the secrets of those that never ended
+    /// up starting a session would otherwise be kept forever. Returns the number of prekeys
+    /// deleted.
+    pub fn prune_prekeys(
+        &mut self,
+        held: &[Prekey],
+        before: Time,
+    ) -> Result<usize, Error> {
+        let stale = {
+            let mut stmt = st!(self, "prekeys", "one_time_before");
+
+            let res = w!(stmt.query_map_named(np!("@ts": before.as_i64()), |row| {
+                row.get::<_, Vec<u8>>("public_key")
+            }));
+
+            let mut stale = Vec::new();
+            for raw in res {
+                let pre = w!(Prekey::from_slice(&w!(raw)).ok_or(Error::BadKey));
+
+                if !held.contains(&pre) {
+                    stale.push(pre);
+                }
+            }
+
+            stale
+        };
+
+        for pre in stale.iter().copied() {
+            w!(self.discard_prekey(pre));
+        }
+
+        Ok(stale.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::in_memory;
+    use coremacros::womp;
+
+    #[test]
+    fn prekey_ops() {
+        let mut conn = in_memory();
+        let mut conn = Conn::from(conn.transaction().expect(womp!()));
+
+        let kp = kx::KeyPair::gen_new();
+        let pre = Prekey(*kp.public());
+
+        assert!(conn.get_prekey(pre).expect(womp!()).is_none());
+
+        conn.store_prekey(kp.clone()).expect(womp!());
+
+        let stored = conn.get_prekey(pre).expect(womp!()).expect(womp!());
+        assert_eq!(stored.public(), kp.public());
+
+        conn.del_prekey(pre).expect(womp!());
+
+        assert!(conn.get_prekey(pre).expect(womp!()).is_none());
+        assert_eq!(conn.consumed_prekeys().expect(womp!()), vec![pre]);
+
+        conn.del_consumed_prekey(pre).expect(womp!());
+
+        assert!(conn.consumed_prekeys().expect(womp!()).is_empty());
+    }
+
+    #[test]
+    fn prune_prekeys() {
+        let mut conn = in_memory();
+        let mut conn = Conn::from(conn.transaction().expect(womp!()));
+
+        let held = kx::KeyPair::gen_new();
+        let handed_out = kx::KeyPair::gen_new();
+        let signed = kx::KeyPair::gen_new();
+
+        conn.store_prekey(held.clone()).expect(womp!());
+        conn.store_prekey(handed_out.clone()).expect(womp!());
+        conn.rotate_signed_prekey(signed.clone()).expect(womp!());
+
+        let held = Prekey(*held.public());
+        let handed_out = Prekey(*handed_out.public());
+        let signed = Prekey(*signed.public());
+
+        // nothing is old enough yet
+        assert_eq!(
+            conn.prune_prekeys(&[held], Time::from(0)).expect(womp!()),
+            0
+        );
+
+        let later = Time::from(*Time::now().as_i64() + 1);
+        assert_eq!(conn.prune_prekeys(&[held], later).expect(womp!()), 1);
+
+        assert!(conn.get_prekey(held).expect(womp!()).is_some());
+        assert!(conn.get_prekey(handed_out).expect(womp!()).is_none());
+        assert!(conn.get_prekey(signed).expect(womp!()).is_some());
+
+        // pruned prekeys weren't used, so there is nothing to replace
+        assert!(conn.consumed_prekeys().expect(womp!()).is_empty());
+    }
+
+    #[test]
+    fn signed_prekey_rotation() {
+        let mut conn = in_memory();
+        let mut conn = Conn::from(conn.transaction().expect(womp!()));
+
+        assert!(conn.signed_prekey().expect(womp!()).is_none());
+
+        let first = kx::KeyPair::gen_new();
+        let first_pre = Prekey(*first.public());
+
+        assert!(conn
+            .rotate_signed_prekey(first.clone())
+            .expect(womp!())
+            .is_none());
+        assert_eq!(
+            conn.signed_prekey().expect(womp!()).map(|(p, _)| p),
+            Some(first_pre)
+        );
+
+        let second = kx::KeyPair::gen_new();
+        let second_pre = Prekey(*second.public());
+
+        assert_eq!(
+            conn.rotate_signed_prekey(second).expect(womp!()),
+            Some(first_pre)
+        );
+        assert_eq!(
+            conn.signed_prekey().expect(womp!()).map(|(p, _)| p),
+            Some(second_pre)
+        );
+
+        // retired prekeys are still usable
+        assert!(conn.get_prekey(first_pre).expect(womp!()).is_some());
+
+        // and using them doesn't mark them as consumed
+        conn.del_prekey(first_pre).expect(womp!());
+        assert!(conn.consumed_prekeys().expect(womp!()).is_empty());
+
+        conn.rotate_signed_prekey(kx::KeyPair::gen_new())
+            .expect(womp!());
+        assert!(conn.get_prekey(second_pre).expect(womp!()).is_some());
+        assert!(conn.get_prekey(first_pre).expect(womp!()).is_none());
+    }
+}
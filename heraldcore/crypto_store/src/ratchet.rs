@@ -1,7 +1,10 @@
 use crate::*;
 use coremacros::w;
 use herald_common::{kson, sig};
-use ratchet_chat::{protocol::RatchetStore, ratchet::double as dr};
+use ratchet_chat::{
+    protocol::{RatchetStore, SessionInit},
+    ratchet::double as dr,
+};
 
 impl<'conn> RatchetStore for Conn<'conn> {
     fn get_ratchet(
@@ -25,6 +28,59 @@ impl<'conn> RatchetStore for Conn<'conn> {
 
         Ok(())
     }
+
+    fn get_session_init(
+        &mut self,
+        with: sig::PublicKey,
+    ) -> Result<Option<SessionInit>, Self::Error> {
+        let mut stmt = st!(self, "ratchet", "get_init");
+
+        let params = np!("@public_key": with.as_ref());
+        let mut res = w!(stmt.query_map_named(params, |row| row.get::<_, Vec<u8>>("init")));
+
+        let bytes = ok_none!(w!(res.next().transpose()));
+
+        Ok(Some(w!(kson::from_slice(&bytes))))
+    }
+
+    fn store_session_init(
+        &mut self,
+        with: sig::PublicKey,
+        init: SessionInit,
+    ) -> Result<(), Self::Error> {
+        let mut stmt = st!(self, "ratchet", "store_init");
+
+        let params = np!("@public_key": with.as_ref(), "@init": kson::to_vec(&init));
+
+        w!(stmt.execute_named(params));
+
+        Ok(())
+    }
+
+    fn del_session_init(
+        &mut self,
+        with: sig::PublicKey,
+    ) -> Result<(), Self::Error> {
+        let mut stmt = st!(self, "ratchet", "del_init");
+
+        let params = np!("@public_key": with.as_ref());
+
+        w!(stmt.execute_named(params));
+
+        Ok(())
+    }
+
+    fn del_ratchet(
+        &mut self,
+        with: sig::PublicKey,
+    ) -> Result<(), Self::Error> {
+        let params = np!("@public_key": with.as_ref());
+
+        w!(st!(self, "ratchet", "del").execute_named(params));
+        w!(st!(self, "ratchet", "del_init").execute_named(params));
+
+        Ok(())
+    }
 }
 
 impl<'conn> Conn<'conn> {
@@ -60,6 +116,7 @@ mod tests {
     use super::*;
     use crate::connection::in_memory;
     use coremacros::womp;
+    use herald_common::Prekey;
 
     #[test]
     fn ratchet_ops() {
@@ -79,5 +136,33 @@ mod tests {
         conn.store_ratchet_raw(pk, dummy(2)).expect(womp!());
 
         assert_eq!(conn.get_ratchet_raw(pk).expect(womp!()), Some(dummy(2)));
+
+        conn.del_ratchet(pk).expect(womp!());
+
+        assert!(conn.get_ratchet_raw(pk).expect(womp!()).is_none());
+    }
+
+    #[test]
+    fn session_init_ops() {
+        let mut conn = in_memory();
+        let mut conn = Conn::from(conn.transaction().expect(womp!()));
+
+        let pk = *sig::KeyPair::gen_new().public();
+
+        let init = SessionInit {
+            ephemeral: *kcl::kx::KeyPair::gen_new().public(),
+            signed_prekey: Prekey(*kcl::kx::KeyPair::gen_new().public()),
+            one_time_prekey: None,
+        };
+
+        assert!(conn.get_session_init(pk).expect(womp!()).is_none());
+
+        conn.store_session_init(pk, init).expect(womp!());
+
+        assert_eq!(conn.get_session_init(pk).expect(womp!()), Some(init));
+
+        conn.del_session_init(pk).expect(womp!());
+
+        assert!(conn.get_session_init(pk).expect(womp!()).is_none());
     }
 }
@@ -4,11 +4,11 @@ use herald_common::{
     sig::{self, PublicKey as PK, Signature as Sig},
     SigMeta, Signed, Time, UserId,
 };
-use ratchet_chat::protocol::SigStore;
+use ratchet_chat::protocol::{RatchetStore, SigStore};
 use rusqlite::NO_PARAMS;
 use std::{convert::TryFrom, ops::Not};
 
-use Error::{BadKey, BadSignature};
+use Error::{BadHash, BadKey, BadSignature};
 
 mod chain_getters;
 mod checks;
@@ -21,17 +21,21 @@ impl<'conn> SigStore for Conn<'conn> {
         &mut self,
         init: Signed<UserId>,
     ) -> Result<(), Self::Error> {
-        let mut stmt = st!(self, "sigchain", "start");
+        {
+            let mut stmt = st!(self, "sigchain", "start");
 
-        let sig = init.sig();
-        let params = np!(
-            "@user_id": init.data(),
-            "@ts": init.timestamp(),
-            "@signature": sig.as_ref(),
-            "@signed_by": init.signed_by().as_ref()
-        );
+            let sig = init.sig();
+            let params = np!(
+                "@user_id": init.data(),
+                "@ts": init.timestamp(),
+                "@signature": sig.as_ref(),
+                "@signed_by": init.signed_by().as_ref()
+            );
 
-        w!(stmt.execute_named(params));
+            w!(stmt.execute_named(params));
+        }
+
+        w!(self.update_head(*init.data()));
 
         Ok(())
     }
@@ -72,20 +76,26 @@ impl<'conn> SigStore for Conn<'conn> {
                 w!(stmt.execute_named(params));
             }
             Deprecate(key) => {
-                let mut stmt = st!(self, "sigchain", "deprecate");
+                {
+                    let mut stmt = st!(self, "sigchain", "deprecate");
 
-                let params = np!(
-                    "@ts": outer_ts,
-                    "@signature": outer_sig.as_ref(),
-                    "@signed_by": outer_signed_by.as_ref(),
-                    "@key": key.as_ref(),
-                    "@user_id": from
-                );
+                    let params = np!(
+                        "@ts": outer_ts,
+                        "@signature": outer_sig.as_ref(),
+                        "@signed_by": outer_signed_by.as_ref(),
+                        "@key": key.as_ref(),
+                        "@user_id": from
+                    );
 
-                w!(stmt.execute_named(params));
+                    w!(stmt.execute_named(params));
+                }
+
+                w!(self.purge_key(key));
             }
         };
 
+        w!(self.update_head(from));
+
         Ok(())
     }
 
@@ -134,6 +144,76 @@ impl<'conn> SigStore for Conn<'conn> {
 }
 
 impl<'conn> Conn<'conn> {
+    /// Tears down everything shared with `key`: its sessions, its sender keys and the payloads
+    /// waiting on its acknowledgement. Our sender keys for the groups it was sent them in are
+    /// replaced.
+    ///
+    /// This is done whenever `key` is deprecated, so a revoked device can't read anything sent
+    /// after it was revoked.
+    pub fn purge_key(
+        &mut self,
+        key: PK,
+    ) -> Result<(), <Self as StoreLike>::Error> {
+        let groups = w!(self.sender_key_groups(key));
+
+        w!(self.del_ratchet(key));
+        w!(self.purge_sender_keys(key));
+        w!(self.drop_pending_to(key));
+
+        for group in groups {
+            w!(ratchet_chat::protocol::rotate_sender_key(self, group));
+        }
+
+        Ok(())
+    }
+
+    /// The head of the latest version of `of`'s sigchain that has been stored.
+    ///
+    /// Later versions of the chain, e.g., as sent by the server, must extend this head.
+    pub fn sigchain_head(
+        &self,
+        of: UserId,
+    ) -> Result<Option<sig::SigChainHead>, <Self as StoreLike>::Error> {
+        let mut stmt = st!(self, "sigchain", "get_head");
+        let params = np!("@user_id": of);
+
+        let mut res = w!(stmt.query_map_named(params, |row| {
+            Ok((
+                w!(row.get::<_, i64>("len")),
+                w!(row.get::<_, Vec<u8>>("hash")),
+            ))
+        }));
+
+        let (len, raw_hash) = w!(ok_none!(res.next()));
+        let hash = w!(sig::ChainHash::from_slice(&raw_hash).ok_or(BadHash));
+
+        Ok(Some(sig::SigChainHead {
+            len: len as u64,
+            hash,
+        }))
+    }
+
+    fn update_head(
+        &mut self,
+        of: UserId,
+    ) -> Result<(), <Self as StoreLike>::Error> {
+        let head = match w!(self.get_sigchain(of)) {
+            Some(chain) => chain.head(),
+            None => return Ok(()),
+        };
+
+        let mut stmt = st!(self, "sigchain", "set_head");
+        let params = np!(
+            "@user_id": of,
+            "@len": head.len as i64,
+            "@hash": head.hash.as_ref()
+        );
+
+        w!(stmt.execute_named(params));
+
+        Ok(())
+    }
+
     pub fn get_all_users(&mut self) -> Result<Vec<UserId>, <Self as StoreLike>::Error> {
         let mut stmt = st!(self, "sigchain", "all_users");
 
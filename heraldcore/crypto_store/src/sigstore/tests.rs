@@ -75,3 +75,115 @@ fn chain_ops() {
     assert_eq!(conn.all_active_keys().expect(womp!()), vec![*kp2.public()]);
     assert_eq!(conn.get_all_users().expect(womp!()), vec![user_id]);
 }
+
+#[test]
+fn deprecation_purges_key() {
+    use herald_common::{Bytes, UQ};
+    use ratchet_chat::{
+        protocol::{GroupId, GroupStore, PayloadId, PendingStore},
+        ratchet::sender,
+    };
+
+    let mut conn = in_memory();
+    let mut conn = Conn::from(conn.transaction().expect(womp!()));
+
+    let user_id: UserId = "a".try_into().expect(womp!());
+    let kp1 = sig::KeyPair::gen_new();
+    let kp2 = sig::KeyPair::gen_new();
+
+    conn.start_sigchain(sig::sign_ser(&kp1, user_id))
+        .expect(womp!());
+    conn.extend_sigchain(
+        user_id,
+        sig::sign_ser(&kp1, sig::SigUpdate::Endorse(sig::sign_ser(&kp2, user_id))),
+    )
+    .expect(womp!());
+
+    let id = PayloadId::gen_new();
+    let group = GroupId(UQ::gen_new());
+    let other_group = GroupId(UQ::gen_new());
+
+    conn.add_pending_payload(
+        id,
+        Bytes::from_static(b"a"),
+        &[*kp1.public(), *kp2.public()],
+    )
+    .expect(womp!());
+    for group in &[group, other_group] {
+        conn.store_own_sender_ratchet(*group, sender::Ratchet::gen_new(0))
+            .expect(womp!());
+    }
+    conn.add_sender_key_recipients(group, &[*kp1.public(), *kp2.public()])
+        .expect(womp!());
+    conn.add_sender_key_recipients(other_group, &[*kp1.public()])
+        .expect(womp!());
+
+    conn.extend_sigchain(
+        user_id,
+        sig::sign_ser(&kp1, sig::SigUpdate::Deprecate(*kp2.public())),
+    )
+    .expect(womp!());
+
+    assert!(conn.pending_to(*kp2.public()).expect(womp!()).is_empty());
+    assert_eq!(conn.pending_to(*kp1.public()).expect(womp!()), vec![id]);
+
+    // the revoked device had our sender key for `group`, so it was replaced and has to be sent
+    // out again
+    let ratchet = conn
+        .get_own_sender_ratchet(group)
+        .expect(womp!())
+        .expect(womp!());
+    assert_eq!(ratchet.generation(), 1);
+    assert!(conn.sender_key_recipients(group).expect(womp!()).is_empty());
+
+    let ratchet = conn
+        .get_own_sender_ratchet(other_group)
+        .expect(womp!())
+        .expect(womp!());
+    assert_eq!(ratchet.generation(), 0);
+    assert_eq!(
+        conn.sender_key_recipients(other_group).expect(womp!()),
+        vec![*kp1.public()]
+    );
+}
+
+#[test]
+fn heads() {
+    let mut conn = in_memory();
+    let mut conn = Conn::from(conn.transaction().expect(womp!()));
+
+    let user_id: UserId = "a".try_into().expect(womp!());
+    let kp1 = sig::KeyPair::gen_new();
+    let kp2 = sig::KeyPair::gen_new();
+
+    assert!(conn.sigchain_head(user_id).expect(womp!()).is_none());
+
+    let init = sig::sign_ser(&kp1, user_id);
+    conn.start_sigchain(init).expect(womp!());
+
+    let head = conn.sigchain_head(user_id).expect(womp!()).expect(womp!());
+    assert_eq!(head.len, 1);
+    assert_eq!(
+        head,
+        sig::SigChain {
+            initial: init,
+            sig_chain: vec![],
+        }
+        .head()
+    );
+
+    let endorse = sig::sign_ser(&kp1, sig::SigUpdate::Endorse(sig::sign_ser(&kp2, user_id)));
+
+    // storing the same link twice doesn't lengthen the chain
+    conn.extend_sigchain(user_id, endorse).expect(womp!());
+    conn.extend_sigchain(user_id, endorse).expect(womp!());
+
+    let chain = conn.get_sigchain(user_id).expect(womp!()).expect(womp!());
+    assert_eq!(chain.sig_chain.len(), 1);
+
+    assert_eq!(
+        conn.sigchain_head(user_id).expect(womp!()),
+        Some(chain.head())
+    );
+    assert!(chain.extends(&head));
+}
@@ -0,0 +1,19 @@
+DROP TABLE IF EXISTS members;
+
+DROP TABLE IF EXISTS ratchets;
+DROP TABLE IF EXISTS session_inits;
+DROP TABLE IF EXISTS prekeys;
+DROP TABLE IF EXISTS consumed_prekeys;
+
+DROP TABLE IF EXISTS own_sender_ratchets;
+DROP TABLE IF EXISTS sender_key_recipients;
+DROP TABLE IF EXISTS sender_ratchets;
+DROP TABLE IF EXISTS sender_keys;
+
+DROP TABLE IF EXISTS payloads;
+DROP TABLE IF EXISTS pending;
+
+DROP TABLE IF EXISTS sigchain_genesis;
+DROP TABLE IF EXISTS sigchain_endorsements;
+DROP TABLE IF EXISTS sigchain_deprecations;
+DROP TABLE IF EXISTS sigchain_heads;
@@ -0,0 +1,2 @@
+INSERT OR IGNORE INTO sender_key_recipients(group_id, public_key)
+VALUES(@group_id, @public_key)
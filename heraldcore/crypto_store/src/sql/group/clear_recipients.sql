@@ -0,0 +1,4 @@
+DELETE FROM
+    sender_key_recipients
+WHERE
+    group_id = @group_id
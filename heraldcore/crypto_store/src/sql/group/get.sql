@@ -0,0 +1,7 @@
+SELECT
+    ratchet
+FROM
+    sender_ratchets
+WHERE
+    group_id = @group_id AND
+    public_key = @public_key
@@ -0,0 +1,6 @@
+SELECT
+    ratchet
+FROM
+    own_sender_ratchets
+WHERE
+    group_id = @group_id
@@ -0,0 +1,4 @@
+DELETE FROM
+    sender_keys
+WHERE
+    public_key = @public_key
@@ -0,0 +1,4 @@
+DELETE FROM
+    sender_ratchets
+WHERE
+    public_key = @public_key
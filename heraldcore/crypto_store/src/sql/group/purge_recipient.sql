@@ -0,0 +1,4 @@
+DELETE FROM
+    sender_key_recipients
+WHERE
+    public_key = @public_key
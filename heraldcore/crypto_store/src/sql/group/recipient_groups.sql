@@ -0,0 +1,6 @@
+SELECT DISTINCT
+    group_id
+FROM
+    sender_key_recipients
+WHERE
+    public_key = @public_key
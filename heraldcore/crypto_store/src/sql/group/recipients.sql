@@ -0,0 +1,6 @@
+SELECT
+    public_key
+FROM
+    sender_key_recipients
+WHERE
+    group_id = @group_id
@@ -0,0 +1,7 @@
+DELETE FROM
+    sender_keys
+WHERE
+    group_id = @group_id AND
+    public_key = @public_key AND
+    generation = @generation AND
+    ix = @ix
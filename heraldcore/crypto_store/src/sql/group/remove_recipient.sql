@@ -0,0 +1,5 @@
+DELETE FROM
+    sender_key_recipients
+WHERE
+    group_id = @group_id AND
+    public_key = @public_key
@@ -0,0 +1,2 @@
+INSERT OR REPLACE INTO sender_ratchets(group_id, public_key, ratchet)
+VALUES(@group_id, @public_key, @ratchet)
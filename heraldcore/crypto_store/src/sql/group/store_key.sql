@@ -0,0 +1,8 @@
+INSERT OR IGNORE INTO sender_keys(
+    group_id,
+    public_key,
+    generation,
+    ix,
+    key
+)
+VALUES(@group_id, @public_key, @generation, @ix, @key)
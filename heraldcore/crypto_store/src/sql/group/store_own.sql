@@ -0,0 +1,2 @@
+INSERT OR REPLACE INTO own_sender_ratchets(group_id, ratchet)
+VALUES(@group_id, @ratchet)
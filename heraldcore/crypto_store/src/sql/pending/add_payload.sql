@@ -1,5 +1,6 @@
 INSERT OR IGNORE INTO payloads (
    payload_id,
-   payload
+   payload,
+   ts
 )
-VALUES(@id, @payload);
+VALUES(@id, @payload, @ts);
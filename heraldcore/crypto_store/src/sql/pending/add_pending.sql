@@ -1,8 +1,10 @@
 INSERT INTO pending (
    pending_payload_id,
-   recipient
+   recipient,
+   sent
 )
 VALUES(
     @id,
-    @recipient
+    @recipient,
+    @ts
 );
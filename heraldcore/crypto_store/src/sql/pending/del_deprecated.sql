@@ -0,0 +1,9 @@
+DELETE FROM
+    pending
+WHERE
+    recipient IN (
+        SELECT
+            key
+        FROM
+            sigchain_deprecations
+    )
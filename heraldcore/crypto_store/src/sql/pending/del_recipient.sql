@@ -0,0 +1,4 @@
+DELETE FROM
+    pending
+WHERE
+    recipient = @recipient
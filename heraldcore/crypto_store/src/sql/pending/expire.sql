@@ -0,0 +1,11 @@
+DELETE FROM
+    pending
+WHERE
+    pending_payload_id IN (
+        SELECT
+            payload_id
+        FROM
+            payloads
+        WHERE
+            ts < @cutoff
+    )
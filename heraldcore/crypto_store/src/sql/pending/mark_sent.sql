@@ -0,0 +1,7 @@
+UPDATE
+    pending
+SET
+    sent = @ts
+WHERE
+    pending_payload_id = @id AND
+    recipient = @recipient
@@ -0,0 +1,7 @@
+UPDATE
+    pending
+SET
+    retries = retries + 1
+WHERE
+    pending_payload_id = @id AND
+    recipient = @recipient
@@ -0,0 +1,7 @@
+SELECT
+    retries
+FROM
+    pending
+WHERE
+    pending_payload_id = @id AND
+    recipient = @recipient
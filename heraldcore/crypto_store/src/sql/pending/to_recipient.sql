@@ -0,0 +1,6 @@
+SELECT
+    pending_payload_id
+FROM
+    pending
+WHERE
+    recipient = @recipient
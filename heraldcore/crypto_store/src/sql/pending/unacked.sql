@@ -0,0 +1,7 @@
+SELECT
+    pending_payload_id,
+    recipient
+FROM
+    pending
+WHERE
+    sent < @cutoff
@@ -0,0 +1,9 @@
+INSERT OR IGNORE INTO consumed_prekeys(public_key)
+SELECT
+    public_key
+FROM
+    prekeys
+WHERE
+    public_key = @public_key AND
+    signed = 0 AND
+    retired = 0
@@ -0,0 +1,4 @@
+SELECT
+    public_key
+FROM
+    consumed_prekeys
@@ -0,0 +1,4 @@
+DELETE FROM
+    prekeys
+WHERE
+    public_key = @public_key
@@ -0,0 +1,4 @@
+DELETE FROM
+    consumed_prekeys
+WHERE
+    public_key = @public_key
@@ -0,0 +1,4 @@
+DELETE FROM
+    prekeys
+WHERE
+    retired = 1
@@ -0,0 +1,6 @@
+SELECT
+   keypair
+FROM
+   prekeys
+WHERE
+    public_key = @public_key
@@ -0,0 +1,8 @@
+SELECT
+    public_key
+FROM
+    prekeys
+WHERE
+    signed = 0 AND
+    retired = 0 AND
+    ts < @ts
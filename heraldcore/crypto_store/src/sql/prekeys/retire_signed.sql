@@ -0,0 +1,7 @@
+UPDATE
+    prekeys
+SET
+    signed = 0,
+    retired = 1
+WHERE
+    signed = 1
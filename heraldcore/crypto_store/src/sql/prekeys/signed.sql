@@ -0,0 +1,8 @@
+SELECT
+    public_key,
+    ts
+FROM
+    prekeys
+WHERE
+    signed = 1
+LIMIT 1
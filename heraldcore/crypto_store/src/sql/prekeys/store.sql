@@ -0,0 +1,2 @@
+INSERT OR REPLACE INTO prekeys(public_key, keypair, ts)
+VALUES(@public_key, @keypair, @ts)
@@ -0,0 +1,2 @@
+INSERT OR REPLACE INTO prekeys(public_key, keypair, signed, ts)
+VALUES(@public_key, @keypair, 1, @ts)
@@ -0,0 +1,4 @@
+DELETE FROM
+    ratchets
+WHERE
+    public_key = @public_key
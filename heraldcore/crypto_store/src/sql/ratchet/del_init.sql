@@ -0,0 +1,4 @@
+DELETE FROM
+    session_inits
+WHERE
+    public_key = @public_key
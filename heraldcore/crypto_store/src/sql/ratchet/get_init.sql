@@ -0,0 +1,6 @@
+SELECT
+   init
+FROM
+   session_inits
+WHERE
+    public_key = @public_key
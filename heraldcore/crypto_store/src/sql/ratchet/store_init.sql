@@ -0,0 +1,2 @@
+INSERT OR REPLACE INTO session_inits(public_key, init)
+VALUES(@public_key, @init)
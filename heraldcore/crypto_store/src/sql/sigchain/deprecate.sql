@@ -1,4 +1,4 @@
-INSERT INTO sigchain_deprecations(
+INSERT OR IGNORE INTO sigchain_deprecations(
     ts,
     signature,
     signed_by,
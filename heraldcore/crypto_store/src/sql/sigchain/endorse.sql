@@ -1,4 +1,4 @@
-INSERT INTO sigchain_endorsements (
+INSERT OR IGNORE INTO sigchain_endorsements (
     outer_ts,
     outer_signature,
     outer_signed_by,
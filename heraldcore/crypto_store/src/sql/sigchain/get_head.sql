@@ -0,0 +1,7 @@
+SELECT
+    len,
+    hash
+FROM
+    sigchain_heads
+WHERE
+    user_id = @user_id
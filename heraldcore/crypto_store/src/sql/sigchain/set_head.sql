@@ -0,0 +1,10 @@
+INSERT OR REPLACE INTO sigchain_heads(
+    user_id,
+    len,
+    hash
+)
+VALUES(
+    @user_id,
+    @len,
+    @hash
+);
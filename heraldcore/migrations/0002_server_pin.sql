@@ -0,0 +1,2 @@
+-- Fingerprint of the home server's TLS certificate, NULL if the server doesn't use TLS
+ALTER TABLE config ADD COLUMN server_pin BLOB DEFAULT NULL;
@@ -0,0 +1,27 @@
+-- Key the home server signs delivery receipts with, NULL until it has been fetched
+ALTER TABLE config ADD COLUMN server_key BLOB DEFAULT NULL;
+
+-- pushes we sent that carried a message, for matching up delivery receipts
+CREATE TABLE sent_pushes (
+  -- id the server gave the push
+  push_id INTEGER NOT NULL,
+  -- id of the message the push carried
+  msg_id BLOB NOT NULL,
+  PRIMARY KEY(push_id, msg_id),
+  FOREIGN KEY(msg_id) REFERENCES messages(msg_id) ON DELETE CASCADE
+);
+
+CREATE INDEX sent_push_msg_id_ix ON sent_pushes(msg_id);
+
+-- devices the server has delivered our pushes to
+CREATE TABLE push_deliveries (
+  -- id the server gave the push
+  push_id INTEGER NOT NULL,
+  -- key of the device the push was delivered to
+  device BLOB NOT NULL,
+  -- user the device belongs to
+  user_id TEXT NOT NULL,
+  -- when we were told about the delivery
+  delivered_ts INTEGER NOT NULL,
+  PRIMARY KEY(push_id, device)
+);
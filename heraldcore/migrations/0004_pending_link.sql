@@ -0,0 +1,13 @@
+-- link request this device is waiting on, kept so linking survives a restart
+CREATE TABLE pending_link (
+  -- there is only ever one
+  id INTEGER PRIMARY KEY CHECK (id = 0),
+  -- keypair of this device, sealed like the identity key
+  kp BLOB NOT NULL,
+  -- the request shown to the existing device
+  request BLOB NOT NULL,
+  -- server the account is registered on
+  home_server TEXT NOT NULL,
+  -- fingerprint of the server's TLS certificate, NULL if it doesn't use TLS
+  server_pin BLOB DEFAULT NULL
+);
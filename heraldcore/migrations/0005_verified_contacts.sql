@@ -0,0 +1,8 @@
+-- contacts the user has checked the safety number with
+CREATE TABLE verified_contacts (
+  -- user id of the contact
+  user_id TEXT PRIMARY KEY NOT NULL,
+  -- fingerprint of the contact's keys when they were verified
+  fingerprint BLOB NOT NULL,
+  FOREIGN KEY(user_id) REFERENCES users(user_id) ON DELETE CASCADE
+);
@@ -1,11 +1,21 @@
+use crate::cmessages::AddedToConvo;
+use coretypes::conversation::ExpirationPeriod;
 use herald_common::*;
 use herald_ids::ConversationId;
 
-#[derive(Ser, De, Hash, Debug, Clone, PartialEq, Eq)]
+#[derive(Ser, De, Debug, Clone, PartialEq, Eq)]
 /// A message sent to a specific user.
 pub enum UserMessage {
     /// A contact request
     Req(UserReq),
+    /// Sent by a newly linked device to the user's other devices, asking for the account's
+    /// settings, contacts and conversations.
+    SyncReq,
+    /// The account's settings, contacts and conversations, sent to a device that asked for them.
+    Sync(Box<AccountSync>),
+    /// Sigchain heads as the sender sees them, so that the recipient can tell if the server is
+    /// showing them a different version of either chain.
+    Heads(SigChainHeads),
 }
 
 #[derive(Ser, De, Hash, Debug, Clone, PartialEq, Eq)]
@@ -14,3 +24,46 @@ pub struct UserReq {
     /// The proposed conversation id.
     pub cid: ConversationId,
 }
+
+#[derive(Ser, De, Hash, Debug, Clone, Copy, PartialEq, Eq)]
+/// Sigchain heads, as seen by the sender of a message.
+pub struct SigChainHeads {
+    /// The head of the sender's sigchain
+    pub sender: sig::SigChainHead,
+    /// The head of the recipient's sigchain, if the sender has it
+    pub recipient: Option<sig::SigChainHead>,
+}
+
+#[derive(Ser, De, Debug, Clone, PartialEq, Eq)]
+/// Everything a newly linked device needs to pick up where the user's other devices are.
+pub struct AccountSync {
+    /// The user's display name
+    pub name: String,
+    /// The user's color
+    pub color: u32,
+    /// The user's colorscheme
+    pub colorscheme: u32,
+    /// The user's profile picture (as bytes)
+    pub profile_picture: Option<Vec<u8>>,
+    /// The default expiration period for new conversations
+    pub preferred_expiration: ExpirationPeriod,
+    /// The user's contacts, along with their pairwise conversations
+    pub contacts: Vec<SyncedContact>,
+    /// The group conversations the user is in
+    pub conversations: Vec<AddedToConvo>,
+}
+
+#[derive(Ser, De, Debug, Clone, PartialEq, Eq)]
+/// A contact, as sent to a newly linked device.
+pub struct SyncedContact {
+    /// The contact's id
+    pub id: UserId,
+    /// The contact's display name
+    pub name: String,
+    /// The contact's color
+    pub color: u32,
+    /// The contact's profile picture (as bytes)
+    pub profile_picture: Option<Vec<u8>>,
+    /// The pairwise conversation with the contact
+    pub pairwise_conversation: ConversationId,
+}
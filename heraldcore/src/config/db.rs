@@ -4,6 +4,9 @@ use coremacros::w;
 use rusqlite::named_params;
 use std::net::SocketAddr;
 
+/// Additional data for the identity key when it is sealed with the storage key.
+const KEYPAIR_AD: &[u8] = b"herald identity key";
+
 /// Get's the "Note to Self" conversation
 pub(crate) fn nts_conversation(conn: &rusqlite::Connection) -> Result<ConversationId, HErr> {
     let id = id(conn)?;
@@ -74,6 +77,7 @@ pub(crate) fn get(conn: &rusqlite::Connection) -> Result<Config, HErr> {
         colorscheme,
         nts_conversation,
         home_server,
+        server_pin,
         preferred_expiration,
     ) = w!(
         conn.query_row(include_str!("sql/get_config.sql"), NO_PARAMS, |row| {
@@ -85,6 +89,7 @@ pub(crate) fn get(conn: &rusqlite::Connection) -> Result<Config, HErr> {
                 row.get("colorscheme")?,
                 row.get("pairwise_conversation")?,
                 row.get::<_, String>("home_server")?,
+                row.get("server_pin")?,
                 row.get::<_, ExpirationPeriod>("preferred_expiration")?,
             ))
         })
@@ -98,6 +103,7 @@ pub(crate) fn get(conn: &rusqlite::Connection) -> Result<Config, HErr> {
         colorscheme,
         nts_conversation,
         home_server: home_server.parse()?,
+        server_pin,
         preferred_expiration,
     })
 }
@@ -129,6 +135,7 @@ pub(crate) fn keypair(conn: &rusqlite::Connection) -> Result<sig::KeyPair, HErr>
         NO_PARAMS,
         |row| { row.get(0) }
     ));
+    let raw_kp = w!(crate::db::open_secret(KEYPAIR_AD, raw_kp));
     let kp = w!(kson::from_bytes(raw_kp.into()));
     Ok(kp)
 }
@@ -151,6 +158,39 @@ pub(crate) fn home_server(conn: &rusqlite::Connection) -> Result<SocketAddr, HEr
     Ok(server_addr_raw.parse()?)
 }
 
+/// Gets the pin for the home server's TLS certificate
+pub(crate) fn server_pin(conn: &rusqlite::Connection) -> Result<Option<CertPin>, HErr> {
+    Ok(w!(conn.query_row(
+        include_str!("sql/server_pin.sql"),
+        NO_PARAMS,
+        |row| { row.get("server_pin") }
+    )))
+}
+
+/// Gets the key the home server signs delivery receipts with, if it has been fetched
+pub(crate) fn server_key(conn: &rusqlite::Connection) -> Result<Option<sig::PublicKey>, HErr> {
+    let raw: Option<Vec<u8>> = w!(conn.query_row(
+        include_str!("sql/server_key.sql"),
+        NO_PARAMS,
+        |row| { row.get("server_key") }
+    ));
+
+    raw.map(|raw| {
+        sig::PublicKey::from_slice(&raw)
+            .ok_or_else(|| HErr::HeraldError("invalid server key in config".into()))
+    })
+    .transpose()
+}
+
+/// Sets the key the home server signs delivery receipts with
+pub(crate) fn set_server_key(
+    conn: &rusqlite::Connection,
+    key: sig::PublicKey,
+) -> Result<(), HErr> {
+    w!(conn.execute(include_str!("sql/update_server_key.sql"), &[key.as_ref()]));
+    Ok(())
+}
+
 impl ConfigBuilder {
     /// Adds configuration.
     pub(crate) fn add_db(
@@ -165,6 +205,7 @@ impl ConfigBuilder {
             name,
             nts_conversation,
             home_server,
+            server_pin,
             preferred_expiration,
         } = self;
 
@@ -193,9 +234,10 @@ impl ConfigBuilder {
             include_str!("sql/add_config.sql"),
             named_params! {
                 "@id": id,
-                "@kp": kson::to_vec(&keypair),
+                "@kp": crate::db::seal_secret(KEYPAIR_AD, kson::to_vec(&keypair)),
                 "@colorscheme": colorscheme,
                 "@home_server": home_server.to_string(),
+                "@server_pin": server_pin,
                 "@preferred_expiration": preferred_expiration
             },
         ));
@@ -212,6 +254,7 @@ impl ConfigBuilder {
             colorscheme,
             nts_conversation: user.pairwise_conversation,
             home_server,
+            server_pin,
             preferred_expiration,
         };
 
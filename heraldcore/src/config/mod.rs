@@ -22,6 +22,7 @@ pub struct ConfigBuilder {
     color: Option<u32>,
     nts_conversation: Option<ConversationId>,
     home_server: Option<SocketAddr>,
+    server_pin: Option<CertPin>,
     preferred_expiration: Option<ExpirationPeriod>,
 }
 
@@ -39,6 +40,7 @@ impl ConfigBuilder {
             colorscheme: None,
             nts_conversation: None,
             home_server: None,
+            server_pin: None,
             preferred_expiration: None,
         }
     }
@@ -89,6 +91,16 @@ impl ConfigBuilder {
         self
     }
 
+    /// Sets the pin for the home server's TLS certificate.
+    /// Without a pin the client talks to the server in plaintext.
+    pub fn server_pin(
+        mut self,
+        server_pin: CertPin,
+    ) -> Self {
+        self.server_pin.replace(server_pin);
+        self
+    }
+
     /// Sets the preferred expiration period
     pub fn preferred_expiration(
         mut self,
@@ -143,6 +155,24 @@ pub fn home_server() -> Result<std::net::SocketAddr, HErr> {
     db::home_server(&db)
 }
 
+/// Gets the pin for the home server's TLS certificate, if it has one
+pub fn server_pin() -> Result<Option<CertPin>, HErr> {
+    let db = Database::get()?;
+    db::server_pin(&db)
+}
+
+/// Gets the key the home server signs delivery receipts with, if it has been fetched
+pub(crate) fn server_key() -> Result<Option<sig::PublicKey>, HErr> {
+    let db = Database::get()?;
+    db::server_key(&db)
+}
+
+/// Sets the key the home server signs delivery receipts with
+pub(crate) fn set_server_key(key: sig::PublicKey) -> Result<(), HErr> {
+    let db = Database::get()?;
+    db::set_server_key(&db, key)
+}
+
 /// Updates user's display name
 pub fn set_name(name: String) -> Result<NetworkAction, HErr> {
     let db = Database::get()?;
@@ -1,3 +1,3 @@
 INSERT INTO
-  config(id, kp, colorscheme, home_server, preferred_expiration)
-VALUES(@id, @kp, @colorscheme, @home_server, @preferred_expiration)
+  config(id, kp, colorscheme, home_server, server_pin, preferred_expiration)
+VALUES(@id, @kp, @colorscheme, @home_server, @server_pin, @preferred_expiration)
@@ -6,6 +6,7 @@ SELECT
   colorscheme,
   pairwise_conversation,
   home_server,
+  server_pin,
   preferred_expiration
 FROM
   config
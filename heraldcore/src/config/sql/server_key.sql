@@ -0,0 +1,6 @@
+SELECT
+    server_key
+FROM
+    config
+LIMIT
+    1
@@ -0,0 +1,6 @@
+SELECT
+    server_pin
+FROM
+    config
+LIMIT
+    1
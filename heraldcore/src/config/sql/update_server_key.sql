@@ -0,0 +1,4 @@
+UPDATE
+  config
+SET
+  server_key = ?
@@ -20,6 +20,40 @@ fn simple_add_get_set_config() {
     assert_eq!(config.color, crate::utils::id_to_color(id));
     assert_eq!(config.name.as_str(), id.as_str());
     assert!(config.profile_picture.is_none());
+    assert!(config.server_pin.is_none());
+}
+
+#[test]
+fn server_pin() {
+    let mut conn = Database::in_memory().expect(womp!());
+
+    let id = "HelloWorld".try_into().expect(womp!());
+    let kp = KeyPair::gen_new();
+    let pin = CertPin::of(b"not really a certificate");
+
+    ConfigBuilder::new(id, kp)
+        .server_pin(pin)
+        .add_db(&mut conn)
+        .expect(womp!());
+
+    assert_eq!(db::server_pin(&conn).expect(womp!()), Some(pin));
+    assert_eq!(db::get(&conn).expect(womp!()).server_pin, Some(pin));
+    assert_eq!(pin.to_string().parse::<CertPin>().expect(womp!()), pin);
+}
+
+#[test]
+fn server_key() {
+    let mut conn = Database::in_memory().expect(womp!());
+
+    let id = "HelloWorld".try_into().expect(womp!());
+    let kp = KeyPair::gen_new();
+    ConfigBuilder::new(id, kp).add_db(&mut conn).expect(womp!());
+
+    assert!(db::server_key(&conn).expect(womp!()).is_none());
+
+    let key = *KeyPair::gen_new().public();
+    db::set_server_key(&conn, key).expect(womp!());
+    assert_eq!(db::server_key(&conn).expect(womp!()), Some(key));
 }
 
 #[test]
@@ -0,0 +1,332 @@
+//! Optional at-rest encryption of the client's storage.
+//!
+//! A random storage key encrypts both the main store and the crypto store with SQLCipher, and
+//! seals the identity key inside the main store. The storage key is itself sealed with a key
+//! derived from the user's passphrase, and kept next to the databases in a key file. Changing
+//! the passphrase only reseals the storage key, so the databases never need to be re-encrypted.
+//!
+//! Encryption has to be enabled before the storage is created, and an encrypted storage has to
+//! be unlocked before it's used.
+
+use super::*;
+use herald_common::sqlite_cipher;
+use kcl::{aead, pwhash};
+use std::{fs, io::Write};
+
+/// Additional data for the sealed storage key.
+const STORAGE_KEY_AD: &[u8] = b"herald storage key";
+
+static STORAGE_KEY: OnceCell<aead::Key> = OnceCell::new();
+
+#[derive(Ser, De)]
+struct KeyFile {
+    /// Salt for the passphrase
+    salt: pwhash::Salt,
+    /// The storage key, sealed with the passphrase
+    sealed: Vec<u8>,
+}
+
+/// Indicates whether the storage is encrypted.
+pub fn is_encrypted() -> bool {
+    key_file_path().exists()
+}
+
+/// Indicates whether the storage is encrypted and hasn't been unlocked yet.
+pub fn is_locked() -> bool {
+    STORAGE_KEY.get().is_none() && is_encrypted()
+}
+
+/// Unlocks encrypted storage with the user's passphrase, failing with `HErr::BadPassphrase` if
+/// it is wrong.
+pub fn unlock(passphrase: &str) -> Result<(), HErr> {
+    if !is_encrypted() {
+        return Err(HErr::HeraldError("storage isn't encrypted".into()));
+    }
+
+    let key = w!(open_key_file(&key_file_path(), passphrase));
+
+    set_storage_key(key)
+}
+
+/// Encrypts the storage with `passphrase`.
+///
+/// This has to be done before registering, since storage that already holds an account isn't
+/// re-encrypted.
+pub fn set_passphrase(passphrase: &str) -> Result<(), HErr> {
+    if is_encrypted() {
+        return Err(HErr::HeraldError("storage is already encrypted".into()));
+    }
+
+    if DB_POOL.get().is_some() || crypto_store::connection::is_open() {
+        return Err(HErr::HeraldError("storage is already open".into()));
+    }
+
+    if w!(is_init()) {
+        return Err(HErr::HeraldError("storage already holds an account".into()));
+    }
+
+    let key = aead::Key::new();
+
+    // fails if SQLCipher isn't linked, before anything on disk is touched
+    w!(sqlite_cipher::set_key(
+        &w!(Connection::open_in_memory()),
+        &key
+    ));
+
+    // anything left over holds no account, and can't be opened once the storage is encrypted
+    for path in &[db_path(), crypto_store::connection::db_path()] {
+        for suffix in &["", "-wal", "-shm"] {
+            let path = PathBuf::from(format!("{}{}", path.display(), suffix));
+
+            if path.exists() {
+                w!(fs::remove_file(path));
+            }
+        }
+    }
+
+    w!(write_key_file(
+        &key_file_path(),
+        &w!(seal_storage_key(passphrase, &key))
+    ));
+
+    set_storage_key(key)
+}
+
+/// Changes the passphrase encrypted storage is unlocked with, failing with `HErr::BadPassphrase`
+/// if `old` is wrong.
+pub fn change_passphrase(
+    old: &str,
+    new: &str,
+) -> Result<(), HErr> {
+    if !is_encrypted() {
+        return Err(HErr::HeraldError("storage isn't encrypted".into()));
+    }
+
+    reseal_key_file(&key_file_path(), old, new)
+}
+
+/// Keys a newly opened connection to the main store, if the storage is encrypted.
+pub(super) fn key_connection(conn: &Connection) -> Result<(), HErr> {
+    if let Some(key) = STORAGE_KEY.get() {
+        w!(sqlite_cipher::set_key(conn, key));
+    } else if is_encrypted() {
+        return Err(HErr::Locked);
+    }
+
+    Ok(())
+}
+
+/// Seals a secret kept in the main store, such as the identity key, if the storage is encrypted.
+pub(crate) fn seal_secret(
+    ad: &[u8],
+    secret: Vec<u8>,
+) -> Vec<u8> {
+    seal_with(STORAGE_KEY.get(), ad, secret)
+}
+
+/// Opens a secret sealed with `seal_secret`.
+pub(crate) fn open_secret(
+    ad: &[u8],
+    sealed: Vec<u8>,
+) -> Result<Vec<u8>, HErr> {
+    open_with(STORAGE_KEY.get(), ad, sealed)
+}
+
+fn seal_with(
+    key: Option<&aead::Key>,
+    ad: &[u8],
+    secret: Vec<u8>,
+) -> Vec<u8> {
+    match key {
+        Some(key) => key.seal_attached(ad, &secret),
+        None => secret,
+    }
+}
+
+fn open_with(
+    key: Option<&aead::Key>,
+    ad: &[u8],
+    sealed: Vec<u8>,
+) -> Result<Vec<u8>, HErr> {
+    match key {
+        Some(key) => Ok(w!(key.open_attached(ad, &sealed).ok_or_else(|| {
+            HErr::HeraldError("failed to open sealed secret".into())
+        }))),
+        None => Ok(sealed),
+    }
+}
+
+fn set_storage_key(key: aead::Key) -> Result<(), HErr> {
+    w!(crypto_store::prelude::set_key(key.clone()));
+
+    // unlocking again with the same passphrase is fine
+    drop(STORAGE_KEY.set(key));
+
+    Ok(())
+}
+
+/// Opens the storage key kept in the key file at `path`.
+fn open_key_file(
+    path: &Path,
+    passphrase: &str,
+) -> Result<aead::Key, HErr> {
+    let file = w!(read_key_file(path));
+    open_storage_key(passphrase, &file)
+}
+
+/// Reseals the storage key kept in the key file at `path` with a new passphrase.
+fn reseal_key_file(
+    path: &Path,
+    old: &str,
+    new: &str,
+) -> Result<(), HErr> {
+    let key = w!(open_key_file(path, old));
+    write_key_file(path, &w!(seal_storage_key(new, &key)))
+}
+
+fn open_storage_key(
+    passphrase: &str,
+    file: &KeyFile,
+) -> Result<aead::Key, HErr> {
+    let kek = w!(derive_key(passphrase, &file.salt));
+
+    let raw = w!(kek
+        .open_attached(STORAGE_KEY_AD, &file.sealed)
+        .ok_or(HErr::BadPassphrase));
+
+    Ok(w!(aead::Key::from_slice(&raw).ok_or(HErr::BadPassphrase)))
+}
+
+fn seal_storage_key(
+    passphrase: &str,
+    key: &aead::Key,
+) -> Result<KeyFile, HErr> {
+    let salt = pwhash::Salt::gen_new();
+    let kek = w!(derive_key(passphrase, &salt));
+
+    Ok(KeyFile {
+        salt,
+        sealed: kek.seal_attached(STORAGE_KEY_AD, key.as_ref()),
+    })
+}
+
+fn derive_key(
+    passphrase: &str,
+    salt: &pwhash::Salt,
+) -> Result<aead::Key, HErr> {
+    kcl::init();
+
+    Ok(w!(pwhash::derive_key(passphrase.as_bytes(), salt)
+        .ok_or_else(|| HErr::HeraldError(
+            "not enough memory to derive key from passphrase".into()
+        ))))
+}
+
+fn read_key_file(path: &Path) -> Result<KeyFile, HErr> {
+    let raw = w!(fs::read(path));
+    Ok(w!(kson::from_bytes(raw.into())))
+}
+
+fn write_key_file(
+    path: &Path,
+    file: &KeyFile,
+) -> Result<(), HErr> {
+    let tmp = path.with_extension("tmp");
+
+    // written to the side first, so the key isn't lost if this is interrupted
+    let mut out = w!(fs::File::create(&tmp));
+    w!(out.write_all(&kson::to_vec(file)));
+    w!(out.sync_all());
+    drop(out);
+
+    w!(fs::rename(tmp, path));
+
+    Ok(())
+}
+
+fn key_file_path() -> PathBuf {
+    db_dir().join("storage.key")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use coremacros::womp;
+
+    /// A key file path of its own for each test, so they don't touch the real storage.
+    fn scratch_key_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("herald-keys-{}", std::process::id()));
+        fs::create_dir_all(&dir).expect(womp!());
+
+        let path = dir.join(name);
+        drop(fs::remove_file(&path));
+        path
+    }
+
+    #[test]
+    fn wrong_passphrase() {
+        let path = scratch_key_file("wrong_passphrase");
+        let key = aead::Key::new();
+
+        write_key_file(&path, &seal_storage_key("right", &key).expect(womp!())).expect(womp!());
+
+        assert_eq!(open_key_file(&path, "right").expect(womp!()), key);
+
+        match open_key_file(&path, "wrong") {
+            Err(HErr::BadPassphrase) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        fs::remove_file(&path).expect(womp!());
+    }
+
+    #[test]
+    fn change_passphrase() {
+        let path = scratch_key_file("change_passphrase");
+        let key = aead::Key::new();
+
+        write_key_file(&path, &seal_storage_key("old", &key).expect(womp!())).expect(womp!());
+
+        match reseal_key_file(&path, "wrong", "new") {
+            Err(HErr::BadPassphrase) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        reseal_key_file(&path, "old", "new").expect(womp!());
+
+        // the storage key itself is unchanged, so the databases still open
+        assert_eq!(open_key_file(&path, "new").expect(womp!()), key);
+
+        match open_key_file(&path, "old") {
+            Err(HErr::BadPassphrase) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        fs::remove_file(&path).expect(womp!());
+    }
+
+    #[test]
+    fn sealed_secrets() {
+        let key = aead::Key::new();
+        let secret = b"identity key".to_vec();
+
+        let sealed = seal_with(Some(&key), b"ad", secret.clone());
+        assert_ne!(sealed, secret);
+
+        assert_eq!(
+            open_with(Some(&key), b"ad", sealed.clone()).expect(womp!()),
+            secret
+        );
+
+        // sealed for something else, or with another key
+        assert!(open_with(Some(&key), b"other ad", sealed.clone()).is_err());
+        assert!(open_with(Some(&aead::Key::new()), b"ad", sealed).is_err());
+
+        // without encryption secrets are stored as they are
+        assert_eq!(seal_with(None, b"ad", secret.clone()), secret);
+        assert_eq!(
+            open_with(None, b"ad", secret.clone()).expect(womp!()),
+            secret
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use super::*;
 use crate::errors::*;
 use coremacros::w;
+use herald_common::sqlite_migrations;
 use once_cell::sync::OnceCell;
 use platform_dirs::db_dir;
 use rusqlite::{Connection, NO_PARAMS};
@@ -12,10 +13,23 @@ use std::{
 mod pool;
 use pool::*;
 
+mod encryption;
+pub use encryption::{change_passphrase, is_encrypted, is_locked, set_passphrase, unlock};
+pub(crate) use encryption::{open_secret, seal_secret};
+
+/// Schema migrations for the main store, see `herald_common::sqlite_migrations`.
+const MIGRATIONS: &[&str] = &[
+    include_str!("../../migrations/0001_initial.sql"),
+    include_str!("../../migrations/0002_server_pin.sql"),
+    include_str!("../../migrations/0003_delivery_receipts.sql"),
+    include_str!("../../migrations/0004_pending_link.sql"),
+    include_str!("../../migrations/0005_verified_contacts.sql"),
+];
+
 static DB_POOL: OnceCell<Pool> = OnceCell::new();
 
-fn db_pool() -> &'static Pool {
-    DB_POOL.get_or_init(Pool::new)
+fn db_pool() -> Result<&'static Pool, HErr> {
+    DB_POOL.get_or_try_init(|| Pool::open(db_path()))
 }
 
 fn db_path() -> PathBuf {
@@ -27,7 +41,7 @@ pub(crate) struct Database(Connection);
 
 impl Database {
     pub(crate) fn get() -> Result<Wrapper, HErr> {
-        db_pool().get()
+        db_pool()?.get()
     }
 }
 
@@ -45,18 +59,26 @@ impl DerefMut for Database {
     }
 }
 
-/// Initializes storage
+/// Initializes storage, creating or migrating the tables as needed.
 pub fn init() -> Result<(), HErr> {
-    let db = Database::get()?;
-
-    w!(db.execute_batch(include_str!("../sql/create_all.sql")));
+    // the pool migrates the database before handing out its first connection
+    Database::get()?;
 
     Ok(())
 }
 
 /// Indicates whether the database is initialized
 pub fn is_init() -> Result<bool, HErr> {
-    let conn = Database::get()?;
+    // checked without going through the pool, which would create the tables
+    let path = db_path();
+
+    if !path.exists() {
+        return Ok(false);
+    }
+
+    let conn = w!(Connection::open(path));
+    w!(encryption::key_connection(&conn));
+
     is_init_raw(&conn)
 }
 
@@ -70,15 +92,11 @@ fn is_init_raw(conn: &rusqlite::Connection) -> Result<bool, HErr> {
 
 /// Resets all tables in database.
 pub fn reset_all() -> Result<(), HErr> {
-    let mut db = w!(Database::get());
-    let tx = w!(db.transaction());
-
-    // drop
-    w!(tx.execute_batch(include_str!("../sql/drop_all.sql")));
+    // the account is going away, so there's nothing left to stay logged in as
+    crate::network::logout();
 
-    // create
-    w!(tx.execute_batch(include_str!("../sql/create_all.sql")));
-    w!(tx.commit());
+    let mut db = w!(Database::get());
+    w!(db.reset());
     Ok(())
 }
 
@@ -87,9 +105,30 @@ impl Database {
     /// Creates a database if one does not exist.
     fn new<P: AsRef<Path>>(path: P) -> Result<Database, HErr> {
         let conn = Connection::open(path)?;
+        encryption::key_connection(&conn)?;
+
         Self::setup(conn)
     }
 
+    /// Brings the schema up to date, returning the version it started at.
+    fn migrate(&mut self) -> Result<u32, HErr> {
+        Ok(w!(sqlite_migrations::migrate(&mut self.0, MIGRATIONS)))
+    }
+
+    /// Drops all tables and recreates them at the latest schema.
+    fn reset(&mut self) -> Result<(), HErr> {
+        let tx = w!(self.transaction());
+
+        // drop
+        w!(tx.execute_batch(include_str!("../sql/drop_all.sql")));
+
+        // create
+        w!(sqlite_migrations::set_schema_version(&tx, 0));
+        w!(sqlite_migrations::migrate_tx(&tx, MIGRATIONS));
+        w!(tx.commit());
+        Ok(())
+    }
+
     fn setup(conn: Connection) -> Result<Self, HErr> {
         fn busy_handler(_: i32) -> bool {
             true
@@ -111,15 +150,14 @@ impl Database {
     #[cfg(test)]
     pub(crate) fn in_memory() -> Result<Self, HErr> {
         let conn = w!(Connection::open_in_memory());
-        w!(conn.execute_batch(include_str!("../sql/create_all.sql")));
-        Self::setup(conn)
+        let mut db = Self::setup(conn)?;
+        db.migrate()?;
+        Ok(db)
     }
 
     #[cfg(test)]
     pub(crate) fn in_memory_with_config() -> Result<Self, HErr> {
-        let conn = w!(Connection::open_in_memory());
-        w!(conn.execute_batch(include_str!("../sql/create_all.sql")));
-        let mut conn = Self::setup(conn)?;
+        let mut conn = Self::in_memory()?;
         crate::config::db::test_config(&mut conn);
         Ok(conn)
     }
@@ -128,15 +166,7 @@ impl Database {
     #[cfg(test)]
     pub(crate) fn reset_all() -> Result<(), HErr> {
         let mut db = w!(Self::get());
-        let tx = w!(db.transaction());
-
-        // drop
-        w!(tx.execute_batch(include_str!("../sql/drop_all.sql")));
-
-        // create
-        w!(tx.execute_batch(include_str!("../sql/create_all.sql")));
-        w!(tx.commit());
-        Ok(())
+        db.reset()
     }
 }
 
@@ -148,9 +178,50 @@ mod tests {
 
         assert!(!super::is_init_raw(&conn).expect(womp!()));
 
-        conn.execute_batch(include_str!("../sql/create_all.sql"))
+        conn.execute_batch(include_str!("../../migrations/0001_initial.sql"))
             .expect(womp!());
 
         assert!(super::is_init_raw(&conn).expect(womp!()));
     }
+
+    #[test]
+    fn migrates_unversioned_db() {
+        use super::*;
+
+        // a database created before schema versions were tracked
+        let conn = Connection::open_in_memory().expect(womp!());
+        conn.execute_batch(include_str!("../../migrations/0001_initial.sql"))
+            .expect(womp!());
+        conn.execute(
+            "INSERT INTO users(user_id, name, pairwise_conversation, color, status, user_type)
+            VALUES ('a', 'a', x'00', 0, 0, 0)",
+            NO_PARAMS,
+        )
+        .expect(womp!());
+
+        let mut db = Database::setup(conn).expect(womp!());
+        assert_eq!(db.migrate().expect(womp!()), 0);
+        assert_eq!(
+            sqlite_migrations::schema_version(&db).expect(womp!()),
+            MIGRATIONS.len() as u32
+        );
+
+        let users: i64 = db
+            .query_row("SELECT COUNT(*) FROM users", NO_PARAMS, |row| row.get(0))
+            .expect(womp!());
+        assert_eq!(users, 1);
+
+        // columns and tables added since are there
+        db.prepare("SELECT server_pin FROM config").expect(womp!());
+        db.prepare("SELECT server_key FROM config").expect(womp!());
+        db.prepare("SELECT push_id FROM push_deliveries")
+            .expect(womp!());
+        db.prepare("SELECT request FROM pending_link")
+            .expect(womp!());
+        db.prepare("SELECT fingerprint FROM verified_contacts")
+            .expect(womp!());
+
+        // migrating an up to date database does nothing
+        assert_eq!(db.migrate().expect(womp!()), MIGRATIONS.len() as u32);
+    }
 }
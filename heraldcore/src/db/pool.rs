@@ -46,10 +46,15 @@ impl Drop for Wrapper {
 }
 
 impl Pool {
-    pub fn new() -> Pool {
+    /// Opens the database at `path`, migrating it before any connections are handed out.
+    pub fn open(path: PathBuf) -> Result<Pool, HErr> {
         let (tx, rx) = bounded(SIZE);
 
-        Self { tx, rx }
+        let mut first = Database::new(path)?;
+        first.migrate()?;
+        drop(tx.try_send(first));
+
+        Ok(Self { tx, rx })
     }
 
     pub fn get(&self) -> Result<Wrapper, HErr> {
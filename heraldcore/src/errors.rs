@@ -19,6 +19,9 @@ pub enum HErr {
     #[error("Database error: {0}")]
     /// Database error.
     DatabaseError(#[from] rusqlite::Error),
+    #[error("Schema migration failed: {0}")]
+    /// The database schema couldn't be brought up to date
+    MigrationError(#[from] sqlite_migrations::MigrationError),
     #[error("Invalid ID: {0}")]
     /// Invalid `ConversationId` or `MsgId`
     BadRandomId(#[from] InvalidRandomIdLength),
@@ -74,7 +77,25 @@ pub enum HErr {
     #[error("Cryptographic payload error: {0}")]
     PayloadError(#[from] PayloadError<crypto_store::Error>),
     #[error("Credential store error: {0}")]
-    CryptoStoreError(#[from] crypto_store::Error),
+    CryptoStoreError(crypto_store::Error),
+    #[error("Server refused request: {0}")]
+    /// The server didn't handle a request
+    RequestRefused(#[from] RequestError),
+    #[error("Wrong passphrase")]
+    /// The passphrase the storage was unlocked with is wrong
+    BadPassphrase,
+    #[error("Storage is locked")]
+    /// The storage is encrypted and hasn't been unlocked yet
+    Locked,
+    #[error("Storage encryption error: {0}")]
+    /// The storage couldn't be encrypted or decrypted
+    CipherError(#[from] sqlite_cipher::CipherError),
+    #[error("Gave up delivering message to {to:#?}: {reason:?}")]
+    /// A message could not be delivered to a device, even after retransmitting it
+    DeliveryFailed {
+        to: sig::PublicKey,
+        reason: FailureReason,
+    },
 }
 
 impl From<image_utils::ImageError> for HErr {
@@ -87,6 +108,15 @@ impl From<image_utils::ImageError> for HErr {
     }
 }
 
+impl From<crypto_store::Error> for HErr {
+    fn from(e: crypto_store::Error) -> Self {
+        match e {
+            crypto_store::Error::Locked => HErr::Locked,
+            e => HErr::CryptoStoreError(e),
+        }
+    }
+}
+
 #[macro_export]
 /// Creates a `ChannelSendError`
 macro_rules! channel_send_err {
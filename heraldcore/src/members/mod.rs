@@ -12,7 +12,9 @@ pub fn add_member(
     member_id: UserId,
 ) -> Result<(), HErr> {
     let db = Database::get()?;
-    db::add_member(&db, conversation_id, member_id)
+    w!(db::add_member(&db, conversation_id, member_id));
+
+    crate::network::rotate_sender_key(*conversation_id)
 }
 
 /// Remove a user with `member_id` to the conversation with `conversation_id`.
@@ -21,7 +23,9 @@ pub fn remove_member(
     member_id: UserId,
 ) -> Result<(), HErr> {
     let db = Database::get()?;
-    db::remove_member(&db, conversation_id, member_id)
+    w!(db::remove_member(&db, conversation_id, member_id));
+
+    crate::network::rotate_sender_key(*conversation_id)
 }
 
 /// Gets the members of a conversation.
@@ -0,0 +1,80 @@
+use super::*;
+
+/// Deliveries of pushes that don't match a message we sent are kept this long, in milliseconds,
+/// in case the receipt arrived before we recorded sending the push.
+const UNMATCHED_DELIVERY_TTL: i64 = 10 * 60 * 1000;
+
+/// Records that the message `msg_id` was sent in the pushes the server accepted as `push_ids`.
+pub(crate) fn add_sent_pushes(
+    conn: &Conn,
+    msg_id: MsgId,
+    push_ids: &[i64],
+) -> Result<(), rusqlite::Error> {
+    let mut add_stmt = w!(conn.prepare_cached(include_str!("../sql/add_sent_push.sql")));
+    let mut mark_stmt = w!(conn.prepare_cached(include_str!("../sql/mark_delivered.sql")));
+
+    for push_id in push_ids {
+        w!(add_stmt.execute(params![push_id, msg_id]));
+        // the receipt can beat us here
+        w!(mark_stmt.execute(params![SendStatus::Delivered, push_id]));
+    }
+
+    Ok(())
+}
+
+/// Records that the push the server accepted as `push_id` was delivered to `device`, returning the messages
+/// it carried along with their conversations.
+pub(crate) fn add_delivery(
+    conn: &Conn,
+    push_id: i64,
+    device: GlobalId,
+) -> Result<Vec<(MsgId, ConversationId)>, rusqlite::Error> {
+    let now = Time::now();
+
+    w!(conn.execute(
+        include_str!("../sql/add_push_delivery.sql"),
+        params![push_id, device.did.as_ref(), device.uid, now],
+    ));
+
+    w!(conn.execute(
+        include_str!("../sql/mark_delivered.sql"),
+        params![SendStatus::Delivered, push_id],
+    ));
+
+    w!(conn.execute(
+        include_str!("../sql/prune_push_deliveries.sql"),
+        params![Time::from(*now.as_i64() - UNMATCHED_DELIVERY_TTL)],
+    ));
+
+    let mut stmt = w!(conn.prepare_cached(include_str!("../sql/delivered_messages.sql")));
+    let res = w!(stmt.query_map(params![push_id], |row| Ok((row.get(0)?, row.get(1)?))));
+
+    Ok(w!(res.collect()))
+}
+
+/// The devices the message `msg_id` has been delivered to, and when we were first told.
+pub(crate) fn deliveries(
+    conn: &Conn,
+    msg_id: &MsgId,
+) -> Result<Vec<(GlobalId, Time)>, HErr> {
+    let mut stmt = w!(conn.prepare_cached(include_str!("../sql/message_deliveries.sql")));
+
+    let rows = w!(stmt.query_map(params![msg_id], |row| {
+        Ok((
+            row.get::<_, Vec<u8>>(0)?,
+            row.get::<_, UserId>(1)?,
+            row.get::<_, Time>(2)?,
+        ))
+    }));
+
+    let mut out = Vec::new();
+    for row in rows {
+        let (did, uid, ts) = w!(row);
+        let did = w!(sig::PublicKey::from_slice(&did)
+            .ok_or_else(|| HErr::HeraldError("invalid device key in delivery receipts".into())));
+
+        out.push((GlobalId { uid, did }, ts));
+    }
+
+    Ok(out)
+}
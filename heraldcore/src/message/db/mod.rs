@@ -6,6 +6,7 @@ use rusqlite::{named_params, Connection as Conn};
 use std::collections::HashSet;
 
 mod builder;
+pub(crate) mod deliveries;
 pub(crate) mod receipts;
 use receipts::*;
 pub(crate) mod reactions;
@@ -175,7 +176,7 @@ pub(crate) fn get_message_opt(
     }
 }
 
-/// Sets the message status of an item in the database
+/// Sets the message status of an item in the database, unless it is already further along
 pub(crate) fn update_send_status(
     conn: &Conn,
     msg_id: MsgId,
@@ -57,7 +57,7 @@ pub fn get_message_opt(msg_id: &MsgId) -> Result<Option<Message>, HErr> {
     db::get_message_opt(&db, msg_id)
 }
 
-/// Sets the message status of an item in the database
+/// Sets the message status of an item in the database, unless it is already further along
 pub fn update_send_status(
     msg_id: MsgId,
     status: SendStatus,
@@ -66,6 +66,12 @@ pub fn update_send_status(
     db::update_send_status(&db, msg_id, status)
 }
 
+/// Gets the devices a message has been delivered to, and when we were told, by message id
+pub fn deliveries(msg_id: &MsgId) -> Result<Vec<(GlobalId, Time)>, HErr> {
+    let db = Database::get()?;
+    db::deliveries::deliveries(&db, msg_id)
+}
+
 /// Get message read receipts by message id
 pub fn get_message_receipts(msg_id: &MsgId) -> Result<HashMap<UserId, ReceiptStatus>, HErr> {
     let db = Database::get()?;
@@ -0,0 +1,3 @@
+INSERT OR IGNORE INTO
+  push_deliveries(push_id, device, user_id, delivered_ts)
+VALUES(@1, @2, @3, @4)
@@ -0,0 +1,3 @@
+INSERT OR IGNORE INTO
+  sent_pushes(push_id, msg_id)
+VALUES(@1, @2)
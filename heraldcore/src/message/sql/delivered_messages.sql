@@ -0,0 +1,7 @@
+SELECT
+  messages.msg_id,
+  conversation_id
+FROM
+  messages INNER JOIN sent_pushes ON messages.msg_id = sent_pushes.msg_id
+WHERE
+  sent_pushes.push_id = @1
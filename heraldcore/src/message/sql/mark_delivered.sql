@@ -0,0 +1,13 @@
+UPDATE
+  messages
+SET
+  send_status = MAX(send_status, @1)
+WHERE
+  msg_id IN (
+    SELECT
+      sent_pushes.msg_id
+    FROM
+      sent_pushes INNER JOIN push_deliveries ON sent_pushes.push_id = push_deliveries.push_id
+    WHERE
+      sent_pushes.push_id = @2
+  )
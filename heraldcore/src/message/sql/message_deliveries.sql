@@ -0,0 +1,10 @@
+SELECT
+  device,
+  user_id,
+  MIN(delivered_ts)
+FROM
+  push_deliveries INNER JOIN sent_pushes ON push_deliveries.push_id = sent_pushes.push_id
+WHERE
+  sent_pushes.msg_id = @1
+GROUP BY
+  device, user_id
@@ -0,0 +1,7 @@
+DELETE FROM
+  push_deliveries
+WHERE
+  delivered_ts < @1 AND
+  NOT EXISTS (
+    SELECT 1 FROM sent_pushes WHERE sent_pushes.push_id = push_deliveries.push_id
+  )
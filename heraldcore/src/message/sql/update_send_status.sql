@@ -1,6 +1,6 @@
 UPDATE
   messages
 SET
-  send_status = @1
+  send_status = MAX(send_status, @1)
 WHERE
-  msg_id = @2
\ No newline at end of file
+  msg_id = @2
@@ -257,6 +257,54 @@ fn message_receipt_status_updates() {
     assert_eq!(*receipt, ReceiptStatus::Read);
 }
 
+#[test]
+fn delivery_receipts() {
+    let mut conn = Database::in_memory_with_config().expect(womp!());
+
+    let receiver = crate::user::db::test_user(&mut conn, "receiver");
+    let conv = receiver.pairwise_conversation;
+
+    let device = |uid| GlobalId {
+        uid,
+        did: *sig::KeyPair::gen_new().public(),
+    };
+    let (first, second) = (device(receiver.id), device(receiver.id));
+
+    let (msg_id, _) = db::test_outbound_text(&mut conn, "hi", conv);
+
+    // the receipt arrives before the send is recorded
+    assert!(db::deliveries::add_delivery(&conn, 1, first)
+        .expect(womp!())
+        .is_empty());
+
+    db::deliveries::add_sent_pushes(&conn, msg_id, &[1, 2]).expect(womp!());
+
+    assert_eq!(
+        db::get_message(&conn, &msg_id).expect(womp!()).send_status,
+        SendStatus::Delivered
+    );
+
+    assert_eq!(
+        db::deliveries::add_delivery(&conn, 2, second).expect(womp!()),
+        vec![(msg_id, conv)]
+    );
+
+    let devices: std::collections::HashSet<_> = db::deliveries::deliveries(&conn, &msg_id)
+        .expect(womp!())
+        .into_iter()
+        .map(|(gid, _)| gid.did)
+        .collect();
+
+    assert_eq!(devices, vec![first.did, second.did].into_iter().collect());
+
+    // the send finishing afterwards doesn't undo the delivery
+    db::update_send_status(&conn, msg_id, SendStatus::Ack).expect(womp!());
+    assert_eq!(
+        db::get_message(&conn, &msg_id).expect(womp!()).send_status,
+        SendStatus::Delivered
+    );
+}
+
 #[test]
 fn reply_to_unknown_message() {
     let mut conn = Database::in_memory_with_config().expect(womp!());
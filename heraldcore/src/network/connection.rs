@@ -0,0 +1,162 @@
+use super::*;
+use std::{sync::atomic::AtomicU8, time::Duration};
+
+/// Delay before the first reconnection attempt.
+const BASE_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Upper bound on the delay between reconnection attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+/// State of the connection to the server
+pub enum ConnectionState {
+    /// Not connected, and not trying to connect
+    Down = 0,
+    /// Logging in or catching up
+    Connecting = 1,
+    /// Logged in and caught up
+    Up = 2,
+    /// The connection was lost, waiting to reconnect
+    Waiting = 3,
+}
+
+static STATE: AtomicU8 = AtomicU8::new(ConnectionState::Down as u8);
+
+/// Whether a thread is already keeping the connection alive.
+static SUPERVISED: AtomicBool = AtomicBool::new(false);
+
+/// Set by `logout` to tell the supervising thread to stop reconnecting.
+static STOPPED: AtomicBool = AtomicBool::new(false);
+
+/// The current state of the connection to the server.
+pub fn connection_state() -> ConnectionState {
+    match STATE.load(Ordering::Acquire) {
+        1 => ConnectionState::Connecting,
+        2 => ConnectionState::Up,
+        3 => ConnectionState::Waiting,
+        _ => ConnectionState::Down,
+    }
+}
+
+fn set_state(state: ConnectionState) {
+    if STATE.swap(state as u8, Ordering::AcqRel) != state as u8 {
+        crate::push(Notification::Connection(state));
+    }
+}
+
+/// Logs in to the server and keeps the connection alive.
+///
+/// The first attempt happens before this returns, so that errors like an unregistered device
+/// are reported to the caller. After that, a background thread reconnects with exponential
+/// backoff whenever the connection is lost, until `logout` is called or reconnecting fails
+/// with an error retrying can't fix. Queued messages are sent after every successful catchup.
+/// Calling this while the connection is already being maintained does nothing.
+pub fn login() -> Result<(), HErr> {
+    STOPPED.store(false, Ordering::Release);
+
+    if SUPERVISED
+        .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        return Ok(());
+    }
+
+    set_state(ConnectionState::Connecting);
+
+    let reader = match login_imp::connect() {
+        Ok(reader) => reader,
+        Err(e) => {
+            set_state(ConnectionState::Down);
+            SUPERVISED.store(false, Ordering::Release);
+            return Err(e);
+        }
+    };
+
+    set_state(ConnectionState::Up);
+
+    std::thread::spawn(move || {
+        let mut reader = reader;
+
+        loop {
+            drop(reader.join());
+
+            if STOPPED.load(Ordering::Acquire) {
+                break;
+            }
+
+            set_state(ConnectionState::Waiting);
+
+            match reconnect() {
+                Some(r) => reader = r,
+                None => break,
+            }
+
+            set_state(ConnectionState::Up);
+        }
+
+        set_state(ConnectionState::Down);
+        SUPERVISED.store(false, Ordering::Release);
+    });
+
+    Ok(())
+}
+
+/// Closes the connection to the server and stops reconnecting.
+pub fn logout() {
+    STOPPED.store(true, Ordering::Release);
+    socket::detach();
+}
+
+/// Tries to connect until it succeeds, backing off between attempts.
+///
+/// Returns `None` if `logout` was called or the error can't be fixed by retrying.
+fn reconnect() -> Option<std::thread::JoinHandle<()>> {
+    let mut attempt = 0;
+
+    loop {
+        std::thread::sleep(backoff(attempt));
+
+        if STOPPED.load(Ordering::Acquire) {
+            return None;
+        }
+
+        set_state(ConnectionState::Connecting);
+
+        match login_imp::connect() {
+            Ok(reader) => return Some(reader),
+            Err(e) if is_permanent(&e) => {
+                crate::err(e);
+                return None;
+            }
+            Err(e) => {
+                set_state(ConnectionState::Waiting);
+                crate::err(e);
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Whether retrying can't fix `e`, e.g., because this device isn't registered or the local
+/// store is locked.
+fn is_permanent(e: &HErr) -> bool {
+    match e {
+        HErr::LoginClaimFailed(_) | HErr::LoginChallengeFailed | HErr::Locked => true,
+        _ => false,
+    }
+}
+
+/// Exponential backoff with full jitter, so that clients don't reconnect in lockstep after a
+/// server restart.
+fn backoff(attempt: u32) -> Duration {
+    let max = BASE_BACKOFF
+        .checked_mul(1 << attempt.min(16))
+        .unwrap_or(MAX_BACKOFF)
+        .min(MAX_BACKOFF);
+
+    let mut buf = [0u8; 4];
+    kcl::random::gen_into(&mut buf);
+
+    max.mul_f64(f64::from(u32::from_le_bytes(buf)) / f64::from(u32::max_value()))
+}
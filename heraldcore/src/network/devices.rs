@@ -0,0 +1,120 @@
+//! The devices linked to this account, as recorded in its sigchain.
+
+use super::*;
+
+/// A device linked to the account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Device {
+    /// The device's key
+    pub key: sig::PublicKey,
+    /// When the device was added to the account
+    pub created: Time,
+    /// The device that added it, `None` for the device the account was registered on
+    pub endorsed_by: Option<sig::PublicKey>,
+    /// When the device was revoked, if it has been
+    pub revoked: Option<Time>,
+}
+
+impl Device {
+    /// Whether the device can still send and receive messages for the account.
+    pub fn is_active(&self) -> bool {
+        self.revoked.is_none()
+    }
+}
+
+/// Lists the account's devices, including revoked ones, in the order they were added.
+pub fn devices() -> Result<Vec<Device>, HErr> {
+    let uid = w!(config::id());
+
+    get_crypto_conn!(store);
+    let chain = w!(w!(store.get_sigchain(uid)).ok_or(HeraldError("missing sigchain".into())));
+
+    Ok(devices_of(&chain))
+}
+
+/// Revokes another of the account's devices, e.g. one that was lost.
+///
+/// Once the server accepts the revocation, the sessions, sender keys and pending payloads shared
+/// with the device are torn down, and the account's contacts are told to do the same.
+pub fn revoke_device(key: sig::PublicKey) -> Result<PKIResponse, HErr> {
+    if key == *w!(config::keypair()).public() {
+        return Err(HeraldError("can't revoke this device".into()));
+    }
+
+    if !w!(devices()).iter().any(|d| d.key == key && d.is_active()) {
+        return Err(HeraldError("not an active device of this account".into()));
+    }
+
+    dep_key(key)
+}
+
+fn devices_of(chain: &sig::SigChain) -> Vec<Device> {
+    let mut devices = vec![Device {
+        key: *chain.initial.signed_by(),
+        created: *chain.initial.timestamp(),
+        endorsed_by: None,
+        revoked: None,
+    }];
+
+    for update in &chain.sig_chain {
+        match update.data() {
+            sig::SigUpdate::Endorse(endorsed) => devices.push(Device {
+                key: *endorsed.signed_by(),
+                created: *update.timestamp(),
+                endorsed_by: Some(*update.signed_by()),
+                revoked: None,
+            }),
+            sig::SigUpdate::Deprecate(key) => {
+                for device in devices
+                    .iter_mut()
+                    .filter(|d| d.key == *key && d.is_active())
+                {
+                    device.revoked = Some(*update.timestamp());
+                }
+            }
+        }
+    }
+
+    devices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use coremacros::womp;
+    use std::convert::TryInto;
+
+    #[test]
+    fn lists_devices() {
+        let uid: UserId = "a".try_into().expect(womp!());
+        let kp1 = sig::KeyPair::gen_new();
+        let kp2 = sig::KeyPair::gen_new();
+
+        let initial = sign_ser(&kp1, uid);
+        let endorse = sign_ser(&kp1, sig::SigUpdate::Endorse(sign_ser(&kp2, uid)));
+        let deprecate = sign_ser(&kp2, sig::SigUpdate::Deprecate(*kp1.public()));
+
+        let chain = sig::SigChain {
+            initial,
+            sig_chain: vec![endorse, deprecate],
+        };
+
+        assert_eq!(
+            devices_of(&chain),
+            vec![
+                Device {
+                    key: *kp1.public(),
+                    created: *initial.timestamp(),
+                    endorsed_by: None,
+                    revoked: Some(*deprecate.timestamp()),
+                },
+                Device {
+                    key: *kp2.public(),
+                    created: *endorse.timestamp(),
+                    endorsed_by: Some(*kp1.public()),
+                    revoked: None,
+                },
+            ]
+        );
+    }
+}
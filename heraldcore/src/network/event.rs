@@ -30,7 +30,7 @@ impl Event {
             outbox,
         } = self;
 
-        let from = w!(crate::config::gid());
+        let kp = w!(crate::config::keypair());
 
         for note in notifications {
             crate::push(note);
@@ -41,11 +41,13 @@ impl Event {
         }
 
         for (recip, msg) in outbox {
-            w!(helper::push(&push::Req {
-                from,
-                to: recip,
-                msg: kson::to_vec(&msg).into()
-            }));
+            w!(helper::push(&sign_ser(
+                &kp,
+                push::Body {
+                    to: recip,
+                    msg: kson::to_vec(&msg).into(),
+                }
+            )));
         }
 
         Ok(())
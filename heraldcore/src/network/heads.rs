@@ -0,0 +1,109 @@
+//! Sigchain heads, which let clients catch a server that shows different users different
+//! versions of a sigchain, e.g., to hide a deprecated key.
+//!
+//! Heads are gossiped inside encrypted messages, where the server can't tamper with them. The
+//! sender's head of its own chain is authoritative, and the head it has of ours shows whether
+//! the server has been telling it the truth about us.
+//!
+//! Besides being sent when contacts are added and chains change, heads are sent along with
+//! regular conversation traffic, at most once per `GOSSIP_INTERVAL` to each member, so a fork
+//! is noticed by people who keep talking to each other.
+
+use super::*;
+use network_types::umessages::SigChainHeads;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// How often heads are sent to each contact we're talking to.
+const GOSSIP_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// When heads were last sent to each contact since starting.
+static LAST_GOSSIP: Lazy<Mutex<HashMap<UserId, Instant>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Sends heads to the members of `cid` they haven't been sent to in the last `GOSSIP_INTERVAL`.
+pub(super) fn gossip_heads(cid: ConversationId) -> Result<(), HErr> {
+    let own = w!(config::id());
+
+    let due: Vec<UserId> = {
+        let last = LAST_GOSSIP.lock();
+
+        w!(crate::members::members(&cid))
+            .into_iter()
+            .filter(|uid| *uid != own)
+            .filter(|uid| {
+                last.get(uid)
+                    .map(|sent| sent.elapsed() >= GOSSIP_INTERVAL)
+                    .unwrap_or(true)
+            })
+            .collect()
+    };
+
+    for uid in due {
+        w!(send_umessage(uid, UserMessage::Heads(w!(heads_for(uid)))));
+        LAST_GOSSIP.lock().insert(uid, Instant::now());
+    }
+
+    Ok(())
+}
+
+/// The heads to send to `uid`.
+pub(super) fn heads_for(uid: UserId) -> Result<SigChainHeads, HErr> {
+    let own = w!(config::id());
+
+    get_crypto_conn!(store);
+
+    let sender = w!(w!(store.sigchain_head(own)).ok_or(HeraldError("missing sigchain".into())));
+    let recipient = w!(store.sigchain_head(uid));
+
+    Ok(SigChainHeads { sender, recipient })
+}
+
+/// Checks heads gossiped by `from` against the sigchains we have.
+pub(super) fn handle_heads(
+    from: UserId,
+    SigChainHeads { sender, recipient }: SigChainHeads,
+    ev: &mut Event,
+) -> Result<(), HErr> {
+    w!(check_head(from, &sender, ev));
+
+    if let Some(recipient) = recipient {
+        w!(check_head(w!(config::id()), &recipient, ev));
+    }
+
+    Ok(())
+}
+
+/// Checks that the sigchain of `uid` is the one `head` was taken from.
+fn check_head(
+    uid: UserId,
+    head: &sig::SigChainHead,
+    ev: &mut Event,
+) -> Result<(), HErr> {
+    if w!(stored_chain(uid))
+        .map(|c| c.extends(head))
+        .unwrap_or(false)
+    {
+        return Ok(());
+    }
+
+    // we may just be behind, in which case the server has the missing links
+    let chain = w!(fetch_sigchain(&uid));
+
+    if chain.extends(head) {
+        w!(store_sigchain(uid, chain));
+    } else {
+        ev.notifications.push(Notification::SigchainFork(uid));
+    }
+
+    Ok(())
+}
+
+fn stored_chain(uid: UserId) -> Result<Option<sig::SigChain>, HErr> {
+    get_crypto_conn!(store);
+    Ok(w!(store.get_sigchain(uid)))
+}
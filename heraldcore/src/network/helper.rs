@@ -1,45 +1,103 @@
-use super::{server_url, SocketAddr};
+use super::{server_pin, server_url, socket, tls, SocketAddr};
 use crate::errors::*;
 use coremacros::w;
 use herald_common::*;
 
+/// Restricts `req` to servers presenting the pinned certificate, if there is one.
+fn with_pin(
+    mut req: ureq::Request,
+    pin: Option<CertPin>,
+) -> ureq::Request {
+    if let Some(pin) = pin {
+        req.set_tls_config(tls::client_config(pin));
+    }
+    req
+}
+
 macro_rules! mk_request {
-    ($method: tt, $path: tt) => {
+    ($method: tt, $path: tt, $variant: ident) => {
         pub fn $path(req: &$path::Req) -> Result<$path::Res, HErr> {
             use ::coremacros::w;
-            use ::std::io::Read;
-            let mut res_buf = Vec::new();
-            w!(ureq::$method(&server_url(stringify!($path)))
-                .send_bytes(&kson::to_vec(req))
-                .into_reader()
-                .read_to_end(&mut res_buf));
-            let res = w!(kson::from_bytes(res_buf.into()));
-            Ok(res)
+
+            // prefer the login socket, if we have one
+            match w!(socket::request(Request::$variant(req.clone()))) {
+                Some(Response::$variant(res)) => return Ok(res),
+                Some(Response::Err(e)) => return Err(HErr::RequestRefused(e)),
+                Some(res) => {
+                    return Err(HErr::HeraldError(format!(
+                        "unexpected response from server: {:?}",
+                        res
+                    )))
+                }
+                None => {}
+            }
+
+            let pin = w!(server_pin());
+            let res = with_pin(ureq::$method(&server_url(stringify!($path), pin)), pin)
+                .send_bytes(&kson::to_vec(req));
+            read_res(res)
         }
     };
 }
 
-mk_request!(get, get_sigchain);
-mk_request!(get, recip_exists);
-mk_request!(get, new_sig);
-mk_request!(get, new_prekeys);
-mk_request!(get, get_prekeys);
-mk_request!(get, push);
+/// Decodes the response to a request made over HTTP.
+fn read_res<T: De>(res: ureq::Response) -> Result<T, HErr> {
+    use std::io::Read;
+
+    if let Some(e) = res.synthetic_error() {
+        return Err(HErr::HeraldError(e.to_string()));
+    }
+
+    let ok = res.ok();
+    let status = res.status();
+
+    let mut res_buf = Vec::new();
+    w!(res.into_reader().read_to_end(&mut res_buf));
+
+    if ok {
+        Ok(w!(kson::from_bytes(res_buf.into())))
+    } else {
+        // refused requests come with a `RequestError`, anything else is unexpected
+        match kson::from_bytes::<RequestError>(res_buf.into()) {
+            Ok(e) => Err(HErr::RequestRefused(e)),
+            Err(_) => Err(HErr::HeraldError(format!(
+                "server responded with status {}",
+                status
+            ))),
+        }
+    }
+}
+
+mk_request!(get, get_sigchain, GetSigchain);
+mk_request!(get, recip_exists, RecipExists);
+mk_request!(get, new_sig, NewSig);
+mk_request!(get, new_prekeys, NewPrekey);
+mk_request!(get, get_prekeys, GetPrekey);
+mk_request!(get, prekey_count, PrekeyCount);
+mk_request!(get, held_prekeys, HeldPrekeys);
+mk_request!(get, push, Push);
+mk_request!(get, server_key, ServerKey);
 // mk_request!(get, register);
 
 pub fn register(
     req: &register::Req,
     home_server: SocketAddr,
+    pin: Option<CertPin>,
 ) -> Result<register::Res, HErr> {
-    use std::io::Read;
+    let scheme = if pin.is_some() { "https" } else { "http" };
+    let url = format!("{}://{}/register", scheme, home_server);
 
-    let mut res_buf = Vec::new();
-    let url = format!("http://{}/register", home_server);
-
-    w!(ureq::post(&url)
-        .send_bytes(&kson::to_vec(req))
-        .into_reader()
-        .read_to_end(&mut res_buf));
-    let res = w!(kson::from_bytes(res_buf.into()));
-    Ok(res)
+    read_res(with_pin(ureq::post(&url), pin).send_bytes(&kson::to_vec(req)))
+}
+
+/// Fetches the sigchain of `req` from `home_server`, for use before this device has a config.
+pub fn get_sigchain_from(
+    req: &get_sigchain::Req,
+    home_server: SocketAddr,
+    pin: Option<CertPin>,
+) -> Result<get_sigchain::Res, HErr> {
+    let scheme = if pin.is_some() { "https" } else { "http" };
+    let url = format!("{}://{}/get_sigchain", scheme, home_server);
+
+    read_res(with_pin(ureq::get(&url), pin).send_bytes(&kson::to_vec(req)))
 }
@@ -0,0 +1,474 @@
+//! Linking new devices to an existing account.
+//!
+//! The new device generates a keypair and shows a `LinkRequest`, e.g. as a QR code. An existing
+//! device checks it with `verify_link` and, once the user has compared the pairing codes shown on
+//! both devices, endorses the new key with `approve_link`. The new device then calls
+//! `finish_link`, which sets it up for the account and asks the other devices for the account's
+//! settings, contacts and conversations over the usual encrypted sessions.
+
+use super::*;
+use crate::{conversation::ConversationBuilder, user::UserBuilder};
+use herald_user::{UserChange, UserStatus, UserType};
+use network_types::{
+    cmessages::AddedToConvo,
+    umessages::{AccountSync, SyncedContact, UserMessage},
+};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::collections::HashSet;
+
+/// Domain separator for pairing codes, so they can't be confused with other hashes of keys.
+const PAIRING_CODE_TAG: &[u8] = b"herald pairing code v1";
+
+/// Number of five digit groups a pairing code is shown as.
+const PAIRING_CODE_GROUPS: usize = 4;
+
+/// A request from a new device to be linked to an account, signed by the new device.
+#[derive(Ser, De, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkRequest(Signed<UserId>);
+
+impl LinkRequest {
+    /// The account the device wants to be linked to.
+    pub fn uid(&self) -> UserId {
+        *self.0.data()
+    }
+
+    /// The new device's key.
+    pub fn key(&self) -> sig::PublicKey {
+        *self.0.signed_by()
+    }
+
+    /// A code both devices show, so the user can check they are linking the right device.
+    ///
+    /// It is shown as groups of five digits, each taken from five bytes of a hash of the request,
+    /// so it carries about 66 bits and a device key matching a given code can't be searched for.
+    pub fn pairing_code(&self) -> String {
+        let mut hasher = kcl::hash::Builder::new().build();
+
+        hasher.update(PAIRING_CODE_TAG);
+        hasher.update(self.key().as_ref());
+        hasher.update(self.uid().as_str().as_bytes());
+
+        hasher
+            .finalize()
+            .0
+            .chunks(5)
+            .take(PAIRING_CODE_GROUPS)
+            .map(|chunk| {
+                let n = chunk.iter().fold(0u64, |n, b| (n << 8) | u64::from(*b));
+                format!("{:05}", n % 100_000)
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Encodes the request as text, e.g. for a QR code.
+    pub fn encode(&self) -> String {
+        hex::encode(kson::to_vec(self))
+    }
+
+    /// Decodes a request encoded with `encode`.
+    pub fn decode(text: &str) -> Result<Self, HErr> {
+        let bytes =
+            hex::decode(text.trim()).map_err(|_| HeraldError("malformed link request".into()))?;
+
+        Ok(w!(kson::from_bytes(bytes.into())))
+    }
+}
+
+/// Additional data for the pending link's keypair when it is sealed with the storage key.
+const LINK_KEYPAIR_AD: &[u8] = b"herald pending link key";
+
+/// A link request this device is waiting on. It is stored so linking can be finished after a
+/// restart, since the existing device endorses this keypair.
+struct PendingLink {
+    keypair: sig::KeyPair,
+    request: LinkRequest,
+    home_server: SocketAddr,
+    server_pin: Option<CertPin>,
+}
+
+impl PendingLink {
+    fn store(
+        &self,
+        conn: &rusqlite::Connection,
+    ) -> Result<(), HErr> {
+        w!(conn.execute_named(
+            include_str!("sql/set_pending_link.sql"),
+            rusqlite::named_params! {
+                "@kp": crate::db::seal_secret(LINK_KEYPAIR_AD, kson::to_vec(&self.keypair)),
+                "@request": kson::to_vec(&self.request),
+                "@home_server": self.home_server.to_string(),
+                "@server_pin": self.server_pin,
+            },
+        ));
+
+        Ok(())
+    }
+
+    fn load(conn: &rusqlite::Connection) -> Result<Option<Self>, HErr> {
+        let mut stmt = w!(conn.prepare(include_str!("sql/pending_link.sql")));
+
+        let mut rows = w!(stmt.query_map(rusqlite::NO_PARAMS, |row| {
+            Ok((
+                row.get::<_, Vec<u8>>("kp")?,
+                row.get::<_, Vec<u8>>("request")?,
+                row.get::<_, String>("home_server")?,
+                row.get::<_, Option<CertPin>>("server_pin")?,
+            ))
+        }));
+
+        let (kp, request, home_server, server_pin) = match rows.next() {
+            Some(row) => w!(row),
+            None => return Ok(None),
+        };
+
+        let kp = w!(crate::db::open_secret(LINK_KEYPAIR_AD, kp));
+
+        Ok(Some(PendingLink {
+            keypair: w!(kson::from_bytes(kp.into())),
+            request: w!(kson::from_bytes(request.into())),
+            home_server: home_server.parse()?,
+            server_pin,
+        }))
+    }
+
+    fn clear(conn: &rusqlite::Connection) -> Result<(), HErr> {
+        w!(conn.execute(
+            include_str!("sql/clear_pending_link.sql"),
+            rusqlite::NO_PARAMS
+        ));
+        Ok(())
+    }
+}
+
+/// Held while the pending link is replaced or finished, so a link isn't finished twice.
+static FINISHING: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// Starts linking this device to the account `uid`, returning the request to show the existing
+/// device. Replaces any link that was in progress.
+pub fn start_link(
+    uid: UserId,
+    home_server: Option<SocketAddr>,
+    server_pin: Option<CertPin>,
+) -> Result<LinkRequest, HErr> {
+    kcl::init();
+
+    let keypair = sig::KeyPair::gen_new();
+    let request = LinkRequest(sign_ser(&keypair, uid));
+
+    let pending = PendingLink {
+        keypair,
+        request,
+        home_server: home_server.unwrap_or_else(|| *default_server()),
+        server_pin,
+    };
+
+    let _finishing = FINISHING.lock();
+    w!(pending.store(&w!(crate::db::Database::get())));
+
+    Ok(request)
+}
+
+/// Checks a request shown by a new device before it is approved, failing if it isn't signed by
+/// the new device or is for another account.
+pub fn verify_link(request: &LinkRequest) -> Result<(), HErr> {
+    if request.0.verify_sig() != SigValid::Yes {
+        return Err(HeraldError("link request has an invalid signature".into()));
+    }
+
+    if request.uid() != w!(config::id()) {
+        return Err(HeraldError("link request is for another account".into()));
+    }
+
+    Ok(())
+}
+
+/// Endorses the device that made `request`, adding it to the account.
+pub fn approve_link(request: &LinkRequest) -> Result<PKIResponse, HErr> {
+    w!(verify_link(request));
+    new_key(request.0)
+}
+
+/// Sets this device up for the account once the link request has been approved, and asks the
+/// account's other devices for its settings, contacts and conversations.
+///
+/// Returns `false` if the request hasn't been approved yet, in which case this can be called
+/// again later.
+pub fn finish_link() -> Result<bool, HErr> {
+    let finishing = FINISHING.lock();
+
+    let PendingLink {
+        keypair,
+        request,
+        home_server,
+        server_pin,
+    } = w!(w!(PendingLink::load(&w!(crate::db::Database::get())))
+        .ok_or_else(|| HeraldError("no link in progress".into())));
+
+    let uid = request.uid();
+
+    let chain = w!(w!(helper::get_sigchain_from(&uid, home_server, server_pin))
+        .ok_or_else(|| HeraldError("account to link to doesn't exist".into())));
+
+    if chain.validate() != SigValid::Yes {
+        return Err(HeraldError("bad sigchain found on server".into()));
+    }
+
+    if !chain.active_keys().contains(keypair.public()) {
+        return Ok(false);
+    }
+
+    w!(store_sigchain(uid, chain));
+
+    let mut builder = crate::config::ConfigBuilder::new(uid, keypair).home_server(home_server);
+
+    if let Some(pin) = server_pin {
+        builder = builder.server_pin(pin);
+    }
+
+    w!(builder.add());
+    w!(PendingLink::clear(&w!(crate::db::Database::get())));
+    drop(finishing);
+
+    w!(replenish_prekeys());
+    w!(send_umessage_to_self(UserMessage::SyncReq));
+
+    Ok(true)
+}
+
+/// Collects the account's settings, contacts and group conversations for a newly linked device.
+pub(super) fn account_sync() -> Result<AccountSync, HErr> {
+    let config = w!(config::get());
+
+    let read_picture = |path: Option<String>| path.map(std::fs::read).transpose();
+
+    let mut contacts = Vec::new();
+    for user in w!(crate::user::all()) {
+        if user.user_type != UserType::Remote || user.status != UserStatus::Active {
+            continue;
+        }
+
+        contacts.push(SyncedContact {
+            id: user.id,
+            name: user.name,
+            color: user.color,
+            profile_picture: w!(read_picture(user.profile_picture)),
+            pairwise_conversation: user.pairwise_conversation,
+        });
+    }
+
+    let mut conversations = Vec::new();
+    // pairwise conversations are recreated along with their contacts
+    for meta in w!(crate::conversation::all_meta()) {
+        if meta.pairwise_uid.is_some() {
+            continue;
+        }
+
+        conversations.push(AddedToConvo {
+            members: w!(crate::members::members(&meta.conversation_id)),
+            cid: meta.conversation_id,
+            title: meta.title,
+            picture: w!(read_picture(meta.picture)),
+            expiration_period: meta.expiration_period,
+        });
+    }
+
+    Ok(AccountSync {
+        name: config.name,
+        color: config.color,
+        colorscheme: config.colorscheme,
+        profile_picture: w!(read_picture(config.profile_picture)),
+        preferred_expiration: config.preferred_expiration,
+        contacts,
+        conversations,
+    })
+}
+
+/// Applies the account data sent by another of the user's devices. Contacts and conversations
+/// this device already has are left as they are.
+pub(super) fn apply_account_sync(
+    sync: AccountSync,
+    ev: &mut Event,
+) -> Result<(), HErr> {
+    let AccountSync {
+        name,
+        color,
+        colorscheme,
+        profile_picture,
+        preferred_expiration,
+        contacts,
+        conversations,
+    } = sync;
+
+    let mut db = w!(crate::db::Database::get());
+    let uid = w!(crate::config::db::id(&db));
+
+    w!(crate::config::db::set_name(&db, &name));
+    w!(crate::config::db::set_color(&db, color));
+    w!(crate::config::db::set_colorscheme(&db, colorscheme));
+    w!(crate::config::db::set_preferred_expiration(
+        &db,
+        preferred_expiration
+    ));
+    let picture = w!(crate::user::db::set_profile_picture_buf(
+        &db,
+        uid,
+        profile_picture.as_ref().map(Vec::as_slice)
+    ));
+
+    ev.notifications.extend(vec![
+        Notification::UserChanged(uid, UserChange::DisplayName(Some(name))),
+        Notification::UserChanged(uid, UserChange::Color(color)),
+        Notification::UserChanged(uid, UserChange::Picture(picture)),
+    ]);
+
+    // the sigchains of everyone we'll talk to, so we know their devices
+    let mut known: HashSet<UserId> = {
+        get_crypto_conn!(store);
+        w!(store.get_all_users()).into_iter().collect()
+    };
+
+    let mut learn = |uid: UserId| -> Result<(), HErr> {
+        if known.insert(uid) {
+            w!(store_sigchain(uid, w!(fetch_sigchain(&uid))));
+        }
+        Ok(())
+    };
+
+    for contact in contacts {
+        if w!(crate::user::db::user_exists(&db, contact.id)) {
+            continue;
+        }
+
+        let (mut user, conversation) = w!(UserBuilder::new(contact.id)
+            .name(contact.name)
+            .color(contact.color)
+            .pairwise_conversation(contact.pairwise_conversation)
+            .add_db(&mut db));
+
+        if let Some(buf) = contact.profile_picture {
+            user.profile_picture = w!(crate::user::db::set_profile_picture_buf(
+                &db,
+                contact.id,
+                Some(&buf)
+            ));
+        }
+
+        w!(learn(contact.id));
+
+        ev.notifications
+            .push(Notification::NewUser(Box::new((user, conversation.meta))));
+    }
+
+    let existing: HashSet<ConversationId> = w!(crate::conversation::db::all_meta(&db))
+        .into_iter()
+        .map(|meta| meta.conversation_id)
+        .collect();
+
+    for AddedToConvo {
+        members,
+        cid,
+        title,
+        picture,
+        expiration_period,
+    } in conversations
+    {
+        if existing.contains(&cid) {
+            continue;
+        }
+
+        for member in &members {
+            w!(learn(*member));
+        }
+
+        let mut builder = ConversationBuilder::new();
+        builder
+            .conversation_id(cid)
+            .override_members(members)
+            .expiration_period(expiration_period);
+
+        builder.title = title;
+        builder.picture = match picture {
+            Some(bytes) => Some(w!(image_utils::update_picture_buf(&bytes))),
+            None => None,
+        };
+
+        let conversation = w!(builder.add_db(&mut db));
+
+        ev.notifications
+            .push(Notification::NewConversation(conversation.meta));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+    use coremacros::womp;
+    use std::convert::TryInto;
+
+    #[test]
+    fn pending_link_is_stored() {
+        let conn = Database::in_memory().expect(womp!());
+
+        assert!(PendingLink::load(&conn).expect(womp!()).is_none());
+
+        let uid: UserId = "a".try_into().expect(womp!());
+        let keypair = sig::KeyPair::gen_new();
+        let pin = CertPin::of(b"not really a certificate");
+
+        let pending = PendingLink {
+            request: LinkRequest(sign_ser(&keypair, uid)),
+            keypair,
+            home_server: *default_server(),
+            server_pin: Some(pin),
+        };
+        pending.store(&conn).expect(womp!());
+
+        let loaded = PendingLink::load(&conn).expect(womp!()).expect(womp!());
+        assert_eq!(loaded.keypair, pending.keypair);
+        assert_eq!(loaded.request, pending.request);
+        assert_eq!(loaded.home_server, pending.home_server);
+        assert_eq!(loaded.server_pin, Some(pin));
+
+        // starting over replaces the link
+        let keypair = sig::KeyPair::gen_new();
+        PendingLink {
+            request: LinkRequest(sign_ser(&keypair, uid)),
+            keypair,
+            home_server: *default_server(),
+            server_pin: None,
+        }
+        .store(&conn)
+        .expect(womp!());
+
+        let loaded = PendingLink::load(&conn).expect(womp!()).expect(womp!());
+        assert_ne!(loaded.request, pending.request);
+        assert!(loaded.server_pin.is_none());
+
+        PendingLink::clear(&conn).expect(womp!());
+        assert!(PendingLink::load(&conn).expect(womp!()).is_none());
+    }
+
+    #[test]
+    fn encodes_requests() {
+        let uid: UserId = "a".try_into().expect(womp!());
+        let kp = sig::KeyPair::gen_new();
+        let request = LinkRequest(sign_ser(&kp, uid));
+
+        let decoded = LinkRequest::decode(&request.encode()).expect(womp!());
+        assert_eq!(decoded, request);
+        assert_eq!(decoded.uid(), uid);
+        assert_eq!(decoded.key(), *kp.public());
+
+        let code = request.pairing_code();
+        assert_eq!(code.split(' ').count(), PAIRING_CODE_GROUPS);
+        assert!(code.split(' ').all(|g| g.len() == 5));
+        assert_eq!(code, decoded.pairing_code());
+
+        assert!(LinkRequest::decode("not hex").is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use super::*;
 use coremacros::w;
-use herald_common::protocol::auth::*;
+use herald_common::protocol::{auth::*, socket::ClientMsg};
+use std::thread::JoinHandle;
 
 macro_rules! send {
     ($ws:expr, $send:expr) => {
@@ -17,9 +18,12 @@ macro_rules! recv {
     };
 }
 
-/// Attempts to login to the server, spawning a long-lived thread to handle messages pushed from
+/// Attempts to login to the server, spawning long-lived threads to handle messages pushed from
 /// the server.
-pub fn login() -> Result<(), HErr> {
+///
+/// Returns a handle to the thread reading from the connection, which finishes once the
+/// connection is closed.
+pub(super) fn connect() -> Result<JoinHandle<()>, HErr> {
     use login_types::*;
 
     kcl::init();
@@ -34,11 +38,16 @@ pub fn login() -> Result<(), HErr> {
         did: *kp.public(),
     };
 
-    let wsurl = format!("ws://{}/login", home_server());
+    let pin = w!(server_pin());
+    let scheme = if pin.is_some() { "wss" } else { "ws" };
+    let wsurl = format!("{}://{}/login", scheme, home_server());
 
+    // TLS, if any, is handled by the connection itself, so the websocket sees a plain stream
+    let conn = w!(tls::Conn::connect(*home_server(), pin));
+    w!(conn.set_read_timeout(Some(socket::READ_TIMEOUT)));
     let mut ws = w!(wsclient::ClientBuilder::new(&wsurl)
         .expect("failed to parse server url")
-        .connect_insecure());
+        .connect_on(conn));
 
     send!(ws, LOGIN);
     send!(ws, *kp.public());
@@ -47,9 +56,7 @@ pub fn login() -> Result<(), HErr> {
     {
         let res = recv!(ws, ClaimResponse);
         if res != ClaimResponse::Challenge {
-            return Err(HeraldError(
-                "login failed - did you register this device yet?".into(),
-            ));
+            return Err(HErr::LoginClaimFailed(res));
         }
     }
 
@@ -60,37 +67,128 @@ pub fn login() -> Result<(), HErr> {
     {
         let res = recv!(ws, ChallengeResult);
         if res != ChallengeResult::Success {
-            return Err(HeraldError("login failed - bad sig".into()));
+            return Err(HErr::LoginChallengeFailed);
         }
     }
 
+    // needed to check delivery receipts, which may arrive during catchup
+    if w!(config::server_key()).is_none() {
+        w!(config::set_server_key(w!(helper::server_key(&()))));
+    }
+
+    w!(replenish_prekeys());
+
     let ev = w!(catchup(&mut ws));
 
+    let (reader, writer) = w!(ws.split());
+    socket::attach(writer);
+
     CAUGHT_UP.store(true, Ordering::Release);
 
-    // clear pending
-    for (tag, cid, content) in w!(pending::get_pending()) {
-        w!(send_cmessage(cid, content));
-        w!(pending::remove_pending(tag));
-    }
+    let (push_tx, push_rx) = crossbeam_channel::unbounded();
 
-    // send read receipts, etc
-    w!(ev.execute());
+    // dropped when the reader finishes, which stops the pings
+    let (alive_tx, alive_rx) = crossbeam_channel::bounded::<()>(0);
+
+    let reader = std::thread::spawn(move || {
+        let _alive = alive_tx;
+
+        socket::read_loop(reader, push_tx)
+            .unwrap_or_else(|e| eprintln!("login connection closed with message: {}", e));
 
+        CAUGHT_UP.store(false, Ordering::Release);
+        socket::detach();
+    });
+
+    std::thread::spawn(move || {
+        while let Err(crossbeam_channel::RecvTimeoutError::Timeout) =
+            alive_rx.recv_timeout(socket::PING_INTERVAL)
+        {
+            if socket::ping().is_err() {
+                break;
+            }
+        }
+    });
+
+    // pushes are handled on their own thread, since handling them may involve requests whose
+    // responses arrive on the reader thread
     std::thread::spawn(move || {
         move || -> Result<(), HErr> {
-            loop {
-                let push = recv!(ws, Push);
-                let ev = w!(handle_push(push));
-                send!(ws, PushAck::Success);
+            for push in push_rx {
+                // a push we can never handle would be redelivered forever, so the server is told
+                // to drop it instead; after any other failure the connection is dropped without
+                // acking, so that the push is delivered again once we reconnect
+                let ev = match handle_push(push) {
+                    Ok(ev) => ev,
+                    Err(e) => match failure_code(&e) {
+                        Some(reason) => {
+                            crate::err(e);
+                            w!(socket::send(&ClientMsg::PushAck(PushAck::LogFailure {
+                                reason
+                            })));
+                            continue;
+                        }
+                        None => return Err(e),
+                    },
+                };
+                w!(socket::send(&ClientMsg::PushAck(PushAck::Success)));
 
                 w!(ev.execute());
+
+                // the push may have used up one of our prekeys
+                if let Err(e) = maybe_replenish_prekeys() {
+                    crate::err(e);
+                }
             }
+
+            Ok(())
         }()
-        .unwrap_or_else(|e| eprintln!("login connection closed with message: {}", e));
-        CAUGHT_UP.store(false, Ordering::Release);
+        .unwrap_or_else(|e| {
+            eprintln!("failed to handle push: {}", e);
+            socket::detach();
+        });
     });
 
+    // the connection is up at this point, so failures here shouldn't cause a reconnect
+    if let Err(e) = flush_pending() {
+        crate::err(e);
+    }
+
+    // send read receipts, etc
+    if let Err(e) = ev.execute() {
+        crate::err(e);
+    }
+
+    spawn_retransmitter();
+
+    Ok(reader)
+}
+
+/// Code reported to the server for a push we can never handle, see `PushAck::LogFailure`.
+///
+/// Other failures, e.g., of the database, may go away when the push is handled again.
+fn failure_code(e: &HErr) -> Option<FailureCode> {
+    match e {
+        HErr::KsonError(_) | HErr::TError(proto::TransitError::Kson(_)) => {
+            Some(FailureCode::Malformed)
+        }
+        HErr::TError(proto::TransitError::BadSig(_))
+        | HErr::PayloadError(proto::PayloadError::BadSig(_)) => Some(FailureCode::BadSig),
+        HErr::TError(proto::TransitError::InvalidSender)
+        | HErr::PayloadError(proto::PayloadError::InvalidSender) => {
+            Some(FailureCode::InvalidSender)
+        }
+        _ => None,
+    }
+}
+
+/// Sends the messages that were queued while we were offline.
+fn flush_pending() -> Result<(), HErr> {
+    for (tag, cid, content) in w!(pending::get_pending()) {
+        w!(send_cmessage(cid, content));
+        w!(pending::remove_pending(tag));
+    }
+
     Ok(())
 }
 
@@ -147,6 +147,8 @@ fn handle_msg(
             w!(crate::members::db::add_members_with_tx(&tx, cid, &nm.0));
             w!(tx.commit());
 
+            w!(rotate_sender_key(cid));
+
             let msg = w!(crate::message::db::inbound_aux(
                 &mut conn, nm, cid, mid, uid, ts, expiration
             ));
@@ -9,6 +9,10 @@ use network_types::{
 use ratchet_chat::protocol as proto;
 
 pub(crate) fn handle_push(push: Push) -> Result<Event, HErr> {
+    if push.tag == PushTag::Receipt {
+        return handle_delivery_receipt(push);
+    }
+
     let ts = push.timestamp;
     let from = push.gid;
     let (substance, mut event) = w!(decode_push(push));
@@ -22,6 +26,55 @@ pub(crate) fn handle_push(push: Push) -> Result<Event, HErr> {
     Ok(event)
 }
 
+/// Handles the server telling us that `push.gid` fetched one of our pushes.
+fn handle_delivery_receipt(push: Push) -> Result<Event, HErr> {
+    let mut ev = Event::default();
+
+    let signed: Signed<DeliveryReceipt> = w!(kson::from_bytes(push.msg));
+
+    let server_key = w!(w!(config::server_key())
+        .ok_or_else(|| HeraldError("got a delivery receipt before the server key".into())));
+
+    if *signed.signed_by() != server_key {
+        return Err(HeraldError(
+            "delivery receipt not signed by the server".into(),
+        ));
+    }
+
+    let valid = signed.verify_sig();
+    if valid != SigValid::Yes {
+        return Err(HeraldError(format!(
+            "bad signature on delivery receipt: {:?}",
+            valid
+        )));
+    }
+
+    let DeliveryReceipt { push_id, to } = *signed.data();
+
+    if to != push.gid {
+        return Err(HeraldError(
+            "delivery receipt names a different device than it came from".into(),
+        ));
+    }
+
+    let delivered = {
+        let conn = w!(crate::db::Database::get());
+        w!(crate::message::db::deliveries::add_delivery(
+            &conn, push_id, to
+        ))
+    };
+
+    for (msg_id, cid) in delivered {
+        ev.notifications.push(Notification::MsgDelivered {
+            cid,
+            msg_id,
+            device: push.gid,
+        });
+    }
+
+    Ok(ev)
+}
+
 fn decode_push(
     Push {
         tag,
@@ -36,16 +89,26 @@ fn decode_push(
     let uid = w!(config::id());
     let kp = w!(config::keypair());
 
+    let msg: proto::Msg = w!(kson::from_bytes(msg));
+
+    // sigchain updates may change the safety number of a verified contact
+    let keys_changed = match &msg {
+        proto::Msg::SigUpdate(_) => Some(from.uid),
+        proto::Msg::Forwarded(uid, _) => Some(*uid),
+        _ => None,
+    };
+
     let proto::MsgResult {
         ack,
         forward,
         output,
         response,
+        to_sender,
+        needs_session,
+        gave_up,
     } = {
         get_crypto_conn!(store);
 
-        let msg: proto::Msg = w!(kson::from_bytes(msg));
-
         let res = w!(proto::handle_incoming(&mut store, &kp, from, msg));
 
         w!(store.commit());
@@ -53,6 +116,20 @@ fn decode_push(
         res
     };
 
+    if let Some(changed) = keys_changed {
+        if let Some(notif) = w!(crate::user::check_keys(changed)) {
+            ev.notifications.push(notif);
+        }
+
+        // let the sender check that we were shown the same update
+        if changed == from.uid && changed != uid {
+            ev.outbox.extend(w!(prepare_send_umessage(
+                changed,
+                UserMessage::Heads(w!(super::heads::heads_for(changed)))
+            )));
+        }
+    }
+
     if let Some(ack) = ack {
         ev.add_msg_to_device(from.did, proto::Msg::Ack(ack));
     }
@@ -65,6 +142,32 @@ fn decode_push(
         ev.add_msg_to_user(from.uid, response);
     }
 
+    for msg in to_sender {
+        ev.add_msg_to_device(from.did, msg);
+    }
+
+    if let Some(id) = needs_session {
+        w!(super::message_senders::establish_sessions_with(
+            &kp,
+            &[from.did]
+        ));
+
+        get_crypto_conn!(store);
+
+        if let Some(msg) = w!(proto::prepare_resend(&mut store, from.did, id)) {
+            ev.add_msg_to_device(from.did, msg);
+        }
+
+        w!(store.commit());
+    }
+
+    if let Some((_, reason)) = gave_up {
+        ev.errors.push(HErr::DeliveryFailed {
+            to: from.did,
+            reason,
+        });
+    }
+
     if let Some(msg) = output {
         match kson::from_bytes(msg) {
             Ok(s) => {
@@ -151,26 +254,43 @@ fn handle_umessage(
             ev.notifications
                 .push(Notification::NewUser(Box::new((user, meta))));
 
-            let chain =
-                w!(w!(helper::get_sigchain(&from.uid)).ok_or(HeraldError("missing user".into())));
-            let valid = chain.validate();
-            if valid != SigValid::Yes {
-                return Err(HeraldError("bad sigchain found on server".into()));
-            }
-            let sig::SigChain { initial, sig_chain } = chain;
-
-            get_crypto_conn!(lock, store);
-            w!(store.start_sigchain(initial));
-            for link in sig_chain {
-                w!(store.extend_sigchain(uid, link));
-            }
-            w!(store.commit());
-            drop(lock);
+            w!(store_sigchain(uid, w!(fetch_sigchain(&uid))));
 
             w!(ev.push_cm(
                 cid,
                 ConversationMessage::Message(NetContent::UserReqAck(cmessages::UserReqAck(true))),
             ));
+
+            ev.outbox.extend(w!(prepare_send_umessage(
+                uid,
+                UserMessage::Heads(w!(super::heads::heads_for(uid)))
+            )));
+        }
+
+        UserMessage::Heads(heads) => {
+            w!(super::heads::handle_heads(uid, heads, &mut ev));
+        }
+
+        UserMessage::SyncReq => {
+            // account data only goes to the user's own devices
+            if uid != w!(config::id()) {
+                return Err(HeraldError("account data requested by another user".into()));
+            }
+
+            let sync = UserMessage::Sync(Box::new(w!(super::link::account_sync())));
+
+            ev.outbox
+                .extend(w!(super::message_senders::prepare_send_umessage_to_device(
+                    from.did, sync
+                )));
+        }
+
+        UserMessage::Sync(sync) => {
+            if uid != w!(config::id()) {
+                return Err(HeraldError("account data sent by another user".into()));
+            }
+
+            w!(super::link::apply_account_sync(*sync, &mut ev));
         }
     }
 
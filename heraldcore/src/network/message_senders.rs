@@ -11,38 +11,122 @@ pub enum SendOutcome {
     Pending,
 }
 
+/// Starts sessions with any devices of `uids` we haven't talked to yet, using prekey bundles
+/// fetched from the server.
+fn establish_sessions(
+    kp: &sig::KeyPair,
+    uids: &[UserId],
+) -> Result<(), HErr> {
+    let keys = {
+        get_crypto_conn!(store);
+
+        let mut keys = Vec::new();
+        for uid in uids {
+            keys.extend(w!(store.active_keys(*uid)));
+        }
+
+        keys
+    };
+
+    establish_sessions_with(kp, &keys)
+}
+
+/// Starts sessions with any of `keys` we haven't talked to yet.
+///
+/// Devices the server has no prekeys for are left without a session. Payloads for them stay
+/// pending and are sent by the retransmitter once a session can be started.
+pub(super) fn establish_sessions_with(
+    kp: &sig::KeyPair,
+    keys: &[sig::PublicKey],
+) -> Result<(), HErr> {
+    let missing = {
+        get_crypto_conn!(store);
+
+        let mut missing = w!(proto::missing_sessions(&mut store, keys));
+        missing.retain(|k| k != kp.public());
+
+        missing
+    };
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    // the crypto store is unlocked while we wait on the server
+    let bundles = w!(helper::get_prekeys(&missing));
+
+    get_crypto_conn!(store);
+
+    for (key, bundle) in bundles {
+        w!(proto::start_session(&mut store, kp, key, bundle));
+    }
+
+    w!(store.commit());
+
+    Ok(())
+}
+
+/// Replaces our sender key for `cid`, should be called whenever its membership changes.
+pub(crate) fn rotate_sender_key(cid: ConversationId) -> Result<(), HErr> {
+    get_crypto_conn!(store);
+
+    w!(proto::rotate_sender_key(&mut store, group_id(cid)));
+    w!(store.commit());
+
+    Ok(())
+}
+
+fn group_id(cid: ConversationId) -> proto::GroupId {
+    proto::GroupId(cid.0)
+}
+
+/// Encrypts `msg` once with our sender key for `cid`, to be fanned out to all members' devices.
+///
+/// Devices that don't have our current sender key yet get it over their pairwise session first.
 pub(crate) fn prepare_send_cmessage(
     cid: ConversationId,
     msg: ConversationMessage,
 ) -> Result<Vec<(Recip, proto::Msg)>, HErr> {
     let kp = w!(config::keypair());
+    let members = w!(crate::members::members(&cid));
+
+    w!(establish_sessions(&kp, &members));
 
     get_crypto_conn!(store);
 
+    let mut keys = Vec::new();
+    for user in members {
+        keys.extend(w!(store.active_keys(user)));
+    }
+    keys.retain(|k| k != kp.public());
+
     let substance = network_types::Substance::Cm { cid, msg };
     let payload = proto::Payload::from(kson::to_vec(&substance));
 
-    let members = w!(crate::members::members(&cid));
-    let mut out = Vec::with_capacity(members.len());
-
-    for user in members {
-        let pairs = w!(proto::prepare_send_to_user(
-            &mut store,
-            &kp,
-            user,
-            payload.clone()
-        ));
-
-        out.extend(
-            pairs
-                .into_iter()
-                .map(|(k, m)| (Recip::One(SingleRecip::Key(k)), m)),
-        );
-    }
+    let proto::GroupSend {
+        distribute,
+        to,
+        msg,
+    } = w!(proto::prepare_send_to_group(
+        &mut store,
+        &kp,
+        group_id(cid),
+        keys,
+        payload
+    ));
 
     // todo: consider committing later
     w!(store.commit());
 
+    let mut out: Vec<(Recip, proto::Msg)> = distribute
+        .into_iter()
+        .map(|(k, m)| (Recip::One(SingleRecip::Key(k)), m))
+        .collect();
+
+    if !to.is_empty() {
+        out.push((Recip::Many(Recips::Keys(to)), msg));
+    }
+
     Ok(out)
 }
 
@@ -51,23 +135,40 @@ pub(crate) fn send_cmessage(
     content: ConversationMessage,
 ) -> Result<SendOutcome, HErr> {
     let prepared = w!(prepare_send_cmessage(cid, content.clone()));
-    let from: GlobalId = w!(crate::config::gid());
+    let kp = w!(config::keypair());
 
     if CAUGHT_UP.load(Ordering::Acquire) {
+        let mut pushed = Vec::with_capacity(prepared.len());
+
         for (to, msg) in prepared {
-            let req = push::Req {
-                from,
-                to,
-                msg: kson::to_vec(&msg).into(),
+            // sender key distributions don't carry the message, so only the group push counts
+            let carries_content = match msg {
+                proto::Msg::Group { .. } => true,
+                _ => false,
             };
+
+            let req = sign_ser(
+                &kp,
+                push::Body {
+                    to,
+                    msg: kson::to_vec(&msg).into(),
+                },
+            );
             match helper::push(&req) {
-                Ok(push::Res::Success(ts)) => {}
+                Ok(push::Res::Success { push_id, .. }) => {
+                    if carries_content {
+                        pushed.extend(push_id);
+                    }
+                }
                 Ok(push::Res::Missing(missing)) => {
                     return Err(HeraldError(format!(
                         "tried to send messages to nonexistent users {:?}",
                         missing
                     )));
                 }
+                Ok(res) => {
+                    return Err(HeraldError(format!("server rejected push: {:?}", res)));
+                }
                 Err(e) => {
                     CAUGHT_UP.store(false, Ordering::Release);
                     w!(pending::add_to_pending(cid, &content));
@@ -75,6 +176,20 @@ pub(crate) fn send_cmessage(
                 }
             }
         }
+
+        // remembered so the server's delivery receipts can be matched up with the message
+        if let ConversationMessage::Message(NetContent::Msg(msg)) = &content {
+            let conn = w!(crate::db::Database::get());
+            w!(crate::message::db::deliveries::add_sent_pushes(
+                &conn, msg.mid, &pushed
+            ));
+        }
+
+        // the message was sent, so failing to gossip heads is only reported
+        if let Err(e) = super::heads::gossip_heads(cid) {
+            crate::err(e);
+        }
+
         Ok(SendOutcome::Success)
     } else {
         w!(pending::add_to_pending(cid, &content));
@@ -88,12 +203,14 @@ pub(crate) fn prepare_send_umessage(
 ) -> Result<Vec<(Recip, proto::Msg)>, HErr> {
     let kp = w!(config::keypair());
 
+    w!(establish_sessions(&kp, &[uid]));
+
     get_crypto_conn!(store);
 
     let substance = network_types::Substance::Um(um);
     let payload = proto::Payload::from(kson::to_vec(&substance));
 
-    let prepared = w!(proto::prepare_send_to_user(&mut store, &kp, uid, payload));
+    let prepared = w!(proto::prepare_send_to_user(&mut store, uid, payload));
 
     // todo: consider committing later
     w!(store.commit());
@@ -104,18 +221,77 @@ pub(crate) fn prepare_send_umessage(
         .collect())
 }
 
+/// Prepares `um` for our own device `key` alone.
+pub(super) fn prepare_send_umessage_to_device(
+    key: sig::PublicKey,
+    um: UserMessage,
+) -> Result<Vec<(Recip, proto::Msg)>, HErr> {
+    let kp = w!(config::keypair());
+
+    w!(establish_sessions_with(&kp, &[key]));
+
+    get_crypto_conn!(store);
+
+    let substance = network_types::Substance::Um(um);
+    let payload = proto::Payload::from(kson::to_vec(&substance));
+
+    let prepared = w!(proto::prepare_send_to_key(&mut store, key, payload));
+
+    w!(store.commit());
+
+    Ok(prepared
+        .into_iter()
+        .map(|(k, m)| (Recip::One(SingleRecip::Key(k)), m))
+        .collect())
+}
+
+/// Sends `msg` to our other devices.
+pub(super) fn send_umessage_to_self(msg: UserMessage) -> Result<(), HErr> {
+    let kp = w!(config::keypair());
+    let uid = w!(config::id());
+
+    w!(establish_sessions(&kp, &[uid]));
+
+    let prepared = {
+        get_crypto_conn!(store);
+
+        let substance = network_types::Substance::Um(msg);
+        let payload = proto::Payload::from(kson::to_vec(&substance));
+
+        let prepared = w!(proto::prepare_send_to_self(&mut store, &kp, uid, payload));
+        w!(store.commit());
+
+        prepared
+    };
+
+    for (key, msg) in prepared {
+        let req = sign_ser(
+            &kp,
+            push::Body {
+                to: Recip::One(SingleRecip::Key(key)),
+                msg: kson::to_vec(&msg).into(),
+            },
+        );
+        w!(helper::push(&req));
+    }
+
+    Ok(())
+}
+
 pub(super) fn send_umessage(
     uid: UserId,
     msg: UserMessage,
 ) -> Result<(), HErr> {
     let prepared = w!(prepare_send_umessage(uid, msg));
-    let from: GlobalId = w!(crate::config::gid());
+    let kp = w!(config::keypair());
     for (to, msg) in prepared {
-        let req = push::Req {
-            from,
-            to,
-            msg: kson::to_vec(&msg).into(),
-        };
+        let req = sign_ser(
+            &kp,
+            push::Body {
+                to,
+                msg: kson::to_vec(&msg).into(),
+            },
+        );
         w!(helper::push(&req));
     }
 
@@ -22,13 +22,16 @@ pub(crate) use statics::default_server;
 use statics::*;
 
 mod login_imp;
-pub use login_imp::login;
+
+mod connection;
+pub use connection::{connection_state, login, logout, ConnectionState};
 
 mod message_handlers;
 use message_handlers::*;
 
 mod message_senders;
 // pub(crate) use message_senders::send_cmessage;
+pub(crate) use message_senders::rotate_sender_key;
 pub use message_senders::SendOutcome;
 use message_senders::*;
 
@@ -37,15 +40,34 @@ use event::*;
 
 mod helper;
 
+mod socket;
+
+mod tls;
+
+mod prekeys;
+use prekeys::{maybe_replenish_prekeys, replenish_prekeys};
+
+mod devices;
+pub use devices::{devices, revoke_device, Device};
+
+mod heads;
+
+mod link;
+pub use link::{approve_link, finish_link, start_link, verify_link, LinkRequest};
+
+mod retransmit;
+pub use retransmit::set_pending_expiry;
+use retransmit::spawn_retransmitter;
+
 #[macro_export]
 macro_rules! get_crypto_conn {
     ($store:ident) => {
-        let raw = crypto_store::prelude::raw_conn();
+        let raw = w!(crypto_store::prelude::raw_conn());
         let mut lock = raw.lock();
         let mut $store = w!(crypto_store::prelude::as_conn(&mut lock));
     };
     ($lock:ident, $store:ident) => {
-        let raw = crypto_store::prelude::raw_conn();
+        let raw = w!(crypto_store::prelude::raw_conn());
         let mut $lock = raw.lock();
         let mut $store = w!(crypto_store::prelude::as_conn(&mut $lock));
     };
@@ -62,18 +84,21 @@ pub fn dep_key(to_dep: sig::PublicKey) -> Result<PKIResponse, HErr> {
     let res = w!(helper::new_sig(&Box::new(update)));
 
     if res == PKIResponse::Success {
-        store.extend_sigchain(gid.uid, update.clone());
+        // this also tears down everything shared with the deprecated key
+        w!(store.extend_sigchain(gid.uid, update.clone()));
 
         let as_msg = proto::Msg::SigUpdate(update);
         let users: Vec<UserId> = w!(store.get_all_users());
 
         w!(store.commit());
 
-        w!(helper::push(&push::Req {
-            from: gid,
-            to: Recip::Many(Recips::Users(users)),
-            msg: kson::to_vec(&as_msg).into(),
-        }));
+        w!(helper::push(&sign_ser(
+            &kp,
+            push::Body {
+                to: Recip::Many(Recips::Users(users)),
+                msg: kson::to_vec(&as_msg).into(),
+            }
+        )));
     }
 
     Ok(res)
@@ -97,20 +122,26 @@ pub fn new_key(to_new: Signed<UserId>) -> Result<PKIResponse, HErr> {
 
         w!(store.commit());
 
-        w!(helper::push(&push::Req {
-            from: gid,
-            to: Recip::Many(Recips::Users(users)),
-            msg: kson::to_vec(&as_msg).into(),
-        }));
+        w!(helper::push(&sign_ser(
+            &kp,
+            push::Body {
+                to: Recip::Many(Recips::Users(users)),
+                msg: kson::to_vec(&as_msg).into(),
+            }
+        )));
     }
 
     Ok(res)
 }
 
 /// Registers new user on the server.
+///
+/// If `server_pin` is set, the server is reached over TLS and must present a certificate
+/// matching the pin. The pin is stored alongside the home server for later connections.
 pub fn register(
     uid: UserId,
     home_server: Option<SocketAddr>,
+    server_pin: Option<CertPin>,
 ) -> Result<protocol::auth::RegisterResponse, HErr> {
     use protocol::auth::RegisterResponse;
 
@@ -122,16 +153,25 @@ pub fn register(
 
     let sig = sign_ser(&kp, uid);
 
-    get_crypto_conn!(store);
+    get_crypto_conn!(lock, store);
 
-    let res = w!(helper::register(&sig, home_server));
+    let res = w!(helper::register(&sig, home_server, server_pin));
 
     // TODO: retry if this fails?
     if res == RegisterResponse::Success {
         w!(store.start_sigchain(sig));
-        w!(crate::config::ConfigBuilder::new(uid, kp)
-            .home_server(home_server)
-            .add());
+        w!(store.commit());
+        drop(lock);
+
+        let mut builder = crate::config::ConfigBuilder::new(uid, kp).home_server(home_server);
+
+        if let Some(pin) = server_pin {
+            builder = builder.server_pin(pin);
+        }
+
+        w!(builder.add());
+
+        w!(replenish_prekeys());
     }
 
     Ok(res)
@@ -169,22 +209,57 @@ pub fn send_user_req(
 ) -> Result<(), HErr> {
     let req = network_types::umessages::UserReq { cid };
 
-    let chain = w!(w!(helper::get_sigchain(&uid)).ok_or(HeraldError("missing user".into())));
+    w!(store_sigchain(uid, w!(fetch_sigchain(&uid))));
+
+    w!(send_umessage(uid, UserMessage::Req(req)));
+    w!(send_umessage(
+        uid,
+        UserMessage::Heads(w!(heads::heads_for(uid)))
+    ));
+
+    Ok(())
+}
+
+/// Fetches the sigchain of `uid` from the server, failing if it isn't valid.
+fn fetch_sigchain(uid: &UserId) -> Result<sig::SigChain, HErr> {
+    let chain = w!(w!(helper::get_sigchain(uid)).ok_or(HeraldError("missing user".into())));
     let valid = chain.validate();
     if valid != SigValid::Yes {
         return Err(HeraldError("bad sigchain found on server".into()));
     }
+
+    Ok(chain)
+}
+
+/// Stores the sigchain of `uid`, so we know which devices it has.
+///
+/// The chain must extend the one stored before, otherwise the server has dropped links from it.
+fn store_sigchain(
+    uid: UserId,
+    chain: sig::SigChain,
+) -> Result<(), HErr> {
+    get_crypto_conn!(store);
+
+    if let Some(head) = w!(store.sigchain_head(uid)) {
+        if !chain.extends(&head) {
+            return Err(HeraldError(format!(
+                "sigchain of {} doesn't extend the one seen before",
+                uid
+            )));
+        }
+    }
+
     let sig::SigChain { initial, sig_chain } = chain;
 
-    get_crypto_conn!(lock, store);
     w!(store.start_sigchain(initial));
     for link in sig_chain {
         w!(store.extend_sigchain(uid, link));
     }
     w!(store.commit());
-    drop(lock);
 
-    w!(send_umessage(uid, UserMessage::Req(req)));
+    if let Some(notif) = w!(crate::user::check_keys(uid)) {
+        crate::push(notif);
+    }
 
     Ok(())
 }
@@ -326,6 +401,12 @@ pub fn run_action(act: NetworkAction) -> Result<(), HErr> {
     Ok(())
 }
 
-pub(crate) fn server_url(ext: &str) -> String {
-    format!("http://{}/{}", home_server(), ext)
+/// URL of the endpoint `ext` on the home server, over TLS if `pin` is set.
+pub(crate) fn server_url(
+    ext: &str,
+    pin: Option<CertPin>,
+) -> String {
+    let scheme = if pin.is_some() { "https" } else { "http" };
+
+    format!("{}://{}/{}", scheme, home_server(), ext)
 }
@@ -0,0 +1,155 @@
+use super::*;
+use kcl::kx;
+use ratchet_chat::protocol::PrekeyStore;
+use std::sync::atomic::AtomicI64;
+
+/// Number of unused one-time prekeys we try to keep published.
+const ONE_TIME_PREKEYS: usize = 50;
+
+/// How long a signed prekey is used before it is rotated, in milliseconds.
+const SIGNED_PREKEY_LIFETIME: i64 = 7 * 24 * 60 * 60 * 1000;
+
+/// Minimum time between prekey checks while logged in, in milliseconds.
+const CHECK_INTERVAL: i64 = 10 * 60 * 1000;
+
+static LAST_CHECK: AtomicI64 = AtomicI64::new(0);
+
+/// Like `replenish_prekeys`, but does nothing if we checked less than `CHECK_INTERVAL` ago.
+pub(crate) fn maybe_replenish_prekeys() -> Result<(), HErr> {
+    let now = Time::now();
+
+    if now.within(
+        CHECK_INTERVAL,
+        Time::from(LAST_CHECK.load(Ordering::Acquire)),
+    ) {
+        return Ok(());
+    }
+
+    replenish_prekeys()
+}
+
+/// Makes sure the server has a fresh signed prekey and enough one-time prekeys for this device.
+///
+/// The server hands out each one-time prekey only once, so we top them up to `ONE_TIME_PREKEYS`
+/// based on how many it has left, replacing those that were used to start a session with us. The
+/// signed prekey is rotated once it is older than `SIGNED_PREKEY_LIFETIME`, at which point the
+/// secrets of one-time prekeys that were handed out but never used are pruned.
+///
+/// New prekey secrets are committed to the crypto store before their public halves are uploaded,
+/// so that the store isn't locked while we wait on the server. A new signed prekey only replaces
+/// the current one once the server has accepted it, and keys the server rejected are deleted
+/// again.
+pub(crate) fn replenish_prekeys() -> Result<(), HErr> {
+    LAST_CHECK.store(*Time::now().as_i64(), Ordering::Release);
+
+    let kp = w!(config::keypair());
+
+    let rotate = {
+        get_crypto_conn!(store);
+
+        match w!(store.signed_prekey()) {
+            Some((_, ts)) => !Time::now().within(SIGNED_PREKEY_LIFETIME, ts),
+            None => true,
+        }
+    };
+
+    let held = if rotate {
+        let held = w!(helper::held_prekeys(kp.public()));
+
+        get_crypto_conn!(store);
+
+        // someone may still be about to start a session with a prekey they were just handed
+        let before = Time::from(*Time::now().as_i64() - SIGNED_PREKEY_LIFETIME);
+        w!(store.prune_prekeys(&held, before));
+        w!(store.commit());
+
+        held.len()
+    } else {
+        w!(helper::prekey_count(kp.public())) as usize
+    };
+
+    let (signed, consumed, req) = {
+        get_crypto_conn!(store);
+
+        let signed = if rotate {
+            let new = kx::KeyPair::gen_new();
+            let signed = sign_ser(&kp, Prekey(*new.public()));
+            w!(store.store_prekey(new.clone()));
+
+            Some((signed, new))
+        } else {
+            None
+        };
+
+        let consumed = w!(store.consumed_prekeys());
+
+        // the server normally dropped consumed prekeys when it handed them out, naming them as
+        // replaced only matters if it didn't
+        let mut olds = consumed.iter().copied();
+
+        let mut one_time = Vec::new();
+        for _ in held..ONE_TIME_PREKEYS {
+            one_time.push((w!(new_one_time(&kp, &mut store)), olds.next()));
+        }
+
+        if signed.is_none() && one_time.is_empty() {
+            return Ok(());
+        }
+
+        w!(store.commit());
+
+        let req = new_prekeys::Req {
+            signed: signed.as_ref().map(|(signed, _)| *signed),
+            one_time,
+        };
+
+        (signed, consumed, req)
+    };
+
+    // without a response the server may or may not have stored the keys, so their secrets are
+    // kept: the signed prekey is rotated again at the next check, and whatever the server
+    // doesn't hold is pruned at a later rotation
+    let res = w!(helper::new_prekeys(&req));
+
+    get_crypto_conn!(store);
+
+    if let new_prekeys::Res::Success = res {
+        if let Some((_, new)) = signed {
+            w!(store.rotate_signed_prekey(new));
+        }
+
+        // the new one-time prekeys make up for all of them
+        for old in consumed {
+            w!(store.del_consumed_prekey(old));
+        }
+
+        w!(store.commit());
+
+        return Ok(());
+    }
+
+    // the server stores all of these or none of them, so no one can start a session with them
+    for new in req
+        .signed
+        .iter()
+        .chain(req.one_time.iter().map(|(new, _)| new))
+    {
+        w!(store.discard_prekey(*new.data()));
+    }
+
+    w!(store.commit());
+
+    Err(HeraldError(format!("failed to upload prekeys: {:?}", res)))
+}
+
+fn new_one_time(
+    kp: &sig::KeyPair,
+    store: &mut crypto_store::Conn,
+) -> Result<Signed<Prekey>, HErr> {
+    let new = kx::KeyPair::gen_new();
+    let signed = sign_ser(kp, Prekey(*new.public()));
+
+    w!(store.store_prekey(new));
+
+    Ok(signed)
+}
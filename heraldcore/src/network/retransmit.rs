@@ -0,0 +1,114 @@
+use super::*;
+use std::{
+    sync::atomic::{AtomicI64, AtomicU64},
+    time::Duration,
+};
+
+/// How long we wait for a device to acknowledge a payload before sending it again, in
+/// milliseconds.
+const RESEND_AFTER: i64 = 5 * 60 * 1000;
+
+/// How often the background task checks for unacknowledged payloads.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Default age after which payloads are dropped even if they were never acknowledged, in
+/// milliseconds.
+const DEFAULT_PENDING_EXPIRY: i64 = 30 * 24 * 60 * 60 * 1000;
+
+static PENDING_EXPIRY: AtomicI64 = AtomicI64::new(DEFAULT_PENDING_EXPIRY);
+
+/// Incremented on each login, so that a resend task from an earlier connection stops.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Sets how long payloads are kept around waiting for acknowledgements, in milliseconds.
+pub fn set_pending_expiry(millis: i64) {
+    PENDING_EXPIRY.store(millis, Ordering::Release);
+}
+
+/// Spawns a thread that periodically runs `retransmit_pending` for as long as we stay caught
+/// up with the server.
+pub(super) fn spawn_retransmitter() {
+    let generation = GENERATION.fetch_add(1, Ordering::AcqRel) + 1;
+
+    std::thread::spawn(move || loop {
+        std::thread::sleep(CHECK_INTERVAL);
+
+        if GENERATION.load(Ordering::Acquire) != generation || !CAUGHT_UP.load(Ordering::Acquire) {
+            return;
+        }
+
+        if let Err(e) = retransmit_pending() {
+            crate::err(e);
+        }
+    });
+}
+
+/// Cleans up and resends payloads that are still waiting on acknowledgements.
+///
+/// Recipients that were deprecated since the payload was sent are dropped, payloads older than
+/// the pending expiry are deleted, and everything else that hasn't been acknowledged within
+/// `RESEND_AFTER` is sent again, starting new sessions where necessary. Each resend counts
+/// towards `MAX_RETRIES` like those a recipient asks for, after which we give up on the device.
+pub(crate) fn retransmit_pending() -> Result<(), HErr> {
+    use proto::{PendingStore, RatchetStore, MAX_RETRIES};
+
+    let kp = w!(config::keypair());
+    let now = *Time::now().as_i64();
+    let expiry = PENDING_EXPIRY.load(Ordering::Acquire);
+
+    let unacked = {
+        get_crypto_conn!(store);
+
+        w!(store.drop_deprecated_pending());
+        w!(store.expire_pending(Time::from(now - expiry)));
+
+        let unacked = w!(store.unacked_pending(Time::from(now - RESEND_AFTER)));
+
+        w!(store.commit());
+
+        unacked
+    };
+
+    if unacked.is_empty() {
+        return Ok(());
+    }
+
+    let mut keys: Vec<sig::PublicKey> = unacked.iter().map(|(_, k)| *k).collect();
+    keys.sort_unstable();
+    keys.dedup();
+
+    w!(establish_sessions_with(&kp, &keys));
+
+    let mut ev = Event::default();
+
+    {
+        get_crypto_conn!(store);
+
+        for (id, to) in unacked {
+            // still waiting on a session, which doesn't count as a retry
+            if w!(store.get_ratchet(to)).is_none() {
+                continue;
+            }
+
+            if w!(store.note_retry(id, to)) > MAX_RETRIES {
+                w!(store.del_pending(id, to));
+                ev.errors.push(HeraldError(format!(
+                    "gave up resending payload to {:?}, it was never acknowledged",
+                    to
+                )));
+                continue;
+            }
+
+            if let Some(msg) = w!(proto::prepare_resend(&mut store, to, id)) {
+                ev.add_msg_to_device(to, msg);
+                w!(store.mark_sent(id, to));
+            }
+        }
+
+        w!(store.commit());
+    }
+
+    w!(ev.execute());
+
+    Ok(())
+}
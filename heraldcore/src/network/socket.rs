@@ -0,0 +1,126 @@
+//! Requests multiplexed over the login websocket.
+//!
+//! Once logged in, the socket is split: a reader thread dispatches pushes and responses, while
+//! requests and push acknowledgements are written through the shared writer. Requests made while
+//! no socket is attached fall back to plain HTTP in `helper`.
+
+use super::tls::Conn;
+use super::*;
+use crossbeam_channel::{bounded, Sender};
+use herald_common::protocol::socket::*;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::{collections::HashMap, sync::atomic::AtomicU64, time::Duration};
+use websocket::{receiver::Reader, sender::Writer};
+
+/// How long we wait for the server to respond to a request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often we ping the server while the socket is attached.
+pub(super) const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long the socket can go without receiving anything, pongs included, before we consider
+/// the connection dead.
+pub(super) const READ_TIMEOUT: Duration = Duration::from_secs(90);
+
+static WRITER: Lazy<Mutex<Option<Writer<Conn>>>> = Lazy::new(|| Mutex::new(None));
+static WAITING: Lazy<Mutex<HashMap<RequestId, Sender<Response>>>> = Lazy::new(Default::default);
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Routes requests through `writer` until the socket is detached.
+pub(super) fn attach(writer: Writer<Conn>) {
+    WRITER.lock().replace(writer);
+}
+
+/// Closes the socket, failing any requests still waiting on a response.
+pub(super) fn detach() {
+    if let Some(writer) = WRITER.lock().take() {
+        drop(writer.stream.shutdown());
+    }
+
+    // dropping the senders wakes up the waiting requests
+    WAITING.lock().clear();
+}
+
+pub(super) fn is_attached() -> bool {
+    WRITER.lock().is_some()
+}
+
+pub(super) fn send(msg: &ClientMsg) -> Result<(), HErr> {
+    send_raw(&WMessage::Binary(kson::to_vec(msg)))
+}
+
+/// Pings the server. Its pong keeps the reader from timing out, so a connection that died
+/// without being closed is noticed within `READ_TIMEOUT`.
+pub(super) fn ping() -> Result<(), HErr> {
+    send_raw(&WMessage::Ping(Vec::new()))
+}
+
+fn send_raw(msg: &WMessage) -> Result<(), HErr> {
+    let mut writer = WRITER.lock();
+    let writer = writer
+        .as_mut()
+        .ok_or_else(|| HeraldError("login socket is not connected".into()))?;
+
+    w!(writer.send_message(msg));
+
+    Ok(())
+}
+
+/// Sends `req` over the login socket and waits for the response.
+///
+/// Returns `None` if no socket is attached.
+pub(super) fn request(req: Request) -> Result<Option<Response>, HErr> {
+    if !is_attached() {
+        return Ok(None);
+    }
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let (tx, rx) = bounded(1);
+
+    WAITING.lock().insert(id, tx);
+
+    if let Err(e) = send(&ClientMsg::Request(id, req)) {
+        WAITING.lock().remove(&id);
+        return Err(e);
+    }
+
+    match rx.recv_timeout(REQUEST_TIMEOUT) {
+        Ok(res) => Ok(Some(res)),
+        Err(_) => {
+            WAITING.lock().remove(&id);
+            Err(HeraldError(format!(
+                "request {} timed out or the connection was closed",
+                id
+            )))
+        }
+    }
+}
+
+/// Reads messages from the server until the socket is closed, passing pushes on to `pushes` and
+/// responses to the requests waiting on them.
+pub(super) fn read_loop(
+    mut reader: Reader<Conn>,
+    pushes: Sender<Push>,
+) -> Result<(), HErr> {
+    loop {
+        let msg = match w!(reader.recv_message()) {
+            WMessage::Binary(v) => w!(kson::from_bytes(v.into())),
+            WMessage::Close(_) => return Ok(()),
+            _ => continue,
+        };
+
+        match msg {
+            ServerMsg::Push(push) => {
+                if pushes.send(push).is_err() {
+                    return Ok(());
+                }
+            }
+            ServerMsg::Response(id, res) => {
+                if let Some(tx) = WAITING.lock().remove(&id) {
+                    drop(tx.send(res));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,9 @@
+SELECT
+  kp,
+  request,
+  home_server,
+  server_pin
+FROM
+  pending_link
+LIMIT
+  1
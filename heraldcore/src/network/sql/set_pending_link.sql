@@ -0,0 +1,3 @@
+INSERT OR REPLACE INTO
+  pending_link(id, kp, request, home_server, server_pin)
+VALUES(0, @kp, @request, @home_server, @server_pin)
@@ -6,6 +6,7 @@ const DEFAULT_SERVER_IP_ADDR: [u8; 4] = [127, 0, 0, 1];
 
 static HOME_SERVER: OnceCell<SocketAddr> = OnceCell::new();
 static DEFAULT_SERVER: OnceCell<SocketAddr> = OnceCell::new();
+static SERVER_PIN: OnceCell<Option<CertPin>> = OnceCell::new();
 
 pub(super) fn home_server() -> &'static SocketAddr {
     match HOME_SERVER.get_or_try_init(crate::config::home_server) {
@@ -14,6 +15,13 @@ pub(super) fn home_server() -> &'static SocketAddr {
     }
 }
 
+/// Pin for the home server's certificate, `None` if we talk to it in plaintext.
+///
+/// Fails if the pin can't be read, rather than falling back to plaintext.
+pub(super) fn server_pin() -> Result<Option<CertPin>, HErr> {
+    Ok(*SERVER_PIN.get_or_try_init(crate::config::server_pin)?)
+}
+
 pub(crate) fn default_server() -> &'static SocketAddr {
     DEFAULT_SERVER.get_or_init(|| SocketAddr::new(DEFAULT_SERVER_IP_ADDR.into(), DEFAULT_PORT))
 }
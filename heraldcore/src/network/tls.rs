@@ -0,0 +1,247 @@
+//! Connections to the home server, pinned to its TLS certificate.
+//!
+//! Servers are identified by the fingerprint of their leaf certificate rather than by a
+//! certificate authority, so that self-hosted servers don't need a CA-signed certificate. If no
+//! pin is configured the connection is made in plaintext, which is only meant for local
+//! development.
+
+use super::*;
+use parking_lot::Mutex;
+use rustls::{
+    Certificate, ClientConfig, ClientSession, RootCertStore, ServerCertVerified,
+    ServerCertVerifier, Session, TLSError,
+};
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, TcpStream},
+    sync::Arc,
+    time::Duration,
+};
+use websocket::stream::sync::Splittable;
+
+/// Name sent in the TLS handshake. Certificates are checked against the pin, not against this
+/// name, so it doesn't need to match the server's address.
+const SERVER_NAME: &str = "herald.server";
+
+/// Accepts exactly the certificate whose fingerprint matches the pin.
+struct PinVerifier(CertPin);
+
+impl ServerCertVerifier for PinVerifier {
+    fn verify_server_cert(
+        &self,
+        _: &RootCertStore,
+        presented_certs: &[Certificate],
+        _: webpki::DNSNameRef,
+        _: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        let leaf = presented_certs
+            .first()
+            .ok_or(TLSError::NoCertificatesPresented)?;
+
+        if CertPin::of(&leaf.0) == self.0 {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(TLSError::WebPKIError(webpki::Error::UnknownIssuer))
+        }
+    }
+}
+
+/// TLS configuration that only trusts the certificate matching `pin`.
+pub(super) fn client_config(pin: CertPin) -> Arc<ClientConfig> {
+    let mut config = ClientConfig::new();
+    config
+        .dangerous()
+        .set_certificate_verifier(Arc::new(PinVerifier(pin)));
+    Arc::new(config)
+}
+
+/// A TLS stream that can be read from and written to on different threads.
+///
+/// Reads from the socket happen without holding the session lock, so a blocked reader doesn't
+/// stop writers from making progress.
+pub(super) struct TlsStream {
+    session: Arc<Mutex<ClientSession>>,
+    sock: TcpStream,
+}
+
+impl TlsStream {
+    fn connect(
+        mut sock: TcpStream,
+        pin: CertPin,
+    ) -> io::Result<Self> {
+        let name = webpki::DNSNameRef::try_from_ascii_str(SERVER_NAME)
+            .expect("SERVER_NAME is a valid DNS name");
+        let mut session = ClientSession::new(&client_config(pin), name);
+
+        // finish the handshake up front, so a bad pin fails the connection immediately
+        while session.is_handshaking() {
+            session.complete_io(&mut sock)?;
+        }
+
+        Ok(Self {
+            session: Arc::new(Mutex::new(session)),
+            sock,
+        })
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            session: self.session.clone(),
+            sock: self.sock.try_clone()?,
+        })
+    }
+
+    fn flush_tls(
+        session: &mut ClientSession,
+        sock: &mut TcpStream,
+    ) -> io::Result<()> {
+        while session.wants_write() {
+            session.write_tls(sock)?;
+        }
+        Ok(())
+    }
+}
+
+impl Read for TlsStream {
+    fn read(
+        &mut self,
+        buf: &mut [u8],
+    ) -> io::Result<usize> {
+        let mut raw = [0u8; 4096];
+
+        loop {
+            {
+                let mut session = self.session.lock();
+                let n = session.read(buf)?;
+                if n > 0 {
+                    return Ok(n);
+                }
+            }
+
+            let n = self.sock.read(&mut raw)?;
+            if n == 0 {
+                return Ok(0);
+            }
+
+            let mut session = self.session.lock();
+            session.read_tls(&mut &raw[..n])?;
+            session
+                .process_new_packets()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            Self::flush_tls(&mut session, &mut self.sock)?;
+        }
+    }
+}
+
+impl Write for TlsStream {
+    fn write(
+        &mut self,
+        buf: &[u8],
+    ) -> io::Result<usize> {
+        let mut session = self.session.lock();
+        let n = session.write(buf)?;
+        Self::flush_tls(&mut session, &mut self.sock)?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut session = self.session.lock();
+        session.flush()?;
+        Self::flush_tls(&mut session, &mut self.sock)
+    }
+}
+
+/// A connection to the home server, encrypted if the server has a pinned certificate.
+pub(super) enum Conn {
+    Plain(TcpStream),
+    Tls(TlsStream),
+}
+
+impl Conn {
+    /// Connects to `addr`, using TLS if `pin` is set.
+    pub(super) fn connect(
+        addr: SocketAddr,
+        pin: Option<CertPin>,
+    ) -> io::Result<Self> {
+        let sock = TcpStream::connect(addr)?;
+
+        match pin {
+            Some(pin) => Ok(Conn::Tls(TlsStream::connect(sock, pin)?)),
+            None => Ok(Conn::Plain(sock)),
+        }
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        match self {
+            Conn::Plain(s) => Ok(Conn::Plain(s.try_clone()?)),
+            Conn::Tls(s) => Ok(Conn::Tls(s.try_clone()?)),
+        }
+    }
+
+    /// Makes reads fail once nothing has been received for `timeout`. This applies to every clone
+    /// of the connection, since they share the underlying socket.
+    pub(super) fn set_read_timeout(
+        &self,
+        timeout: Option<Duration>,
+    ) -> io::Result<()> {
+        match self {
+            Conn::Plain(s) => s.set_read_timeout(timeout),
+            Conn::Tls(s) => s.sock.set_read_timeout(timeout),
+        }
+    }
+
+    /// Closes the connection in both directions, waking up any thread blocked reading from it.
+    pub(super) fn shutdown(&self) -> io::Result<()> {
+        match self {
+            Conn::Plain(s) => s.shutdown(Shutdown::Both),
+            Conn::Tls(s) => {
+                let mut session = s.session.lock();
+                session.send_close_notify();
+                // best effort, the socket is going away regardless
+                drop(session.write_tls(&mut &s.sock));
+                s.sock.shutdown(Shutdown::Both)
+            }
+        }
+    }
+}
+
+impl Read for Conn {
+    fn read(
+        &mut self,
+        buf: &mut [u8],
+    ) -> io::Result<usize> {
+        match self {
+            Conn::Plain(s) => s.read(buf),
+            Conn::Tls(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Conn {
+    fn write(
+        &mut self,
+        buf: &[u8],
+    ) -> io::Result<usize> {
+        match self {
+            Conn::Plain(s) => s.write(buf),
+            Conn::Tls(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Conn::Plain(s) => s.flush(),
+            Conn::Tls(s) => s.flush(),
+        }
+    }
+}
+
+impl Splittable for Conn {
+    type Reader = Conn;
+    type Writer = Conn;
+
+    fn split(self) -> io::Result<(Conn, Conn)> {
+        let reader = self.try_clone()?;
+        Ok((reader, self))
+    }
+}
@@ -4,7 +4,11 @@ DROP INDEX IF EXISTS reply_op;
 DROP INDEX IF EXISTS hash_dir_ix;
 DROP INDEX IF EXISTS expiration_ts_ix;
 DROP INDEX IF EXISTS msg_id_react_ix;
+DROP INDEX IF EXISTS sent_push_msg_id_ix;
 -- drop tables
+DROP TABLE IF EXISTS verified_contacts;
+DROP TABLE IF EXISTS push_deliveries;
+DROP TABLE IF EXISTS sent_pushes;
 DROP TABLE IF EXISTS msg_attachments;
 DROP TABLE IF EXISTS replies;
 DROP TABLE IF EXISTS read_receipts;
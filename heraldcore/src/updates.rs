@@ -4,7 +4,7 @@ use crate::errors::HErr;
 use crate::message;
 use coretypes::conversation::ConversationMeta;
 use crossbeam_channel::{unbounded, Receiver, Sender};
-use herald_common::UserId;
+use herald_common::{GlobalId, UserId};
 use herald_ids::{ConversationId, MsgId};
 use once_cell::sync::OnceCell;
 
@@ -15,6 +15,15 @@ pub enum Notification {
     NewMsg(Box<message::Message>),
     /// A message has been received.
     MsgReceipt(message::MessageReceipt),
+    /// The server has delivered a message to one of the recipients' devices
+    MsgDelivered {
+        /// Conversation id
+        cid: ConversationId,
+        /// The message that was delivered
+        msg_id: MsgId,
+        /// The device it was delivered to
+        device: GlobalId,
+    },
     /// A message reaction has been received
     Reaction {
         /// Conversation id
@@ -48,6 +57,13 @@ pub enum Notification {
     OutboundAux(crate::message::OutboundAux),
     /// User profile information changed
     UserChanged(UserId, herald_user::UserChange),
+    /// The keys of a verified contact changed, so their safety number must be checked again
+    SafetyNumberChanged(UserId),
+    /// The server showed a version of this user's sigchain that conflicts with the one another
+    /// user sees
+    SigchainFork(UserId),
+    /// The state of the connection to the server changed
+    Connection(crate::network::ConnectionState),
 }
 
 /// Registers handlers for notifications
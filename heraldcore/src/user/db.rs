@@ -118,6 +118,39 @@ pub fn user_exists(
     Ok(w!(stmt.exists(&[id])))
 }
 
+/// Gets the fingerprint of a contact's keys when they were verified, if they have been.
+pub(crate) fn verified_fingerprint(
+    conn: &rusqlite::Connection,
+    id: UserId,
+) -> Result<Option<Vec<u8>>, HErr> {
+    let mut stmt = w!(conn.prepare(include_str!("sql/get_verified.sql")));
+    let mut rows = w!(stmt.query_map(params![id], |row| row.get(0)));
+
+    Ok(w!(rows.next().transpose()))
+}
+
+/// Marks a contact as verified, recording the fingerprint of their keys
+pub(crate) fn set_verified(
+    conn: &rusqlite::Connection,
+    id: UserId,
+    fingerprint: &[u8],
+) -> Result<(), HErr> {
+    w!(conn.execute(
+        include_str!("sql/set_verified.sql"),
+        params![id, fingerprint]
+    ));
+    Ok(())
+}
+
+/// Marks a contact as unverified
+pub(crate) fn clear_verified(
+    conn: &rusqlite::Connection,
+    id: UserId,
+) -> Result<(), HErr> {
+    w!(conn.execute(include_str!("sql/clear_verified.sql"), params![id]));
+    Ok(())
+}
+
 /// Sets user status
 pub fn set_status(
     conn: &mut rusqlite::Connection,
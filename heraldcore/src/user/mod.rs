@@ -6,6 +6,9 @@ use herald_user::{User, UserStatus, UserType};
 use rusqlite::{params, NO_PARAMS};
 
 pub(crate) mod db;
+mod safety;
+pub(crate) use safety::check_keys;
+pub use safety::{is_verified, safety_number, set_verified};
 
 /// Gets a user's name by their `id`.
 pub fn name(id: UserId) -> Result<Option<String>, HErr> {
@@ -0,0 +1,237 @@
+//! Safety numbers, which two users can compare to check they see the same keys for each other.
+//!
+//! A safety number is made of a fingerprint of each user's active keys, so it is the same on
+//! both sides and changes whenever either user adds or revokes a device. Marking a contact as
+//! verified records the fingerprint of their keys, and the mark is cleared with a
+//! `SafetyNumberChanged` notification if those keys change.
+
+use super::*;
+use crate::updates::Notification;
+use coremacros::w;
+use kcl::hash;
+use ratchet_chat::protocol::SigStore;
+use std::collections::BTreeSet;
+
+/// Domain separator for fingerprints, so they can't be confused with other hashes of keys.
+const FINGERPRINT_TAG: &[u8] = b"herald safety number v1";
+
+/// Number of digits in each group of a safety number.
+const GROUP_DIGITS: usize = 5;
+
+/// Number of groups each user's fingerprint is shown as.
+const GROUPS_PER_USER: usize = 6;
+
+/// Gets the safety number for this account and the contact `uid`.
+pub fn safety_number(uid: UserId) -> Result<String, HErr> {
+    let own = w!(crate::config::id());
+
+    let own_keys = w!(active_keys(own));
+    let their_keys = w!(active_keys(uid));
+
+    Ok(safety_number_of((own, &own_keys), (uid, &their_keys)))
+}
+
+/// Indicates whether the user has verified the safety number for `uid` since their keys last
+/// changed.
+pub fn is_verified(uid: UserId) -> Result<bool, HErr> {
+    let db = w!(Database::get());
+    Ok(w!(db::verified_fingerprint(&db, uid)).is_some())
+}
+
+/// Marks the contact `uid` as verified, given the safety number the user compared with them, or
+/// clears the mark if no number is given.
+///
+/// Fails if `safety_number` is no longer the safety number for `uid`, e.g. because either user's
+/// keys changed after it was shown, since the keys it was computed from are the ones that were
+/// checked.
+pub fn set_verified(
+    uid: UserId,
+    safety_number: Option<&str>,
+) -> Result<(), HErr> {
+    let db = w!(Database::get());
+
+    let shown = match safety_number {
+        Some(shown) => shown,
+        None => {
+            w!(db::clear_verified(&db, uid));
+            return Ok(());
+        }
+    };
+
+    let own = w!(crate::config::id());
+    let own_keys = w!(active_keys(own));
+    let their_keys = w!(active_keys(uid));
+
+    let fingerprint = w!(
+        checked_fingerprint(shown, (own, &own_keys), (uid, &their_keys)).ok_or_else(|| {
+            HErr::HeraldError(format!(
+                "safety number doesn't match the current keys for {}",
+                uid
+            ))
+        })
+    );
+
+    w!(db::set_verified(&db, uid, &fingerprint));
+
+    Ok(())
+}
+
+/// Checks whether the keys of `uid` changed since they were verified. If so, the contact is
+/// no longer verified and a notification is returned to warn the user.
+pub(crate) fn check_keys(uid: UserId) -> Result<Option<Notification>, HErr> {
+    let db = w!(Database::get());
+
+    let verified = match w!(db::verified_fingerprint(&db, uid)) {
+        Some(verified) => verified,
+        None => return Ok(None),
+    };
+
+    let keys = w!(active_keys(uid));
+
+    if fingerprint(uid, &keys) == verified {
+        return Ok(None);
+    }
+
+    w!(db::clear_verified(&db, uid));
+
+    Ok(Some(Notification::SafetyNumberChanged(uid)))
+}
+
+fn active_keys(uid: UserId) -> Result<BTreeSet<sig::PublicKey>, HErr> {
+    crate::get_crypto_conn!(store);
+
+    let chain = w!(w!(store.get_sigchain(uid))
+        .ok_or(HErr::HeraldError(format!("missing sigchain for {}", uid))));
+
+    Ok(chain.active_keys().into_iter().collect())
+}
+
+fn fingerprint(
+    uid: UserId,
+    keys: &BTreeSet<sig::PublicKey>,
+) -> Vec<u8> {
+    let mut hasher = hash::Builder::new().build();
+
+    hasher.update(FINGERPRINT_TAG);
+    hasher.update(&(uid.as_str().len() as u64).to_le_bytes());
+    hasher.update(uid.as_str().as_bytes());
+
+    for key in keys {
+        hasher.update(key.as_ref());
+    }
+
+    hasher.finalize().0.to_vec()
+}
+
+/// Shows a fingerprint as digits, five at a time from each five bytes.
+fn digits(fingerprint: &[u8]) -> Vec<String> {
+    fingerprint
+        .chunks(5)
+        .take(GROUPS_PER_USER)
+        .map(|chunk| {
+            let n = chunk.iter().fold(0u64, |n, b| (n << 8) | u64::from(*b));
+            format!("{:0width$}", n % 100_000, width = GROUP_DIGITS)
+        })
+        .collect()
+}
+
+/// The fingerprint of `them`'s keys, if `shown` is the safety number for `us` and `them`.
+/// Whitespace is ignored, so the number can be given however it was shown.
+fn checked_fingerprint(
+    shown: &str,
+    us: (UserId, &BTreeSet<sig::PublicKey>),
+    them: (UserId, &BTreeSet<sig::PublicKey>),
+) -> Option<Vec<u8>> {
+    let compact =
+        |number: &str| -> String { number.chars().filter(|c| !c.is_whitespace()).collect() };
+
+    if compact(shown) != compact(&safety_number_of(us, them)) {
+        return None;
+    }
+
+    Some(fingerprint(them.0, them.1))
+}
+
+fn safety_number_of(
+    a: (UserId, &BTreeSet<sig::PublicKey>),
+    b: (UserId, &BTreeSet<sig::PublicKey>),
+) -> String {
+    // both users must put the halves in the same order
+    let (first, second) = if a.0.as_str() <= b.0.as_str() {
+        (a, b)
+    } else {
+        (b, a)
+    };
+
+    let mut groups = digits(&fingerprint(first.0, first.1));
+    groups.extend(digits(&fingerprint(second.0, second.1)));
+
+    groups.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use coremacros::womp;
+    use std::convert::TryInto;
+
+    #[test]
+    fn safety_numbers() {
+        let a: UserId = "a".try_into().expect(womp!());
+        let b: UserId = "b".try_into().expect(womp!());
+
+        let keys = |n: usize| -> BTreeSet<sig::PublicKey> {
+            (0..n).map(|_| *sig::KeyPair::gen_new().public()).collect()
+        };
+
+        let a_keys = keys(1);
+        let mut b_keys = keys(2);
+
+        let number = safety_number_of((a, &a_keys), (b, &b_keys));
+
+        // both users see the same number
+        assert_eq!(number, safety_number_of((b, &b_keys), (a, &a_keys)));
+
+        let groups: Vec<&str> = number.split(' ').collect();
+        assert_eq!(groups.len(), 2 * GROUPS_PER_USER);
+        assert!(groups
+            .iter()
+            .all(|g| g.len() == GROUP_DIGITS && g.chars().all(|c| c.is_ascii_digit())));
+
+        // and it changes when a device is added
+        b_keys.extend(keys(1));
+        assert_ne!(number, safety_number_of((a, &a_keys), (b, &b_keys)));
+    }
+
+    #[test]
+    fn verifying_checks_the_number() {
+        let a: UserId = "a".try_into().expect(womp!());
+        let b: UserId = "b".try_into().expect(womp!());
+
+        let keys = |n: usize| -> BTreeSet<sig::PublicKey> {
+            (0..n).map(|_| *sig::KeyPair::gen_new().public()).collect()
+        };
+
+        let a_keys = keys(1);
+        let mut b_keys = keys(1);
+
+        let shown = safety_number_of((a, &a_keys), (b, &b_keys));
+
+        assert_eq!(
+            checked_fingerprint(&shown, (a, &a_keys), (b, &b_keys)),
+            Some(fingerprint(b, &b_keys))
+        );
+
+        // however the number was laid out
+        assert_eq!(
+            checked_fingerprint(&shown.replace(' ', "\n"), (a, &a_keys), (b, &b_keys)),
+            Some(fingerprint(b, &b_keys))
+        );
+
+        assert!(checked_fingerprint("12345", (a, &a_keys), (b, &b_keys)).is_none());
+
+        // a number shown before the contact's keys changed doesn't verify the new keys
+        b_keys.extend(keys(1));
+        assert!(checked_fingerprint(&shown, (a, &a_keys), (b, &b_keys)).is_none());
+    }
+}
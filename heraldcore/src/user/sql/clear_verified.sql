@@ -0,0 +1,4 @@
+DELETE FROM
+  verified_contacts
+WHERE
+  user_id = ?
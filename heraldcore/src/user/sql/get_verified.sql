@@ -0,0 +1,6 @@
+SELECT
+  fingerprint
+FROM
+  verified_contacts
+WHERE
+  user_id = ?
@@ -0,0 +1,3 @@
+INSERT OR REPLACE INTO
+  verified_contacts(user_id, fingerprint)
+VALUES(@1, @2)
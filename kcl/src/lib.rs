@@ -8,6 +8,7 @@ pub mod box_;
 pub mod ed25519;
 pub mod hash;
 pub mod kx;
+pub mod pwhash;
 pub mod random;
 pub mod sign;
 pub mod x25519;
@@ -0,0 +1,78 @@
+//! Memory-hard key derivation from passphrases, using Argon2id.
+
+use super::*;
+
+pub const SALT_LEN: usize = ffi::crypto_pwhash_SALTBYTES as usize;
+
+/// Operations per derivation, libsodium's "interactive" level.
+const OPS_LIMIT: u64 = ffi::crypto_pwhash_OPSLIMIT_INTERACTIVE as u64;
+
+/// Memory used per derivation, libsodium's "interactive" level (64 MiB).
+const MEM_LIMIT: usize = ffi::crypto_pwhash_MEMLIMIT_INTERACTIVE as usize;
+
+new_type! {
+    /// Salt for a passphrase, stored alongside whatever its key protects
+    public Salt(SALT_LEN)
+}
+
+impl Salt {
+    pub fn gen_new() -> Self {
+        let mut buf = [0u8; SALT_LEN];
+        random::gen_into(&mut buf);
+        Salt(buf)
+    }
+}
+
+/// Derives an `aead` key from `passphrase`.
+///
+/// This is deliberately slow and uses a lot of memory, to make guessing passphrases expensive.
+/// Returns `None` if the memory couldn't be allocated.
+pub fn derive_key(
+    passphrase: &[u8],
+    salt: &Salt,
+) -> Option<aead::Key> {
+    let mut buf = [0u8; aead::KEY_LEN];
+
+    let res = unsafe {
+        ffi::crypto_pwhash(
+            buf.as_mut_ptr(),
+            buf.len() as _,
+            passphrase.as_ptr() as *const _,
+            passphrase.len() as _,
+            salt.as_ref().as_ptr(),
+            OPS_LIMIT as _,
+            MEM_LIMIT as _,
+            ffi::crypto_pwhash_ALG_ARGON2ID13 as _,
+        )
+    };
+
+    if res != 0 {
+        return None;
+    }
+
+    let key = aead::Key::from_slice(&buf);
+
+    unsafe {
+        ffi::sodium_memzero(buf.as_mut_ptr() as *mut _, buf.len());
+    }
+
+    key
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn derivation_is_deterministic() {
+        crate::init();
+        let salt = Salt::gen_new();
+
+        let key = derive_key(b"correct horse", &salt);
+        assert!(key.is_some());
+        assert!(key == derive_key(b"correct horse", &salt));
+
+        assert!(key != derive_key(b"battery staple", &salt));
+        assert!(key != derive_key(b"correct horse", &Salt::gen_new()));
+    }
+}
@@ -81,15 +81,18 @@ impl Container {
         Some(())
     }
 
-    pub fn handle_send_done<M: MessageModel, E: MessageEmit>(
+    /// Moves the send status of `mid` forward to `status`.
+    pub fn handle_send_status<M: MessageModel, E: MessageEmit>(
         &self,
         mid: MsgId,
+        status: heraldcore::message::SendStatus,
         model: &mut M,
         emit: &mut E,
         cid: ConversationId,
     ) -> Option<()> {
         update(&mid, move |data| {
-            data.send_status = heraldcore::message::SendStatus::Ack;
+            // updates can arrive out of order, e.g. a delivery before the send finished
+            data.send_status = data.send_status.max(status);
         })?;
 
         let ix = self
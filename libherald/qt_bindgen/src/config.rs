@@ -53,6 +53,7 @@ fn objects() -> BTreeMap<String, Rc<Object>> {
        message_builder(),
 
        shared_conversations(),
+       devices(),
 
        user(),
 
@@ -67,6 +68,8 @@ fn herald() -> Object {
         configInit: Prop::new().simple(Bool),
         connectionUp: Prop::new().simple(Bool),
         connectionPending: Prop::new().simple(Bool),
+        // Whether the storage is encrypted and waiting for the passphrase
+        locked: Prop::new().simple(Bool),
 
         // Registration failure code
         // UserIdTaken  => 0,
@@ -88,10 +91,14 @@ fn herald() -> Object {
     };
 
     let funcs = functions! {
-        mut registerNewUser(user_id: QString, addr: QString, port: QString) => Void,
+        // `pin` is the hex-encoded fingerprint of the server's TLS certificate, empty if it doesn't use TLS
+        mut registerNewUser(user_id: QString, addr: QString, port: QString, pin: QString) => Void,
         mut login() => Bool,
         mut setAppLocalDataDir(path: QString) => Void,
         mut pollUpdate() => Void,
+        mut unlock(passphrase: QString) => Bool,
+        mut setPassphrase(passphrase: QString) => Bool,
+        mut changePassphrase(old_passphrase: QString, new_passphrase: QString) => Bool,
     };
 
     let hooks = signals! {
@@ -377,6 +384,8 @@ fn messages() -> Object {
         mut removeReaction(index: QUint64, content: QString) => Void,
         mut sendTypingIndicator() => Void,
         const indexById(msg_id: QByteArray) => Qint64,
+        // Devices the message at `index` has been delivered to, serialized as JSON
+        const deliveries(index: QUint64) => QString,
         const saveAllAttachments(index: QUint64, dest: QString) => Bool,
     };
 
@@ -561,7 +570,9 @@ fn user() -> Object {
        name: Prop::new().simple(QString).get_by_value(),
        pairwiseConversationId: Prop::new().simple(QByteArray).get_by_value(),
        profilePicture: Prop::new().simple(QString).optional().get_by_value(),
-       userColor: Prop::new().simple(QUint32).write()
+       userColor: Prop::new().simple(QUint32).write(),
+       safetyNumber: Prop::new().simple(QString).get_by_value(),
+       verified: Prop::new().simple(Bool).write()
     };
 
     obj! {
@@ -601,6 +612,41 @@ fn shared_conversations() -> Object {
     }
 }
 
+/// The account's active devices
+fn devices() -> Object {
+    let item_props = item_props! {
+        deviceKey: ItemProp::new(QByteArray),
+        // Milliseconds since the epoch the device was added to the account
+        created: ItemProp::new(Qint64),
+        // Key of the device that added this one, absent for the device the account was
+        // registered on
+        endorsedBy: ItemProp::new(QByteArray).optional(),
+        isCurrent: ItemProp::new(Bool)
+    };
+
+    let funcs = functions! {
+        mut refresh() => Void,
+        mut load() => Void,
+        // Revokes the device at `row_index`. Returns false if it is this device.
+        mut revoke(row_index: QUint64) => Bool,
+    };
+
+    let hooks = signals! {
+        tryLoad(),
+        | connect tryLoad load
+    };
+
+    let o = Obj::new()
+        .list()
+        .item_props(item_props)
+        .funcs(funcs)
+        .hooks(hooks);
+
+    obj! {
+       Devices: o
+    }
+}
+
 fn conv_id_prop() -> Prop {
     Prop::new()
         .simple(SimpleType::QByteArray)
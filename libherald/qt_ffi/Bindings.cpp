@@ -176,6 +176,7 @@ inline void heraldConnectionPendingChanged(Herald *o) {
 inline void heraldConnectionUpChanged(Herald *o) {
   Q_EMIT o->connectionUpChanged();
 }
+inline void heraldLockedChanged(Herald *o) { Q_EMIT o->lockedChanged(); }
 inline void heraldRegistrationFailureCodeChanged(Herald *o) {
   Q_EMIT o->registrationFailureCodeChanged();
 }
@@ -256,8 +257,12 @@ inline void userPairwiseConversationIdChanged(User *o) {
 inline void userProfilePictureChanged(User *o) {
   Q_EMIT o->profilePictureChanged();
 }
+inline void userSafetyNumberChanged(User *o) {
+  Q_EMIT o->safetyNumberChanged();
+}
 inline void userUserColorChanged(User *o) { Q_EMIT o->userColorChanged(); }
 inline void userUserIdChanged(User *o) { Q_EMIT o->userIdChanged(); }
+inline void userVerifiedChanged(User *o) { Q_EMIT o->verifiedChanged(); }
 inline void usersFilterChanged(Users *o) { Q_EMIT o->filterChanged(); }
 inline void usersFilterRegexChanged(Users *o) {
   Q_EMIT o->filterRegexChanged();
@@ -598,6 +603,157 @@ bool conversations_remove_conversation(Conversations::Private *, quint64);
 bool conversations_toggle_filter_regex(Conversations::Private *);
 }
 extern "C" {
+qint64 devices_data_created(const Devices::Private *, int);
+void devices_data_device_key(const Devices::Private *, int, QByteArray *,
+                             qbytearray_set);
+void devices_data_endorsed_by(const Devices::Private *, int, QByteArray *,
+                              qbytearray_set);
+bool devices_data_is_current(const Devices::Private *, int);
+void devices_sort(Devices::Private *, unsigned char column,
+                  Qt::SortOrder order = Qt::AscendingOrder);
+int devices_row_count(const Devices::Private *);
+bool devices_insert_rows(Devices::Private *, int, int);
+bool devices_remove_rows(Devices::Private *, int, int);
+bool devices_can_fetch_more(const Devices::Private *);
+void devices_fetch_more(Devices::Private *);
+}
+int Devices::columnCount(const QModelIndex &parent) const {
+  return (parent.isValid()) ? 0 : 1;
+}
+
+bool Devices::hasChildren(const QModelIndex &parent) const {
+  return rowCount(parent) > 0;
+}
+
+int Devices::rowCount(const QModelIndex &parent) const {
+  return (parent.isValid()) ? 0 : devices_row_count(m_d);
+}
+
+bool Devices::insertRows(int row, int count, const QModelIndex &) {
+  return devices_insert_rows(m_d, row, count);
+}
+
+bool Devices::removeRows(int row, int count, const QModelIndex &) {
+  return devices_remove_rows(m_d, row, count);
+}
+
+QModelIndex Devices::index(int row, int column,
+                           const QModelIndex &parent) const {
+  if (!parent.isValid() && row >= 0 && row < rowCount(parent) && column >= 0 &&
+      column < 1) {
+    return createIndex(row, column, static_cast<quintptr>(row));
+  }
+  return {};
+}
+
+QModelIndex Devices::parent(const QModelIndex &) const { return {}; }
+
+bool Devices::canFetchMore(const QModelIndex &parent) const {
+  return (parent.isValid()) ? false : devices_can_fetch_more(m_d);
+}
+
+void Devices::fetchMore(const QModelIndex &parent) {
+  if (!parent.isValid()) {
+    devices_fetch_more(m_d);
+  }
+}
+void Devices::updatePersistentIndexes() {}
+
+void Devices::sort(int column, Qt::SortOrder order) {
+  devices_sort(m_d, column, order);
+}
+
+Qt::ItemFlags Devices::flags(const QModelIndex &i) const {
+  auto flags = QAbstractItemModel::flags(i);
+  return flags;
+}
+
+qint64 Devices::created(int row) const {
+  return devices_data_created(m_d, row);
+}
+
+QByteArray Devices::deviceKey(int row) const {
+  QByteArray b;
+  devices_data_device_key(m_d, row, &b, set_qbytearray);
+  return b;
+}
+
+QByteArray Devices::endorsedBy(int row) const {
+  QByteArray b;
+  devices_data_endorsed_by(m_d, row, &b, set_qbytearray);
+  return b;
+}
+
+bool Devices::isCurrent(int row) const {
+  return devices_data_is_current(m_d, row);
+}
+
+QVariant Devices::data(const QModelIndex &index, int role) const {
+  Q_ASSERT(rowCount(index.parent()) > index.row());
+  switch (index.column()) {
+  case 0:
+    switch (role) {
+    case Qt::UserRole + 0:
+      return QVariant::fromValue(created(index.row()));
+    case Qt::UserRole + 1:
+      return QVariant::fromValue(deviceKey(index.row()));
+    case Qt::UserRole + 2:
+      return cleanNullQVariant(QVariant::fromValue(endorsedBy(index.row())));
+    case Qt::UserRole + 3:
+      return QVariant::fromValue(isCurrent(index.row()));
+    }
+    break;
+  }
+  return QVariant();
+}
+int Devices::role(const char *name) const {
+  auto names = roleNames();
+  auto i = names.constBegin();
+  while (i != names.constEnd()) {
+    if (i.value() == name) {
+      return i.key();
+    }
+    ++i;
+  }
+  return -1;
+}
+QHash<int, QByteArray> Devices::roleNames() const {
+  QHash<int, QByteArray> names = QAbstractItemModel::roleNames();
+  names.insert(Qt::UserRole + 0, "created");
+  names.insert(Qt::UserRole + 1, "deviceKey");
+  names.insert(Qt::UserRole + 2, "endorsedBy");
+  names.insert(Qt::UserRole + 3, "isCurrent");
+  return names;
+}
+
+QVariant Devices::headerData(int section, Qt::Orientation orientation,
+                             int role) const {
+  if (orientation != Qt::Horizontal) {
+    return QVariant();
+  }
+  return m_headerData.value(
+      qMakePair(section, static_cast<Qt::ItemDataRole>(role)),
+      role == Qt::DisplayRole ? QString::number(section + 1) : QVariant());
+}
+
+bool Devices::setHeaderData(int section, Qt::Orientation orientation,
+                            const QVariant &value, int role) {
+  if (orientation != Qt::Horizontal) {
+    return false;
+  }
+  m_headerData.insert(qMakePair(section, static_cast<Qt::ItemDataRole>(role)),
+                      value);
+  return true;
+}
+
+extern "C" {
+Devices::Private *devices_new(DevicesPtrBundle *);
+void devices_free(Devices::Private *);
+void devices_load(Devices::Private *);
+void devices_refresh(Devices::Private *);
+bool devices_revoke(Devices::Private *, quint64);
+}
+extern "C" {
 void document_attachments_data_document_attachment_name(
     const DocumentAttachments::Private *, int, QString *, qstring_set);
 quint64 document_attachments_data_document_attachment_size(
@@ -890,17 +1046,23 @@ ConversationBuilder::Private *
 herald_conversation_builder_get(const Herald::Private *);
 Conversations::Private *herald_conversations_get(const Herald::Private *);
 Errors::Private *herald_errors_get(const Herald::Private *);
+bool herald_locked_get(const Herald::Private *);
 MessageSearch::Private *herald_message_search_get(const Herald::Private *);
 Notifications::Private *herald_notifications_get(const Herald::Private *);
 option_quint8 herald_registration_failure_code_get(const Herald::Private *);
 Users::Private *herald_users_get(const Herald::Private *);
 UsersSearch::Private *herald_users_search_get(const Herald::Private *);
 Utils::Private *herald_utils_get(const Herald::Private *);
+bool herald_change_passphrase(Herald::Private *, const ushort *, int,
+                              const ushort *, int);
 bool herald_login(Herald::Private *);
 void herald_poll_update(Herald::Private *);
 void herald_register_new_user(Herald::Private *, const ushort *, int,
-                              const ushort *, int, const ushort *, int);
+                              const ushort *, int, const ushort *, int,
+                              const ushort *, int);
 void herald_set_app_local_data_dir(Herald::Private *, const ushort *, int);
+bool herald_set_passphrase(Herald::Private *, const ushort *, int);
+bool herald_unlock(Herald::Private *, const ushort *, int);
 }
 extern "C" {
 void media_attachments_data_media_attachment_path(
@@ -1959,6 +2121,8 @@ bool messages_clear_conversation_history(Messages::Private *);
 void messages_clear_search(Messages::Private *);
 bool messages_delete_message(Messages::Private *, quint64);
 bool messages_delete_message_by_id(Messages::Private *, const char *, int);
+void messages_deliveries(const Messages::Private *, quint64, QString *,
+                         qstring_set);
 qint64 messages_index_by_id(const Messages::Private *, const char *, int);
 void messages_mark_read_by_id(Messages::Private *, const char *, int);
 qint64 messages_next_search_match(Messages::Private *);
@@ -2118,11 +2282,14 @@ void user_name_get(const User::Private *, QString *, qstring_set);
 void user_pairwise_conversation_id_get(const User::Private *, QByteArray *,
                                        qbytearray_set);
 void user_profile_picture_get(const User::Private *, QString *, qstring_set);
+void user_safety_number_get(const User::Private *, QString *, qstring_set);
 quint32 user_user_color_get(const User::Private *);
 void user_user_color_set(User::Private *, quint32);
 void user_user_id_get(const User::Private *, QString *, qstring_set);
 void user_user_id_set(User::Private *, const ushort *str, int len);
 void user_user_id_set_none(User::Private *);
+bool user_verified_get(const User::Private *);
+void user_verified_set(User::Private *, bool);
 }
 extern "C" {
 bool users_data_matched(const Users::Private *, int);
@@ -2971,6 +3138,69 @@ bool Conversations::toggleFilterRegex() {
   return conversations_toggle_filter_regex(m_d);
 }
 
+Devices::Devices(bool /*owned*/, QObject *parent)
+    : QAbstractItemModel(parent), m_d(nullptr), m_ownsPrivate(false) {
+  initHeaderData();
+}
+
+Devices::Devices(QObject *parent)
+    : QAbstractItemModel(parent),
+      m_d(devices_new(new DevicesPtrBundle{
+          this,
+          [](const Devices *o) { Q_EMIT o->newDataReady(QModelIndex()); },
+          [](Devices *o) { Q_EMIT o->layoutAboutToBeChanged(); },
+          [](Devices *o) {
+            o->updatePersistentIndexes();
+            Q_EMIT o->layoutChanged();
+          },
+          [](Devices *o, quintptr first, quintptr last) {
+            o->dataChanged(o->createIndex(first, 0, first),
+                           o->createIndex(last, 0, last));
+          },
+          [](Devices *o) { o->beginResetModel(); },
+          [](Devices *o) { o->endResetModel(); },
+          [](Devices *o, int first, int last) {
+            o->beginInsertRows(QModelIndex(), first, last);
+          },
+          [](Devices *o) { o->endInsertRows(); },
+          [](Devices *o, int first, int last, int destination) {
+            o->beginMoveRows(QModelIndex(), first, last, QModelIndex(),
+                             destination);
+          },
+          [](Devices *o) { o->endMoveRows(); },
+          [](Devices *o, int first, int last) {
+            o->beginRemoveRows(QModelIndex(), first, last);
+          },
+          [](Devices *o) { o->endRemoveRows(); }
+
+          ,
+          [](const Devices *o) { Q_EMIT o->tryLoad(); }})),
+      m_ownsPrivate(true) {
+
+  connect(
+      this, &Devices::tryLoad, this, [this]() { this->load(); },
+      Qt::QueuedConnection);
+
+  connect(
+      this, &Devices::newDataReady, this,
+      [this](const QModelIndex &i) { this->fetchMore(i); },
+      Qt::QueuedConnection);
+  initHeaderData();
+}
+
+Devices::~Devices() {
+  if (m_ownsPrivate) {
+    devices_free(m_d);
+  }
+}
+void Devices::initHeaderData() {}
+
+void Devices::load() { return devices_load(m_d); }
+void Devices::refresh() { return devices_refresh(m_d); }
+bool Devices::revoke(quint64 row_index) {
+  return devices_revoke(m_d, row_index);
+}
+
 DocumentAttachments::DocumentAttachments(bool /*owned*/, QObject *parent)
     : QAbstractItemModel(parent), m_d(nullptr), m_ownsPrivate(false) {
   initHeaderData();
@@ -3239,6 +3469,7 @@ Herald::Herald(QObject *parent)
           ,
           m_errors,
           [](const Errors *o) { Q_EMIT o->newError(); },
+          heraldLockedChanged,
           m_messageSearch,
           messageSearchRegexSearchChanged,
           messageSearchSearchPatternChanged,
@@ -3405,6 +3636,8 @@ Conversations *Herald::conversations() { return m_conversations; }
 const Errors *Herald::errors() const { return m_errors; }
 Errors *Herald::errors() { return m_errors; }
 
+bool Herald::locked() const { return herald_locked_get(m_d); }
+
 const MessageSearch *Herald::messageSearch() const { return m_messageSearch; }
 MessageSearch *Herald::messageSearch() { return m_messageSearch; }
 
@@ -3428,17 +3661,29 @@ UsersSearch *Herald::usersSearch() { return m_usersSearch; }
 
 const Utils *Herald::utils() const { return m_utils; }
 Utils *Herald::utils() { return m_utils; }
+bool Herald::changePassphrase(const QString &old_passphrase,
+                              const QString &new_passphrase) {
+  return herald_change_passphrase(m_d, old_passphrase.utf16(),
+                                  old_passphrase.size(), new_passphrase.utf16(),
+                                  new_passphrase.size());
+}
 bool Herald::login() { return herald_login(m_d); }
 void Herald::pollUpdate() { return herald_poll_update(m_d); }
 void Herald::registerNewUser(const QString &user_id, const QString &addr,
-                             const QString &port) {
+                             const QString &port, const QString &pin) {
   return herald_register_new_user(m_d, user_id.utf16(), user_id.size(),
                                   addr.utf16(), addr.size(), port.utf16(),
-                                  port.size());
+                                  port.size(), pin.utf16(), pin.size());
 }
 void Herald::setAppLocalDataDir(const QString &path) {
   return herald_set_app_local_data_dir(m_d, path.utf16(), path.size());
 }
+bool Herald::setPassphrase(const QString &passphrase) {
+  return herald_set_passphrase(m_d, passphrase.utf16(), passphrase.size());
+}
+bool Herald::unlock(const QString &passphrase) {
+  return herald_unlock(m_d, passphrase.utf16(), passphrase.size());
+}
 
 MediaAttachments::MediaAttachments(bool /*owned*/, QObject *parent)
     : QAbstractItemModel(parent), m_d(nullptr), m_ownsPrivate(false) {
@@ -4149,6 +4394,11 @@ bool Messages::deleteMessage(quint64 row_index) {
 bool Messages::deleteMessageById(const QByteArray &id) {
   return messages_delete_message_by_id(m_d, id.data(), id.size());
 }
+QString Messages::deliveries(quint64 index) const {
+  QString s;
+  messages_deliveries(m_d, index, &s, set_qstring);
+  return s;
+}
 qint64 Messages::indexById(const QByteArray &msg_id) const {
   return messages_index_by_id(m_d, msg_id.data(), msg_id.size());
 }
@@ -4280,7 +4530,8 @@ User::User(QObject *parent)
     : QObject(parent),
       m_d(user_new(new UserPtrBundle{
           this, userNameChanged, userPairwiseConversationIdChanged,
-          userProfilePictureChanged, userUserColorChanged, userUserIdChanged})),
+          userProfilePictureChanged, userSafetyNumberChanged,
+          userUserColorChanged, userUserIdChanged, userVerifiedChanged})),
       m_ownsPrivate(true) {}
 
 User::~User() {
@@ -4307,6 +4558,12 @@ QString User::profilePicture() const {
   return v;
 }
 
+QString User::safetyNumber() const {
+  QString v;
+  user_safety_number_get(m_d, &v, set_qstring);
+  return v;
+}
+
 quint32 User::userColor() const { return user_user_color_get(m_d); }
 void User::setUserColor(quint32 v) { user_user_color_set(m_d, v); }
 
@@ -4323,6 +4580,9 @@ void User::setUserId(const QString &v) {
   }
 }
 
+bool User::verified() const { return user_verified_get(m_d); }
+void User::setVerified(bool v) { user_verified_set(m_d, v); }
+
 Users::Users(bool /*owned*/, QObject *parent)
     : QAbstractItemModel(parent), m_d(nullptr), m_ownsPrivate(false) {
   initHeaderData();
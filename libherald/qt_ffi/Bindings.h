@@ -10,6 +10,7 @@ class Config;
 class ConversationBuilder;
 class ConversationContent;
 class Conversations;
+class Devices;
 class DocumentAttachments;
 class EmojiPicker;
 class Errors;
@@ -30,6 +31,7 @@ using ConfigPtrBundle = struct ConfigPtrBundle;
 using ConversationBuilderPtrBundle = struct ConversationBuilderPtrBundle;
 using ConversationContentPtrBundle = struct ConversationContentPtrBundle;
 using ConversationsPtrBundle = struct ConversationsPtrBundle;
+using DevicesPtrBundle = struct DevicesPtrBundle;
 using DocumentAttachmentsPtrBundle = struct DocumentAttachmentsPtrBundle;
 using EmojiPickerPtrBundle = struct EmojiPickerPtrBundle;
 using ErrorsPtrBundle = struct ErrorsPtrBundle;
@@ -203,6 +205,22 @@ struct ConversationsPtrBundle {
   void (*conversations_begin_remove_rows)(Conversations *, int, int);
   void (*conversations_end_remove_rows)(Conversations *);
 };
+struct DevicesPtrBundle {
+  Devices *devices;
+  void (*devices_new_data_ready)(const Devices *);
+  void (*devices_layout_about_to_be_changed)(Devices *);
+  void (*devices_layout_changed)(Devices *);
+  void (*devices_data_changed)(Devices *, quintptr, quintptr);
+  void (*devices_begin_reset_model)(Devices *);
+  void (*devices_end_reset_model)(Devices *);
+  void (*devices_begin_insert_rows)(Devices *, int, int);
+  void (*devices_end_insert_rows)(Devices *);
+  void (*devices_begin_move_rows)(Devices *, int, int, int);
+  void (*devices_end_move_rows)(Devices *);
+  void (*devices_begin_remove_rows)(Devices *, int, int);
+  void (*devices_end_remove_rows)(Devices *);
+  void (*devices_tryLoad)(const Devices *);
+};
 struct DocumentAttachmentsPtrBundle {
   DocumentAttachments *document_attachments;
 
@@ -301,6 +319,7 @@ struct HeraldPtrBundle {
   void (*conversations_end_remove_rows)(Conversations *);
   Errors *errors;
   void (*errors_newError)(const Errors *);
+  void (*herald_locked_changed)(Herald *);
   MessageSearch *message_search;
   void (*message_search_regex_search_changed)(MessageSearch *);
   void (*message_search_search_pattern_changed)(MessageSearch *);
@@ -586,8 +605,10 @@ struct UserPtrBundle {
   void (*user_name_changed)(User *);
   void (*user_pairwise_conversation_id_changed)(User *);
   void (*user_profile_picture_changed)(User *);
+  void (*user_safety_number_changed)(User *);
   void (*user_user_color_changed)(User *);
   void (*user_user_id_changed)(User *);
+  void (*user_verified_changed)(User *);
 };
 struct UsersPtrBundle {
   Users *users;
@@ -633,6 +654,7 @@ class Config : public QObject {
   friend class ConversationBuilder;
   friend class ConversationContent;
   friend class Conversations;
+  friend class Devices;
   friend class DocumentAttachments;
   friend class EmojiPicker;
   friend class Errors;
@@ -681,6 +703,7 @@ class ConversationBuilder : public QAbstractItemModel {
   friend class Config;
   friend class ConversationContent;
   friend class Conversations;
+  friend class Devices;
   friend class DocumentAttachments;
   friend class EmojiPicker;
   friend class Errors;
@@ -761,6 +784,7 @@ class ConversationContent : public QObject {
   friend class Config;
   friend class ConversationBuilder;
   friend class Conversations;
+  friend class Devices;
   friend class DocumentAttachments;
   friend class EmojiPicker;
   friend class Errors;
@@ -841,6 +865,7 @@ class Conversations : public QAbstractItemModel {
   friend class Config;
   friend class ConversationBuilder;
   friend class ConversationContent;
+  friend class Devices;
   friend class DocumentAttachments;
   friend class EmojiPicker;
   friend class Errors;
@@ -924,12 +949,90 @@ Q_SIGNALS:
   void filterChanged();
   void filterRegexChanged();
 };
+class Devices : public QAbstractItemModel {
+  Q_OBJECT
+  friend class Config;
+  friend class ConversationBuilder;
+  friend class ConversationContent;
+  friend class Conversations;
+  friend class DocumentAttachments;
+  friend class EmojiPicker;
+  friend class Errors;
+  friend class Herald;
+  friend class MediaAttachments;
+  friend class Members;
+  friend class MessageBuilder;
+  friend class MessageSearch;
+  friend class Messages;
+  friend class Notifications;
+  friend class SharedConversations;
+  friend class User;
+  friend class Users;
+  friend class UsersSearch;
+  friend class Utils;
+
+public:
+  class Private;
+
+private:
+  Private *m_d;
+  bool m_ownsPrivate;
+  explicit Devices(bool owned, QObject *parent);
+
+public:
+  explicit Devices(QObject *parent = nullptr);
+  ~Devices() override;
+  Q_INVOKABLE void load();
+  Q_INVOKABLE void refresh();
+  Q_INVOKABLE bool revoke(quint64 row_index);
+  int columnCount(const QModelIndex &parent = QModelIndex()) const override;
+  QVariant data(const QModelIndex &index,
+                int role = Qt::DisplayRole) const override;
+  QModelIndex index(int row, int column,
+                    const QModelIndex &parent = QModelIndex()) const override;
+  QModelIndex parent(const QModelIndex &index) const override;
+  bool hasChildren(const QModelIndex &parent = QModelIndex()) const override;
+  int rowCount(const QModelIndex &parent = QModelIndex()) const override;
+  bool canFetchMore(const QModelIndex &parent) const override;
+  void fetchMore(const QModelIndex &parent) override;
+  Qt::ItemFlags flags(const QModelIndex &index) const override;
+  void sort(int column, Qt::SortOrder order = Qt::AscendingOrder) override;
+  int role(const char *name) const;
+  QHash<int, QByteArray> roleNames() const override;
+  QVariant headerData(int section, Qt::Orientation orientation,
+                      int role = Qt::DisplayRole) const override;
+  bool setHeaderData(int section, Qt::Orientation orientation,
+                     const QVariant &value, int role = Qt::EditRole) override;
+  Q_INVOKABLE bool
+  insertRows(int row, int count,
+             const QModelIndex &parent = QModelIndex()) override;
+  Q_INVOKABLE bool
+  removeRows(int row, int count,
+             const QModelIndex &parent = QModelIndex()) override;
+
+  Q_INVOKABLE qint64 created(int row) const;
+  Q_INVOKABLE QByteArray deviceKey(int row) const;
+  Q_INVOKABLE QByteArray endorsedBy(int row) const;
+  Q_INVOKABLE bool isCurrent(int row) const;
+
+Q_SIGNALS:
+  // new data is ready to be made available to the model with fetchMore()
+  void newDataReady(const QModelIndex &parent) const;
+
+private:
+  QHash<QPair<int, Qt::ItemDataRole>, QVariant> m_headerData;
+  void initHeaderData();
+  void updatePersistentIndexes();
+Q_SIGNALS:
+  void tryLoad() const;
+};
 class DocumentAttachments : public QAbstractItemModel {
   Q_OBJECT
   friend class Config;
   friend class ConversationBuilder;
   friend class ConversationContent;
   friend class Conversations;
+  friend class Devices;
   friend class EmojiPicker;
   friend class Errors;
   friend class Herald;
@@ -1000,6 +1103,7 @@ class EmojiPicker : public QAbstractItemModel {
   friend class ConversationBuilder;
   friend class ConversationContent;
   friend class Conversations;
+  friend class Devices;
   friend class DocumentAttachments;
   friend class Errors;
   friend class Herald;
@@ -1106,6 +1210,7 @@ class Errors : public QObject {
   friend class ConversationBuilder;
   friend class ConversationContent;
   friend class Conversations;
+  friend class Devices;
   friend class DocumentAttachments;
   friend class EmojiPicker;
   friend class Herald;
@@ -1142,6 +1247,7 @@ class Herald : public QObject {
   friend class ConversationBuilder;
   friend class ConversationContent;
   friend class Conversations;
+  friend class Devices;
   friend class DocumentAttachments;
   friend class EmojiPicker;
   friend class Errors;
@@ -1183,6 +1289,7 @@ private:
   Q_PROPERTY(Conversations *conversations READ conversations NOTIFY
                  conversationsChanged FINAL)
   Q_PROPERTY(Errors *errors READ errors NOTIFY errorsChanged FINAL)
+  Q_PROPERTY(bool locked READ locked NOTIFY lockedChanged FINAL)
   Q_PROPERTY(MessageSearch *messageSearch READ messageSearch NOTIFY
                  messageSearchChanged FINAL)
   Q_PROPERTY(Notifications *notifications READ notifications NOTIFY
@@ -1209,6 +1316,7 @@ public:
   Conversations *conversations();
   const Errors *errors() const;
   Errors *errors();
+  bool locked() const;
   const MessageSearch *messageSearch() const;
   MessageSearch *messageSearch();
   const Notifications *notifications() const;
@@ -1220,11 +1328,15 @@ public:
   UsersSearch *usersSearch();
   const Utils *utils() const;
   Utils *utils();
+  Q_INVOKABLE bool changePassphrase(const QString &old_passphrase,
+                                    const QString &new_passphrase);
   Q_INVOKABLE bool login();
   Q_INVOKABLE void pollUpdate();
   Q_INVOKABLE void registerNewUser(const QString &user_id, const QString &addr,
-                                   const QString &port);
+                                   const QString &port, const QString &pin);
   Q_INVOKABLE void setAppLocalDataDir(const QString &path);
+  Q_INVOKABLE bool setPassphrase(const QString &passphrase);
+  Q_INVOKABLE bool unlock(const QString &passphrase);
 Q_SIGNALS:
   void configChanged();
   void configInitChanged();
@@ -1233,6 +1345,7 @@ Q_SIGNALS:
   void conversationBuilderChanged();
   void conversationsChanged();
   void errorsChanged();
+  void lockedChanged();
   void messageSearchChanged();
   void notificationsChanged();
   void registrationFailureCodeChanged();
@@ -1247,6 +1360,7 @@ class MediaAttachments : public QAbstractItemModel {
   friend class ConversationBuilder;
   friend class ConversationContent;
   friend class Conversations;
+  friend class Devices;
   friend class DocumentAttachments;
   friend class EmojiPicker;
   friend class Errors;
@@ -1316,6 +1430,7 @@ class Members : public QAbstractItemModel {
   friend class ConversationBuilder;
   friend class ConversationContent;
   friend class Conversations;
+  friend class Devices;
   friend class DocumentAttachments;
   friend class EmojiPicker;
   friend class Errors;
@@ -1402,6 +1517,7 @@ class MessageBuilder : public QAbstractItemModel {
   friend class ConversationBuilder;
   friend class ConversationContent;
   friend class Conversations;
+  friend class Devices;
   friend class DocumentAttachments;
   friend class EmojiPicker;
   friend class Errors;
@@ -1535,6 +1651,7 @@ class MessageSearch : public QAbstractItemModel {
   friend class ConversationBuilder;
   friend class ConversationContent;
   friend class Conversations;
+  friend class Devices;
   friend class DocumentAttachments;
   friend class EmojiPicker;
   friend class Errors;
@@ -1625,6 +1742,7 @@ class Messages : public QAbstractItemModel {
   friend class ConversationBuilder;
   friend class ConversationContent;
   friend class Conversations;
+  friend class Devices;
   friend class DocumentAttachments;
   friend class EmojiPicker;
   friend class Errors;
@@ -1681,6 +1799,7 @@ public:
   Q_INVOKABLE void clearSearch();
   Q_INVOKABLE bool deleteMessage(quint64 row_index);
   Q_INVOKABLE bool deleteMessageById(const QByteArray &id);
+  Q_INVOKABLE QString deliveries(quint64 index) const;
   Q_INVOKABLE qint64 indexById(const QByteArray &msg_id) const;
   Q_INVOKABLE void markReadById(const QByteArray &id);
   Q_INVOKABLE qint64 nextSearchMatch();
@@ -1768,6 +1887,7 @@ class Notifications : public QObject {
   friend class ConversationBuilder;
   friend class ConversationContent;
   friend class Conversations;
+  friend class Devices;
   friend class DocumentAttachments;
   friend class EmojiPicker;
   friend class Errors;
@@ -1804,6 +1924,7 @@ class SharedConversations : public QAbstractItemModel {
   friend class ConversationBuilder;
   friend class ConversationContent;
   friend class Conversations;
+  friend class Devices;
   friend class DocumentAttachments;
   friend class EmojiPicker;
   friend class Errors;
@@ -1880,6 +2001,7 @@ class User : public QObject {
   friend class ConversationBuilder;
   friend class ConversationContent;
   friend class Conversations;
+  friend class Devices;
   friend class DocumentAttachments;
   friend class EmojiPicker;
   friend class Errors;
@@ -1906,10 +2028,14 @@ private:
                  NOTIFY pairwiseConversationIdChanged FINAL)
   Q_PROPERTY(QString profilePicture READ profilePicture NOTIFY
                  profilePictureChanged FINAL)
+  Q_PROPERTY(QString safetyNumber READ safetyNumber NOTIFY safetyNumberChanged
+                 FINAL)
   Q_PROPERTY(quint32 userColor READ userColor WRITE setUserColor NOTIFY
                  userColorChanged FINAL)
   Q_PROPERTY(
       QString userId READ userId WRITE setUserId NOTIFY userIdChanged FINAL)
+  Q_PROPERTY(
+      bool verified READ verified WRITE setVerified NOTIFY verifiedChanged FINAL)
   explicit User(bool owned, QObject *parent);
 
 public:
@@ -1918,16 +2044,21 @@ public:
   QString name() const;
   QByteArray pairwiseConversationId() const;
   QString profilePicture() const;
+  QString safetyNumber() const;
   quint32 userColor() const;
   void setUserColor(quint32 v);
   QString userId() const;
   void setUserId(const QString &v);
+  bool verified() const;
+  void setVerified(bool v);
 Q_SIGNALS:
   void nameChanged();
   void pairwiseConversationIdChanged();
   void profilePictureChanged();
+  void safetyNumberChanged();
   void userColorChanged();
   void userIdChanged();
+  void verifiedChanged();
 };
 class Users : public QAbstractItemModel {
   Q_OBJECT
@@ -1935,6 +2066,7 @@ class Users : public QAbstractItemModel {
   friend class ConversationBuilder;
   friend class ConversationContent;
   friend class Conversations;
+  friend class Devices;
   friend class DocumentAttachments;
   friend class EmojiPicker;
   friend class Errors;
@@ -2019,6 +2151,7 @@ class UsersSearch : public QAbstractItemModel {
   friend class ConversationBuilder;
   friend class ConversationContent;
   friend class Conversations;
+  friend class Devices;
   friend class DocumentAttachments;
   friend class EmojiPicker;
   friend class Errors;
@@ -2100,6 +2233,7 @@ class Utils : public QObject {
   friend class ConversationBuilder;
   friend class ConversationContent;
   friend class Conversations;
+  friend class Devices;
   friend class DocumentAttachments;
   friend class EmojiPicker;
   friend class Errors;
@@ -0,0 +1,141 @@
+use crate::{
+    err,
+    interface::{DevicesEmitter as Emit, DevicesList as List, DevicesTrait as Interface},
+    none, push, spawn, Update,
+};
+use crossbeam_channel::{bounded, Receiver, Sender};
+use herald_common::{sig, PKIResponse};
+use heraldcore::network::{self, Device};
+
+type Loaded = (Vec<Device>, sig::PublicKey);
+
+/// The account's active devices
+pub struct Devices {
+    current: Option<sig::PublicKey>,
+    inner: Vec<Device>,
+    model: List,
+    emit: Emit,
+    rx: Option<Receiver<Loaded>>,
+}
+
+impl Interface for Devices {
+    fn new(
+        emit: Emit,
+        model: List,
+    ) -> Self {
+        Self {
+            current: None,
+            inner: vec![],
+            model,
+            emit,
+            rx: None,
+        }
+    }
+
+    fn emit(&mut self) -> &mut Emit {
+        &mut self.emit
+    }
+
+    fn refresh(&mut self) {
+        let tx = self.loader();
+        let mut emit = self.emit.clone();
+
+        spawn!(fetch(tx, &mut emit));
+    }
+
+    fn load(&mut self) {
+        if let Some(rx) = self.rx.as_ref() {
+            if let Ok((devices, current)) = rx.recv() {
+                self.model.begin_reset_model();
+                self.inner = devices;
+                self.current = Some(current);
+                self.model.end_reset_model();
+            }
+        }
+    }
+
+    fn revoke(
+        &mut self,
+        row_index: u64,
+    ) -> bool {
+        let key = none!(self.inner.get(row_index as usize), false).key;
+
+        if Some(key) == self.current {
+            return false;
+        }
+
+        let tx = self.loader();
+        let mut emit = self.emit.clone();
+
+        spawn!(
+            {
+                let res = err!(network::revoke_device(key));
+
+                if res != PKIResponse::Success {
+                    push(Update::Error(format!("failed to revoke device: {:?}", res)));
+                }
+
+                fetch(tx, &mut emit);
+            },
+            false
+        );
+
+        true
+    }
+
+    fn row_count(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn device_key(
+        &self,
+        index: usize,
+    ) -> &[u8] {
+        self.inner.get(index).map(|d| d.key.as_ref()).unwrap_or(&[])
+    }
+
+    fn created(
+        &self,
+        index: usize,
+    ) -> i64 {
+        self.inner
+            .get(index)
+            .map(|d| *d.created.as_i64())
+            .unwrap_or(0)
+    }
+
+    fn endorsed_by(
+        &self,
+        index: usize,
+    ) -> Option<&[u8]> {
+        Some(self.inner.get(index)?.endorsed_by.as_ref()?.as_ref())
+    }
+
+    fn is_current(
+        &self,
+        index: usize,
+    ) -> bool {
+        self.current.is_some() && self.inner.get(index).map(|d| d.key) == self.current
+    }
+}
+
+impl Devices {
+    fn loader(&mut self) -> Sender<Loaded> {
+        let (tx, rx) = bounded(1);
+        self.rx.replace(rx);
+        tx
+    }
+}
+
+fn fetch(
+    tx: Sender<Loaded>,
+    emit: &mut Emit,
+) {
+    let devices = err!(network::devices());
+    let current = *err!(heraldcore::config::keypair()).public();
+
+    let active = devices.into_iter().filter(Device::is_active).collect();
+
+    drop(tx.send((active, current)));
+    emit.try_load();
+}
@@ -33,4 +33,5 @@ pub struct Herald {
     utils: Utils,
     load_props: imp::LoadProps,
     registration_failure_code: Option<shared::RegistrationFailureCode>,
+    connection_state: net::ConnectionState,
 }
@@ -38,6 +38,9 @@ impl NotifHandler {
                     }
                 ));
             }
+            MsgDelivered { cid, msg_id, .. } => {
+                err!(content_push(cid, MsgUpdate::Delivered(msg_id)));
+            }
             Reaction {
                 msg_id,
                 reactionary,
@@ -116,6 +119,18 @@ impl NotifHandler {
             UserChanged(uid, update) => {
                 push(UserUpdate::UserChanged(uid, update));
             }
+            SafetyNumberChanged(uid) => {
+                push(UserUpdate::KeysChanged(uid));
+            }
+            SigchainFork(uid) => {
+                push(Update::Error(format!(
+                    "The server is showing conflicting keys for {}",
+                    uid
+                )));
+            }
+            Connection(state) => {
+                push(Update::Connection(state));
+            }
         }
     }
 
@@ -28,6 +28,7 @@ pub enum Update {
     Conv(crate::conversations::shared::ConvUpdate),
     User(crate::users::shared::UserUpdate),
     Conf(crate::config::ConfUpdate),
+    Connection(heraldcore::network::ConnectionState),
     Error(String),
     // This is here because rust doesn't have specialization
     Nil,
@@ -117,6 +118,11 @@ impl super::Herald {
                 Conf(update) => {
                     self.load_props.config.handle_update(update);
                 }
+                Connection(state) => {
+                    self.connection_state = state;
+                    self.emit.connection_up_changed();
+                    self.emit.connection_pending_changed();
+                }
                 Nil => {}
             }
         }
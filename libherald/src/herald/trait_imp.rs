@@ -67,8 +67,9 @@ impl HeraldTrait for Herald {
         user_id: ffi::UserId,
         server_addr: String,
         server_port: String,
+        server_pin: String,
     ) {
-        self.register_new_user_(user_id, server_addr, server_port)
+        self.register_new_user_(user_id, server_addr, server_port, server_pin)
     }
 
     fn registration_failure_code(&self) -> Option<u8> {
@@ -91,6 +92,32 @@ impl HeraldTrait for Herald {
         self.connection_pending_()
     }
 
+    fn locked(&self) -> bool {
+        self.locked_()
+    }
+
+    fn unlock(
+        &mut self,
+        passphrase: String,
+    ) -> bool {
+        self.unlock_(passphrase)
+    }
+
+    fn set_passphrase(
+        &mut self,
+        passphrase: String,
+    ) -> bool {
+        self.set_passphrase_(passphrase)
+    }
+
+    fn change_passphrase(
+        &mut self,
+        old_passphrase: String,
+        new_passphrase: String,
+    ) -> bool {
+        self.change_passphrase_(old_passphrase, new_passphrase)
+    }
+
     fn emit(&mut self) -> &mut HeraldEmitter {
         self.emit_()
     }
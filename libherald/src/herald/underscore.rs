@@ -45,6 +45,7 @@ impl Herald {
             users_search,
             utils,
             registration_failure_code: None,
+            connection_state: net::connection_state(),
         }
     }
 
@@ -57,6 +58,7 @@ impl Herald {
         user_id: ffi::UserId,
         server_addr: String,
         server_port: String,
+        server_pin: String,
     ) {
         use protocol::auth::*;
 
@@ -66,10 +68,16 @@ impl Herald {
             None
         };
 
+        let pin = if !server_pin.is_empty() {
+            Some(err!(server_pin.parse()))
+        } else {
+            None
+        };
+
         let uid = err!(UserId::try_from(user_id.as_str()));
 
         spawn!(
-            match push_err!(net::register(uid, addr), "Registration failed") {
+            match push_err!(net::register(uid, addr, pin), "Registration failed") {
                 Some(RegisterResponse::Taken) => {
                     push(shared::RegistrationFailureCode::UserIdTaken);
                 }
@@ -86,13 +94,15 @@ impl Herald {
         );
     }
 
-    // TODO these need to come back
     pub(crate) fn connection_up_(&self) -> bool {
-        false
+        self.connection_state == net::ConnectionState::Up
     }
 
     pub(crate) fn connection_pending_(&self) -> bool {
-        false
+        match self.connection_state {
+            net::ConnectionState::Connecting | net::ConnectionState::Waiting => true,
+            _ => false,
+        }
     }
 
     pub(crate) fn login_(&mut self) -> bool {
@@ -111,14 +121,59 @@ impl Herald {
     ) {
         none!(heraldcore::set_data_dir(std::path::PathBuf::from(path)));
 
-        if db::is_init().unwrap_or(false) {
+        // encrypted storage is loaded once it's unlocked
+        if db::is_locked() {
+            self.emit.locked_changed();
+            return;
+        }
+
+        // storage that doesn't exist yet is created on registration, so that it
+        // can be encrypted first
+        self.load();
+    }
+
+    pub(crate) fn locked_(&self) -> bool {
+        db::is_locked()
+    }
+
+    pub(crate) fn unlock_(
+        &mut self,
+        passphrase: String,
+    ) -> bool {
+        err!(db::unlock(&passphrase), false);
+
+        self.emit.locked_changed();
+        self.load();
+
+        true
+    }
+
+    pub(crate) fn set_passphrase_(
+        &mut self,
+        passphrase: String,
+    ) -> bool {
+        err!(db::set_passphrase(&passphrase), false);
+
+        true
+    }
+
+    pub(crate) fn change_passphrase_(
+        &mut self,
+        old_passphrase: String,
+        new_passphrase: String,
+    ) -> bool {
+        err!(
+            db::change_passphrase(&old_passphrase, &new_passphrase),
+            false
+        );
+
+        true
+    }
+
+    fn load(&mut self) {
+        if push_err!(db::is_init(), "Couldn't open storage").unwrap_or(false) {
             self.load_props.setup();
             self.emit.config_init_changed();
-        } else {
-            // If this fails, the file system is in a very bad place.
-            // This probably cannot be recovered from, and there's not meaningful
-            // sense in which the application can work. But crashing is still a bad look.
-            push_err!(db::init(), "Couldn't initialize storage");
-        };
+        }
     }
 }
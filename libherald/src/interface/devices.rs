@@ -0,0 +1,403 @@
+use super::*;
+
+pub struct DevicesQObject;
+
+pub struct DevicesEmitter {
+    pub(super) qobject: Arc<AtomicPtr<DevicesQObject>>,
+    pub(super) new_data_ready: fn(*mut DevicesQObject),
+    pub(super) try_load: fn(*mut DevicesQObject),
+}
+
+impl DevicesEmitter {
+    /// Clone the emitter
+    ///
+    /// The emitter can only be cloned when it is mutable. The emitter calls
+    /// into C++ code which may call into Rust again. If emmitting is possible
+    /// from immutable structures, that might lead to access to a mutable
+    /// reference. That is undefined behaviour and forbidden.
+    pub fn clone(&mut self) -> DevicesEmitter {
+        DevicesEmitter {
+            qobject: self.qobject.clone(),
+            try_load: self.try_load,
+            new_data_ready: self.new_data_ready,
+        }
+    }
+
+    pub fn clear(&self) {
+        let n: *const DevicesQObject = null();
+        self.qobject
+            .store(n as *mut DevicesQObject, Ordering::SeqCst);
+    }
+
+    pub fn try_load(&mut self) {
+        let ptr = self.qobject.load(Ordering::SeqCst);
+
+        if !ptr.is_null() {
+            (self.try_load)(ptr);
+        }
+    }
+
+    pub fn new_data_ready(&mut self) {
+        let ptr = self.qobject.load(Ordering::SeqCst);
+        if !ptr.is_null() {
+            (self.new_data_ready)(ptr);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct DevicesList {
+    pub(super) qobject: *mut DevicesQObject,
+    pub(super) layout_about_to_be_changed: fn(*mut DevicesQObject),
+    pub(super) layout_changed: fn(*mut DevicesQObject),
+    pub(super) begin_reset_model: fn(*mut DevicesQObject),
+    pub(super) end_reset_model: fn(*mut DevicesQObject),
+    pub(super) end_insert_rows: fn(*mut DevicesQObject),
+    pub(super) end_move_rows: fn(*mut DevicesQObject),
+    pub(super) end_remove_rows: fn(*mut DevicesQObject),
+    pub(super) begin_insert_rows: fn(*mut DevicesQObject, usize, usize),
+    pub(super) begin_remove_rows: fn(*mut DevicesQObject, usize, usize),
+    pub(super) data_changed: fn(*mut DevicesQObject, usize, usize),
+    pub(super) begin_move_rows: fn(*mut DevicesQObject, usize, usize, usize),
+}
+
+impl DevicesList {
+    pub fn layout_about_to_be_changed(&mut self) {
+        if !self.qobject.is_null() {
+            (self.layout_about_to_be_changed)(self.qobject);
+        }
+    }
+
+    pub fn layout_changed(&mut self) {
+        if !self.qobject.is_null() {
+            (self.layout_changed)(self.qobject)
+        }
+    }
+
+    pub fn begin_reset_model(&mut self) {
+        if !self.qobject.is_null() {
+            (self.begin_reset_model)(self.qobject);
+        }
+    }
+
+    pub fn end_reset_model(&mut self) {
+        if !self.qobject.is_null() {
+            (self.end_reset_model)(self.qobject);
+        }
+    }
+
+    pub fn end_insert_rows(&mut self) {
+        if !self.qobject.is_null() {
+            (self.end_insert_rows)(self.qobject);
+        }
+    }
+
+    pub fn end_move_rows(&mut self) {
+        if !self.qobject.is_null() {
+            (self.end_move_rows)(self.qobject);
+        }
+    }
+
+    pub fn end_remove_rows(&mut self) {
+        if !self.qobject.is_null() {
+            (self.end_remove_rows)(self.qobject);
+        }
+    }
+
+    pub fn begin_insert_rows(
+        &mut self,
+        first: usize,
+        last: usize,
+    ) {
+        if !self.qobject.is_null() {
+            (self.begin_insert_rows)(self.qobject, first, last);
+        }
+    }
+
+    pub fn begin_remove_rows(
+        &mut self,
+        first: usize,
+        last: usize,
+    ) {
+        if !self.qobject.is_null() {
+            (self.begin_remove_rows)(self.qobject, first, last);
+        }
+    }
+
+    pub fn data_changed(
+        &mut self,
+        first: usize,
+        last: usize,
+    ) {
+        if !self.qobject.is_null() {
+            (self.data_changed)(self.qobject, first, last);
+        }
+    }
+
+    pub fn begin_move_rows(
+        &mut self,
+        first: usize,
+        last: usize,
+        destination: usize,
+    ) {
+        if !self.qobject.is_null() {
+            (self.begin_move_rows)(self.qobject, first, last, destination);
+        }
+    }
+}
+
+pub trait DevicesTrait {
+    fn new(
+        emit: DevicesEmitter,
+        model: DevicesList,
+    ) -> Self;
+
+    fn emit(&mut self) -> &mut DevicesEmitter;
+
+    fn load(&mut self) -> ();
+
+    fn refresh(&mut self) -> ();
+
+    fn revoke(
+        &mut self,
+        row_index: u64,
+    ) -> bool;
+
+    fn row_count(&self) -> usize;
+
+    fn insert_rows(
+        &mut self,
+        _row: usize,
+        _count: usize,
+    ) -> bool {
+        false
+    }
+
+    fn remove_rows(
+        &mut self,
+        _row: usize,
+        _count: usize,
+    ) -> bool {
+        false
+    }
+
+    fn can_fetch_more(&self) -> bool {
+        false
+    }
+
+    fn fetch_more(&mut self) {}
+
+    fn sort(
+        &mut self,
+        _: u8,
+        _: SortOrder,
+    ) {
+    }
+
+    fn created(
+        &self,
+        index: usize,
+    ) -> i64;
+
+    fn device_key(
+        &self,
+        index: usize,
+    ) -> &[u8];
+
+    fn endorsed_by(
+        &self,
+        index: usize,
+    ) -> Option<&[u8]>;
+
+    fn is_current(
+        &self,
+        index: usize,
+    ) -> bool;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn devices_new(ptr_bundle: *mut DevicesPtrBundle) -> *mut Devices {
+    let d_devices = devices_new_inner(ptr_bundle);
+    Box::into_raw(Box::new(d_devices))
+}
+
+pub unsafe fn devices_new_inner(ptr_bundle: *mut DevicesPtrBundle) -> Devices {
+    let ptr_bundle = *ptr_bundle;
+
+    let DevicesPtrBundle {
+        devices,
+        devices_new_data_ready,
+        devices_layout_about_to_be_changed,
+        devices_layout_changed,
+        devices_data_changed,
+        devices_begin_reset_model,
+        devices_end_reset_model,
+        devices_begin_insert_rows,
+        devices_end_insert_rows,
+        devices_begin_move_rows,
+        devices_end_move_rows,
+        devices_begin_remove_rows,
+        devices_end_remove_rows,
+        devices_try_load,
+    } = ptr_bundle;
+    let devices_emit = DevicesEmitter {
+        qobject: Arc::new(AtomicPtr::new(devices)),
+        new_data_ready: devices_new_data_ready,
+        try_load: devices_try_load,
+    };
+    let model = DevicesList {
+        qobject: devices,
+        layout_about_to_be_changed: devices_layout_about_to_be_changed,
+        layout_changed: devices_layout_changed,
+        data_changed: devices_data_changed,
+        begin_reset_model: devices_begin_reset_model,
+        end_reset_model: devices_end_reset_model,
+        begin_insert_rows: devices_begin_insert_rows,
+        end_insert_rows: devices_end_insert_rows,
+        begin_move_rows: devices_begin_move_rows,
+        end_move_rows: devices_end_move_rows,
+        begin_remove_rows: devices_begin_remove_rows,
+        end_remove_rows: devices_end_remove_rows,
+    };
+    let d_devices = Devices::new(devices_emit, model);
+    d_devices
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn devices_free(ptr: *mut Devices) {
+    Box::from_raw(ptr).emit().clear();
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn devices_load(ptr: *mut Devices) {
+    let obj = &mut *ptr;
+    obj.load()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn devices_refresh(ptr: *mut Devices) {
+    let obj = &mut *ptr;
+    obj.refresh()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn devices_revoke(
+    ptr: *mut Devices,
+    row_index: u64,
+) -> bool {
+    let obj = &mut *ptr;
+    obj.revoke(row_index)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn devices_row_count(ptr: *const Devices) -> c_int {
+    to_c_int((&*ptr).row_count())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn devices_insert_rows(
+    ptr: *mut Devices,
+    row: c_int,
+    count: c_int,
+) -> bool {
+    match (to_usize(row), to_usize(count)) {
+        (Some(row), Some(count)) => (&mut *ptr).insert_rows(row, count),
+        _ => false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn devices_remove_rows(
+    ptr: *mut Devices,
+    row: c_int,
+    count: c_int,
+) -> bool {
+    match (to_usize(row), to_usize(count)) {
+        (Some(row), Some(count)) => (&mut *ptr).remove_rows(row, count),
+        _ => false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn devices_can_fetch_more(ptr: *const Devices) -> bool {
+    (&*ptr).can_fetch_more()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn devices_fetch_more(ptr: *mut Devices) {
+    (&mut *ptr).fetch_more()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn devices_sort(
+    ptr: *mut Devices,
+    column: u8,
+    order: SortOrder,
+) {
+    (&mut *ptr).sort(column, order)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn devices_data_created(
+    ptr: *const Devices,
+    row: c_int,
+) -> i64 {
+    let obj = &*ptr;
+    obj.created(to_usize(row).unwrap_or(0))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn devices_data_device_key(
+    ptr: *const Devices,
+    row: c_int,
+    d: *mut QByteArray,
+    set: fn(*mut QByteArray, *const c_char, len: c_int),
+) {
+    let obj = &*ptr;
+    let data = obj.device_key(to_usize(row).unwrap_or(0));
+    let str_: *const c_char = data.as_ptr() as *const c_char;
+    set(d, str_, to_c_int(data.len()));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn devices_data_endorsed_by(
+    ptr: *const Devices,
+    row: c_int,
+    d: *mut QByteArray,
+    set: fn(*mut QByteArray, *const c_char, len: c_int),
+) {
+    let obj = &*ptr;
+    let data = obj.endorsed_by(to_usize(row).unwrap_or(0));
+    if let Some(data) = data {
+        let str_: *const c_char = data.as_ptr() as (*const c_char);
+        set(d, str_, to_c_int(data.len()));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn devices_data_is_current(
+    ptr: *const Devices,
+    row: c_int,
+) -> bool {
+    let obj = &*ptr;
+    obj.is_current(to_usize(row).unwrap_or(0))
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct DevicesPtrBundle {
+    devices: *mut DevicesQObject,
+    devices_new_data_ready: fn(*mut DevicesQObject),
+    devices_layout_about_to_be_changed: fn(*mut DevicesQObject),
+    devices_layout_changed: fn(*mut DevicesQObject),
+    devices_data_changed: fn(*mut DevicesQObject, usize, usize),
+    devices_begin_reset_model: fn(*mut DevicesQObject),
+    devices_end_reset_model: fn(*mut DevicesQObject),
+    devices_begin_insert_rows: fn(*mut DevicesQObject, usize, usize),
+    devices_end_insert_rows: fn(*mut DevicesQObject),
+    devices_begin_move_rows: fn(*mut DevicesQObject, usize, usize, usize),
+    devices_end_move_rows: fn(*mut DevicesQObject),
+    devices_begin_remove_rows: fn(*mut DevicesQObject, usize, usize),
+    devices_end_remove_rows: fn(*mut DevicesQObject),
+    devices_try_load: fn(*mut DevicesQObject),
+}
@@ -7,6 +7,7 @@ pub struct HeraldEmitter {
     pub(super) config_init_changed: fn(*mut HeraldQObject),
     pub(super) connection_pending_changed: fn(*mut HeraldQObject),
     pub(super) connection_up_changed: fn(*mut HeraldQObject),
+    pub(super) locked_changed: fn(*mut HeraldQObject),
     pub(super) registration_failure_code_changed: fn(*mut HeraldQObject),
     pub(super) try_poll: fn(*mut HeraldQObject),
 }
@@ -24,6 +25,7 @@ impl HeraldEmitter {
             config_init_changed: self.config_init_changed,
             connection_pending_changed: self.connection_pending_changed,
             connection_up_changed: self.connection_up_changed,
+            locked_changed: self.locked_changed,
             registration_failure_code_changed: self.registration_failure_code_changed,
             try_poll: self.try_poll,
         }
@@ -59,6 +61,14 @@ impl HeraldEmitter {
         }
     }
 
+    pub fn locked_changed(&mut self) {
+        let ptr = self.qobject.load(Ordering::SeqCst);
+
+        if !ptr.is_null() {
+            (self.locked_changed)(ptr);
+        }
+    }
+
     pub fn registration_failure_code_changed(&mut self) {
         let ptr = self.qobject.load(Ordering::SeqCst);
 
@@ -114,6 +124,8 @@ pub trait HeraldTrait {
 
     fn errors_mut(&mut self) -> &mut Errors;
 
+    fn locked(&self) -> bool;
+
     fn message_search(&self) -> &MessageSearch;
 
     fn message_search_mut(&mut self) -> &mut MessageSearch;
@@ -136,6 +148,12 @@ pub trait HeraldTrait {
 
     fn utils_mut(&mut self) -> &mut Utils;
 
+    fn change_passphrase(
+        &mut self,
+        old_passphrase: String,
+        new_passphrase: String,
+    ) -> bool;
+
     fn login(&mut self) -> bool;
 
     fn poll_update(&mut self) -> ();
@@ -145,12 +163,23 @@ pub trait HeraldTrait {
         user_id: String,
         addr: String,
         port: String,
+        pin: String,
     ) -> ();
 
     fn set_app_local_data_dir(
         &mut self,
         path: String,
     ) -> ();
+
+    fn set_passphrase(
+        &mut self,
+        passphrase: String,
+    ) -> bool;
+
+    fn unlock(
+        &mut self,
+        passphrase: String,
+    ) -> bool;
 }
 
 #[no_mangle]
@@ -203,6 +232,7 @@ pub unsafe fn herald_new_inner(ptr_bundle: *mut HeraldPtrBundle) -> Herald {
         conversations_end_remove_rows,
         errors,
         errors_new_error,
+        herald_locked_changed,
         message_search,
         message_search_regex_search_changed,
         message_search_search_pattern_changed,
@@ -383,6 +413,7 @@ pub unsafe fn herald_new_inner(ptr_bundle: *mut HeraldPtrBundle) -> Herald {
         config_init_changed: herald_config_init_changed,
         connection_pending_changed: herald_connection_pending_changed,
         connection_up_changed: herald_connection_up_changed,
+        locked_changed: herald_locked_changed,
         registration_failure_code_changed: herald_registration_failure_code_changed,
         try_poll: herald_try_poll,
     };
@@ -406,6 +437,22 @@ pub unsafe extern "C" fn herald_free(ptr: *mut Herald) {
     Box::from_raw(ptr).emit().clear();
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn herald_change_passphrase(
+    ptr: *mut Herald,
+    old_passphrase_str: *const c_ushort,
+    old_passphrase_len: c_int,
+    new_passphrase_str: *const c_ushort,
+    new_passphrase_len: c_int,
+) -> bool {
+    let obj = &mut *ptr;
+    let mut old_passphrase = String::new();
+    set_string_from_utf16(&mut old_passphrase, old_passphrase_str, old_passphrase_len);
+    let mut new_passphrase = String::new();
+    set_string_from_utf16(&mut new_passphrase, new_passphrase_str, new_passphrase_len);
+    obj.change_passphrase(old_passphrase, new_passphrase)
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn herald_login(ptr: *mut Herald) -> bool {
     let obj = &mut *ptr;
@@ -427,6 +474,8 @@ pub unsafe extern "C" fn herald_register_new_user(
     addr_len: c_int,
     port_str: *const c_ushort,
     port_len: c_int,
+    pin_str: *const c_ushort,
+    pin_len: c_int,
 ) {
     let obj = &mut *ptr;
     let mut user_id = String::new();
@@ -435,7 +484,9 @@ pub unsafe extern "C" fn herald_register_new_user(
     set_string_from_utf16(&mut addr, addr_str, addr_len);
     let mut port = String::new();
     set_string_from_utf16(&mut port, port_str, port_len);
-    obj.register_new_user(user_id, addr, port)
+    let mut pin = String::new();
+    set_string_from_utf16(&mut pin, pin_str, pin_len);
+    obj.register_new_user(user_id, addr, port, pin)
 }
 
 #[no_mangle]
@@ -450,6 +501,30 @@ pub unsafe extern "C" fn herald_set_app_local_data_dir(
     obj.set_app_local_data_dir(path)
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn herald_set_passphrase(
+    ptr: *mut Herald,
+    passphrase_str: *const c_ushort,
+    passphrase_len: c_int,
+) -> bool {
+    let obj = &mut *ptr;
+    let mut passphrase = String::new();
+    set_string_from_utf16(&mut passphrase, passphrase_str, passphrase_len);
+    obj.set_passphrase(passphrase)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn herald_unlock(
+    ptr: *mut Herald,
+    passphrase_str: *const c_ushort,
+    passphrase_len: c_int,
+) -> bool {
+    let obj = &mut *ptr;
+    let mut passphrase = String::new();
+    set_string_from_utf16(&mut passphrase, passphrase_str, passphrase_len);
+    obj.unlock(passphrase)
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn herald_config_get(ptr: *mut Herald) -> *mut Config {
     (&mut *ptr).config_mut()
@@ -487,6 +562,11 @@ pub unsafe extern "C" fn herald_errors_get(ptr: *mut Herald) -> *mut Errors {
     (&mut *ptr).errors_mut()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn herald_locked_get(ptr: *const Herald) -> bool {
+    (&*ptr).locked()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn herald_message_search_get(ptr: *mut Herald) -> *mut MessageSearch {
     (&mut *ptr).message_search_mut()
@@ -569,6 +649,7 @@ pub struct HeraldPtrBundle {
     conversations_end_remove_rows: fn(*mut ConversationsQObject),
     errors: *mut ErrorsQObject,
     errors_new_error: fn(*mut ErrorsQObject),
+    herald_locked_changed: fn(*mut HeraldQObject),
     message_search: *mut MessageSearchQObject,
     message_search_regex_search_changed: fn(*mut MessageSearchQObject),
     message_search_search_pattern_changed: fn(*mut MessageSearchQObject),
@@ -256,6 +256,11 @@ pub trait MessagesTrait {
         id: &[u8],
     ) -> bool;
 
+    fn deliveries(
+        &self,
+        index: u64,
+    ) -> String;
+
     fn index_by_id(
         &self,
         msg_id: &[u8],
@@ -702,6 +707,19 @@ pub unsafe extern "C" fn messages_delete_message_by_id(
     obj.delete_message_by_id(id)
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn messages_deliveries(
+    ptr: *const Messages,
+    index: u64,
+    data: *mut QString,
+    set: fn(*mut QString, str_: *const c_char, len: c_int),
+) {
+    let obj = &*ptr;
+    let ret = obj.deliveries(index);
+    let str_: *const c_char = ret.as_ptr() as (*const c_char);
+    set(data, str_, ret.len() as i32);
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn messages_index_by_id(
     ptr: *const Messages,
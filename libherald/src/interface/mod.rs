@@ -3,6 +3,7 @@ pub use config::*;
 pub use conversation_builder::*;
 pub use conversation_content::*;
 pub use conversations::*;
+pub use devices::*;
 pub use document_attachments::*;
 pub use emoji_picker::*;
 pub use errors::*;
@@ -30,6 +31,8 @@ mod conversation_content;
 
 mod conversations;
 
+mod devices;
+
 mod document_attachments;
 
 mod emoji_picker;
@@ -7,8 +7,10 @@ pub struct UserEmitter {
     pub(super) name_changed: fn(*mut UserQObject),
     pub(super) pairwise_conversation_id_changed: fn(*mut UserQObject),
     pub(super) profile_picture_changed: fn(*mut UserQObject),
+    pub(super) safety_number_changed: fn(*mut UserQObject),
     pub(super) user_color_changed: fn(*mut UserQObject),
     pub(super) user_id_changed: fn(*mut UserQObject),
+    pub(super) verified_changed: fn(*mut UserQObject),
 }
 
 impl UserEmitter {
@@ -24,8 +26,10 @@ impl UserEmitter {
             name_changed: self.name_changed,
             pairwise_conversation_id_changed: self.pairwise_conversation_id_changed,
             profile_picture_changed: self.profile_picture_changed,
+            safety_number_changed: self.safety_number_changed,
             user_color_changed: self.user_color_changed,
             user_id_changed: self.user_id_changed,
+            verified_changed: self.verified_changed,
         }
     }
 
@@ -58,6 +62,14 @@ impl UserEmitter {
         }
     }
 
+    pub fn safety_number_changed(&mut self) {
+        let ptr = self.qobject.load(Ordering::SeqCst);
+
+        if !ptr.is_null() {
+            (self.safety_number_changed)(ptr);
+        }
+    }
+
     pub fn user_color_changed(&mut self) {
         let ptr = self.qobject.load(Ordering::SeqCst);
 
@@ -73,6 +85,14 @@ impl UserEmitter {
             (self.user_id_changed)(ptr);
         }
     }
+
+    pub fn verified_changed(&mut self) {
+        let ptr = self.qobject.load(Ordering::SeqCst);
+
+        if !ptr.is_null() {
+            (self.verified_changed)(ptr);
+        }
+    }
 }
 
 pub trait UserTrait {
@@ -86,6 +106,8 @@ pub trait UserTrait {
 
     fn profile_picture(&self) -> Option<String>;
 
+    fn safety_number(&self) -> String;
+
     fn user_color(&self) -> u32;
 
     fn set_user_color(
@@ -99,6 +121,13 @@ pub trait UserTrait {
         &mut self,
         value: Option<String>,
     );
+
+    fn verified(&self) -> bool;
+
+    fn set_verified(
+        &mut self,
+        value: bool,
+    );
 }
 
 #[no_mangle]
@@ -115,16 +144,20 @@ pub unsafe fn user_new_inner(ptr_bundle: *mut UserPtrBundle) -> User {
         user_name_changed,
         user_pairwise_conversation_id_changed,
         user_profile_picture_changed,
+        user_safety_number_changed,
         user_user_color_changed,
         user_user_id_changed,
+        user_verified_changed,
     } = ptr_bundle;
     let user_emit = UserEmitter {
         qobject: Arc::new(AtomicPtr::new(user)),
         name_changed: user_name_changed,
         pairwise_conversation_id_changed: user_pairwise_conversation_id_changed,
         profile_picture_changed: user_profile_picture_changed,
+        safety_number_changed: user_safety_number_changed,
         user_color_changed: user_user_color_changed,
         user_id_changed: user_user_id_changed,
+        verified_changed: user_verified_changed,
     };
     let d_user = User::new(user_emit);
     d_user
@@ -173,6 +206,18 @@ pub unsafe extern "C" fn user_profile_picture_get(
     }
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn user_safety_number_get(
+    ptr: *const User,
+    prop: *mut QString,
+    set: fn(*mut QString, *const c_char, c_int),
+) {
+    let obj = &*ptr;
+    let value = obj.safety_number();
+    let str_: *const c_char = value.as_ptr() as *const c_char;
+    set(prop, str_, to_c_int(value.len()));
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn user_user_color_get(ptr: *const User) -> u32 {
     (&*ptr).user_color()
@@ -218,6 +263,19 @@ pub unsafe extern "C" fn user_user_id_set_none(ptr: *mut User) {
     obj.set_user_id(None);
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn user_verified_get(ptr: *const User) -> bool {
+    (&*ptr).verified()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn user_verified_set(
+    ptr: *mut User,
+    value: bool,
+) {
+    (&mut *ptr).set_verified(value)
+}
+
 #[derive(Clone, Copy)]
 #[repr(C)]
 pub struct UserPtrBundle {
@@ -225,6 +283,8 @@ pub struct UserPtrBundle {
     user_name_changed: fn(*mut UserQObject),
     user_pairwise_conversation_id_changed: fn(*mut UserQObject),
     user_profile_picture_changed: fn(*mut UserQObject),
+    user_safety_number_changed: fn(*mut UserQObject),
     user_user_color_changed: fn(*mut UserQObject),
     user_user_id_changed: fn(*mut UserQObject),
+    user_verified_changed: fn(*mut UserQObject),
 }
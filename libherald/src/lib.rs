@@ -21,6 +21,8 @@ pub mod conversation_builder;
 pub mod conversation_content;
 /// Conversations object
 pub mod conversations;
+/// Devices linked to the account
+pub mod devices;
 /// Emoji Picker backend Object
 pub mod emoji_picker;
 /// Error queue
@@ -52,7 +54,7 @@ pub mod utils;
 
 pub(crate) use conversation_content::content_push;
 pub(crate) use herald::{push, Update};
-pub(crate) use user::user_push;
+pub(crate) use user::{keys_changed, user_push};
 
 pub(crate) trait Loadable {
     type Error;
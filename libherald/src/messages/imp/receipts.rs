@@ -45,6 +45,36 @@ impl Messages {
         json::JsonValue::from(receipts).dump().into()
     }
 
+    /// The devices the message at `index` has been delivered to, and when we were told, serialized
+    /// as a JSON array.
+    pub(crate) fn deliveries_(
+        &self,
+        index: usize,
+    ) -> Option<String> {
+        let msg_id = self.container.msg_id(index)?;
+        let deliveries = err!(heraldcore::message::deliveries(msg_id), None);
+
+        let deliveries = deliveries
+            .into_iter()
+            .map(|(device, time)| {
+                let did: String = device
+                    .did
+                    .as_ref()
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect();
+
+                json::object! {
+                    "userId" => device.uid.as_str(),
+                    "device" => did,
+                    "time" => *time.as_i64()
+                }
+            })
+            .collect::<Vec<json::JsonValue>>();
+
+        Some(json::JsonValue::from(deliveries).dump())
+    }
+
     pub(crate) fn mark_read_(
         &mut self,
         id: ffi::MsgIdRef,
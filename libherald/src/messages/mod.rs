@@ -6,7 +6,7 @@ use crate::{
 use crossbeam_channel::Sender;
 use herald_common::UserId;
 use heraldcore::{
-    message::{Elider, ReceiptStatus},
+    message::{Elider, ReceiptStatus, SendStatus},
     types::*,
 };
 use messages_helper::{container::Container, search::SearchState};
@@ -86,7 +86,13 @@ impl Messages {
             }
 
             MsgUpdate::SendDone(mid) => {
-                self.container.handle_send_done(mid, model, emit, cid);
+                self.container
+                    .handle_send_status(mid, SendStatus::Ack, model, emit, cid);
+            }
+
+            MsgUpdate::Delivered(mid) => {
+                self.container
+                    .handle_send_status(mid, SendStatus::Delivered, model, emit, cid);
             }
 
             MsgUpdate::ExpiredMessages(mids) => {
@@ -148,4 +154,7 @@ pub(crate) enum MsgUpdate {
 
     /// An outbound message has arrived at the server
     SendDone(MsgId),
+
+    /// An outbound message has been fetched by one of the recipients' devices
+    Delivered(MsgId),
 }
@@ -319,6 +319,13 @@ impl Interface for Messages {
         self.user_receipts_(index).unwrap_or_default()
     }
 
+    fn deliveries(
+        &self,
+        index: u64,
+    ) -> String {
+        self.deliveries_(index as usize).unwrap_or_default()
+    }
+
     fn mark_read_by_id(
         &mut self,
         id: ffi::MsgIdRef,
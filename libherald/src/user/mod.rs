@@ -5,20 +5,26 @@ use crate::{
     users::shared as data,
 };
 use herald_common::UserId;
-use std::convert::TryInto;
+use std::{cell::RefCell, convert::TryInto};
 
 mod shared;
-pub(crate) use shared::user_push;
+pub(crate) use shared::{keys_changed, user_push};
 
 /// Handle for user information
 pub struct User {
     emit: Emit,
     id: Option<UserId>,
+    /// The safety number last shown, which is what the user checks before verifying
+    shown_safety_number: RefCell<Option<String>>,
 }
 
 impl Interface for User {
     fn new(emit: Emit) -> Self {
-        User { emit, id: None }
+        User {
+            emit,
+            id: None,
+            shown_safety_number: RefCell::new(None),
+        }
     }
 
     fn emit(&mut self) -> &mut Emit {
@@ -73,6 +79,39 @@ impl Interface for User {
         self.emit.user_color_changed();
     }
 
+    fn safety_number(&self) -> String {
+        let uid = none!(self.id, "".to_owned());
+        let number = err!(heraldcore::user::safety_number(uid), "".to_owned());
+
+        self.shown_safety_number.replace(Some(number.clone()));
+
+        number
+    }
+
+    fn verified(&self) -> bool {
+        let uid = none!(self.id, false);
+        err!(heraldcore::user::is_verified(uid), false)
+    }
+
+    fn set_verified(
+        &mut self,
+        verified: bool,
+    ) {
+        let uid = none!(self.id);
+
+        if verified {
+            let shown = none!(self.shown_safety_number.borrow().clone());
+
+            // fails if the number changed after it was shown, in which case it has to be
+            // checked again
+            err!(heraldcore::user::set_verified(uid, Some(&shown)));
+        } else {
+            err!(heraldcore::user::set_verified(uid, None));
+        }
+
+        self.emit.verified_changed();
+    }
+
     fn user_id(&self) -> Option<ffi::UserIdRef> {
         self.id.as_ref().map(UserId::as_str)
     }
@@ -55,3 +55,11 @@ pub(crate) fn user_push(
         }
     }
 }
+
+pub(crate) fn keys_changed(uid: UserId) {
+    let mut emit_lock = user_emit().lock();
+    let emit = none!(emit_lock.get_mut(&uid));
+
+    emit.safety_number_changed();
+    emit.verified_changed();
+}
@@ -53,6 +53,9 @@ impl Users {
             UserUpdate::UserChanged(uid, update) => {
                 crate::user_push(uid, update);
             }
+            UserUpdate::KeysChanged(uid) => {
+                crate::keys_changed(uid);
+            }
         }
     }
 }
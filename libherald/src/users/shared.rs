@@ -72,6 +72,8 @@ pub enum UserUpdate {
     ReqResp(UserId, bool),
     /// User profile information has been updated
     UserChanged(UserId, herald_user::UserChange),
+    /// A verified user's keys have changed
+    KeysChanged(UserId),
 }
 
 impl From<UserUpdate> for crate::Update {
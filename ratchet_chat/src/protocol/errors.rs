@@ -7,9 +7,26 @@ pub enum FailureReason {
     BadSig(SigValid),
     InvalidSender,
     StoreError,
+    /// The recipient had no session with the sender, or couldn't start the one it was offered
+    MissingSession,
+    /// Both sides started a session at the same time and the recipient kept its own
+    SessionConflict,
+    NoSenderKey(GroupId),
     WhoKnows,
 }
 
+impl FailureReason {
+    /// Whether resending the payload can succeed.
+    pub fn is_recoverable(&self) -> bool {
+        match self {
+            FailureReason::Deserialization
+            | FailureReason::BadSig(_)
+            | FailureReason::InvalidSender => false,
+            _ => true,
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum TransitError<E: StdError + Send + 'static> {
     #[error("Failed to decrypt: {0}")]
@@ -26,6 +43,16 @@ pub enum TransitError<E: StdError + Send + 'static> {
     BadSig(SigValid),
     #[error("Message should not have been sent by this device, the sender is being sketchy")]
     InvalidSender,
+    #[error("Session was started with prekey {0:#?}, which we don't have")]
+    MissingPrekey(Prekey),
+    #[error("Both sides started a session with {0:#?}, keeping ours")]
+    SessionConflict(sig::PublicKey),
+    #[error("Received group message for {0:#?} without a matching sender key")]
+    NoSenderKey(GroupId),
+    #[error("Failed to decrypt group message for {0:#?}")]
+    GroupDecryption(GroupId),
+    #[error("Our sender key for {0:#?} can't sign messages, THIS SHOULD NEVER HAPPEN")]
+    NotOwnSenderKey(GroupId),
 }
 
 impl<E: StdError + Send + 'static> From<TransitError<E>> for FailureReason {
@@ -37,10 +64,16 @@ impl<E: StdError + Send + 'static> From<TransitError<E>> for FailureReason {
             },
             TransitError::Kson(_) => FailureReason::Deserialization,
             TransitError::Store(_) => FailureReason::StoreError,
-            TransitError::NoSession(_) => FailureReason::WhoKnows,
+            TransitError::NoSession(_) => FailureReason::MissingSession,
             TransitError::Uninit(_) => FailureReason::WhoKnows,
             TransitError::BadSig(v) => FailureReason::BadSig(v),
             TransitError::InvalidSender => FailureReason::InvalidSender,
+            TransitError::MissingPrekey(_) => FailureReason::MissingSession,
+            TransitError::SessionConflict(_) => FailureReason::SessionConflict,
+            TransitError::NoSenderKey(group) => FailureReason::NoSenderKey(group),
+            // the sender key can't be trusted anymore, ask for a new one
+            TransitError::GroupDecryption(group) => FailureReason::NoSenderKey(group),
+            TransitError::NotOwnSenderKey(_) => FailureReason::WhoKnows,
         }
     }
 }
@@ -0,0 +1,207 @@
+//! Sender-key group messaging.
+//!
+//! Each device keeps one sender ratchet per group and hands its current state to the other
+//! members' devices over the pairwise sessions. Messages to the group are then sealed once with
+//! the sender ratchet and fanned out by the server, instead of being encrypted separately for
+//! every device. The sender ratchet should be replaced with `rotate_sender_key` whenever the
+//! membership of the group changes, so that removed devices can't read later messages.
+//!
+//! Every member can derive the message keys of a sender ratchet, so group messages are also
+//! signed with a key whose secret half never leaves the sender.
+
+use super::*;
+use crate::ratchet::sender;
+use bytes::BytesMut;
+
+#[derive(Ser, De, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, Debug)]
+pub struct GroupId(pub random::UQ);
+
+/// Everything that needs to be sent for a single group message.
+pub struct GroupSend {
+    /// Sender key distributions, each sent to a single device before `msg`
+    pub distribute: Vec<(sig::PublicKey, Msg)>,
+    /// The devices `msg` should be fanned out to
+    pub to: Vec<sig::PublicKey>,
+    /// The group message itself
+    pub msg: Msg,
+}
+
+/// Replaces our sender ratchet for `group` with one of the next generation.
+///
+/// The new sender key is distributed lazily, the next time we send to the group.
+pub fn rotate_sender_key<S: GroupStore>(
+    store: &mut S,
+    group: GroupId,
+) -> Result<(), S::Error> {
+    let generation = store
+        .get_own_sender_ratchet(group)?
+        .map(|r| r.generation().wrapping_add(1))
+        .unwrap_or(0);
+
+    store.store_own_sender_ratchet(group, sender::Ratchet::gen_new(generation))?;
+    store.clear_sender_key_recipients(group)
+}
+
+pub fn prepare_send_to_group<S>(
+    store: &mut S,
+    my_keypair: &sig::KeyPair,
+    group: GroupId,
+    to: Vec<sig::PublicKey>,
+    payload: Payload,
+) -> Result<GroupSend, TransitError<S::Error>>
+where
+    S: RatchetStore + PendingStore + GroupStore,
+{
+    let id = PayloadId(UQ::gen_new());
+
+    let mut ratchet = store
+        .get_own_sender_ratchet(group)
+        .map_err(TransitError::Store)?
+        .unwrap_or_else(|| sender::Ratchet::gen_new(0));
+
+    let have_key = store
+        .sender_key_recipients(group)
+        .map_err(TransitError::Store)?;
+
+    store
+        .add_pending_payload(id, payload.clone(), &to)
+        .map_err(TransitError::Store)?;
+
+    // devices we have no session with can't be given the sender key, they keep the payload
+    // pending and are sent it pairwise with `prepare_resend` once a session has been established
+    let missing = missing_sessions(store, &to).map_err(TransitError::Store)?;
+    let to: Vec<sig::PublicKey> = to.into_iter().filter(|k| !missing.contains(k)).collect();
+
+    let needs_key: Vec<sig::PublicKey> = to
+        .iter()
+        .filter(|k| !have_key.contains(k))
+        .copied()
+        .collect();
+
+    let mut distribute = Vec::with_capacity(needs_key.len());
+    for key in needs_key.iter().copied() {
+        let dist = Plaintext::SenderKey {
+            group,
+            ratchet: ratchet.distribution(),
+        };
+
+        let msg = encrypt_plaintext(store, key, PayloadId(UQ::gen_new()), &dist)?;
+        distribute.push((key, msg));
+    }
+
+    store
+        .add_sender_key_recipients(group, &needs_key)
+        .map_err(TransitError::Store)?;
+
+    let ad = mk_group_ad(group, id, *my_keypair.public());
+    let cipher = ratchet
+        .seal(ad, BytesMut::from(payload.as_ref()))
+        .ok_or(TransitError::NotOwnSenderKey(group))?;
+
+    store
+        .store_own_sender_ratchet(group, ratchet)
+        .map_err(TransitError::Store)?;
+
+    Ok(GroupSend {
+        distribute,
+        to,
+        msg: Msg::Group { group, id, cipher },
+    })
+}
+
+pub(super) fn store_sender_key<S: GroupStore>(
+    store: &mut S,
+    from: sig::PublicKey,
+    group: GroupId,
+    ratchet: sender::Ratchet,
+) -> Result<(), S::Error> {
+    // only the sender may sign with it, even if it sent us its secret signing key
+    let ratchet = ratchet.distribution();
+
+    // a distribution from before the last rotation may arrive late
+    if let Some(current) = store.get_sender_ratchet(group, from)? {
+        if current.generation() > ratchet.generation() {
+            return Ok(());
+        }
+    }
+
+    store.store_sender_ratchet(group, from, ratchet)
+}
+
+pub(super) fn decrypt_group<S: GroupStore>(
+    store: &mut S,
+    from: sig::PublicKey,
+    group: GroupId,
+    id: PayloadId,
+    cipher: sender::Cipher,
+) -> Result<Payload, TransitError<S::Error>> {
+    if cipher.ad != mk_group_ad(group, id, from) {
+        return Err(TransitError::GroupDecryption(group));
+    }
+
+    let mut ratchet = store
+        .get_sender_ratchet(group, from)
+        .map_err(TransitError::Store)?
+        .ok_or(TransitError::NoSenderKey(group))?;
+
+    if cipher.generation != ratchet.generation() {
+        return Err(TransitError::NoSenderKey(group));
+    }
+
+    // anyone in the group can derive the message keys, but only the sender can sign
+    if !ratchet.verify(&cipher) {
+        return Err(TransitError::BadSig(SigValid::BadSign));
+    }
+
+    // the message was skipped over earlier
+    if cipher.index < ratchet.ix() {
+        let key = store
+            .take_sender_key(group, from, cipher.generation, cipher.index)
+            .map_err(TransitError::Store)?
+            .ok_or(TransitError::GroupDecryption(group))?;
+
+        let sender::Cipher { tag, ad, ct, .. } = cipher;
+        let mut pt = ct.to_vec();
+
+        if !key.open(&ad, tag, &mut pt) {
+            return Err(TransitError::GroupDecryption(group));
+        }
+
+        return Ok(Bytes::from(pt));
+    }
+
+    if cipher.index - ratchet.ix() > dr::MAX_SKIP {
+        return Err(TransitError::GroupDecryption(group));
+    }
+
+    let generation = cipher.generation;
+
+    match ratchet.open(cipher) {
+        sender::Decrypted::Success { pt, extra, .. } => {
+            store
+                .store_sender_keys(group, from, generation, extra)
+                .map_err(TransitError::Store)?;
+            store
+                .store_sender_ratchet(group, from, ratchet)
+                .map_err(TransitError::Store)?;
+
+            Ok(pt)
+        }
+        _ => Err(TransitError::GroupDecryption(group)),
+    }
+}
+
+fn mk_group_ad(
+    group: GroupId,
+    id: PayloadId,
+    from: sig::PublicKey,
+) -> Bytes {
+    group
+        .0
+        .as_ref()
+        .iter()
+        .chain(id.0.as_ref().iter())
+        .chain(from.as_ref().iter())
+        .copied()
+        .collect()
+}
@@ -1,18 +1,28 @@
 use super::*;
-use crate::ratchet::double as dr;
+use crate::ratchet::{double as dr, sender};
 use herald_common::*;
 use kcl::*;
 use std::error::Error as StdError;
 use thiserror::*;
 
 mod errors;
+mod group;
+mod session;
 mod traits;
 pub use errors::*;
+pub use group::{prepare_send_to_group, rotate_sender_key, GroupId, GroupSend};
+pub use session::{missing_sessions, start_session, SessionInit};
 pub use traits::*;
 
 #[derive(Ser, De, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, Debug)]
 pub struct PayloadId(random::UQ);
 
+impl PayloadId {
+    pub fn gen_new() -> Self {
+        PayloadId(UQ::gen_new())
+    }
+}
+
 pub type Payload = Bytes;
 
 #[allow(clippy::large_enum_variant)]
@@ -20,9 +30,15 @@ pub type Payload = Bytes;
 pub enum Msg {
     Encrypted {
         id: PayloadId,
+        init: Option<SessionInit>,
         header: dr::Header,
         payload: Bytes,
     },
+    Group {
+        group: GroupId,
+        id: PayloadId,
+        cipher: sender::Cipher,
+    },
     Ack(Ack),
     SigUpdate(Signed<sig::SigUpdate>),
     Forwarded(UserId, Signed<sig::SigUpdate>),
@@ -37,42 +53,46 @@ pub enum Ack {
     },
 }
 
-pub fn start_session<S: RatchetStore>(
-    store: &mut S,
-    me: &sig::KeyPair,
-    them: sig::PublicKey,
-) -> Result<dr::DoubleRatchet, TransitError<S::Error>> {
-    let me_as_kx = x25519::KeyPair::from(me.clone());
-    let them_as_kx = x25519::PublicKey::from(them);
-    let secret = dr::diffie_hellman(&me_as_kx, &them_as_kx);
-    let ratchet = dr::DoubleRatchet::new_alice(&secret, them_as_kx, None);
-
-    store
-        .store_ratchet(them, ratchet.clone())
-        .map_err(TransitError::Store)?;
-
-    Ok(ratchet)
+/// What is actually encrypted inside `Msg::Encrypted`.
+#[derive(Ser, De)]
+enum Plaintext {
+    Payload(Payload),
+    SenderKey {
+        group: GroupId,
+        ratchet: sender::Ratchet,
+    },
 }
 
 pub fn encrypt_payload<S: RatchetStore>(
     store: &mut S,
-    me: &sig::KeyPair,
     to: sig::PublicKey,
     id: PayloadId,
     payload: &Payload,
+) -> Result<Msg, TransitError<S::Error>> {
+    encrypt_plaintext(store, to, id, &Plaintext::Payload(payload.clone()))
+}
+
+fn encrypt_plaintext<S: RatchetStore>(
+    store: &mut S,
+    to: sig::PublicKey,
+    id: PayloadId,
+    plaintext: &Plaintext,
 ) -> Result<Msg, TransitError<S::Error>> {
     let ad = mk_ad(to, id);
-    let mut ratchet = if let Some(r) = store.get_ratchet(to).map_err(TransitError::Store)? {
-        r
-    } else {
-        start_session(store, me, to)?
-    };
+    let mut ratchet = store
+        .get_ratchet(to)
+        .map_err(TransitError::Store)?
+        .ok_or(TransitError::NoSession(to))?;
 
     let (header, ct) = ratchet
-        .ratchet_encrypt(&kson::to_vec(&payload), ad.as_ref())
+        .ratchet_encrypt(&kson::to_vec(plaintext), ad.as_ref())
         .ok_or(TransitError::Uninit(to))?;
+
+    let init = store.get_session_init(to).map_err(TransitError::Store)?;
+
     let msg = Msg::Encrypted {
         id,
+        init,
         header,
         payload: Bytes::from(ct),
     };
@@ -82,39 +102,80 @@ pub fn encrypt_payload<S: RatchetStore>(
     Ok(msg)
 }
 
-pub fn decrypt_payload<S: RatchetStore + dr::KeyStore>(
+fn decrypt_plaintext<S: RatchetStore + PrekeyStore + dr::KeyStore>(
     store: &mut S,
     me: &sig::KeyPair,
     them: sig::PublicKey,
     id: PayloadId,
+    init: Option<SessionInit>,
     header: dr::Header,
     payload: Bytes,
-) -> Result<Payload, TransitError<S::Error>> {
+) -> Result<(Plaintext, bool), TransitError<S::Error>> {
     let ad = mk_ad(*me.public(), id);
 
-    let mut ratchet = store
-        .get_ratchet(them)
-        .map_err(TransitError::Store)?
-        .unwrap_or_else(|| {
-            let me_as_kx = x25519::KeyPair::from(me.clone());
-            let them_as_kx = x25519::PublicKey::from(them);
-            let secret = dr::diffie_hellman(&me_as_kx, &them_as_kx);
+    let existing = store.get_ratchet(them).map_err(TransitError::Store)?;
+
+    // messages from the initiator keep carrying the init until we reply, so only start over if
+    // the header doesn't belong to the session we already have
+    let current = match &existing {
+        Some(r) => init.is_none() || in_session(store, r, &header)?,
+        None => false,
+    };
+
+    let (mut ratchet, accepted) = match (existing, init) {
+        (Some(r), _) if current => (r, None),
+        (_, Some(init)) => {
+            // both sides started a session at the same time, the one started by the larger key
+            // wins
+            let pending = store
+                .get_session_init(them)
+                .map_err(TransitError::Store)?
+                .is_some();
 
-            dr::DoubleRatchet::new_bob(secret, me_as_kx, them_as_kx, None)
-        });
+            if pending && me.public() > &them {
+                return Err(TransitError::SessionConflict(them));
+            }
+
+            (session::accept_session(store, me, them, init)?, Some(init))
+        }
+        (_, None) => return Err(TransitError::NoSession(them)),
+    };
 
     let decrypted = ratchet.ratchet_decrypt(store, &header, &payload, &ad)?;
     store
         .store_ratchet(them, ratchet)
         .map_err(TransitError::Store)?;
-    let payload = kson::from_bytes(decrypted.into())?;
 
-    Ok(payload)
+    // either they replied to the session we started, or we just replaced it with theirs
+    store.del_session_init(them).map_err(TransitError::Store)?;
+
+    if let Some(SessionInit {
+        one_time_prekey: Some(one_time),
+        ..
+    }) = accepted
+    {
+        store.del_prekey(one_time).map_err(TransitError::Store)?;
+    }
+
+    let plaintext = kson::from_bytes(decrypted.into())?;
+
+    Ok((plaintext, accepted.is_some()))
+}
+
+fn in_session<S: dr::KeyStore>(
+    store: &mut S,
+    ratchet: &dr::DoubleRatchet,
+    header: &dr::Header,
+) -> Result<bool, TransitError<S::Error>> {
+    if ratchet.dhr() == &Some(*header.dh()) {
+        return Ok(true);
+    }
+
+    store.contains_pk(*header.dh()).map_err(TransitError::Store)
 }
 
 fn prepare_send_to_keys<S>(
     store: &mut S,
-    my_keypair: &sig::KeyPair,
     send_to: Vec<sig::PublicKey>,
     payload: Payload,
 ) -> Result<Vec<(sig::PublicKey, Msg)>, TransitError<S::Error>>
@@ -127,9 +188,17 @@ where
         .add_pending_payload(id, payload.clone(), &send_to)
         .map_err(TransitError::Store)?;
 
+    // devices we have no session with yet keep the payload pending, and are sent it with
+    // `prepare_resend` once a session has been established
+    let missing = missing_sessions(store, &send_to).map_err(TransitError::Store)?;
+
     let mut out = Vec::with_capacity(send_to.len());
     for key in send_to {
-        let msg = encrypt_payload(store, my_keypair, key, id, &payload)?;
+        if missing.contains(&key) {
+            continue;
+        }
+
+        let msg = encrypt_payload(store, key, id, &payload)?;
         out.push((key, msg));
     }
 
@@ -152,24 +221,22 @@ where
         .filter(|k| k != my_keypair.public())
         .collect();
 
-    prepare_send_to_keys(store, my_keypair, keys, payload)
+    prepare_send_to_keys(store, keys, payload)
 }
 
 pub fn prepare_send_to_all<S>(
     store: &mut S,
-    my_keypair: &sig::KeyPair,
     payload: Payload,
 ) -> Result<Vec<(sig::PublicKey, Msg)>, TransitError<S::Error>>
 where
     S: dr::KeyStore + RatchetStore + PendingStore + SigStore,
 {
     let keys = store.all_active_keys().map_err(TransitError::Store)?;
-    prepare_send_to_keys(store, my_keypair, keys, payload)
+    prepare_send_to_keys(store, keys, payload)
 }
 
 pub fn prepare_send_to_user<S>(
     store: &mut S,
-    my_keypair: &sig::KeyPair,
     uid: UserId,
     payload: Payload,
 ) -> Result<Vec<(sig::PublicKey, Msg)>, TransitError<S::Error>>
@@ -177,7 +244,19 @@ where
     S: dr::KeyStore + RatchetStore + PendingStore + SigStore,
 {
     let keys = store.active_keys(uid).map_err(TransitError::Store)?;
-    prepare_send_to_keys(store, my_keypair, keys, payload)
+    prepare_send_to_keys(store, keys, payload)
+}
+
+/// Prepares `payload` for the device `key` alone.
+pub fn prepare_send_to_key<S>(
+    store: &mut S,
+    key: sig::PublicKey,
+    payload: Payload,
+) -> Result<Vec<(sig::PublicKey, Msg)>, TransitError<S::Error>>
+where
+    S: dr::KeyStore + RatchetStore + PendingStore + SigStore,
+{
+    prepare_send_to_keys(store, vec![key], payload)
 }
 
 // TODO: replace this with [u8;64]
@@ -197,31 +276,100 @@ pub struct MsgResult {
     pub forward: Option<Msg>,
     pub output: Option<Bytes>,
     pub response: Option<Msg>,
+    /// Messages to send back to the device the handled message came from
+    pub to_sender: Vec<Msg>,
+    /// A payload that can be retransmitted with `prepare_resend` once a new session with the
+    /// sender has been established
+    pub needs_session: Option<PayloadId>,
+    /// A payload we gave up on delivering to the sender
+    pub gave_up: Option<(Payload, FailureReason)>,
+}
+
+/// How many times a payload is retransmitted to a device before giving up.
+pub const MAX_RETRIES: u32 = 3;
+
+/// Re-encrypts the pending payload `id` for `to`, if it is still pending and we have a session
+/// with `to`.
+pub fn prepare_resend<S: RatchetStore + PendingStore>(
+    store: &mut S,
+    to: sig::PublicKey,
+    id: PayloadId,
+) -> Result<Option<Msg>, TransitError<S::Error>> {
+    let payload = match store.get_pending_payload(id).map_err(TransitError::Store)? {
+        Some(payload) => payload,
+        None => return Ok(None),
+    };
+
+    if store
+        .get_ratchet(to)
+        .map_err(TransitError::Store)?
+        .is_none()
+    {
+        return Ok(None);
+    }
+
+    Ok(Some(encrypt_payload(store, to, id, &payload)?))
 }
 
-#[allow(unused_mut)]
 fn handle_ack<S>(
     store: &mut S,
-    _me: &sig::KeyPair,
     them: sig::PublicKey,
     ack: Ack,
-) -> Result<Option<Msg>, TransitError<S::Error>>
+    res: &mut MsgResult,
+) -> Result<(), TransitError<S::Error>>
 where
-    S: dr::KeyStore + RatchetStore + PendingStore,
+    S: dr::KeyStore + RatchetStore + PendingStore + GroupStore,
 {
-    let mut out = None;
-
-    match ack {
+    let (id, reason) = match ack {
         Ack::Success(id) => {
             store.del_pending(id, them).map_err(TransitError::Store)?;
+            return Ok(());
+        }
+        Ack::Failed { id, reason } => (id, reason),
+    };
+
+    let payload = match store.get_pending_payload(id).map_err(TransitError::Store)? {
+        Some(payload) => payload,
+        None => return Ok(()),
+    };
+
+    let retries = store.note_retry(id, them).map_err(TransitError::Store)?;
+
+    if retries > MAX_RETRIES || !reason.is_recoverable() {
+        store.del_pending(id, them).map_err(TransitError::Store)?;
+        res.gave_up.replace((payload, reason));
+        return Ok(());
+    }
+
+    match reason {
+        // our session is unusable, the next message needs to start a new one
+        FailureReason::Decryption | FailureReason::MissingSession => {
+            store.del_ratchet(them).map_err(TransitError::Store)?;
+            res.needs_session.replace(id);
         }
-        Ack::Failed { .. } => {
-            // TODO error handling can be much smarter, for now we just fail
-            println!("{:?}", ack);
+        // they picked their own session over ours, wait for it and retransmit then
+        FailureReason::SessionConflict
+            if store
+                .get_session_init(them)
+                .map_err(TransitError::Store)?
+                .is_some() =>
+        {
+            store.del_ratchet(them).map_err(TransitError::Store)?;
+        }
+        FailureReason::NoSenderKey(group) => {
+            store
+                .remove_sender_key_recipient(group, them)
+                .map_err(TransitError::Store)?;
+            res.to_sender
+                .push(encrypt_payload(store, them, id, &payload)?);
+        }
+        _ => {
+            res.to_sender
+                .push(encrypt_payload(store, them, id, &payload)?);
         }
     }
 
-    Ok(out)
+    Ok(())
 }
 
 pub fn handle_incoming<S>(
@@ -231,20 +379,21 @@ pub fn handle_incoming<S>(
     msg: Msg,
 ) -> Result<MsgResult, TransitError<S::Error>>
 where
-    S: dr::KeyStore + RatchetStore + PendingStore + SigStore,
+    S: dr::KeyStore + RatchetStore + PendingStore + PrekeyStore + GroupStore + SigStore,
 {
     let mut res = MsgResult {
         ack: None,
         forward: None,
         output: None,
         response: None,
+        to_sender: Vec::new(),
+        needs_session: None,
+        gave_up: None,
     };
 
     match msg {
         Msg::Ack(a) => {
-            if let Some(m) = handle_ack(store, me, from.did, a)? {
-                res.response.replace(m);
-            }
+            handle_ack(store, from.did, a, &mut res)?;
         }
         Msg::Forwarded(uid, sig) => {
             let valid = sig::validate_update(&sig);
@@ -284,10 +433,49 @@ where
         }
         Msg::Encrypted {
             id,
+            init,
             header,
             payload,
         } => {
-            match decrypt_payload(store, me, from.did, id, header, payload) {
+            let decrypted = decrypt_plaintext(store, me, from.did, id, init, header, payload)
+                .and_then(|(plaintext, new_session)| {
+                    let payload = match plaintext {
+                        Plaintext::Payload(payload) => Some(payload),
+                        Plaintext::SenderKey { group, ratchet } => {
+                            group::store_sender_key(store, from.did, group, ratchet)
+                                .map_err(TransitError::Store)?;
+                            None
+                        }
+                    };
+
+                    Ok((payload, new_session))
+                });
+
+            match decrypted {
+                Err(e) => {
+                    res.ack.replace(Ack::Failed {
+                        id,
+                        reason: e.into(),
+                    });
+                }
+                Ok((payload, new_session)) => {
+                    res.ack.replace(Ack::Success(id));
+                    res.output = payload;
+
+                    // anything that didn't make it through the old session goes through the
+                    // new one
+                    if new_session {
+                        for pending in store.pending_to(from.did).map_err(TransitError::Store)? {
+                            if let Some(msg) = prepare_resend(store, from.did, pending)? {
+                                res.to_sender.push(msg);
+                            }
+                        }
+                    }
+                }
+            };
+        }
+        Msg::Group { group, id, cipher } => {
+            match group::decrypt_group(store, from.did, group, id, cipher) {
                 Err(e) => {
                     res.ack.replace(Ack::Failed {
                         id,
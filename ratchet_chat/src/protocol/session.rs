@@ -0,0 +1,144 @@
+//! X3DH-style session establishment.
+//!
+//! The initiator combines its long-term key and a fresh ephemeral key with the recipient's
+//! long-term key, signed prekey and (optionally) one-time prekey. The recipient repeats the
+//! computation using the prekey secrets it kept when it published them, so the first message of
+//! a session is never encrypted under a secret derived only from long-term keys. See the
+//! [specification] for details.
+//!
+//! [specification]: https://signal.org/docs/specifications/x3dh/
+
+use super::*;
+
+/// Key agreement data the initiator attaches to its messages until the other side replies.
+#[derive(Ser, De, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+pub struct SessionInit {
+    /// The initiator's ephemeral key
+    pub ephemeral: kx::PublicKey,
+    /// The recipient's signed prekey the session was started with
+    pub signed_prekey: Prekey,
+    /// The recipient's one-time prekey the session was started with, if any
+    pub one_time_prekey: Option<Prekey>,
+}
+
+/// Starts a session with `them` from a prekey bundle fetched from the server.
+///
+/// The resulting ratchet is stored along with the `SessionInit` that must accompany outgoing
+/// messages until `them` replies.
+pub fn start_session<S: RatchetStore>(
+    store: &mut S,
+    me: &sig::KeyPair,
+    them: sig::PublicKey,
+    bundle: PrekeyBundle,
+) -> Result<dr::DoubleRatchet, TransitError<S::Error>> {
+    let valid = bundle.verify(&them);
+    if valid != SigValid::Yes {
+        return Err(TransitError::BadSig(valid));
+    }
+
+    let Prekey(signed_prekey) = *bundle.signed.data();
+    let one_time_prekey = bundle.one_time.map(|p| *p.data());
+
+    let me_as_kx = x25519::KeyPair::from(me.clone());
+    let them_as_kx = x25519::PublicKey::from(them);
+    let ephemeral = kx::KeyPair::gen_new();
+
+    let mut dhs = vec![
+        dr::diffie_hellman(&me_as_kx, &signed_prekey),
+        dr::diffie_hellman(&ephemeral, &them_as_kx),
+        dr::diffie_hellman(&ephemeral, &signed_prekey),
+    ];
+
+    if let Some(Prekey(one_time)) = one_time_prekey {
+        dhs.push(dr::diffie_hellman(&ephemeral, &one_time));
+    }
+
+    let secret = kdf_x3dh(&dhs);
+    let ratchet = dr::DoubleRatchet::new_alice(&secret, signed_prekey, None);
+
+    let init = SessionInit {
+        ephemeral: *ephemeral.public(),
+        signed_prekey: Prekey(signed_prekey),
+        one_time_prekey,
+    };
+
+    store
+        .store_ratchet(them, ratchet.clone())
+        .map_err(TransitError::Store)?;
+    store
+        .store_session_init(them, init)
+        .map_err(TransitError::Store)?;
+
+    Ok(ratchet)
+}
+
+/// Builds the responder's side of a session started by `them` with `init`.
+///
+/// Prekey secrets are looked up but not deleted here; the caller retires the one-time prekey
+/// once the first message has been decrypted successfully.
+pub(super) fn accept_session<S: RatchetStore + PrekeyStore>(
+    store: &mut S,
+    me: &sig::KeyPair,
+    them: sig::PublicKey,
+    init: SessionInit,
+) -> Result<dr::DoubleRatchet, TransitError<S::Error>> {
+    let signed = store
+        .get_prekey(init.signed_prekey)
+        .map_err(TransitError::Store)?
+        .ok_or(TransitError::MissingPrekey(init.signed_prekey))?;
+
+    let me_as_kx = x25519::KeyPair::from(me.clone());
+    let them_as_kx = x25519::PublicKey::from(them);
+
+    let mut dhs = vec![
+        dr::diffie_hellman(&signed, &them_as_kx),
+        dr::diffie_hellman(&me_as_kx, &init.ephemeral),
+        dr::diffie_hellman(&signed, &init.ephemeral),
+    ];
+
+    if let Some(one_time) = init.one_time_prekey {
+        let one_time = store
+            .get_prekey(one_time)
+            .map_err(TransitError::Store)?
+            .ok_or(TransitError::MissingPrekey(one_time))?;
+
+        dhs.push(dr::diffie_hellman(&one_time, &init.ephemeral));
+    }
+
+    let secret = kdf_x3dh(&dhs);
+
+    Ok(dr::DoubleRatchet::new_bob(
+        secret,
+        signed,
+        init.ephemeral,
+        None,
+    ))
+}
+
+/// Returns the subset of `keys` with which no session exists yet.
+pub fn missing_sessions<S: RatchetStore>(
+    store: &mut S,
+    keys: &[sig::PublicKey],
+) -> Result<Vec<sig::PublicKey>, S::Error> {
+    let mut out = Vec::new();
+
+    for key in keys {
+        if store.get_ratchet(*key)?.is_none() {
+            out.push(*key);
+        }
+    }
+
+    Ok(out)
+}
+
+fn kdf_x3dh(dhs: &[dr::SharedSecret]) -> dr::RootKey {
+    let mut buf = [0u8; hash::KEY_LEN];
+    let mut hasher = hash::Builder::new().out_len(hash::KEY_LEN).build();
+
+    for dh in dhs {
+        hasher.update(dh.as_ref());
+    }
+
+    hasher.finalize_into(&mut buf);
+    hash::Key(buf)
+}
@@ -7,10 +7,17 @@ use std::{
 #[derive(Debug, Default)]
 pub struct Stores {
     ratchets: HashMap<sig::PublicKey, dr::DoubleRatchet>,
+    inits: HashMap<sig::PublicKey, SessionInit>,
+    prekeys: HashMap<Prekey, kx::KeyPair>,
     sigs: HashMap<UserId, sig::SigChain>,
     pending_by_id: HashMap<PayloadId, (Payload, HashSet<sig::PublicKey>)>,
     pending_by_to: HashMap<sig::PublicKey, HashSet<PayloadId>>,
+    retries: HashMap<(PayloadId, sig::PublicKey), u32>,
     keys: HashMap<kx::PublicKey, HashMap<dr::Counter, aead::Key>>,
+    own_sender: HashMap<GroupId, sender::Ratchet>,
+    sender_recipients: HashMap<GroupId, HashSet<sig::PublicKey>>,
+    sender_ratchets: HashMap<(GroupId, sig::PublicKey), sender::Ratchet>,
+    sender_keys: HashMap<(GroupId, sig::PublicKey, u32, u32), aead::Key>,
 }
 
 impl StoreLike for Stores {
@@ -80,6 +87,165 @@ impl RatchetStore for Stores {
         self.ratchets.insert(with, ratchet);
         Ok(())
     }
+
+    fn get_session_init(
+        &mut self,
+        with: sig::PublicKey,
+    ) -> Result<Option<SessionInit>, Self::Error> {
+        Ok(self.inits.get(&with).copied())
+    }
+
+    fn store_session_init(
+        &mut self,
+        with: sig::PublicKey,
+        init: SessionInit,
+    ) -> Result<(), Self::Error> {
+        self.inits.insert(with, init);
+        Ok(())
+    }
+
+    fn del_session_init(
+        &mut self,
+        with: sig::PublicKey,
+    ) -> Result<(), Self::Error> {
+        self.inits.remove(&with);
+        Ok(())
+    }
+
+    fn del_ratchet(
+        &mut self,
+        with: sig::PublicKey,
+    ) -> Result<(), Self::Error> {
+        self.ratchets.remove(&with);
+        self.inits.remove(&with);
+        Ok(())
+    }
+}
+
+impl GroupStore for Stores {
+    fn get_own_sender_ratchet(
+        &mut self,
+        group: GroupId,
+    ) -> Result<Option<sender::Ratchet>, Self::Error> {
+        Ok(self.own_sender.get(&group).cloned())
+    }
+
+    fn store_own_sender_ratchet(
+        &mut self,
+        group: GroupId,
+        ratchet: sender::Ratchet,
+    ) -> Result<(), Self::Error> {
+        self.own_sender.insert(group, ratchet);
+        Ok(())
+    }
+
+    fn sender_key_recipients(
+        &mut self,
+        group: GroupId,
+    ) -> Result<Vec<sig::PublicKey>, Self::Error> {
+        Ok(self
+            .sender_recipients
+            .get(&group)
+            .map(|r| r.iter().copied().collect())
+            .unwrap_or_default())
+    }
+
+    fn add_sender_key_recipients(
+        &mut self,
+        group: GroupId,
+        to: &[sig::PublicKey],
+    ) -> Result<(), Self::Error> {
+        self.sender_recipients
+            .entry(group)
+            .or_default()
+            .extend(to.iter().copied());
+        Ok(())
+    }
+
+    fn clear_sender_key_recipients(
+        &mut self,
+        group: GroupId,
+    ) -> Result<(), Self::Error> {
+        self.sender_recipients.remove(&group);
+        Ok(())
+    }
+
+    fn remove_sender_key_recipient(
+        &mut self,
+        group: GroupId,
+        key: sig::PublicKey,
+    ) -> Result<(), Self::Error> {
+        if let Some(recips) = self.sender_recipients.get_mut(&group) {
+            recips.remove(&key);
+        }
+        Ok(())
+    }
+
+    fn get_sender_ratchet(
+        &mut self,
+        group: GroupId,
+        from: sig::PublicKey,
+    ) -> Result<Option<sender::Ratchet>, Self::Error> {
+        Ok(self.sender_ratchets.get(&(group, from)).cloned())
+    }
+
+    fn store_sender_ratchet(
+        &mut self,
+        group: GroupId,
+        from: sig::PublicKey,
+        ratchet: sender::Ratchet,
+    ) -> Result<(), Self::Error> {
+        self.sender_ratchets.insert((group, from), ratchet);
+        Ok(())
+    }
+
+    fn store_sender_keys(
+        &mut self,
+        group: GroupId,
+        from: sig::PublicKey,
+        generation: u32,
+        keys: sender::ExtraKeys,
+    ) -> Result<(), Self::Error> {
+        for (ix, key) in keys {
+            self.sender_keys.insert((group, from, generation, ix), key);
+        }
+        Ok(())
+    }
+
+    fn take_sender_key(
+        &mut self,
+        group: GroupId,
+        from: sig::PublicKey,
+        generation: u32,
+        ix: u32,
+    ) -> Result<Option<aead::Key>, Self::Error> {
+        Ok(self.sender_keys.remove(&(group, from, generation, ix)))
+    }
+}
+
+impl PrekeyStore for Stores {
+    fn store_prekey(
+        &mut self,
+        kp: kx::KeyPair,
+    ) -> Result<(), Self::Error> {
+        self.prekeys.insert(Prekey(*kp.public()), kp);
+        Ok(())
+    }
+
+    fn get_prekey(
+        &mut self,
+        pre: Prekey,
+    ) -> Result<Option<kx::KeyPair>, Self::Error> {
+        Ok(self.prekeys.get(&pre).cloned())
+    }
+
+    fn del_prekey(
+        &mut self,
+        pre: Prekey,
+    ) -> Result<(), Self::Error> {
+        self.prekeys.remove(&pre);
+        Ok(())
+    }
 }
 
 impl SigStore for Stores {
@@ -179,8 +345,31 @@ impl PendingStore for Stores {
             }
         }
 
+        self.retries.remove(&(id, to));
+
         Ok(())
     }
+
+    fn note_retry(
+        &mut self,
+        id: PayloadId,
+        to: sig::PublicKey,
+    ) -> Result<u32, Self::Error> {
+        let retries = self.retries.entry((id, to)).or_insert(0);
+        *retries += 1;
+        Ok(*retries)
+    }
+
+    fn pending_to(
+        &mut self,
+        to: sig::PublicKey,
+    ) -> Result<Vec<PayloadId>, Self::Error> {
+        Ok(self
+            .pending_by_to
+            .get(&to)
+            .map(|p| p.iter().copied().collect())
+            .unwrap_or_default())
+    }
 }
 
 fn setup(name: &str) -> (sig::KeyPair, GlobalId, Stores, Signed<UserId>) {
@@ -197,6 +386,16 @@ fn setup(name: &str) -> (sig::KeyPair, GlobalId, Stores, Signed<UserId>) {
     (keys, gid, store, signed)
 }
 
+fn new_prekey(
+    keys: &sig::KeyPair,
+    store: &mut Stores,
+) -> Signed<Prekey> {
+    let kp = kx::KeyPair::gen_new();
+    let signed = sig::sign_ser(keys, Prekey(*kp.public()));
+    store.store_prekey(kp).expect("failed to store prekey");
+    signed
+}
+
 fn get_pid(msg: &Msg) -> PayloadId {
     match msg {
         Msg::Encrypted { id, .. } => *id,
@@ -211,19 +410,22 @@ fn init_recv() {
     let (alice, alice_gid, mut alice_store, alice_signed) = setup("alice");
     let (bob, bob_gid, mut bob_store, bob_signed) = setup("bob");
 
+    let bundle = PrekeyBundle {
+        signed: new_prekey(&bob, &mut bob_store),
+        one_time: None,
+    };
+
     // now alice gets bob's keys from the server
     alice_store
         .start_sigchain(bob_signed)
         .expect("failed to create bob sigchain for alice");
 
+    start_session(&mut alice_store, &alice, bob_gid.did, bundle)
+        .expect("failed to start session with bob");
+
     // alice sends noop to initialize the session
-    let msgs = prepare_send_to_user(
-        &mut alice_store,
-        &alice,
-        bob_gid.uid,
-        Bytes::from_static(b""),
-    )
-    .expect("failed to prepare messages");
+    let msgs = prepare_send_to_user(&mut alice_store, bob_gid.uid, Bytes::from_static(b""))
+        .expect("failed to prepare messages");
 
     assert_eq!(msgs.len(), 1);
     let (did, msg) = msgs.into_iter().next().unwrap();
@@ -235,6 +437,7 @@ fn init_recv() {
         forward,
         output,
         response,
+        ..
     } = handle_incoming(&mut bob_store, &bob, alice_gid, msg)
         .expect("failed to handle init msg from alice");
 
@@ -255,6 +458,7 @@ fn init_recv() {
         forward,
         output,
         response,
+        ..
     } = handle_incoming(&mut alice_store, &alice, bob_gid, bob_ack)
         .expect("alice failed to handle ack from bob");
 
@@ -263,7 +467,7 @@ fn init_recv() {
     assert_eq!(output, None);
     assert_eq!(response, None);
 
-    let msgs = prepare_send_to_user(&mut bob_store, &bob, alice_gid.uid, Bytes::from_static(b""))
+    let msgs = prepare_send_to_user(&mut bob_store, alice_gid.uid, Bytes::from_static(b""))
         .expect("failed to prepare messages from bob");
 
     assert_eq!(msgs.len(), 1);
@@ -276,6 +480,7 @@ fn init_recv() {
         forward,
         output,
         response,
+        ..
     } = handle_incoming(&mut alice_store, &alice, bob_gid, msg)
         .expect("alice failed to handle noop msg from bob");
 
@@ -291,6 +496,7 @@ fn init_recv() {
         forward,
         output,
         response,
+        ..
     } = handle_incoming(&mut bob_store, &bob, alice_gid, alice_ack)
         .expect("bob failed to handle ack from alice");
 
@@ -299,3 +505,346 @@ fn init_recv() {
     assert_eq!(output, None);
     assert_eq!(response, None);
 }
+
+#[test]
+fn one_time_prekey() {
+    kcl::init();
+
+    let (alice, alice_gid, mut alice_store, _) = setup("alice");
+    let (bob, bob_gid, mut bob_store, bob_signed) = setup("bob");
+
+    let bundle = PrekeyBundle {
+        signed: new_prekey(&bob, &mut bob_store),
+        one_time: Some(new_prekey(&bob, &mut bob_store)),
+    };
+    let one_time = *bundle.one_time.unwrap().data();
+
+    alice_store
+        .start_sigchain(bob_signed)
+        .expect("failed to create bob sigchain for alice");
+
+    start_session(&mut alice_store, &alice, bob_gid.did, bundle)
+        .expect("failed to start session with bob");
+
+    assert!(missing_sessions(&mut alice_store, &[bob_gid.did])
+        .unwrap()
+        .is_empty());
+
+    let mut msgs =
+        prepare_send_to_user(&mut alice_store, bob_gid.uid, Bytes::from_static(b"first"))
+            .expect("failed to prepare messages");
+
+    let (_, msg) = msgs.pop().unwrap();
+
+    match &msg {
+        Msg::Encrypted { init, .. } => {
+            assert_eq!(init.and_then(|i| i.one_time_prekey), Some(one_time))
+        }
+        m => panic!("expected Encrypted{{..}}, found {:?}", m),
+    }
+
+    let MsgResult { ack, output, .. } = handle_incoming(&mut bob_store, &bob, alice_gid, msg)
+        .expect("failed to handle init msg from alice");
+
+    match ack {
+        Some(Ack::Success(_)) => {}
+        a => panic!("expected successful ack, found {:?}", a),
+    }
+    assert_eq!(output.as_ref().map(|b| b.as_ref()), Some(b"first" as &[u8]));
+
+    // the one-time prekey is gone, the signed prekey is kept around for other sessions
+    assert!(bob_store.get_prekey(one_time).unwrap().is_none());
+    assert_eq!(bob_store.prekeys.len(), 1);
+}
+
+#[test]
+fn send_without_session() {
+    kcl::init();
+
+    let (alice, alice_gid, mut alice_store, _) = setup("alice");
+    let (bob, bob_gid, mut bob_store, bob_signed) = setup("bob");
+
+    alice_store
+        .start_sigchain(bob_signed)
+        .expect("failed to create bob sigchain for alice");
+
+    // bob hasn't uploaded any prekeys yet, so alice can't start a session
+    let msgs = prepare_send_to_user(&mut alice_store, bob_gid.uid, Bytes::from_static(b"hi"))
+        .expect("failed to prepare messages");
+    assert!(msgs.is_empty());
+
+    let pending = alice_store
+        .pending_to(bob_gid.did)
+        .expect("failed to get pending payloads");
+    assert_eq!(pending.len(), 1);
+    let pid = pending[0];
+
+    assert_eq!(
+        prepare_resend(&mut alice_store, bob_gid.did, pid).expect("failed to prepare resend"),
+        None
+    );
+
+    let bundle = PrekeyBundle {
+        signed: new_prekey(&bob, &mut bob_store),
+        one_time: None,
+    };
+    start_session(&mut alice_store, &alice, bob_gid.did, bundle)
+        .expect("failed to start session with bob");
+
+    let msg = prepare_resend(&mut alice_store, bob_gid.did, pid)
+        .expect("failed to prepare resend")
+        .expect("payload should still be pending");
+
+    let res = handle_incoming(&mut bob_store, &bob, alice_gid, msg)
+        .expect("failed to handle resent msg from alice");
+    assert_eq!(res.ack, Some(Ack::Success(pid)));
+    assert_eq!(
+        res.output.as_ref().map(|b| b.as_ref()),
+        Some(b"hi" as &[u8])
+    );
+}
+
+#[test]
+fn group_send() {
+    kcl::init();
+
+    let (alice, alice_gid, mut alice_store, _) = setup("alice");
+    let (bob, bob_gid, mut bob_store, _) = setup("bob");
+    let (carol, carol_gid, mut carol_store, _) = setup("carol");
+
+    let bob_bundle = PrekeyBundle {
+        signed: new_prekey(&bob, &mut bob_store),
+        one_time: None,
+    };
+    let carol_bundle = PrekeyBundle {
+        signed: new_prekey(&carol, &mut carol_store),
+        one_time: None,
+    };
+
+    start_session(&mut alice_store, &alice, bob_gid.did, bob_bundle)
+        .expect("failed to start session with bob");
+    start_session(&mut alice_store, &alice, carol_gid.did, carol_bundle)
+        .expect("failed to start session with carol");
+
+    let group = GroupId(UQ::gen_new());
+    let to = vec![bob_gid.did, carol_gid.did];
+
+    let send = |store: &mut Stores, text: &'static [u8]| {
+        prepare_send_to_group(store, &alice, group, to.clone(), Bytes::from_static(text))
+            .expect("failed to prepare group message")
+    };
+
+    let first = send(&mut alice_store, b"first");
+    assert_eq!(first.distribute.len(), 2);
+
+    let second = send(&mut alice_store, b"second");
+    assert!(second.distribute.is_empty());
+
+    for (gid, keys, store) in vec![
+        (bob_gid, &bob, &mut bob_store),
+        (carol_gid, &carol, &mut carol_store),
+    ] {
+        // without the sender key the group message can't be read
+        let res = handle_incoming(&mut *store, keys, alice_gid, first.msg.clone())
+            .expect("failed to handle group message");
+        match res.ack {
+            Some(Ack::Failed {
+                reason: FailureReason::NoSenderKey(g),
+                ..
+            }) if g == group => {}
+            a => panic!("expected missing sender key, found {:?}", a),
+        }
+
+        let (_, dist) = first
+            .distribute
+            .iter()
+            .find(|(k, _)| *k == gid.did)
+            .expect("missing sender key distribution");
+
+        let res = handle_incoming(&mut *store, keys, alice_gid, dist.clone())
+            .expect("failed to handle sender key");
+        assert_eq!(res.output, None);
+
+        // messages can be read out of order
+        let res = handle_incoming(&mut *store, keys, alice_gid, second.msg.clone())
+            .expect("failed to handle group message");
+        assert_eq!(
+            res.output.as_ref().map(|b| b.as_ref()),
+            Some(b"second" as &[u8])
+        );
+
+        let res = handle_incoming(&mut *store, keys, alice_gid, first.msg.clone())
+            .expect("failed to handle group message");
+        assert_eq!(
+            res.output.as_ref().map(|b| b.as_ref()),
+            Some(b"first" as &[u8])
+        );
+    }
+
+    // bob can derive alice's message keys, but can't sign as her
+    let mut bobs_copy = bob_store
+        .get_sender_ratchet(group, alice_gid.did)
+        .expect("failed to get sender key")
+        .expect("missing sender key");
+    assert!(bobs_copy
+        .seal(Bytes::new(), bytes::BytesMut::from(&b"forged"[..]))
+        .is_none());
+
+    let (id, ad, sig) = match &second.msg {
+        Msg::Group { id, cipher, .. } => (*id, cipher.ad.clone(), cipher.sig),
+        _ => panic!("expected group message"),
+    };
+
+    let (index, key) = bobs_copy.ratchet_ix();
+    let mut ct = bytes::BytesMut::from(&b"forged"[..]);
+    let tag = key.seal(&ad, &mut ct);
+
+    let forged = Msg::Group {
+        group,
+        id,
+        cipher: sender::Cipher {
+            generation: bobs_copy.generation(),
+            index,
+            tag,
+            ad,
+            ct: ct.freeze(),
+            sig,
+        },
+    };
+
+    let res = handle_incoming(&mut carol_store, &carol, alice_gid, forged)
+        .expect("failed to handle group message");
+    match res.ack {
+        Some(Ack::Failed {
+            reason: FailureReason::BadSig(SigValid::BadSign),
+            ..
+        }) => {}
+        a => panic!("expected bad signature, found {:?}", a),
+    }
+    assert_eq!(res.output, None);
+
+    // after a rotation, everyone gets the new key again
+    rotate_sender_key(&mut alice_store, group).expect("failed to rotate sender key");
+
+    let third = send(&mut alice_store, b"third");
+    assert_eq!(third.distribute.len(), 2);
+
+    let res = handle_incoming(&mut bob_store, &bob, alice_gid, third.msg.clone())
+        .expect("failed to handle group message");
+    match res.ack {
+        Some(Ack::Failed {
+            reason: FailureReason::NoSenderKey(g),
+            ..
+        }) if g == group => {}
+        a => panic!("expected missing sender key, found {:?}", a),
+    }
+}
+
+#[test]
+fn session_repair() {
+    kcl::init();
+
+    let (alice, alice_gid, mut alice_store, alice_signed) = setup("alice");
+    let (bob, bob_gid, mut bob_store, bob_signed) = setup("bob");
+
+    alice_store
+        .start_sigchain(bob_signed)
+        .expect("failed to create bob sigchain for alice");
+    bob_store
+        .start_sigchain(alice_signed)
+        .expect("failed to create alice sigchain for bob");
+
+    // bob loses the prekey before alice's first message arrives
+    let lost = new_prekey(&bob, &mut bob_store);
+    bob_store
+        .del_prekey(*lost.data())
+        .expect("failed to delete prekey");
+
+    let bundle = PrekeyBundle {
+        signed: lost,
+        one_time: None,
+    };
+
+    start_session(&mut alice_store, &alice, bob_gid.did, bundle)
+        .expect("failed to start session with bob");
+
+    let (_, msg) =
+        prepare_send_to_user(&mut alice_store, bob_gid.uid, Bytes::from_static(b"hello"))
+            .expect("failed to prepare messages")
+            .pop()
+            .unwrap();
+    let pid = get_pid(&msg);
+
+    let res = handle_incoming(&mut bob_store, &bob, alice_gid, msg)
+        .expect("failed to handle init msg from alice");
+    let ack = match res.ack {
+        Some(
+            a @ Ack::Failed {
+                reason: FailureReason::MissingSession,
+                ..
+            },
+        ) => a,
+        a => panic!("expected missing session, found {:?}", a),
+    };
+
+    let res = handle_incoming(&mut alice_store, &alice, bob_gid, Msg::Ack(ack))
+        .expect("alice failed to handle ack from bob");
+    assert_eq!(res.needs_session, Some(pid));
+    assert!(res.to_sender.is_empty());
+    assert_eq!(
+        missing_sessions(&mut alice_store, &[bob_gid.did]).unwrap(),
+        vec![bob_gid.did]
+    );
+
+    // alice fetches a fresh bundle and tries again
+    let bundle = PrekeyBundle {
+        signed: new_prekey(&bob, &mut bob_store),
+        one_time: None,
+    };
+    start_session(&mut alice_store, &alice, bob_gid.did, bundle)
+        .expect("failed to start session with bob");
+
+    let msg = prepare_resend(&mut alice_store, bob_gid.did, pid)
+        .expect("failed to prepare resend")
+        .expect("payload should still be pending");
+
+    let res = handle_incoming(&mut bob_store, &bob, alice_gid, msg)
+        .expect("failed to handle resent msg from alice");
+    assert_eq!(res.ack, Some(Ack::Success(pid)));
+    assert_eq!(
+        res.output.as_ref().map(|b| b.as_ref()),
+        Some(b"hello" as &[u8])
+    );
+
+    // after too many failures, alice gives up
+    let (_, msg) =
+        prepare_send_to_user(&mut alice_store, bob_gid.uid, Bytes::from_static(b"again"))
+            .expect("failed to prepare messages")
+            .pop()
+            .unwrap();
+    let pid = get_pid(&msg);
+
+    for _ in 0..MAX_RETRIES {
+        let ack = Ack::Failed {
+            id: pid,
+            reason: FailureReason::WhoKnows,
+        };
+        let res = handle_incoming(&mut alice_store, &alice, bob_gid, Msg::Ack(ack))
+            .expect("alice failed to handle ack from bob");
+        assert_eq!(res.to_sender.len(), 1);
+        assert_eq!(res.gave_up, None);
+    }
+
+    let ack = Ack::Failed {
+        id: pid,
+        reason: FailureReason::WhoKnows,
+    };
+    let res = handle_incoming(&mut alice_store, &alice, bob_gid, Msg::Ack(ack))
+        .expect("alice failed to handle ack from bob");
+    assert!(res.to_sender.is_empty());
+    assert_eq!(
+        res.gave_up,
+        Some((Bytes::from_static(b"again"), FailureReason::WhoKnows))
+    );
+    assert_eq!(alice_store.get_pending_payload(pid).unwrap(), None);
+}
@@ -11,6 +11,112 @@ pub trait RatchetStore: StoreLike {
         with: sig::PublicKey,
         ratchet: dr::DoubleRatchet,
     ) -> Result<(), Self::Error>;
+
+    fn get_session_init(
+        &mut self,
+        with: sig::PublicKey,
+    ) -> Result<Option<SessionInit>, Self::Error>;
+
+    fn store_session_init(
+        &mut self,
+        with: sig::PublicKey,
+        init: SessionInit,
+    ) -> Result<(), Self::Error>;
+
+    fn del_session_init(
+        &mut self,
+        with: sig::PublicKey,
+    ) -> Result<(), Self::Error>;
+
+    /// Deletes the ratchet with `with`, along with any pending session init.
+    fn del_ratchet(
+        &mut self,
+        with: sig::PublicKey,
+    ) -> Result<(), Self::Error>;
+}
+
+pub trait GroupStore: StoreLike {
+    fn get_own_sender_ratchet(
+        &mut self,
+        group: GroupId,
+    ) -> Result<Option<sender::Ratchet>, Self::Error>;
+
+    fn store_own_sender_ratchet(
+        &mut self,
+        group: GroupId,
+        ratchet: sender::Ratchet,
+    ) -> Result<(), Self::Error>;
+
+    /// Devices we have given our current sender key for `group`
+    fn sender_key_recipients(
+        &mut self,
+        group: GroupId,
+    ) -> Result<Vec<sig::PublicKey>, Self::Error>;
+
+    fn add_sender_key_recipients(
+        &mut self,
+        group: GroupId,
+        to: &[sig::PublicKey],
+    ) -> Result<(), Self::Error>;
+
+    fn clear_sender_key_recipients(
+        &mut self,
+        group: GroupId,
+    ) -> Result<(), Self::Error>;
+
+    fn remove_sender_key_recipient(
+        &mut self,
+        group: GroupId,
+        key: sig::PublicKey,
+    ) -> Result<(), Self::Error>;
+
+    fn get_sender_ratchet(
+        &mut self,
+        group: GroupId,
+        from: sig::PublicKey,
+    ) -> Result<Option<sender::Ratchet>, Self::Error>;
+
+    fn store_sender_ratchet(
+        &mut self,
+        group: GroupId,
+        from: sig::PublicKey,
+        ratchet: sender::Ratchet,
+    ) -> Result<(), Self::Error>;
+
+    /// Stores message keys that were skipped over while decrypting
+    fn store_sender_keys(
+        &mut self,
+        group: GroupId,
+        from: sig::PublicKey,
+        generation: u32,
+        keys: sender::ExtraKeys,
+    ) -> Result<(), Self::Error>;
+
+    /// Removes and returns a skipped message key
+    fn take_sender_key(
+        &mut self,
+        group: GroupId,
+        from: sig::PublicKey,
+        generation: u32,
+        ix: u32,
+    ) -> Result<Option<aead::Key>, Self::Error>;
+}
+
+pub trait PrekeyStore: StoreLike {
+    fn store_prekey(
+        &mut self,
+        kp: kx::KeyPair,
+    ) -> Result<(), Self::Error>;
+
+    fn get_prekey(
+        &mut self,
+        pre: Prekey,
+    ) -> Result<Option<kx::KeyPair>, Self::Error>;
+
+    fn del_prekey(
+        &mut self,
+        pre: Prekey,
+    ) -> Result<(), Self::Error>;
 }
 
 pub trait SigStore: StoreLike {
@@ -67,4 +173,17 @@ pub trait PendingStore: StoreLike {
         id: PayloadId,
         to: sig::PublicKey,
     ) -> Result<(), Self::Error>;
+
+    /// Records another attempt to deliver `id` to `to`, returning the number of retries so far.
+    fn note_retry(
+        &mut self,
+        id: PayloadId,
+        to: sig::PublicKey,
+    ) -> Result<u32, Self::Error>;
+
+    /// The payloads still waiting to be acknowledged by `to`.
+    fn pending_to(
+        &mut self,
+        to: sig::PublicKey,
+    ) -> Result<Vec<PayloadId>, Self::Error>;
 }
@@ -2,12 +2,32 @@ use super::*;
 
 use std::ops::DerefMut;
 
-#[derive(Ser, De)]
+/// The key messages sealed with a sender ratchet are signed with.
+///
+/// Everyone holding the ratchet can derive its message keys, so only the owner gets the secret
+/// half of this key. That way other members of the group can't forge messages from the owner.
+#[derive(Ser, De, Clone, Debug)]
+pub enum SigningKey {
+    Own(sign::KeyPair),
+    Theirs(sign::PublicKey),
+}
+
+impl SigningKey {
+    pub fn public(&self) -> &sign::PublicKey {
+        match self {
+            SigningKey::Own(kp) => kp.public(),
+            SigningKey::Theirs(pk) => pk,
+        }
+    }
+}
+
+#[derive(Ser, De, Clone, Debug)]
 pub struct Ratchet {
     generation: u32,
     ix: u32,
     base_key: hash::Key,
     ratchet_key: RatchetKey,
+    signing_key: SigningKey,
 }
 
 impl Ratchet {
@@ -16,12 +36,14 @@ impl Ratchet {
         ix: u32,
         base_key: hash::Key,
         ratchet_key: RatchetKey,
+        signing_key: SigningKey,
     ) -> Self {
         Self {
             generation,
             ix,
             base_key,
             ratchet_key,
+            signing_key,
         }
     }
 
@@ -37,6 +59,16 @@ impl Ratchet {
             generation,
             base_key,
             ratchet_key,
+            signing_key: SigningKey::Own(sign::KeyPair::gen_new()),
+        }
+    }
+
+    /// A copy of the ratchet to hand to other devices, without the secret half of the signing
+    /// key.
+    pub fn distribution(&self) -> Self {
+        Ratchet {
+            signing_key: SigningKey::Theirs(*self.signing_key.public()),
+            ..self.clone()
         }
     }
 
@@ -73,27 +105,65 @@ impl Ratchet {
         &self.ratchet_key
     }
 
+    pub fn signing_key(&self) -> &SigningKey {
+        &self.signing_key
+    }
+
+    /// Encrypts and signs `msg`, or returns `None` if this isn't our own ratchet.
     pub fn seal(
         &mut self,
         ad: Bytes,
         mut msg: BytesMut,
-    ) -> Cipher {
+    ) -> Option<Cipher> {
+        let kp = match &self.signing_key {
+            SigningKey::Own(kp) => kp.clone(),
+            SigningKey::Theirs(_) => return None,
+        };
+
         let (index, key) = self.ratchet_ix();
         let tag = key.seal(&ad, &mut msg);
-        Cipher {
+        let ct = msg.freeze();
+
+        let sig = kp
+            .secret()
+            .sign(&signed_bytes(self.generation, index, &tag, &ad, &ct));
+
+        Some(Cipher {
             generation: self.generation,
             index,
             tag,
             ad,
-            ct: msg.freeze(),
-        }
+            ct,
+            sig,
+        })
+    }
+
+    /// Checks that `cipher` was signed by the owner of this ratchet.
+    pub fn verify(
+        &self,
+        cipher: &Cipher,
+    ) -> bool {
+        let Cipher {
+            generation,
+            index,
+            tag,
+            ad,
+            ct,
+            sig,
+        } = cipher;
+
+        self.signing_key
+            .public()
+            .verify(&signed_bytes(*generation, *index, tag, ad, ct), *sig)
     }
 
     pub fn open(
         &mut self,
         cipher: Cipher,
     ) -> Decrypted {
-        if cipher.generation != self.generation {
+        if !self.verify(&cipher) {
+            return Decrypted::BadSignature;
+        } else if cipher.generation != self.generation {
             return Decrypted::WrongGeneration;
         } else if cipher.index < self.ix {
             return Decrypted::IndexTooLow;
@@ -123,13 +193,25 @@ impl Ratchet {
     }
 }
 
-#[derive(Debug, Clone, Ser, De, Eq, PartialEq)]
+#[derive(Debug, Clone, Ser, De, Eq, PartialEq, Hash)]
 pub struct Cipher {
     pub generation: u32,
     pub index: u32,
     pub tag: aead::Tag,
     pub ad: Bytes,
     pub ct: Bytes,
+    /// Signature over everything else, by the ratchet's signing key
+    pub sig: sign::Signature,
+}
+
+fn signed_bytes(
+    generation: u32,
+    index: u32,
+    tag: &aead::Tag,
+    ad: &Bytes,
+    ct: &Bytes,
+) -> Vec<u8> {
+    kson::to_vec(&(generation, index, tag, ad, ct))
 }
 
 pub type ExtraKeys = Vec<(u32, aead::Key)>;
@@ -142,6 +224,7 @@ pub enum Decrypted {
         ad: Bytes,
         extra: ExtraKeys,
     },
+    BadSignature,
     WrongGeneration,
     IndexTooLow,
     DecryptionFailed(ExtraKeys),